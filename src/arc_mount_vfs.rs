@@ -0,0 +1,325 @@
+use crate::darc::{DARCReader, DARCVFS};
+use crate::ivfc::DirectoryOrFile;
+use crate::ivfc_vfs::{IVFCMeta, IVFCVFS, IVFCVPATH};
+use crate::{ReadAt, ReadAtPartition};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::{Component, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use vfs::{OpenOptions, VFile, VMetadata, VPath, VFS};
+
+/// A `.arc` file of the romfs, parsed as a DARC
+struct MountedArchive<T: 'static + ReadAt + Send + Sync + fmt::Debug> {
+    darc: DARCVFS<ReadAtPartition<T>>,
+    /// The `.arc` file, that the files of the archive are partitions of
+    partition: ReadAtPartition<T>,
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> MountedArchive<T> {
+    /// Open the file at `path` in the archive, reading directly from the romfs rather than through the mutex of the DARC reader
+    fn open_file(&self, path: &str) -> io::Result<ReadAtPartition<T>> {
+        let reader = self.darc.get_reader();
+        let entry_index = match reader.get_entry_index(path) {
+            Some(value) => value,
+            None => return Err(io::Error::from(io::ErrorKind::NotFound)),
+        };
+        let (offset, lenght) = reader.get_file_position(entry_index)?;
+        Ok(self.partition.sub_partition(offset, lenght))
+    }
+}
+
+type MountedDARC<T> = Arc<MountedArchive<T>>;
+
+/// A read only VFS over a romfs, where the `.arc` files that are valid DARC archives are shown as directories.
+///
+/// Only archives directly in the romfs are mounted: an `.arc` file inside another archive is left as a file.
+pub struct ArcMountVFS<T: 'static + ReadAt + Send + Sync + fmt::Debug> {
+    romfs: Arc<IVFCVFS<T>>,
+    mounted: Arc<Mutex<HashMap<PathBuf, Option<MountedDARC<T>>>>>,
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> ArcMountVFS<T> {
+    pub fn new(romfs: IVFCVFS<T>) -> ArcMountVFS<T> {
+        ArcMountVFS {
+            romfs: Arc::new(romfs),
+            mounted: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> VFS for ArcMountVFS<T> {
+    type PATH = ArcMountVPATH<T>;
+    type METADATA = IVFCMeta;
+    type FILE = ReadAtPartition<T>;
+
+    fn path<A: Into<String>>(&self, path: A) -> Self::PATH {
+        ArcMountVPATH {
+            romfs: self.romfs.clone(),
+            mounted: self.mounted.clone(),
+            path: PathBuf::from(path.into()),
+        }
+    }
+}
+
+/// Where an `ArcMountVPATH` point to
+enum Resolved<T: 'static + ReadAt + Send + Sync + fmt::Debug> {
+    RomFS(IVFCVPATH<T>),
+    /// A path inside a mounted archive. The path is empty for the archive itself.
+    Archive(MountedDARC<T>, String),
+}
+
+pub struct ArcMountVPATH<T: 'static + ReadAt + Send + Sync + fmt::Debug> {
+    romfs: Arc<IVFCVFS<T>>,
+    mounted: Arc<Mutex<HashMap<PathBuf, Option<MountedDARC<T>>>>>,
+    path: PathBuf,
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> fmt::Debug for ArcMountVPATH<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArcMountVPATH")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> Clone for ArcMountVPATH<T> {
+    fn clone(&self) -> ArcMountVPATH<T> {
+        self.with_path(self.path.clone())
+    }
+}
+
+fn is_arc_file_name(path: &PathBuf) -> bool {
+    match path.extension() {
+        Some(extension) => extension.eq_ignore_ascii_case("arc"),
+        None => false,
+    }
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> ArcMountVPATH<T> {
+    fn with_path(&self, path: PathBuf) -> ArcMountVPATH<T> {
+        ArcMountVPATH {
+            romfs: self.romfs.clone(),
+            mounted: self.mounted.clone(),
+            path,
+        }
+    }
+
+    /// Return the archive stored at `romfs_path`, or `None` if it isn't a file or can't be parsed as a DARC.
+    fn get_mounted(&self, romfs_path: &PathBuf) -> Option<MountedDARC<T>> {
+        let mut mounted = match self.mounted.lock() {
+            Ok(value) => value,
+            Err(err) => err.into_inner(),
+        };
+        if let Some(cached) = mounted.get(romfs_path) {
+            return cached.clone();
+        };
+        let romfs_vpath = self.romfs.path(romfs_path.to_string_lossy());
+        let archive = match romfs_vpath.get_internal_meta() {
+            Ok(DirectoryOrFile::File(_)) => {
+                romfs_vpath.open_partition().ok().and_then(|partition| {
+                    DARCReader::new(partition.clone()).ok().map(|reader| {
+                        Arc::new(MountedArchive {
+                            darc: DARCVFS::new(reader),
+                            partition,
+                        })
+                    })
+                })
+            }
+            _ => None,
+        };
+        mounted.insert(romfs_path.clone(), archive.clone());
+        archive
+    }
+
+    /// Find if this path is in the romfs or in a mounted archive
+    fn resolve_path(&self) -> Resolved<T> {
+        let mut romfs_path = PathBuf::new();
+        let mut components = self.path.components();
+        while let Some(component) = components.next() {
+            match component {
+                Component::Normal(part) => romfs_path.push(part),
+                _ => continue,
+            };
+            if is_arc_file_name(&romfs_path) {
+                if let Some(archive) = self.get_mounted(&romfs_path) {
+                    return Resolved::Archive(
+                        archive,
+                        components.as_path().to_string_lossy().into_owned(),
+                    );
+                };
+            };
+        }
+        Resolved::RomFS(self.romfs.path(romfs_path.to_string_lossy()))
+    }
+
+    /// Return the path in the romfs of the archive this path is in, or `None` if it isn't in an archive
+    pub fn archive_path(&self) -> Option<PathBuf> {
+        match self.resolve_path() {
+            Resolved::RomFS(_) => None,
+            Resolved::Archive(_, inner_path) => {
+                let mut inner_depth = PathBuf::from(inner_path).components().count();
+                let mut archive_path = self.path.clone();
+                while inner_depth > 0 {
+                    archive_path.pop();
+                    inner_depth -= 1;
+                }
+                Some(archive_path)
+            }
+        }
+    }
+}
+
+fn return_ro_error<T>() -> io::Result<T> {
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "read only file system",
+    ))
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> VPath for ArcMountVPATH<T> {
+    fn open_with_options(&self, opt: &OpenOptions) -> io::Result<Box<dyn VFile>> {
+        if opt.write || opt.create || opt.append || opt.truncate {
+            return return_ro_error();
+        };
+        match self.resolve_path() {
+            Resolved::RomFS(romfs_path) => romfs_path.open_with_options(opt),
+            Resolved::Archive(archive, inner_path) => Ok(Box::new(archive.open_file(&inner_path)?)),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn read_dir(&self) -> io::Result<Box<dyn Iterator<Item = io::Result<Box<dyn VPath>>>>> {
+        let mut childs = Vec::new();
+        let inner_childs = match self.resolve_path() {
+            Resolved::RomFS(romfs_path) => romfs_path.read_dir()?,
+            Resolved::Archive(archive, inner_path) => archive.darc.path(inner_path).read_dir()?,
+        };
+        for child in inner_childs {
+            if let Some(name) = child?.file_name() {
+                childs.push(name);
+            };
+        }
+        let this = self.clone();
+        Ok(Box::new(
+            childs.into_iter().map(move |name| Ok(this.resolve(&name))),
+        ))
+    }
+
+    fn mkdir(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn rm(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn rmrf(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn file_name(&self) -> Option<String> {
+        self.path.file_name()
+    }
+
+    fn extension(&self) -> Option<String> {
+        self.path.extension()
+    }
+
+    fn resolve(&self, path: &String) -> Box<dyn VPath> {
+        let mut new_path = self.path.clone();
+        new_path.push(path);
+        Box::new(self.with_path(new_path))
+    }
+
+    fn parent(&self) -> Option<Box<dyn VPath>> {
+        let mut new_path = self.path.clone();
+        if !new_path.pop() {
+            return None;
+        };
+        Some(Box::new(self.with_path(new_path)))
+    }
+
+    fn to_string(&self) -> Cow<'_, str> {
+        format!("romfs+arc://{:?}", self.path).into()
+    }
+
+    fn box_clone(&self) -> Box<dyn VPath> {
+        Box::new(self.clone())
+    }
+
+    fn to_path_buf(&self) -> Option<PathBuf> {
+        Some(self.path.clone())
+    }
+
+    fn exists(&self) -> bool {
+        match self.resolve_path() {
+            Resolved::RomFS(romfs_path) => romfs_path.exists(),
+            Resolved::Archive(archive, inner_path) => archive.darc.path(inner_path).exists(),
+        }
+    }
+
+    /// A mounted archive is reported as a directory
+    fn metadata(&self) -> io::Result<Box<dyn VMetadata>> {
+        match self.resolve_path() {
+            Resolved::RomFS(romfs_path) => romfs_path.metadata(),
+            Resolved::Archive(archive, inner_path) => archive.darc.path(inner_path).metadata(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::darc::tests::darc_from_files;
+    use crate::ivfc::romfs_from_files;
+    use crate::IVFCReader;
+    use std::io::{Read, Seek, SeekFrom};
+
+    #[test]
+    fn test_mount_arc() {
+        let layout = darc_from_files(&[("a.bin", b"inner")]);
+        let romfs = romfs_from_files(&[
+            ("layout.arc", &layout),
+            ("broken.arc", b"not a darc"),
+            ("c.bin", b"c"),
+        ]);
+        let vfs = ArcMountVFS::new(IVFCVFS::new(IVFCReader::from_read_at(romfs).unwrap()));
+
+        assert!(vfs.path("layout.arc").metadata().unwrap().is_dir());
+        let mut content = String::new();
+        vfs.path("layout.arc/a.bin")
+            .open()
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "inner");
+        let mut file = vfs.path("layout.arc/a.bin").open().unwrap();
+        file.seek(SeekFrom::Start(2)).unwrap();
+        content.clear();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "ner");
+        assert!(vfs.path("layout.arc").open().is_err());
+        assert!(vfs.path("layout.arc/missing.bin").open().is_err());
+        assert_eq!(
+            vfs.path("layout.arc/a.bin").archive_path(),
+            Some(PathBuf::from("layout.arc"))
+        );
+        assert_eq!(vfs.path("c.bin").archive_path(), None);
+        let names: Vec<String> = vfs
+            .path("layout.arc")
+            .read_dir()
+            .unwrap()
+            .map(|child| child.unwrap().file_name().unwrap())
+            .collect();
+        assert_eq!(names, ["a.bin"]);
+
+        // a .arc file that isn't a darc stay a file
+        let broken_meta = vfs.path("broken.arc").metadata().unwrap();
+        assert!(broken_meta.is_file());
+        assert_eq!(broken_meta.len(), 10);
+        assert!(!vfs.path("broken.arc/a.bin").exists());
+    }
+}
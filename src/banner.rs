@@ -0,0 +1,290 @@
+use crate::lz::{decompress_lz11, LZError};
+use crate::{BCWAVError, BCWAV};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::Read;
+
+#[derive(Debug)]
+pub enum BannerError {
+    ReadError(io::Error),
+    InvalidMagic([u8; 4]), // the invalid magic
+    InvalidOffset(u32),
+    TooBig(usize), // the lenght of the banner
+    DecompressionError(LZError),
+    BCWAVError(BCWAVError),
+}
+
+impl Error for BannerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::ReadError(err) => Some(err),
+            Self::DecompressionError(err) => Some(err),
+            Self::BCWAVError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BannerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadError(_) => write!(f, "failed to read the banner"),
+            Self::InvalidMagic(magic) => write!(
+                f,
+                "the magic of the banner is invalid. Found {:?}, expected [67, 66, 77, 68].",
+                magic
+            ),
+            Self::InvalidOffset(offset) => {
+                write!(f, "the offset {:#x} point outside of the banner", offset)
+            }
+            Self::TooBig(lenght) => write!(
+                f,
+                "the banner is {} bytes long, more than its 32 bits offsets can address",
+                lenght
+            ),
+            Self::DecompressionError(_) => write!(f, "failed to decompress a CGFX of the banner"),
+            Self::BCWAVError(_) => write!(f, "failed to parse the BCWAV of the banner"),
+        }
+    }
+}
+
+/// The regions that can have a specific CGFX in a banner, in the order they are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BannerRegion {
+    EUREnglish = 0,
+    EURFrench = 1,
+    EURGerman = 2,
+    EURItalian = 3,
+    EURSpanish = 4,
+    EURDutch = 5,
+    EURPortuguese = 6,
+    EURRussian = 7,
+    JPNJapanese = 8,
+    USAEnglish = 9,
+    USAFrench = 10,
+    USASpanish = 11,
+    USAPortuguese = 12,
+}
+
+/// Read the CBMD container stored in the `banner` file of the ExeFS, that contain the 3D banner and its jingle.
+///
+/// The whole banner is kept in memory, as it is small.
+#[derive(Debug, Clone)]
+pub struct BannerReader {
+    data: Vec<u8>,
+    lenght: u32,
+    pub common_cgfx_offset: u32,
+    /// The offsets of the region specific CGFX, indexed by `BannerRegion`. 0 mean there is none.
+    pub region_cgfx_offsets: [u32; 13],
+    pub cwav_offset: u32,
+}
+
+impl BannerReader {
+    pub fn new<T: Read>(mut file: T) -> Result<BannerReader, BannerError> {
+        let mut data = Vec::new();
+        match file.read_to_end(&mut data) {
+            Ok(_) => (),
+            Err(err) => return Err(BannerError::ReadError(err)),
+        };
+        if data.len() < 0x88 {
+            return Err(BannerError::ReadError(io::Error::from(
+                io::ErrorKind::UnexpectedEof,
+            )));
+        };
+        let lenght = match u32::try_from(data.len()) {
+            Ok(value) => value,
+            Err(_) => return Err(BannerError::TooBig(data.len())),
+        };
+
+        let mut magic = [0; 4];
+        magic.copy_from_slice(&data[0..4]);
+        if &magic != b"CBMD" {
+            return Err(BannerError::InvalidMagic(magic));
+        };
+
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ])
+        };
+
+        let common_cgfx_offset = read_u32(0x8);
+        let mut region_cgfx_offsets = [0; 13];
+        for (region_nb, offset) in region_cgfx_offsets.iter_mut().enumerate() {
+            *offset = read_u32(0xC + region_nb * 4);
+        }
+        let cwav_offset = read_u32(0x84);
+
+        Ok(BannerReader {
+            data,
+            lenght,
+            common_cgfx_offset,
+            region_cgfx_offsets,
+            cwav_offset,
+        })
+    }
+
+    /// Return the offset of the end of the blob starting at `offset`, that is the start of the next blob
+    fn get_blob_end(&self, offset: u32) -> u32 {
+        let mut end = self.lenght;
+        for other_offset in self
+            .region_cgfx_offsets
+            .iter()
+            .chain([self.common_cgfx_offset, self.cwav_offset].iter())
+        {
+            if *other_offset > offset && *other_offset < end {
+                end = *other_offset;
+            };
+        }
+        end
+    }
+
+    fn get_blob(&self, offset: u32) -> Result<&[u8], BannerError> {
+        let end = self.get_blob_end(offset);
+        match self.data.get(offset as usize..end as usize) {
+            Some(value) => Ok(value),
+            None => Err(BannerError::InvalidOffset(offset)),
+        }
+    }
+
+    /// Return the LZ11 compressed common CGFX, as stored in the banner
+    pub fn get_common_cgfx_raw(&self) -> Result<&[u8], BannerError> {
+        self.get_blob(self.common_cgfx_offset)
+    }
+
+    /// Return the decompressed common CGFX
+    pub fn get_common_cgfx(&self) -> Result<Vec<u8>, BannerError> {
+        match decompress_lz11(self.get_common_cgfx_raw()?) {
+            Ok(value) => Ok(value),
+            Err(err) => Err(BannerError::DecompressionError(err)),
+        }
+    }
+
+    /// Return the LZ11 compressed CGFX specific to a region, if there is one
+    pub fn get_region_cgfx_raw(&self, region: BannerRegion) -> Result<Option<&[u8]>, BannerError> {
+        let offset = self.region_cgfx_offsets[region as usize];
+        if offset == 0 {
+            return Ok(None);
+        };
+        Ok(Some(self.get_blob(offset)?))
+    }
+
+    /// Return the decompressed CGFX specific to a region, if there is one
+    pub fn get_region_cgfx(&self, region: BannerRegion) -> Result<Option<Vec<u8>>, BannerError> {
+        match self.get_region_cgfx_raw(region)? {
+            Some(raw) => match decompress_lz11(raw) {
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(BannerError::DecompressionError(err)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Return the BCWAV jingle, as stored in the banner
+    pub fn get_bcwav_raw(&self) -> Result<&[u8], BannerError> {
+        self.get_blob(self.cwav_offset)
+    }
+
+    /// Return the parsed BCWAV jingle, that can be converted to a WAV file with `BCWAV::to_wav`
+    pub fn get_bcwav(&self) -> Result<BCWAV, BannerError> {
+        match BCWAV::new(self.get_bcwav_raw()?.to_vec()) {
+            Ok(value) => Ok(value),
+            Err(err) => Err(BannerError::BCWAVError(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bcwav::tests::bcwav;
+    use crate::lz::compress_lz11;
+
+    /// A banner with a common CGFX, an english USA CGFX and a BCWAV, in this order
+    fn banner(common_cgfx: &[u8], usa_english_cgfx: &[u8], cwav: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; 0x88];
+        data[0..4].copy_from_slice(b"CBMD");
+        let add_blob = |data: &mut Vec<u8>, header_offset: usize, blob: &[u8]| {
+            let offset = data.len() as u32;
+            data[header_offset..header_offset + 4].copy_from_slice(&offset.to_le_bytes());
+            data.extend_from_slice(blob);
+        };
+        add_blob(&mut data, 0x8, &compress_lz11(common_cgfx).unwrap());
+        add_blob(
+            &mut data,
+            0xC + BannerRegion::USAEnglish as usize * 4,
+            &compress_lz11(usa_english_cgfx).unwrap(),
+        );
+        add_blob(&mut data, 0x84, cwav);
+        data
+    }
+
+    #[test]
+    fn test_banner() {
+        let cwav = bcwav(1, 32000, 4);
+        let data = banner(b"common CGFX", b"english CGFX", &cwav);
+        let banner = BannerReader::new(&data[..]).unwrap();
+
+        assert_eq!(banner.common_cgfx_offset, 0x88);
+        let usa_english_offset = banner.region_cgfx_offsets[BannerRegion::USAEnglish as usize];
+        assert!(usa_english_offset > banner.common_cgfx_offset);
+        assert!(banner.cwav_offset > usa_english_offset);
+        assert_eq!(banner.cwav_offset as usize, data.len() - cwav.len());
+
+        // each blob end at the start of the next one
+        assert_eq!(
+            banner.get_common_cgfx_raw().unwrap(),
+            &data[0x88..usa_english_offset as usize]
+        );
+        assert_eq!(banner.get_common_cgfx().unwrap(), b"common CGFX");
+        assert_eq!(
+            banner
+                .get_region_cgfx(BannerRegion::USAEnglish)
+                .unwrap()
+                .unwrap(),
+            b"english CGFX"
+        );
+        assert_eq!(
+            banner.get_region_cgfx(BannerRegion::EUREnglish).unwrap(),
+            None
+        );
+        assert_eq!(banner.get_bcwav_raw().unwrap(), &cwav[..]);
+        assert_eq!(banner.get_bcwav().unwrap().sample_rate, 32000);
+    }
+
+    #[test]
+    fn test_invalid_banner() {
+        let mut data = banner(b"common CGFX", b"english CGFX", &bcwav(1, 32000, 4));
+        // the BCWAV start past the end of the banner
+        let past_the_end = data.len() as u32 + 1;
+        data[0x84..0x88].copy_from_slice(&past_the_end.to_le_bytes());
+        let banner = BannerReader::new(&data[..]).unwrap();
+        assert!(matches!(
+            banner.get_bcwav_raw(),
+            Err(BannerError::InvalidOffset(offset)) if offset == past_the_end
+        ));
+
+        // the common CGFX isn't LZ11 compressed
+        data[0x8..0xC].copy_from_slice(&0x80u32.to_le_bytes());
+        let banner = BannerReader::new(&data[..]).unwrap();
+        assert!(matches!(
+            banner.get_common_cgfx(),
+            Err(BannerError::DecompressionError(_))
+        ));
+
+        data[0] = b'X';
+        assert!(matches!(
+            BannerReader::new(&data[..]),
+            Err(BannerError::InvalidMagic(_))
+        ));
+        assert!(matches!(
+            BannerReader::new(&data[..0x80]),
+            Err(BannerError::ReadError(_))
+        ));
+    }
+}
@@ -0,0 +1,402 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum BCWAVError {
+    InvalidMagic([u8; 4], &'static str), // the invalid magic, what should contain it
+    BigEndian,
+    Truncated(&'static str),
+    BlockNotFound(&'static str),
+    UnsupportedEncoding(u8),
+    TooBig(&'static str), // the field of the wav that can't hold its value
+}
+
+impl Error for BCWAVError {}
+
+impl fmt::Display for BCWAVError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidMagic(magic, what) => {
+                write!(f, "the magic of the {} is invalid (it's {:?})", what, magic)
+            }
+            Self::BigEndian => write!(f, "big endian bcwav are not supported"),
+            Self::Truncated(what) => write!(f, "the bcwav is truncated while reading the {}", what),
+            Self::BlockNotFound(what) => write!(f, "the {} block is missing in the bcwav", what),
+            Self::UnsupportedEncoding(encoding) => write!(
+                f,
+                "the encoding {} can't be converted to PCM (only PCM8, PCM16 and DSP ADPCM are supported)",
+                encoding
+            ),
+            Self::TooBig(what) => write!(f, "the {} of the wav is too big to be stored", what),
+        }
+    }
+}
+
+/// The encoding of the samples of a BCWAV
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BCWAVEncoding {
+    PCM8,
+    PCM16,
+    DSPADPCM,
+    IMAADPCM,
+    Unknown(u8),
+}
+
+impl From<u8> for BCWAVEncoding {
+    fn from(value: u8) -> BCWAVEncoding {
+        match value {
+            0 => Self::PCM8,
+            1 => Self::PCM16,
+            2 => Self::DSPADPCM,
+            3 => Self::IMAADPCM,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+/// The DSP ADPCM decoding informations of a channel
+#[derive(Debug, Clone)]
+pub struct DSPADPCMInfo {
+    pub coefficients: [i16; 16],
+    pub history_1: i16,
+    pub history_2: i16,
+}
+
+#[derive(Debug, Clone)]
+pub struct BCWAVChannel {
+    /// The offset of the samples of the channel, relative to the start of the bcwav
+    pub samples_offset: usize,
+    pub dsp_adpcm_info: Option<DSPADPCMInfo>,
+}
+
+/// A parsed BCWAV sound. It keep a copy of the whole file.
+#[derive(Debug, Clone)]
+pub struct BCWAV {
+    pub data: Vec<u8>,
+    pub encoding: BCWAVEncoding,
+    pub is_looping: bool,
+    pub sample_rate: u32,
+    pub loop_start: u32,
+    /// The end of the loop. This is also the number of samples of each channel.
+    pub loop_end: u32,
+    pub channels: Vec<BCWAVChannel>,
+}
+
+fn get_u16(data: &[u8], offset: usize, what: &'static str) -> Result<u16, BCWAVError> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_le_bytes([bytes[0], bytes[1]])),
+        None => Err(BCWAVError::Truncated(what)),
+    }
+}
+
+fn get_u32(data: &[u8], offset: usize, what: &'static str) -> Result<u32, BCWAVError> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => Err(BCWAVError::Truncated(what)),
+    }
+}
+
+fn get_magic(data: &[u8], offset: usize, what: &'static str) -> Result<[u8; 4], BCWAVError> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => Ok([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        None => Err(BCWAVError::Truncated(what)),
+    }
+}
+
+impl BCWAV {
+    pub fn new(data: Vec<u8>) -> Result<BCWAV, BCWAVError> {
+        let magic = get_magic(&data, 0, "header")?;
+        if &magic != b"CWAV" {
+            return Err(BCWAVError::InvalidMagic(magic, "header"));
+        };
+        if get_u16(&data, 4, "byte order mark")? != 0xFEFF {
+            return Err(BCWAVError::BigEndian);
+        };
+
+        // find the INFO and DATA blocks
+        let block_count = get_u16(&data, 0x10, "block count")? as usize;
+        let mut info_offset = None;
+        let mut data_offset = None;
+        for block_nb in 0..block_count {
+            let reference_offset = 0x14 + block_nb * 0xC;
+            let block_type = get_u16(&data, reference_offset, "block reference")?;
+            let block_offset = get_u32(&data, reference_offset + 4, "block reference")? as usize;
+            match block_type {
+                0x7000 => info_offset = Some(block_offset),
+                0x7001 => data_offset = Some(block_offset),
+                _ => (),
+            };
+        }
+        let info_offset = match info_offset {
+            Some(value) => value,
+            None => return Err(BCWAVError::BlockNotFound("INFO")),
+        };
+        let data_offset = match data_offset {
+            Some(value) => value,
+            None => return Err(BCWAVError::BlockNotFound("DATA")),
+        };
+
+        let magic = get_magic(&data, info_offset, "INFO block")?;
+        if &magic != b"INFO" {
+            return Err(BCWAVError::InvalidMagic(magic, "INFO block"));
+        };
+        let magic = get_magic(&data, data_offset, "DATA block")?;
+        if &magic != b"DATA" {
+            return Err(BCWAVError::InvalidMagic(magic, "DATA block"));
+        };
+
+        let encoding = match data.get(info_offset + 8) {
+            Some(value) => BCWAVEncoding::from(*value),
+            None => return Err(BCWAVError::Truncated("encoding")),
+        };
+        let is_looping = match data.get(info_offset + 9) {
+            Some(value) => *value != 0,
+            None => return Err(BCWAVError::Truncated("loop flag")),
+        };
+        let sample_rate = get_u32(&data, info_offset + 0xC, "sample rate")?;
+        let loop_start = get_u32(&data, info_offset + 0x10, "loop start")?;
+        let loop_end = get_u32(&data, info_offset + 0x14, "loop end")?;
+
+        // channel informations
+        let channel_table_offset = info_offset + 0x1C;
+        let channel_count = get_u32(&data, channel_table_offset, "channel count")? as usize;
+        let mut channels = Vec::new();
+        for channel_nb in 0..channel_count {
+            let channel_info_offset = channel_table_offset
+                + get_u32(
+                    &data,
+                    channel_table_offset + 4 + channel_nb * 8 + 4,
+                    "channel reference",
+                )? as usize;
+            let samples_offset = data_offset
+                + 8
+                + get_u32(&data, channel_info_offset + 4, "samples reference")? as usize;
+            let adpcm_info_type = get_u16(&data, channel_info_offset + 8, "adpcm reference")?;
+            let dsp_adpcm_info = if adpcm_info_type == 0x0300 {
+                let adpcm_info_offset = channel_info_offset
+                    + get_u32(&data, channel_info_offset + 0xC, "adpcm reference")? as usize;
+                let mut coefficients = [0; 16];
+                for (coefficient_nb, coefficient) in coefficients.iter_mut().enumerate() {
+                    *coefficient = get_u16(
+                        &data,
+                        adpcm_info_offset + coefficient_nb * 2,
+                        "adpcm coefficients",
+                    )? as i16;
+                }
+                Some(DSPADPCMInfo {
+                    coefficients,
+                    history_1: get_u16(&data, adpcm_info_offset + 0x22, "adpcm context")? as i16,
+                    history_2: get_u16(&data, adpcm_info_offset + 0x24, "adpcm context")? as i16,
+                })
+            } else {
+                None
+            };
+            channels.push(BCWAVChannel {
+                samples_offset,
+                dsp_adpcm_info,
+            });
+        }
+
+        Ok(BCWAV {
+            data,
+            encoding,
+            is_looping,
+            sample_rate,
+            loop_start,
+            loop_end,
+            channels,
+        })
+    }
+
+    /// Decode the samples of a channel to signed 16 bits PCM
+    pub fn decode_channel(&self, channel_nb: usize) -> Result<Vec<i16>, BCWAVError> {
+        let channel = match self.channels.get(channel_nb) {
+            Some(value) => value,
+            None => return Err(BCWAVError::Truncated("channel informations")),
+        };
+        let sample_count = self.loop_end as usize;
+        // no encoding store more than 2 samples per byte, so don't trust the header for bigger reservations
+        let available_lenght = self.data.len().saturating_sub(channel.samples_offset);
+        let mut samples = Vec::with_capacity(std::cmp::min(
+            sample_count,
+            available_lenght.saturating_mul(2),
+        ));
+        match self.encoding {
+            BCWAVEncoding::PCM8 => {
+                for sample_nb in 0..sample_count {
+                    match self.data.get(channel.samples_offset + sample_nb) {
+                        Some(value) => samples.push(((*value as i8) as i16) << 8),
+                        None => return Err(BCWAVError::Truncated("samples")),
+                    }
+                }
+            }
+            BCWAVEncoding::PCM16 => {
+                for sample_nb in 0..sample_count {
+                    samples.push(get_u16(
+                        &self.data,
+                        channel.samples_offset + sample_nb * 2,
+                        "samples",
+                    )? as i16);
+                }
+            }
+            BCWAVEncoding::DSPADPCM => {
+                let info = match &channel.dsp_adpcm_info {
+                    Some(value) => value,
+                    None => return Err(BCWAVError::BlockNotFound("DSP ADPCM info")),
+                };
+                let mut history_1 = info.history_1 as i64;
+                let mut history_2 = info.history_2 as i64;
+                let mut frame_offset = channel.samples_offset;
+                // each 8 bytes frame contain an header and 14 samples
+                while samples.len() < sample_count {
+                    let header = match self.data.get(frame_offset) {
+                        Some(value) => *value,
+                        None => return Err(BCWAVError::Truncated("samples")),
+                    };
+                    let predictor = ((header >> 4) & 0x7) as usize;
+                    let scale = 1i64 << (header & 0xF);
+                    let coefficient_1 = info.coefficients[predictor * 2] as i64;
+                    let coefficient_2 = info.coefficients[predictor * 2 + 1] as i64;
+                    for nibble_nb in 0..14 {
+                        if samples.len() >= sample_count {
+                            break;
+                        };
+                        let byte = match self.data.get(frame_offset + 1 + nibble_nb / 2) {
+                            Some(value) => *value,
+                            None => return Err(BCWAVError::Truncated("samples")),
+                        };
+                        let nibble = if nibble_nb % 2 == 0 {
+                            byte >> 4
+                        } else {
+                            byte & 0xF
+                        };
+                        // sign extend the nibble
+                        let nibble = ((nibble as i8) << 4 >> 4) as i64;
+                        // in 64 bits, as a malformed stream can overflow 32 bits before clamping
+                        let sample = ((nibble * scale) << 11)
+                            + 1024
+                            + coefficient_1 * history_1
+                            + coefficient_2 * history_2;
+                        let sample = (sample >> 11).clamp(i16::MIN as i64, i16::MAX as i64);
+                        history_2 = history_1;
+                        history_1 = sample;
+                        samples.push(sample as i16);
+                    }
+                    frame_offset += 8;
+                }
+            }
+            BCWAVEncoding::IMAADPCM => return Err(BCWAVError::UnsupportedEncoding(3)),
+            BCWAVEncoding::Unknown(encoding) => {
+                return Err(BCWAVError::UnsupportedEncoding(encoding))
+            }
+        };
+        Ok(samples)
+    }
+
+    /// Convert this sound to a 16 bits PCM RIFF WAVE file.
+    pub fn to_wav(&self) -> Result<Vec<u8>, BCWAVError> {
+        let mut decoded_channels = Vec::new();
+        for channel_nb in 0..self.channels.len() {
+            decoded_channels.push(self.decode_channel(channel_nb)?);
+        }
+        let sample_count = self.loop_end;
+        let block_align = match u16::try_from(decoded_channels.len() * 2) {
+            Ok(value) => value,
+            Err(_) => return Err(BCWAVError::TooBig("block align")),
+        };
+        let data_lenght = match sample_count.checked_mul(block_align as u32) {
+            Some(value) => value,
+            None => return Err(BCWAVError::TooBig("data lenght")),
+        };
+        let riff_lenght = match data_lenght.checked_add(36) {
+            Some(value) => value,
+            None => return Err(BCWAVError::TooBig("RIFF lenght")),
+        };
+        let byte_rate = match self.sample_rate.checked_mul(block_align as u32) {
+            Some(value) => value,
+            None => return Err(BCWAVError::TooBig("byte rate")),
+        };
+
+        let mut wav = Vec::with_capacity(44 + data_lenght as usize);
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&riff_lenght.to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&(decoded_channels.len() as u16).to_le_bytes());
+        wav.extend_from_slice(&self.sample_rate.to_le_bytes());
+        wav.extend_from_slice(&byte_rate.to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_lenght.to_le_bytes());
+        for sample_nb in 0..sample_count as usize {
+            for channel in &decoded_channels {
+                wav.extend_from_slice(&channel[sample_nb].to_le_bytes());
+            }
+        }
+        Ok(wav)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A mono BCWAV whose DSP ADPCM coefficients and history are all -32768, with a single silent frame
+    pub(crate) fn bcwav(encoding: u8, sample_rate: u32, loop_end: u32) -> Vec<u8> {
+        let mut data = vec![0; 0x110];
+        data[0..4].copy_from_slice(b"CWAV");
+        data[4..6].copy_from_slice(&0xFEFFu16.to_le_bytes());
+        data[0x10..0x12].copy_from_slice(&2u16.to_le_bytes());
+        data[0x14..0x16].copy_from_slice(&0x7000u16.to_le_bytes());
+        data[0x18..0x1C].copy_from_slice(&0x40u32.to_le_bytes());
+        data[0x20..0x22].copy_from_slice(&0x7001u16.to_le_bytes());
+        data[0x24..0x28].copy_from_slice(&0x100u32.to_le_bytes());
+
+        data[0x40..0x44].copy_from_slice(b"INFO");
+        data[0x48] = encoding;
+        data[0x4C..0x50].copy_from_slice(&sample_rate.to_le_bytes());
+        data[0x54..0x58].copy_from_slice(&loop_end.to_le_bytes());
+        // the channel table, and the only channel
+        data[0x5C..0x60].copy_from_slice(&1u32.to_le_bytes());
+        data[0x64..0x68].copy_from_slice(&0x14u32.to_le_bytes());
+        data[0x78..0x7A].copy_from_slice(&0x0300u16.to_le_bytes());
+        data[0x7C..0x80].copy_from_slice(&0x10u32.to_le_bytes());
+        for value_nb in 0..16 {
+            data[0x80 + value_nb * 2..0x82 + value_nb * 2]
+                .copy_from_slice(&0x8000u16.to_le_bytes());
+        }
+        data[0xA2..0xA6].copy_from_slice(&[0x00, 0x80, 0x00, 0x80]);
+
+        data[0x100..0x104].copy_from_slice(b"DATA");
+        data
+    }
+
+    #[test]
+    fn test_dsp_adpcm_clamp() {
+        let sound = BCWAV::new(bcwav(2, 32000, 1)).unwrap();
+        assert_eq!(sound.decode_channel(0).unwrap()[0], i16::MAX);
+    }
+
+    #[test]
+    fn test_to_wav_overflow() {
+        let sound = BCWAV::new(bcwav(1, u32::MAX, 0)).unwrap();
+        assert!(matches!(
+            sound.to_wav(),
+            Err(BCWAVError::TooBig("byte rate"))
+        ));
+        let sound = BCWAV::new(bcwav(1, 32000, 4)).unwrap();
+        assert_eq!(sound.to_wav().unwrap().len(), 44 + 8);
+    }
+
+    #[test]
+    fn test_huge_sample_count() {
+        let sound = BCWAV::new(bcwav(1, 32000, u32::MAX)).unwrap();
+        assert!(matches!(
+            sound.decode_channel(0),
+            Err(BCWAVError::Truncated("samples"))
+        ));
+    }
+}
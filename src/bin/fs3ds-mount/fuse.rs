@@ -0,0 +1,513 @@
+//! A minimal read only FUSE server, speaking the kernel protocol directly over `/dev/fuse`.
+//!
+//! The `fuser` crate isn't used so that the `fuse` feature only depend on `libc`, and build without libfuse or pkg-config. Only the requests a read only file system need are handled (the others are answered with `ENOSYS`), so the protocol part stay small.
+
+use std::convert::TryInto;
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const ROOT_INODE: u64 = 1;
+
+/// How long the kernel can cache the entries and attributes, in seconds. The content never change.
+const TIMEOUT: u64 = 3600;
+const MAX_WRITE: u32 = 0x20000;
+const BUFFER_SIZE: usize = 0x100000 + 0x1000;
+
+const FUSE_LOOKUP: u32 = 1;
+const FUSE_FORGET: u32 = 2;
+const FUSE_GETATTR: u32 = 3;
+const FUSE_OPEN: u32 = 14;
+const FUSE_READ: u32 = 15;
+const FUSE_STATFS: u32 = 17;
+const FUSE_RELEASE: u32 = 18;
+const FUSE_FLUSH: u32 = 25;
+const FUSE_INIT: u32 = 26;
+const FUSE_OPENDIR: u32 = 27;
+const FUSE_READDIR: u32 = 28;
+const FUSE_RELEASEDIR: u32 = 29;
+const FUSE_ACCESS: u32 = 34;
+const FUSE_INTERRUPT: u32 = 36;
+const FUSE_DESTROY: u32 = 38;
+const FUSE_BATCH_FORGET: u32 = 42;
+
+const FOPEN_KEEP_CACHE: u32 = 1 << 1;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Attributes {
+    pub inode: u64,
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+/// A read only file system, where every node is identified by an inode number. The root is `ROOT_INODE`.
+pub trait ReadOnlyFileSystem {
+    fn lookup(&self, parent: u64, name: &[u8]) -> Option<u64>;
+    /// The parent of a node. The root is its own parent.
+    fn parent(&self, inode: u64) -> u64;
+    fn attributes(&self, inode: u64) -> Option<Attributes>;
+    /// Return the childs of a directory, or `None` if it isn't one
+    fn read_dir(&self, inode: u64) -> Option<Vec<(u64, String, bool)>>;
+    fn read(&self, inode: u64, offset: u64, size: u32) -> io::Result<Vec<u8>>;
+}
+
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_stop(_: libc::c_int) {
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// A mounted FUSE file system
+pub struct FuseSession {
+    device: File,
+    mountpoint: PathBuf,
+    privileged: bool,
+    uid: u32,
+    gid: u32,
+}
+
+fn last_os_error<T>() -> io::Result<T> {
+    Err(io::Error::last_os_error())
+}
+
+impl FuseSession {
+    /// Mount a read only FUSE file system at `mountpoint`.
+    ///
+    /// As root, it is mounted directly. Otherwise, it use the setuid `fusermount3` (or `fusermount`) helper.
+    pub fn mount(mountpoint: &Path, fs_name: &str) -> io::Result<FuseSession> {
+        let mountpoint = mountpoint.canonicalize()?;
+        // safety: those calls can't fail
+        let (uid, gid, euid) = unsafe { (libc::getuid(), libc::getgid(), libc::geteuid()) };
+        let privileged = euid == 0;
+        let device = if privileged {
+            mount_privileged(&mountpoint, fs_name, uid, gid)?
+        } else {
+            mount_with_fusermount(&mountpoint, fs_name)?
+        };
+        Ok(FuseSession {
+            device,
+            mountpoint,
+            privileged,
+            uid,
+            gid,
+        })
+    }
+
+    /// Unmount the file system. `run` will then return.
+    pub fn unmount(&self) -> io::Result<()> {
+        unmount(&self.mountpoint, self.privileged)
+    }
+
+    /// Answer the requests of the kernel until the file system is unmounted. SIGINT and SIGTERM unmount it.
+    pub fn run<F: ReadOnlyFileSystem>(&mut self, fs: &F) -> io::Result<()> {
+        install_stop_handler()?;
+        let mut buffer = vec![0; BUFFER_SIZE];
+        loop {
+            if STOP_REQUESTED.swap(false, Ordering::SeqCst) {
+                self.unmount()?;
+            };
+            let read = match self.device.read(&mut buffer) {
+                Ok(value) => value,
+                Err(err) => match err.raw_os_error() {
+                    // the request was interrupted, or a signal was received
+                    Some(libc::ENOENT) | Some(libc::EINTR) | Some(libc::EAGAIN) => continue,
+                    // the file system was unmounted
+                    Some(libc::ENODEV) => return Ok(()),
+                    _ => return Err(err),
+                },
+            };
+            let request = match Request::parse(&buffer[..read]) {
+                Some(value) => value,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "received a truncated fuse request",
+                    ))
+                }
+            };
+            if request.opcode == FUSE_DESTROY {
+                self.reply(&request, Ok(Vec::new()))?;
+                return Ok(());
+            };
+            if let Some(result) = self.handle(fs, &request) {
+                self.reply(&request, result)?;
+            };
+        }
+    }
+
+    /// Return the answer to a request, or `None` if it doesn't need one
+    fn handle<F: ReadOnlyFileSystem>(
+        &self,
+        fs: &F,
+        request: &Request,
+    ) -> Option<Result<Vec<u8>, i32>> {
+        let mut out = Vec::new();
+        let result = match request.opcode {
+            FUSE_INIT => {
+                let kernel_major = request.u32_at(0).unwrap_or(0);
+                let max_readahead = request.u32_at(8).unwrap_or(0);
+                push_u32(&mut out, 7);
+                push_u32(&mut out, 31);
+                if kernel_major == 7 {
+                    push_u32(&mut out, max_readahead);
+                    push_u32(&mut out, 0); // flags
+                    push_u16(&mut out, 0); // max background
+                    push_u16(&mut out, 0); // congestion threshold
+                    push_u32(&mut out, MAX_WRITE);
+                    push_u32(&mut out, 1); // time granularity
+                    push_u16(&mut out, 0); // max pages
+                    push_u16(&mut out, 0); // map alignment
+                    out.resize(64, 0);
+                };
+                Ok(out)
+            }
+            FUSE_FORGET | FUSE_BATCH_FORGET | FUSE_INTERRUPT => return None,
+            FUSE_LOOKUP => {
+                let name = request.data.split(|c| *c == 0).next().unwrap_or_default();
+                match fs
+                    .lookup(request.node_id, name)
+                    .and_then(|inode| fs.attributes(inode))
+                {
+                    Some(attributes) => {
+                        push_u64(&mut out, attributes.inode);
+                        push_u64(&mut out, 0); // generation
+                        push_u64(&mut out, TIMEOUT); // entry valid
+                        push_u64(&mut out, TIMEOUT); // attributes valid
+                        push_u32(&mut out, 0);
+                        push_u32(&mut out, 0);
+                        self.push_attributes(&mut out, &attributes);
+                        Ok(out)
+                    }
+                    None => Err(libc::ENOENT),
+                }
+            }
+            FUSE_GETATTR => match fs.attributes(request.node_id) {
+                Some(attributes) => {
+                    push_u64(&mut out, TIMEOUT);
+                    push_u32(&mut out, 0);
+                    push_u32(&mut out, 0);
+                    self.push_attributes(&mut out, &attributes);
+                    Ok(out)
+                }
+                None => Err(libc::ENOENT),
+            },
+            FUSE_OPEN | FUSE_OPENDIR => {
+                let flags = request.u32_at(0).unwrap_or(0) as i32;
+                match fs.attributes(request.node_id) {
+                    None => Err(libc::ENOENT),
+                    Some(_) if flags & libc::O_ACCMODE != libc::O_RDONLY => Err(libc::EROFS),
+                    Some(attributes) if attributes.is_dir != (request.opcode == FUSE_OPENDIR) => {
+                        Err(if attributes.is_dir {
+                            libc::EISDIR
+                        } else {
+                            libc::ENOTDIR
+                        })
+                    }
+                    Some(_) => {
+                        push_u64(&mut out, 0); // file handle
+                        push_u32(&mut out, FOPEN_KEEP_CACHE);
+                        push_u32(&mut out, 0);
+                        Ok(out)
+                    }
+                }
+            }
+            FUSE_READ => {
+                let offset = request.u64_at(8).unwrap_or(0);
+                let size = request.u32_at(16).unwrap_or(0).min(MAX_WRITE);
+                match fs.read(request.node_id, offset, size) {
+                    Ok(data) => Ok(data),
+                    Err(err) => Err(err.raw_os_error().unwrap_or(libc::EIO)),
+                }
+            }
+            FUSE_READDIR => {
+                let offset = request.u64_at(8).unwrap_or(0);
+                let size = request.u32_at(16).unwrap_or(0) as usize;
+                match fs.read_dir(request.node_id) {
+                    Some(childs) => {
+                        let mut entries = vec![
+                            (request.node_id, ".".to_string(), true),
+                            (fs.parent(request.node_id), "..".to_string(), true),
+                        ];
+                        entries.extend(childs);
+                        for (index, (inode, name, is_dir)) in
+                            entries.iter().enumerate().skip(offset as usize)
+                        {
+                            let entry_size = (24 + name.len() + 7) & !7;
+                            if out.len() + entry_size > size {
+                                break;
+                            };
+                            push_u64(&mut out, *inode);
+                            push_u64(&mut out, index as u64 + 1);
+                            push_u32(&mut out, name.len() as u32);
+                            push_u32(&mut out, if *is_dir { 4 } else { 8 }); // DT_DIR, DT_REG
+                            out.extend_from_slice(name.as_bytes());
+                            out.resize(out.len() + entry_size - 24 - name.len(), 0);
+                        }
+                        Ok(out)
+                    }
+                    None => Err(libc::ENOTDIR),
+                }
+            }
+            FUSE_STATFS => {
+                out.resize(40, 0); // blocks, free blocks, available blocks, files, free files
+                push_u32(&mut out, 512); // block size
+                push_u32(&mut out, 255); // max name lenght
+                push_u32(&mut out, 512); // fragment size
+                out.resize(80, 0);
+                Ok(out)
+            }
+            FUSE_RELEASE | FUSE_RELEASEDIR | FUSE_FLUSH | FUSE_ACCESS => Ok(out),
+            _ => Err(libc::ENOSYS),
+        };
+        Some(result)
+    }
+
+    fn push_attributes(&self, out: &mut Vec<u8>, attributes: &Attributes) {
+        push_u64(out, attributes.inode);
+        push_u64(out, attributes.size);
+        push_u64(out, attributes.size.div_ceil(512)); // blocks
+        out.resize(out.len() + 36, 0); // access, modification and change time
+        push_u32(
+            out,
+            if attributes.is_dir {
+                libc::S_IFDIR | 0o555
+            } else {
+                libc::S_IFREG | 0o444
+            },
+        );
+        push_u32(out, if attributes.is_dir { 2 } else { 1 }); // links
+        push_u32(out, self.uid);
+        push_u32(out, self.gid);
+        push_u32(out, 0); // rdev
+        push_u32(out, 512); // block size
+        push_u32(out, 0); // flags
+    }
+
+    fn reply(&self, request: &Request, result: Result<Vec<u8>, i32>) -> io::Result<()> {
+        let (error, data) = match result {
+            Ok(data) => (0, data),
+            Err(errno) => (-errno, Vec::new()),
+        };
+        let mut message = Vec::with_capacity(16 + data.len());
+        push_u32(&mut message, 16 + data.len() as u32);
+        message.extend_from_slice(&error.to_le_bytes());
+        push_u64(&mut message, request.unique);
+        message.extend_from_slice(&data);
+        match (&self.device).write(&message) {
+            Ok(_) => Ok(()),
+            // the request was interrupted
+            Err(err) if err.raw_os_error() == Some(libc::ENOENT) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+struct Request<'a> {
+    opcode: u32,
+    unique: u64,
+    node_id: u64,
+    data: &'a [u8],
+}
+
+impl<'a> Request<'a> {
+    fn parse(buffer: &'a [u8]) -> Option<Request<'a>> {
+        let header = buffer.get(..40)?;
+        let lenght = u32::from_le_bytes(header[0..4].try_into().ok()?) as usize;
+        Some(Request {
+            opcode: u32::from_le_bytes(header[4..8].try_into().ok()?),
+            unique: u64::from_le_bytes(header[8..16].try_into().ok()?),
+            node_id: u64::from_le_bytes(header[16..24].try_into().ok()?),
+            data: buffer.get(40..lenght)?,
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(
+            self.data.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    }
+
+    fn u64_at(&self, offset: usize) -> Option<u64> {
+        Some(u64::from_le_bytes(
+            self.data.get(offset..offset + 8)?.try_into().ok()?,
+        ))
+    }
+}
+
+fn push_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn path_to_cstring(path: &Path) -> io::Result<CString> {
+    match CString::new(path.as_os_str().as_bytes()) {
+        Ok(value) => Ok(value),
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the mount point contain a nul byte",
+        )),
+    }
+}
+
+fn mount_privileged(mountpoint: &Path, fs_name: &str, uid: u32, gid: u32) -> io::Result<File> {
+    let device = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/fuse")?;
+    let options = format!(
+        "fd={},rootmode=40000,user_id={},group_id={}",
+        device.as_raw_fd(),
+        uid,
+        gid
+    );
+    let source = CString::new(fs_name).unwrap_or_default();
+    let fs_type = CString::new("fuse.fs3ds").unwrap_or_default();
+    let target = path_to_cstring(mountpoint)?;
+    let options = CString::new(options).unwrap_or_default();
+    // safety: every pointer is a valid nul terminated string, that live until the end of the call
+    let result = unsafe {
+        libc::mount(
+            source.as_ptr(),
+            target.as_ptr(),
+            fs_type.as_ptr(),
+            libc::MS_NOSUID | libc::MS_NODEV | libc::MS_RDONLY,
+            options.as_ptr() as *const libc::c_void,
+        )
+    };
+    if result != 0 {
+        return last_os_error();
+    };
+    Ok(device)
+}
+
+/// Run the fusermount helper, that mount the file system and send back the opened `/dev/fuse` over a unix socket
+fn mount_with_fusermount(mountpoint: &Path, fs_name: &str) -> io::Result<File> {
+    let (ours, theirs) = UnixStream::pair()?;
+    // safety: the fd is valid. The helper need to inherit it.
+    if unsafe { libc::fcntl(theirs.as_raw_fd(), libc::F_SETFD, 0) } != 0 {
+        return last_os_error();
+    };
+    let options = format!("ro,nosuid,nodev,fsname={},subtype=fs3ds", fs_name);
+    let mut status = None;
+    for helper in ["fusermount3", "fusermount"] {
+        match Command::new(helper)
+            .arg("-o")
+            .arg(&options)
+            .arg("--")
+            .arg(mountpoint)
+            .env("_FUSE_COMMFD", theirs.as_raw_fd().to_string())
+            .status()
+        {
+            Ok(value) => {
+                status = Some(value);
+                break;
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+    }
+    match status {
+        Some(status) if status.success() => (),
+        Some(status) => return Err(io::Error::other(format!("fusermount failed ({})", status))),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "fusermount3 isn't installed, and mounting without it need to be root",
+            ))
+        }
+    };
+    drop(theirs);
+    receive_fd(&ours)
+}
+
+fn receive_fd(socket: &UnixStream) -> io::Result<File> {
+    let mut data = [0u8; 1];
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+    let mut control = [0u64; 8];
+    // safety: msghdr is a plain C struct, that is valid when zeroed
+    let mut message: libc::msghdr = unsafe { std::mem::zeroed() };
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    message.msg_controllen = std::mem::size_of_val(&control) as _;
+    // safety: every buffer pointed by message live until the end of the function
+    unsafe {
+        if libc::recvmsg(socket.as_raw_fd(), &mut message, 0) < 0 {
+            return last_os_error();
+        };
+        let header = libc::CMSG_FIRSTHDR(&message);
+        if header.is_null()
+            || (*header).cmsg_level != libc::SOL_SOCKET
+            || (*header).cmsg_type != libc::SCM_RIGHTS
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "fusermount didn't send the fuse device",
+            ));
+        };
+        let fd = std::ptr::read_unaligned(libc::CMSG_DATA(header) as *const libc::c_int);
+        Ok(File::from_raw_fd(fd))
+    }
+}
+
+fn unmount(mountpoint: &Path, privileged: bool) -> io::Result<()> {
+    if privileged {
+        let target = path_to_cstring(mountpoint)?;
+        // safety: target is a valid nul terminated string
+        if unsafe { libc::umount2(target.as_ptr(), libc::MNT_DETACH) } != 0 {
+            return last_os_error();
+        };
+        return Ok(());
+    };
+    for helper in ["fusermount3", "fusermount"] {
+        match Command::new(helper)
+            .arg("-u")
+            .arg("-z")
+            .arg("--")
+            .arg(mountpoint)
+            .status()
+        {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => return Err(io::Error::other(format!("fusermount failed ({})", status))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "fusermount3 isn't installed",
+    ))
+}
+
+/// Make SIGINT and SIGTERM interrupt the read of the next request, so the file system can be unmounted
+fn install_stop_handler() -> io::Result<()> {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // safety: the handler only store an atomic. sa_flags doesn't contain SA_RESTART, so a blocking read return EINTR.
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = request_stop as extern "C" fn(libc::c_int) as usize;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                return last_os_error();
+            };
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,140 @@
+//! Mount a .3ds, .cxi, .cfa or decrypted .cia read only with FUSE.
+//!
+//! The first partition is shown at the root (`romfs/`, `exefs/`, `exheader.bin`, `logo.bin`, `plain.bin`), and the other partitions in `p1/`, `p2/`...
+//!
+//! Usage: `fs3ds-mount <rom> <mountpoint>`. It run in the foreground until the file system is unmounted (with `fusermount3 -u <mountpoint>`) or the process receive SIGINT or SIGTERM.
+
+mod fuse;
+
+use fs3ds::{ReadAt, TitleLayout, TitleNodeKind, TitleTree};
+use fuse::{Attributes, FuseSession, ReadOnlyFileSystem, ROOT_INODE};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::process::exit;
+
+/// Serve a `TitleTree`. The inode of a node is its index plus one.
+///
+/// The tree is built once from the `NCCHReader`, `ExeFSReader` and `IVFCReader` of each partition, and keep the offset of every file in the rom. Reads are then plain `read_at` on the rom, so concurrent FUSE requests don't share a seek position (and a lock) like with a `PartitionMutex`, and don't walk the romfs metadata again.
+struct TitleFileSystem {
+    file: File,
+    tree: TitleTree,
+}
+
+impl TitleFileSystem {
+    fn node_id(inode: u64) -> usize {
+        inode.wrapping_sub(ROOT_INODE) as usize
+    }
+
+    fn inode(node_id: usize) -> u64 {
+        node_id as u64 + ROOT_INODE
+    }
+}
+
+impl ReadOnlyFileSystem for TitleFileSystem {
+    fn lookup(&self, parent: u64, name: &[u8]) -> Option<u64> {
+        let name = std::str::from_utf8(name).ok()?;
+        self.tree
+            .get_child(Self::node_id(parent), name)
+            .map(Self::inode)
+    }
+
+    fn parent(&self, inode: u64) -> u64 {
+        match self.tree.get(Self::node_id(inode)) {
+            Some(node) => Self::inode(node.parent),
+            None => ROOT_INODE,
+        }
+    }
+
+    fn attributes(&self, inode: u64) -> Option<Attributes> {
+        let node = self.tree.get(Self::node_id(inode))?;
+        Some(match node.kind {
+            TitleNodeKind::Directory(_) => Attributes {
+                inode,
+                size: 0,
+                is_dir: true,
+            },
+            TitleNodeKind::File { lenght, .. } => Attributes {
+                inode,
+                size: lenght,
+                is_dir: false,
+            },
+        })
+    }
+
+    fn read_dir(&self, inode: u64) -> Option<Vec<(u64, String, bool)>> {
+        match &self.tree.get(Self::node_id(inode))?.kind {
+            TitleNodeKind::Directory(childs) => Some(
+                childs
+                    .iter()
+                    .filter_map(|child| {
+                        let node = self.tree.get(*child)?;
+                        let is_dir = matches!(node.kind, TitleNodeKind::Directory(_));
+                        Some((Self::inode(*child), node.name.clone(), is_dir))
+                    })
+                    .collect(),
+            ),
+            TitleNodeKind::File { .. } => None,
+        }
+    }
+
+    fn read(&self, inode: u64, offset: u64, size: u32) -> io::Result<Vec<u8>> {
+        let (file_offset, lenght) = match self.tree.get(Self::node_id(inode)).map(|node| &node.kind)
+        {
+            Some(TitleNodeKind::File { offset, lenght }) => (*offset, *lenght),
+            _ => return Err(io::Error::from_raw_os_error(libc::EISDIR)),
+        };
+        if offset >= lenght {
+            return Ok(Vec::new());
+        };
+        let mut data = vec![0; (size as u64).min(lenght - offset) as usize];
+        let mut read = 0;
+        while read < data.len() {
+            match self
+                .file
+                .read_at(file_offset + offset + read as u64, &mut data[read..])?
+            {
+                0 => break,
+                value => read += value,
+            };
+        }
+        data.truncate(read);
+        Ok(data)
+    }
+}
+
+fn run(rom_path: &str, mountpoint: &str) -> Result<(), String> {
+    let file = File::open(rom_path).map_err(|err| format!("can't open {}: {}", rom_path, err))?;
+    let layout =
+        TitleLayout::new(&file).map_err(|err| format!("can't read {}: {}", rom_path, err))?;
+    let tree = TitleTree::new(&file, &layout)
+        .map_err(|err| format!("can't read {}: {}", rom_path, err))?;
+    let fs = TitleFileSystem { file, tree };
+    let fs_name = Path::new(rom_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().replace(',', "_"))
+        .unwrap_or_else(|| "fs3ds".to_string());
+    let mut session = FuseSession::mount(Path::new(mountpoint), &fs_name)
+        .map_err(|err| format!("can't mount on {}: {}", mountpoint, err))?;
+    session
+        .run(&fs)
+        .map_err(|err| format!("error while serving the file system: {}", err))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 3 || args[1] == "--help" || args[1] == "-h" {
+        eprintln!(
+            "usage: {} <rom> <mountpoint>",
+            args.first().map(String::as_str).unwrap_or("fs3ds-mount")
+        );
+        eprintln!(
+            "mount a .3ds, .cxi, .cfa or decrypted .cia read only, until unmounted or interrupted"
+        );
+        exit(2);
+    };
+    if let Err(err) = run(&args[1], &args[2]) {
+        eprintln!("{}", err);
+        exit(1);
+    };
+}
@@ -0,0 +1,155 @@
+//! `fs3ds build`: rebuild a NCCH or a NCSD from a directory written by `fs3ds extract`
+
+use crate::extract::{NCCH_HEADER_FILE, NCSD_HEADER_FILE};
+use crate::Args;
+use fs3ds::{ExeFSWriter, IVFCWriter, NCCHWriter, NCSDWriter};
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// The order of the well known files in an exefs. The others are stored after them, sorted by name.
+const EXEFS_ORDER: [&str; 4] = [".code", "banner", "icon", "logo"];
+
+fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|err| format!("can't read {}: {}", path.display(), err))
+}
+
+/// Read the file at `path`, or return `None` if it doesn't exist
+fn read_optional_file(path: &Path) -> Result<Option<Vec<u8>>, String> {
+    if path.exists() {
+        Ok(Some(read_file(path)?))
+    } else {
+        Ok(None)
+    }
+}
+
+fn read_exefs(dir: &Path) -> Result<ExeFSWriter, String> {
+    let mut names = Vec::new();
+    for entry in
+        fs::read_dir(dir).map_err(|err| format!("can't list {}: {}", dir.display(), err))?
+    {
+        let entry = entry.map_err(|err| format!("can't list {}: {}", dir.display(), err))?;
+        match entry.file_name().into_string() {
+            Ok(name) => names.push(name),
+            Err(name) => return Err(format!("invalid exefs file name {:?}", name)),
+        };
+    }
+    names.sort_by_key(|name| {
+        let position = EXEFS_ORDER.iter().position(|known| known == name);
+        (position.unwrap_or(EXEFS_ORDER.len()), name.clone())
+    });
+    let mut exefs = ExeFSWriter::new();
+    for name in names {
+        exefs.add_file(&name, read_file(&dir.join(&name))?);
+    }
+    Ok(exefs)
+}
+
+/// Prepare the NCCH stored in `dir`. Its files are only read from the disk while it is written.
+fn read_ncch(dir: &Path) -> Result<NCCHWriter, String> {
+    let header_path = dir.join(NCCH_HEADER_FILE);
+    let header = read_file(&header_path)?;
+    let header: [u8; 0x200] = match header.as_slice().try_into() {
+        Ok(value) => value,
+        Err(_) => {
+            return Err(format!(
+                "{} should be 0x200 bytes long, but it is {:#x} bytes long",
+                header_path.display(),
+                header.len()
+            ))
+        }
+    };
+    let mut ncch = NCCHWriter::new(header);
+    ncch.exheader = read_optional_file(&dir.join("exheader.bin"))?;
+    ncch.plain_region = read_optional_file(&dir.join("plain.bin"))?.unwrap_or_default();
+    ncch.logo_region = read_optional_file(&dir.join("logo.bin"))?.unwrap_or_default();
+    let exefs_dir = dir.join("exefs");
+    if exefs_dir.is_dir() {
+        ncch.exefs = Some(read_exefs(&exefs_dir)?);
+    };
+    let romfs_dir = dir.join("romfs");
+    if romfs_dir.is_dir() {
+        ncch.romfs = Some(
+            IVFCWriter::from_dir(&romfs_dir)
+                .map_err(|err| format!("can't list {}: {}", romfs_dir.display(), err))?,
+        );
+    };
+    Ok(ncch)
+}
+
+/// Find the directories of the other partitions, the `pN` directories with a NCCH header
+fn find_other_partitions(dir: &Path) -> Result<Vec<(usize, PathBuf)>, String> {
+    let mut partitions = Vec::new();
+    for entry in
+        fs::read_dir(dir).map_err(|err| format!("can't list {}: {}", dir.display(), err))?
+    {
+        let entry = entry.map_err(|err| format!("can't list {}: {}", dir.display(), err))?;
+        let partition_nb = match entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_prefix('p'))
+            .and_then(|number| number.parse::<usize>().ok())
+        {
+            Some(value) if value >= 1 => value,
+            _ => continue,
+        };
+        if entry.path().join(NCCH_HEADER_FILE).is_file() {
+            partitions.push((partition_nb, entry.path()));
+        };
+    }
+    partitions.sort();
+    Ok(partitions)
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &[])?;
+    let (input_dir, output_path) = match args.positionals.as_slice() {
+        [input_dir, output_path] => (Path::new(input_dir), output_path),
+        _ => return Err("usage: fs3ds build <dir> <output>".to_string()),
+    };
+    let ncsd_header = read_optional_file(&input_dir.join(NCSD_HEADER_FILE))?;
+    let first_partition = read_ncch(input_dir)?;
+    let other_partitions = find_other_partitions(input_dir)?;
+    // without the NCSD header, the other partitions are the contents of a cia, that can't be rebuilt
+    if ncsd_header.is_none() {
+        if let Some((_, partition_dir)) = other_partitions.first() {
+            return Err(format!(
+                "{} contain {} but no {}: it was extracted from a cia, and only a ncch or a ncsd can be built",
+                input_dir.display(),
+                partition_dir.display(),
+                NCSD_HEADER_FILE
+            ));
+        };
+    };
+
+    let output = File::create(output_path)
+        .map_err(|err| format!("can't create {}: {}", output_path, err))?;
+    let mut output = BufWriter::new(output);
+    match ncsd_header {
+        Some(header) => {
+            let mut ncsd = NCSDWriter::new(header);
+            ncsd.partitions.push((0, first_partition));
+            for (partition_nb, partition_dir) in other_partitions {
+                if partition_nb >= 8 {
+                    return Err(format!(
+                        "{} can't be stored in a ncsd, that have at most 8 partitions",
+                        partition_dir.display()
+                    ));
+                };
+                ncsd.partitions
+                    .push((partition_nb, read_ncch(&partition_dir)?));
+            }
+            ncsd.write(&mut output)
+                .map_err(|err| format!("can't build {}: {}", output_path, err))?;
+        }
+        None => {
+            first_partition
+                .write(&mut output)
+                .map_err(|err| format!("can't build {}: {}", output_path, err))?;
+        }
+    };
+    output
+        .flush()
+        .map_err(|err| format!("can't write {}: {}", output_path, err))
+}
@@ -0,0 +1,21 @@
+//! `fs3ds cat`: write a file of a romfs or of an exefs to the standard output
+
+use crate::title_path::{OpenedSection, TitlePath};
+use crate::{copy_to_stdout, open_title, Args};
+use std::sync::Arc;
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &[])?;
+    let (rom, path) = match args.positionals.as_slice() {
+        [rom, path] => (rom, path),
+        _ => return Err("usage: fs3ds cat <rom> <path>".to_string()),
+    };
+    let path = TitlePath::parse(path)?;
+    let (file, layout) = open_title(rom)?;
+    let section = OpenedSection::open(Arc::new(file), &layout, path.partition, path.section)?;
+    if section.stat(&path.path)?.is_dir {
+        return Err(format!("{}: is a directory", path.path));
+    };
+    let mut file = section.open_file(&path.path)?;
+    copy_to_stdout(&mut file)
+}
@@ -0,0 +1,111 @@
+//! `fs3ds extract`: write every section of a title to a directory, in the layout read by `fs3ds build`
+
+use crate::{open_title, Args};
+use fs3ds::{ReadAt, TitleContainer, TitleNodeKind, TitleTree};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+/// The header of a NCCH, stored in the directory of its partition
+pub const NCCH_HEADER_FILE: &str = "ncch_header.bin";
+/// Everything before the first partition of a NCSD, stored at the root
+pub const NCSD_HEADER_FILE: &str = "ncsd_header.bin";
+
+/// Copy `lenght` bytes at `offset` in `source` to the file at `path`
+fn extract_range(source: &File, offset: u64, lenght: u64, path: &Path) -> Result<(), String> {
+    let mut output =
+        File::create(path).map_err(|err| format!("can't create {}: {}", path.display(), err))?;
+    let mut buffer = vec![0; 0x10000];
+    let mut copied = 0;
+    while copied < lenght {
+        let to_copy = std::cmp::min(lenght - copied, buffer.len() as u64) as usize;
+        source
+            .read_exact_at(offset + copied, &mut buffer[..to_copy])
+            .map_err(|err| format!("can't read the data of {}: {}", path.display(), err))?;
+        output
+            .write_all(&buffer[..to_copy])
+            .map_err(|err| format!("can't write {}: {}", path.display(), err))?;
+        copied += to_copy as u64;
+    }
+    Ok(())
+}
+
+/// Refuse the names that would write outside of the output directory
+fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        Err(format!(
+            "the title contain a file with the invalid name {:?}",
+            name
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &[])?;
+    let (rom, output_dir) = match args.positionals.as_slice() {
+        [rom, output_dir] => (rom, Path::new(output_dir)),
+        _ => return Err("usage: fs3ds extract <rom> <dir>".to_string()),
+    };
+    let (file, layout) = open_title(rom)?;
+    let tree =
+        TitleTree::new(&file, &layout).map_err(|err| format!("can't read {}: {}", rom, err))?;
+    fs::create_dir_all(output_dir)
+        .map_err(|err| format!("can't create {}: {}", output_dir.display(), err))?;
+
+    let mut to_extract = vec![(TitleTree::ROOT, output_dir.to_path_buf())];
+    while let Some((node_id, path)) = to_extract.pop() {
+        let node = match tree.get(node_id) {
+            Some(value) => value,
+            None => continue,
+        };
+        match &node.kind {
+            TitleNodeKind::Directory(childs) => {
+                fs::create_dir_all(&path)
+                    .map_err(|err| format!("can't create {}: {}", path.display(), err))?;
+                for child in childs {
+                    let child_name = match tree.get(*child) {
+                        Some(child_node) => &child_node.name,
+                        None => continue,
+                    };
+                    check_name(child_name)?;
+                    to_extract.push((*child, path.join(child_name)));
+                }
+            }
+            TitleNodeKind::File { offset, lenght } => {
+                extract_range(&file, *offset, *lenght, &path)?
+            }
+        };
+    }
+
+    // the headers, so the title can be rebuilt
+    for (position, (partition_nb, sections)) in layout.partitions.iter().enumerate() {
+        let partition_dir = if position == 0 {
+            output_dir.to_path_buf()
+        } else {
+            output_dir.join(format!("p{}", partition_nb))
+        };
+        extract_range(
+            &file,
+            sections.ncch.0,
+            0x200,
+            &partition_dir.join(NCCH_HEADER_FILE),
+        )?;
+    }
+    if layout.container == TitleContainer::NCSD {
+        let first_partition = layout
+            .partitions
+            .iter()
+            .map(|(_, sections)| sections.ncch.0)
+            .min()
+            .unwrap_or(0x200);
+        extract_range(
+            &file,
+            0,
+            first_partition,
+            &output_dir.join(NCSD_HEADER_FILE),
+        )?;
+    };
+    Ok(())
+}
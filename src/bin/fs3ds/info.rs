@@ -0,0 +1,426 @@
+//! `fs3ds info`: print the headers and the layout of a title
+
+use crate::json::Json;
+use crate::{open_title, write_stdout, Args};
+use fs3ds::{
+    IVFCReader, NCCHReader, NCCHSections, NCSDError, NCSDReader, ReadAtCursor, ReadAtPartition,
+    TitleContainer,
+};
+use std::fmt;
+use std::fs::File;
+use std::sync::Arc;
+
+struct NCSDPartitionInfo {
+    index: usize,
+    offset: u64,
+    lenght: u64,
+    id: u64,
+}
+
+struct NCSDInfo {
+    size: u64,
+    media_id: u64,
+    partition_type: u64,
+    partitions: Vec<NCSDPartitionInfo>,
+}
+
+struct RomFSCount {
+    files: usize,
+    directories: usize,
+}
+
+struct NCCHInfo {
+    index: usize,
+    offset: u64,
+    lenght: u64,
+    partition_id: u64,
+    program_id: u64,
+    maker_code: String,
+    product_code: String,
+    version: u16,
+    content_size: u64,
+    flags: [u8; 8],
+    is_cxi: bool,
+    no_crypto: bool,
+    /// name, absolute offset and lenght
+    sections: Vec<(&'static str, u64, u64)>,
+    romfs: Option<RomFSCount>,
+}
+
+struct TitleInfo {
+    path: String,
+    file_size: u64,
+    container: TitleContainer,
+    ncsd: Option<NCSDInfo>,
+    partitions: Vec<NCCHInfo>,
+}
+
+fn container_name(container: TitleContainer) -> &'static str {
+    match container {
+        TitleContainer::NCSD => "NCSD",
+        TitleContainer::NCCH => "NCCH",
+        TitleContainer::CIA => "CIA",
+    }
+}
+
+/// The usual content of a NCSD partition or a CIA content, from its index
+fn partition_role(index: usize) -> &'static str {
+    match index {
+        0 => "main",
+        1 => "manual",
+        2 => "download play child",
+        6 => "new 3ds update",
+        7 => "update",
+        _ => "unknown",
+    }
+}
+
+/// The content type, stored in the upper 6 bits of the 6th byte of the NCCH flags. The 2 lower bits tell if it contain data and an executable.
+fn content_type(flags: &[u8; 8]) -> &'static str {
+    match flags[5] >> 2 {
+        0 => "application",
+        1 => "system update",
+        2 => "manual",
+        3 => "child",
+        4 => "trial",
+        5 => "extended system update",
+        _ => "unknown",
+    }
+}
+
+fn read_ncsd(file: &File) -> Result<NCSDInfo, NCSDError> {
+    let ncsd = NCSDReader::new(ReadAtCursor::new(file))?;
+    let mut partitions = Vec::new();
+    for index in 0..8 {
+        let (offset, lenght) = match ncsd.get_partition_bounds(index) {
+            Ok(value) => value,
+            Err(NCSDError::InexistingPartition(_)) => continue,
+            Err(err) => return Err(err),
+        };
+        partitions.push(NCSDPartitionInfo {
+            index,
+            offset,
+            lenght,
+            id: u64::from_le_bytes(ncsd.partitions_id[index]),
+        });
+    }
+    Ok(NCSDInfo {
+        size: ncsd.size,
+        media_id: ncsd.media_id,
+        partition_type: ncsd.partition_type,
+        partitions,
+    })
+}
+
+fn count_romfs(file: &File, (offset, lenght): (u64, u64)) -> Result<RomFSCount, String> {
+    let partition = ReadAtPartition::new(Arc::new(file), offset, lenght)
+        .map_err(|err| format!("can't open the romfs: {}", err))?;
+    let reader = IVFCReader::from_read_at(partition)
+        .map_err(|err| format!("can't read the romfs: {}", err))?;
+    let mut count = RomFSCount {
+        files: 0,
+        directories: 0,
+    };
+    reader
+        .walk_dir(&reader.first_dir_metadata, |_, subdirs, files| {
+            count.files += files.len();
+            count.directories += subdirs.len();
+        })
+        .map_err(|err| format!("can't read the romfs: {}", err))?;
+    Ok(count)
+}
+
+fn read_ncch(file: &File, index: usize, sections: &NCCHSections) -> Result<NCCHInfo, String> {
+    let (offset, lenght) = sections.ncch;
+    let partition = ReadAtPartition::new(Arc::new(file), offset, lenght)
+        .map_err(|err| format!("can't open the partition {}: {}", index, err))?;
+    let ncch = NCCHReader::new(ReadAtCursor::new(&partition))
+        .map_err(|err| format!("can't read the ncch of the partition {}: {}", index, err))?;
+    let romfs = if sections.romfs.1 != 0 {
+        Some(
+            count_romfs(file, sections.romfs)
+                .map_err(|err| format!("partition {}: {}", index, err))?,
+        )
+    } else {
+        None
+    };
+    Ok(NCCHInfo {
+        index,
+        offset,
+        lenght,
+        partition_id: ncch.partition_id,
+        program_id: ncch.program_id,
+        maker_code: ncch.maker_code.clone(),
+        product_code: ncch.product_code.clone(),
+        version: ncch.version,
+        content_size: ncch.content_size,
+        flags: ncch.flags,
+        is_cxi: ncch.exheader_size != 0,
+        no_crypto: ncch.is_no_crypto(),
+        sections: vec![
+            ("exheader", sections.exheader.0, sections.exheader.1),
+            (
+                "plain region",
+                sections.plain_region.0,
+                sections.plain_region.1,
+            ),
+            (
+                "logo region",
+                sections.logo_region.0,
+                sections.logo_region.1,
+            ),
+            ("exefs", sections.exefs.0, sections.exefs.1),
+            ("romfs", sections.romfs.0, sections.romfs.1),
+        ],
+        romfs,
+    })
+}
+
+fn read_info(path: &str) -> Result<TitleInfo, String> {
+    let (file, layout) = open_title(path)?;
+    let file_size = file
+        .metadata()
+        .map_err(|err| format!("can't read the size of {}: {}", path, err))?
+        .len();
+    let ncsd = match layout.container {
+        TitleContainer::NCSD => {
+            Some(read_ncsd(&file).map_err(|err| format!("can't read the ncsd header: {}", err))?)
+        }
+        _ => None,
+    };
+    let mut partitions = Vec::new();
+    for (index, sections) in &layout.partitions {
+        partitions.push(read_ncch(&file, *index, sections)?);
+    }
+    Ok(TitleInfo {
+        path: path.to_string(),
+        file_size,
+        container: layout.container,
+        ncsd,
+        partitions,
+    })
+}
+
+impl fmt::Display for TitleInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "file:           {}", self.path)?;
+        writeln!(f, "format:         {}", container_name(self.container))?;
+        writeln!(f, "file size:      {:#x}", self.file_size)?;
+        if let Some(ncsd) = &self.ncsd {
+            writeln!(f)?;
+            writeln!(f, "NCSD header")?;
+            writeln!(f, "  image size:     {:#x}", ncsd.size)?;
+            writeln!(f, "  media id:       {:016x}", ncsd.media_id)?;
+            writeln!(f, "  partition type: {:016x}", ncsd.partition_type)?;
+            writeln!(f, "  partitions:")?;
+            writeln!(
+                f,
+                "    {:<3} {:<12} {:<12} {:<16} role",
+                "nb", "offset", "size", "id"
+            )?;
+            for partition in &ncsd.partitions {
+                writeln!(
+                    f,
+                    "    {:<3} {:<12} {:<12} {:016x} {}",
+                    partition.index,
+                    format!("{:#x}", partition.offset),
+                    format!("{:#x}", partition.lenght),
+                    partition.id,
+                    partition_role(partition.index)
+                )?;
+            }
+        };
+        for ncch in &self.partitions {
+            writeln!(f)?;
+            writeln!(
+                f,
+                "{} {} ({}, {})",
+                if self.container == TitleContainer::CIA {
+                    "content"
+                } else {
+                    "partition"
+                },
+                ncch.index,
+                if ncch.is_cxi { "CXI" } else { "CFA" },
+                partition_role(ncch.index)
+            )?;
+            writeln!(f, "  offset:         {:#x}", ncch.offset)?;
+            writeln!(f, "  size:           {:#x}", ncch.lenght)?;
+            writeln!(f, "  partition id:   {:016x}", ncch.partition_id)?;
+            writeln!(f, "  program id:     {:016x}", ncch.program_id)?;
+            writeln!(f, "  maker code:     {}", ncch.maker_code)?;
+            writeln!(f, "  product code:   {}", ncch.product_code)?;
+            writeln!(f, "  version:        {}", ncch.version)?;
+            writeln!(f, "  content size:   {:#x}", ncch.content_size)?;
+            writeln!(
+                f,
+                "  flags:          {}",
+                ncch.flags
+                    .iter()
+                    .map(|flag| format!("{:02x}", flag))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )?;
+            writeln!(f, "  content type:   {}", content_type(&ncch.flags))?;
+            writeln!(
+                f,
+                "  encrypted:      {}",
+                if ncch.no_crypto { "no" } else { "yes" }
+            )?;
+            writeln!(f, "  sections:")?;
+            writeln!(f, "    {:<13} {:<12} size", "name", "offset")?;
+            for (name, offset, lenght) in &ncch.sections {
+                if *lenght != 0 {
+                    writeln!(
+                        f,
+                        "    {:<13} {:<12} {:#x}",
+                        name,
+                        format!("{:#x}", offset),
+                        lenght
+                    )?;
+                };
+            }
+            if let Some(romfs) = &ncch.romfs {
+                writeln!(
+                    f,
+                    "  romfs:          {} files, {} directories",
+                    romfs.files, romfs.directories
+                )?;
+            };
+        }
+        Ok(())
+    }
+}
+
+fn to_json(info: &TitleInfo) -> Json {
+    let ncsd = match &info.ncsd {
+        Some(ncsd) => Json::Object(vec![
+            ("image_size", Json::Number(ncsd.size)),
+            ("media_id", Json::hex(ncsd.media_id)),
+            ("partition_type", Json::hex(ncsd.partition_type)),
+            (
+                "partitions",
+                Json::Array(
+                    ncsd.partitions
+                        .iter()
+                        .map(|partition| {
+                            Json::Object(vec![
+                                ("index", Json::Number(partition.index as u64)),
+                                ("offset", Json::Number(partition.offset)),
+                                ("size", Json::Number(partition.lenght)),
+                                ("id", Json::hex(partition.id)),
+                                ("role", Json::string(partition_role(partition.index))),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+        ]),
+        None => Json::Null,
+    };
+    let partitions = info
+        .partitions
+        .iter()
+        .map(|ncch| {
+            Json::Object(vec![
+                ("index", Json::Number(ncch.index as u64)),
+                (
+                    "kind",
+                    Json::string(if ncch.is_cxi { "CXI" } else { "CFA" }),
+                ),
+                ("role", Json::string(partition_role(ncch.index))),
+                ("offset", Json::Number(ncch.offset)),
+                ("size", Json::Number(ncch.lenght)),
+                ("partition_id", Json::hex(ncch.partition_id)),
+                ("program_id", Json::hex(ncch.program_id)),
+                ("maker_code", Json::string(ncch.maker_code.as_str())),
+                ("product_code", Json::string(ncch.product_code.as_str())),
+                ("version", Json::Number(ncch.version as u64)),
+                ("content_size", Json::Number(ncch.content_size)),
+                (
+                    "flags",
+                    Json::Array(
+                        ncch.flags
+                            .iter()
+                            .map(|flag| Json::Number(*flag as u64))
+                            .collect(),
+                    ),
+                ),
+                ("content_type", Json::string(content_type(&ncch.flags))),
+                ("encrypted", Json::Bool(!ncch.no_crypto)),
+                (
+                    "sections",
+                    Json::Array(
+                        ncch.sections
+                            .iter()
+                            .filter(|(_, _, lenght)| *lenght != 0)
+                            .map(|(name, offset, lenght)| {
+                                Json::Object(vec![
+                                    ("name", Json::string(*name)),
+                                    ("offset", Json::Number(*offset)),
+                                    ("size", Json::Number(*lenght)),
+                                ])
+                            })
+                            .collect(),
+                    ),
+                ),
+                (
+                    "romfs",
+                    match &ncch.romfs {
+                        Some(romfs) => Json::Object(vec![
+                            ("files", Json::Number(romfs.files as u64)),
+                            ("directories", Json::Number(romfs.directories as u64)),
+                        ]),
+                        None => Json::Null,
+                    },
+                ),
+            ])
+        })
+        .collect();
+    Json::Object(vec![
+        ("file", Json::string(info.path.as_str())),
+        ("format", Json::string(container_name(info.container))),
+        ("file_size", Json::Number(info.file_size)),
+        ("ncsd", ncsd),
+        ("partitions", Json::Array(partitions)),
+    ])
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["--json"])?;
+    let path = match args.positionals.as_slice() {
+        [path] => path,
+        _ => return Err("usage: fs3ds info [--json] <rom>".to_string()),
+    };
+    let info = read_info(path)?;
+    if args.has("--json") {
+        write_stdout(format!("{}\n", to_json(&info).to_pretty_string()).as_bytes())
+    } else {
+        write_stdout(info.to_string().as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_type() {
+        for (flag, expected) in [
+            (0x03, "application"),
+            (0x01, "application"),
+            (0x05, "system update"),
+            (0x09, "manual"),
+            (0x0D, "child"),
+            (0x12, "trial"),
+            (0x15, "extended system update"),
+            (0x18, "unknown"),
+        ]
+        .iter()
+        {
+            let mut flags = [0; 8];
+            flags[5] = *flag;
+            assert_eq!(content_type(&flags), *expected, "flag {:#x}", flag);
+        }
+    }
+}
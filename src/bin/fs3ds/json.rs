@@ -0,0 +1,141 @@
+//! A minimal json writer for the `--json` outputs, so the crate doesn't need a json dependency
+
+use std::fmt::Write;
+
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(u64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+    pub fn string<S: Into<String>>(value: S) -> Json {
+        Json::String(value.into())
+    }
+
+    /// A number written as a "0x..." string, for the identifiers that are usually read in hexadecimal
+    pub fn hex(value: u64) -> Json {
+        Json::String(format!("{:#018x}", value))
+    }
+
+    /// Write the value with an indentation of 2 spaces per level
+    pub fn to_pretty_string(&self) -> String {
+        let mut output = String::new();
+        self.write(&mut output, 0);
+        output
+    }
+
+    fn write(&self, output: &mut String, indent: usize) {
+        match self {
+            Json::Null => output.push_str("null"),
+            Json::Bool(value) => output.push_str(if *value { "true" } else { "false" }),
+            Json::Number(value) => {
+                let _ = write!(output, "{}", value);
+            }
+            Json::String(value) => write_string(output, value),
+            Json::Array(values) => {
+                if values.is_empty() {
+                    output.push_str("[]");
+                    return;
+                };
+                output.push('[');
+                for (position, value) in values.iter().enumerate() {
+                    if position != 0 {
+                        output.push(',');
+                    };
+                    new_line(output, indent + 1);
+                    value.write(output, indent + 1);
+                }
+                new_line(output, indent);
+                output.push(']');
+            }
+            Json::Object(fields) => {
+                if fields.is_empty() {
+                    output.push_str("{}");
+                    return;
+                };
+                output.push('{');
+                for (position, (key, value)) in fields.iter().enumerate() {
+                    if position != 0 {
+                        output.push(',');
+                    };
+                    new_line(output, indent + 1);
+                    write_string(output, key);
+                    output.push_str(": ");
+                    value.write(output, indent + 1);
+                }
+                new_line(output, indent);
+                output.push('}');
+            }
+        }
+    }
+}
+
+fn new_line(output: &mut String, indent: usize) {
+    output.push('\n');
+    for _ in 0..indent {
+        output.push_str("  ");
+    }
+}
+
+fn write_string(output: &mut String, value: &str) {
+    output.push('"');
+    for chara in value.chars() {
+        match chara {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            chara if (chara as u32) < 0x20 => {
+                let _ = write!(output, "\\u{:04x}", chara as u32);
+            }
+            chara => output.push(chara),
+        }
+    }
+    output.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        let mut output = String::new();
+        write_string(&mut output, "a \"b\" \\ c\nd\r\te\u{1}\u{1f} é");
+        assert_eq!(output, r#""a \"b\" \\ c\nd\r\te\u0001\u001f é""#);
+    }
+
+    #[test]
+    fn test_pretty_string() {
+        let value = Json::Object(vec![
+            ("null", Json::Null),
+            ("bool", Json::Bool(true)),
+            ("id", Json::hex(0x4_0000_0012_3400)),
+            ("empty", Json::Array(Vec::new())),
+            (
+                "array",
+                Json::Array(vec![Json::Number(1), Json::Object(Vec::new())]),
+            ),
+            ("name", Json::string("\"quoted\"")),
+        ]);
+        assert_eq!(
+            value.to_pretty_string(),
+            r#"{
+  "null": null,
+  "bool": true,
+  "id": "0x0004000000123400",
+  "empty": [],
+  "array": [
+    1,
+    {}
+  ],
+  "name": "\"quoted\""
+}"#
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! `fs3ds ls`: list the content of a directory of a romfs or of an exefs
+
+use crate::title_path::{Entry, OpenedSection, TitlePath};
+use crate::{open_title, write_stdout, Args};
+use std::fmt::Write;
+use std::sync::Arc;
+
+fn write_entry(output: &mut String, entry: &Entry, path: &str, long: bool) {
+    let suffix = if entry.is_dir { "/" } else { "" };
+    if long {
+        if entry.is_dir {
+            let _ = writeln!(output, "{:>10} {:>12} {}{}", "-", "-", path, suffix);
+        } else {
+            let _ = writeln!(
+                output,
+                "{:>10} {:>12} {}",
+                entry.lenght,
+                format!("{:#x}", entry.offset),
+                path
+            );
+        };
+    } else {
+        let _ = writeln!(output, "{}{}", path, suffix);
+    };
+}
+
+/// List the content of `dir`, with the path of the entries relative to the listed directory
+fn list_dir(
+    section: &OpenedSection,
+    dir: &str,
+    long: bool,
+    recursive: bool,
+    output: &mut String,
+) -> Result<(), String> {
+    if !recursive {
+        for entry in section.list(dir)? {
+            write_entry(output, &entry, &entry.name, long);
+        }
+        return Ok(());
+    };
+    // each directory is followed by its content, depth first
+    let contents = section.list_recursive(dir)?;
+    let mut to_write: Vec<(String, &Entry)> = match contents.get("") {
+        Some(entries) => entries
+            .iter()
+            .rev()
+            .map(|entry| (entry.name.clone(), entry))
+            .collect(),
+        None => Vec::new(),
+    };
+    while let Some((relative_path, entry)) = to_write.pop() {
+        write_entry(output, entry, &relative_path, long);
+        if let Some(childs) = contents.get(&relative_path).filter(|_| entry.is_dir) {
+            for child in childs.iter().rev() {
+                to_write.push((format!("{}/{}", relative_path, child.name), child));
+            }
+        };
+    }
+    Ok(())
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["-l", "-R"])?;
+    let (rom, path) = match args.positionals.as_slice() {
+        [rom] => (rom, "/"),
+        [rom, path] => (rom, path.as_str()),
+        _ => return Err("usage: fs3ds ls [-l] [-R] <rom> [path]".to_string()),
+    };
+    let path = TitlePath::parse(path)?;
+    let (file, layout) = open_title(rom)?;
+    let section = OpenedSection::open(Arc::new(file), &layout, path.partition, path.section)?;
+    let long = args.has("-l");
+    let mut output = String::new();
+    let entry = section.stat(&path.path)?;
+    if entry.is_dir {
+        list_dir(&section, &path.path, long, args.has("-R"), &mut output)?;
+    } else {
+        write_entry(&mut output, &entry, &entry.name, long);
+    };
+    write_stdout(output.as_bytes())
+}
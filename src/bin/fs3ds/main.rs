@@ -0,0 +1,191 @@
+//! The fs3ds command line tool, to inspect .3ds, .cxi, .cfa and decrypted .cia files.
+//!
+//! Run `fs3ds --help` for the list of commands.
+
+mod build;
+mod cat;
+mod extract;
+mod info;
+mod json;
+mod ls;
+mod title_path;
+mod verify;
+
+use fs3ds::TitleLayout;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process::exit;
+
+const USAGE: &str = "usage: fs3ds <command> [options] <arguments>
+
+commands:
+  info [--json] <rom>
+      print the ncsd header, the partition table, the ncch headers, the offset and
+      the size of every section and the number of files in the romfs
+  ls [-l] [-R] <rom> [path]
+      list a directory. -l show the size and the offset in the rom of the files,
+      and -R list the subdirectories too
+  cat <rom> <path>
+      write a file to the standard output
+  extract <rom> <dir>
+      write every section of the title in a directory: exheader.bin, plain.bin,
+      logo.bin, exefs/ and romfs/, with the other partitions in p1/, p2/...
+  build <dir> <output>
+      build a ncch from a directory written by extract, or a ncsd if it contain
+      ncsd_header.bin. A cia can't be rebuilt
+  verify [--certs <file>]... <rom>
+      check the magics, the partition table, the hashes of the ncch headers, of
+      the exefs files and of the ivfc levels of the romfs. With certificates,
+      check the signatures too. Exit with 1 if a check fail
+
+paths:
+  /a/b.bin           a file in the romfs of the first partition
+  romfs:/a/b.bin     the same
+  exefs:/icon        a file in the exefs of the first partition
+  p1:romfs:/a.bcma   a file in the romfs of the partition 1 (or of the content 1 of a cia)
+";
+
+/// The options and the positional arguments of a command
+pub struct Args {
+    pub options: Vec<String>,
+    /// The options followed by a value, like `--certs <file>`
+    pub values: Vec<(String, String)>,
+    pub positionals: Vec<String>,
+}
+
+impl Args {
+    /// Split `args` in options and positional arguments. Short options can be grouped, like `-lR`, and `--` end the options.
+    pub fn parse(args: &[String], known_options: &[&str]) -> Result<Args, String> {
+        Args::parse_with_values(args, known_options, &[])
+    }
+
+    /// Like `parse`, but the long options in `value_options` take a value, given as `--option value` or `--option=value`
+    pub fn parse_with_values(
+        args: &[String],
+        known_options: &[&str],
+        value_options: &[&str],
+    ) -> Result<Args, String> {
+        let mut options = Vec::new();
+        let mut values = Vec::new();
+        let mut positionals = Vec::new();
+        let mut only_positionals = false;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if only_positionals || arg == "-" || !arg.starts_with('-') {
+                positionals.push(arg.clone());
+            } else if arg == "--" {
+                only_positionals = true;
+            } else if arg.starts_with("--") {
+                let (option, value) = match arg.split_once('=') {
+                    Some((option, value)) => (option, Some(value.to_string())),
+                    None => (arg.as_str(), None),
+                };
+                if value_options.contains(&option) {
+                    let value = match value.or_else(|| args.next().cloned()) {
+                        Some(value) => value,
+                        None => return Err(format!("the option {} need a value", option)),
+                    };
+                    values.push((option.to_string(), value));
+                } else {
+                    options.push(arg.clone());
+                };
+            } else {
+                options.extend(arg.chars().skip(1).map(|chara| format!("-{}", chara)));
+            }
+        }
+        if let Some(unknown) = options
+            .iter()
+            .find(|option| !known_options.contains(&option.as_str()))
+        {
+            return Err(format!("unknown option {}", unknown));
+        };
+        Ok(Args {
+            options,
+            values,
+            positionals,
+        })
+    }
+
+    pub fn has(&self, option: &str) -> bool {
+        self.options.iter().any(|value| value == option)
+    }
+
+    /// Return every value given to `option`, in order
+    pub fn values_of<'a>(&'a self, option: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.values
+            .iter()
+            .filter(move |(name, _)| name == option)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Open a .3ds, .cxi, .cfa or decrypted .cia, and find its partitions
+pub fn open_title(path: &str) -> Result<(File, TitleLayout), String> {
+    let file = File::open(path).map_err(|err| format!("can't open {}: {}", path, err))?;
+    let layout = TitleLayout::new(&file).map_err(|err| format!("can't read {}: {}", path, err))?;
+    Ok((file, layout))
+}
+
+/// Write `data` to the standard output. A closed output (like with `fs3ds ... | head`) isn't an error.
+pub fn write_stdout(data: &[u8]) -> Result<(), String> {
+    let mut stdout = io::stdout();
+    match stdout.write_all(data).and_then(|_| stdout.flush()) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        Err(err) => Err(format!("can't write to the standard output: {}", err)),
+    }
+}
+
+/// Copy all the content of `source` to the standard output. A closed output isn't an error.
+pub fn copy_to_stdout(source: &mut dyn Read) -> Result<(), String> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut buffer = vec![0; 0x10000];
+    loop {
+        let read = match source.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(value) => value,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(format!("can't read the file: {}", err)),
+        };
+        match stdout.write_all(&buffer[..read]) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            Err(err) => return Err(format!("can't write to the standard output: {}", err)),
+        };
+    }
+    match stdout.flush() {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        Err(err) => Err(format!("can't write to the standard output: {}", err)),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("info") => info::run(&args[1..]),
+        Some("ls") => ls::run(&args[1..]),
+        Some("cat") => cat::run(&args[1..]),
+        Some("extract") => extract::run(&args[1..]),
+        Some("build") => build::run(&args[1..]),
+        Some("verify") => verify::run(&args[1..]),
+        Some("help") | Some("--help") | Some("-h") => {
+            print!("{}", USAGE);
+            return;
+        }
+        Some(command) => {
+            eprintln!("fs3ds: unknown command {}", command);
+            eprint!("{}", USAGE);
+            exit(2);
+        }
+        None => {
+            eprint!("{}", USAGE);
+            exit(2);
+        }
+    };
+    if let Err(err) = result {
+        eprintln!("fs3ds: {}", err);
+        exit(1);
+    };
+}
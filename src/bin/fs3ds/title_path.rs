@@ -0,0 +1,334 @@
+//! The paths inside a title, used by `ls` and `cat`, like `/a/b.bin`, `exefs:/icon` or `p1:romfs:/a/b.bin`
+
+use fs3ds::{
+    DirectoryMetadata, DirectoryOrFile, ExeFSReader, FileMetadata, IVFCReader, ReadAtCursor,
+    ReadAtPartition, TitleLayout, IVFCVFS,
+};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::sync::Arc;
+use vfs::VFS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    RomFS,
+    ExeFS,
+}
+
+/// A path in a section of a partition. Without prefix, it is in the romfs of the first partition.
+#[derive(Debug, Clone)]
+pub struct TitlePath {
+    /// `None` for the first partition of the title
+    pub partition: Option<usize>,
+    pub section: Section,
+    /// The path inside the section, without the empty components (like "a/b.bin")
+    pub path: String,
+}
+
+/// Split `value` on the first ':', if what is before it look like a prefix rather than a part of the path
+fn split_prefix(value: &str) -> Option<(&str, &str)> {
+    let position = value.find(':')?;
+    let prefix = &value[..position];
+    if prefix.contains('/') {
+        None
+    } else {
+        Some((prefix, &value[position + 1..]))
+    }
+}
+
+impl TitlePath {
+    pub fn parse(value: &str) -> Result<TitlePath, String> {
+        let mut rest = value;
+        let mut partition = None;
+        if let Some((prefix, after)) = split_prefix(rest) {
+            if let Some(number) = prefix.strip_prefix('p') {
+                partition = Some(
+                    number
+                        .parse()
+                        .map_err(|_| format!("invalid partition {:?} in {:?}", prefix, value))?,
+                );
+                rest = after;
+            };
+        };
+        let mut section = Section::RomFS;
+        if let Some((prefix, after)) = split_prefix(rest) {
+            section = match prefix {
+                "romfs" => Section::RomFS,
+                "exefs" => Section::ExeFS,
+                _ => {
+                    return Err(format!(
+                        "unknown section {:?} in {:?}, expected romfs or exefs",
+                        prefix, value
+                    ))
+                }
+            };
+            rest = after;
+        };
+        let path = rest
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("/");
+        Ok(TitlePath {
+            partition,
+            section,
+            path,
+        })
+    }
+}
+
+/// A file or a directory of a section
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+    /// The offset of the data of a file, relative to the start of the input file. 0 for a directory.
+    pub offset: u64,
+    /// The lenght of a file. 0 for a directory.
+    pub lenght: u64,
+}
+
+enum SectionContent {
+    RomFS {
+        vfs: IVFCVFS<ReadAtPartition<File>>,
+        offset: u64,
+    },
+    ExeFS(Vec<Entry>),
+}
+
+/// The entries of the subdirectories and the files of a romfs directory, sorted by name. `offset` is the offset of the romfs in the input file.
+fn romfs_entries(
+    reader: &IVFCReader<ReadAtPartition<File>>,
+    offset: u64,
+    subdirs: &[DirectoryMetadata],
+    files: &[FileMetadata],
+) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = subdirs
+        .iter()
+        .map(|subdir| Entry {
+            name: subdir.name.clone().unwrap_or_default(),
+            is_dir: true,
+            offset: 0,
+            lenght: 0,
+        })
+        .collect();
+    entries.extend(files.iter().map(|file| Entry {
+        name: file.name.clone(),
+        is_dir: false,
+        offset: offset.saturating_add(reader.get_file_real_offset(file)),
+        lenght: file.lenght_file_data,
+    }));
+    entries.sort_by(|first, second| first.name.cmp(&second.name));
+    entries
+}
+
+/// A romfs or an exefs of a title, opened to be browsed
+pub struct OpenedSection {
+    file: Arc<File>,
+    content: SectionContent,
+}
+
+impl OpenedSection {
+    pub fn open(
+        file: Arc<File>,
+        layout: &TitleLayout,
+        partition: Option<usize>,
+        section: Section,
+    ) -> Result<OpenedSection, String> {
+        let (partition_nb, sections) = match partition {
+            None => match layout.partitions.first() {
+                Some((nb, sections)) => (*nb, sections),
+                None => return Err("the title has no partition".to_string()),
+            },
+            Some(nb) => match layout.get_partition(nb) {
+                Some(sections) => (nb, sections),
+                None => return Err(format!("the title has no partition {}", nb)),
+            },
+        };
+        let content = match section {
+            Section::RomFS => {
+                let (offset, lenght) = sections.romfs;
+                if lenght == 0 {
+                    return Err(format!("the partition {} has no romfs", partition_nb));
+                };
+                let romfs = ReadAtPartition::new(file.clone(), offset, lenght)
+                    .map_err(|err| format!("can't open the romfs: {}", err))?;
+                let reader = IVFCReader::from_read_at(romfs)
+                    .map_err(|err| format!("can't read the romfs: {}", err))?;
+                SectionContent::RomFS {
+                    vfs: IVFCVFS::new(reader),
+                    offset,
+                }
+            }
+            Section::ExeFS => {
+                let (offset, lenght) = sections.exefs;
+                if lenght == 0 {
+                    return Err(format!("the partition {} has no exefs", partition_nb));
+                };
+                let exefs = ReadAtPartition::new(file.clone(), offset, lenght)
+                    .map_err(|err| format!("can't open the exefs: {}", err))?;
+                let reader = ExeFSReader::new(ReadAtCursor::new(&exefs))
+                    .map_err(|err| format!("can't read the exefs: {}", err))?;
+                SectionContent::ExeFS(
+                    reader
+                        .files
+                        .iter()
+                        .map(|exefs_file| Entry {
+                            name: exefs_file.name.clone(),
+                            is_dir: false,
+                            offset: offset.saturating_add(reader.get_file_real_offset(exefs_file)),
+                            lenght: exefs_file.lenght as u64,
+                        })
+                        .collect(),
+                )
+            }
+        };
+        Ok(OpenedSection { file, content })
+    }
+
+    /// Return the file or the directory at `path` (like "a/b.bin", "" being the root)
+    pub fn stat(&self, path: &str) -> Result<Entry, String> {
+        let name = path.rsplit('/').next().unwrap_or_default().to_string();
+        match &self.content {
+            SectionContent::RomFS { vfs, offset } => match vfs.path(path).get_internal_meta() {
+                Ok(DirectoryOrFile::Dir(_)) => Ok(Entry {
+                    name,
+                    is_dir: true,
+                    offset: 0,
+                    lenght: 0,
+                }),
+                Ok(DirectoryOrFile::File(file)) => Ok(Entry {
+                    name,
+                    is_dir: false,
+                    offset: offset.saturating_add(vfs.get_reader().get_file_real_offset(&file)),
+                    lenght: file.lenght_file_data,
+                }),
+                Err(_) => Err(format!("{}: no such file or directory", path)),
+            },
+            SectionContent::ExeFS(files) => {
+                if path.is_empty() {
+                    return Ok(Entry {
+                        name,
+                        is_dir: true,
+                        offset: 0,
+                        lenght: 0,
+                    });
+                };
+                match files.iter().find(|file| file.name == path) {
+                    Some(file) => Ok(file.clone()),
+                    None => Err(format!("{}: no such file or directory", path)),
+                }
+            }
+        }
+    }
+
+    /// Return the romfs directory at `path`
+    fn get_romfs_dir(
+        vfs: &IVFCVFS<ReadAtPartition<File>>,
+        path: &str,
+    ) -> Result<DirectoryMetadata, String> {
+        match vfs.path(path).get_internal_meta() {
+            Ok(DirectoryOrFile::Dir(dir)) => Ok(dir),
+            Ok(DirectoryOrFile::File(_)) => Err(format!("{}: not a directory", path)),
+            Err(_) => Err(format!("{}: no such file or directory", path)),
+        }
+    }
+
+    /// Return the content of the directory at `path`, sorted by name
+    pub fn list(&self, path: &str) -> Result<Vec<Entry>, String> {
+        match &self.content {
+            SectionContent::RomFS { vfs, offset } => {
+                let dir = OpenedSection::get_romfs_dir(vfs, path)?;
+                let reader = vfs.get_reader();
+                let mut subdirs = Vec::new();
+                let mut files = Vec::new();
+                reader
+                    .list_dir_child_metadata(&dir, &mut subdirs)
+                    .and_then(|_| reader.list_file_child_metadata(&dir, &mut files))
+                    .map_err(|err| format!("{}: can't list the directory: {}", path, err))?;
+                Ok(romfs_entries(&reader, *offset, &subdirs, &files))
+            }
+            SectionContent::ExeFS(files) => {
+                if !path.is_empty() {
+                    return Err(format!("{}: not a directory", path));
+                };
+                let mut entries = files.clone();
+                entries.sort_by(|first, second| first.name.cmp(&second.name));
+                Ok(entries)
+            }
+        }
+    }
+
+    /// Return the content of the directory at `path` and of every directory under it, by path relative to `path` ("" being `path` itself). Each content is sorted by name.
+    pub fn list_recursive(&self, path: &str) -> Result<HashMap<String, Vec<Entry>>, String> {
+        let mut result = HashMap::new();
+        match &self.content {
+            SectionContent::RomFS { vfs, offset } => {
+                let dir = OpenedSection::get_romfs_dir(vfs, path)?;
+                let reader = vfs.get_reader();
+                reader
+                    .walk_dir(&dir, |dir_path, subdirs, files| {
+                        result.insert(
+                            dir_path.to_string(),
+                            romfs_entries(&reader, *offset, subdirs, files),
+                        );
+                    })
+                    .map_err(|err| format!("/{}: can't list the directory: {}", path, err))?;
+            }
+            SectionContent::ExeFS(_) => {
+                result.insert(String::new(), self.list(path)?);
+            }
+        };
+        Ok(result)
+    }
+
+    /// Open the file at `path` for reading
+    pub fn open_file(&self, path: &str) -> Result<Box<dyn Read>, String> {
+        match &self.content {
+            SectionContent::RomFS { vfs, .. } => match vfs.path(path).open_partition() {
+                Ok(partition) => Ok(Box::new(partition)),
+                Err(err) => Err(format!("{}: can't open the file: {}", path, err)),
+            },
+            SectionContent::ExeFS(_) => {
+                let entry = self.stat(path)?;
+                match ReadAtPartition::new(self.file.clone(), entry.offset, entry.lenght) {
+                    Ok(partition) => Ok(Box::new(partition)),
+                    Err(err) => Err(format!("{}: can't open the file: {}", path, err)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        for (value, partition, section, path) in [
+            ("/a/b.bin", None, Section::RomFS, "a/b.bin"),
+            ("a//b.bin/", None, Section::RomFS, "a/b.bin"),
+            ("/", None, Section::RomFS, ""),
+            ("exefs:/icon", None, Section::ExeFS, "icon"),
+            ("romfs:", None, Section::RomFS, ""),
+            ("p1:romfs:/", Some(1), Section::RomFS, ""),
+            ("p2:exefs:banner", Some(2), Section::ExeFS, "banner"),
+            ("p0:/a.bin", Some(0), Section::RomFS, "a.bin"),
+            // a ':' after a '/' is a part of the path
+            ("/a:b/c", None, Section::RomFS, "a:b/c"),
+            ("exefs:/a:b", None, Section::ExeFS, "a:b"),
+        ]
+        .iter()
+        {
+            let parsed = TitlePath::parse(value).unwrap();
+            assert_eq!(parsed.partition, *partition, "{}", value);
+            assert_eq!(parsed.section, *section, "{}", value);
+            assert_eq!(parsed.path, *path, "{}", value);
+        }
+        assert!(TitlePath::parse("px:/a.bin").is_err());
+        assert!(TitlePath::parse("p1:logo:/a.bin").is_err());
+        assert!(TitlePath::parse("other:/a.bin").is_err());
+    }
+}
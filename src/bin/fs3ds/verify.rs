@@ -0,0 +1,855 @@
+//! `fs3ds verify`: check the integrity of a title, and print the result of every check in a table
+
+use crate::{write_stdout, Args};
+use fs3ds::{
+    signature_block_lenght, Certificate, NCCHSections, RSAPublicKey, ReadAt, TitleContainer,
+    TitleLayout,
+};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::File;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pass,
+    Fail,
+    Skip,
+}
+
+struct Check {
+    status: Status,
+    item: String,
+    detail: String,
+}
+
+/// The result of all the checks, in the order they were done
+#[derive(Default)]
+struct Report {
+    checks: Vec<Check>,
+}
+
+impl Report {
+    fn add(&mut self, status: Status, item: &str, detail: String) {
+        self.checks.push(Check {
+            status,
+            item: item.to_string(),
+            detail,
+        });
+    }
+
+    fn pass(&mut self, item: &str, detail: String) {
+        self.add(Status::Pass, item, detail)
+    }
+
+    fn fail(&mut self, item: &str, detail: String) {
+        self.add(Status::Fail, item, detail)
+    }
+
+    fn skip(&mut self, item: &str, detail: String) {
+        self.add(Status::Skip, item, detail)
+    }
+
+    /// Check that the SHA-256 of the `lenght` bytes at `offset` in `file` is `expected`
+    fn check_hash(
+        &mut self,
+        item: &str,
+        file: &File,
+        (offset, lenght): (u64, u64),
+        expected: &[u8],
+    ) {
+        let hash = match hash_range(file, offset, lenght, 0) {
+            Ok(value) => value,
+            Err(err) => return self.fail(item, err),
+        };
+        if hash.as_slice() == expected {
+            self.pass(item, String::new());
+        } else {
+            self.fail(
+                item,
+                format!("expected {}, found {}", to_hex(expected), to_hex(&hash)),
+            );
+        };
+    }
+
+    fn count(&self, status: Status) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| check.status == status)
+            .count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let item_width = self
+            .checks
+            .iter()
+            .map(|check| check.item.len())
+            .max()
+            .unwrap_or(0);
+        writeln!(
+            f,
+            "{:<6} {:<width$} detail",
+            "result",
+            "check",
+            width = item_width
+        )?;
+        for check in &self.checks {
+            let status = match check.status {
+                Status::Pass => "PASS",
+                Status::Fail => "FAIL",
+                Status::Skip => "SKIP",
+            };
+            writeln!(
+                f,
+                "{:<6} {:<width$} {}",
+                status,
+                check.item,
+                check.detail,
+                width = item_width
+            )?;
+        }
+        writeln!(
+            f,
+            "\n{} checks: {} passed, {} failed, {} skipped",
+            self.checks.len(),
+            self.count(Status::Pass),
+            self.count(Status::Fail),
+            self.count(Status::Skip)
+        )
+    }
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn read_u64_le(data: &[u8], offset: usize) -> u64 {
+    let mut value = [0; 8];
+    value.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(value)
+}
+
+fn align(value: u64, alignment: u64) -> Option<u64> {
+    Some(value.checked_add(alignment - 1)? / alignment * alignment)
+}
+
+/// The biggest range read in memory at once, for the headers, the certificates, the ticket, the tmd and the ivfc master hash
+const MAX_READ_LENGHT: u64 = 0x400000;
+/// The biggest ivfc hash level (the levels 1 and 2), that are kept in memory as the expected hashes of the next level
+const MAX_HASH_LEVEL_LENGHT: u64 = 0x4000000;
+/// The size of the chunks the hashed data is read in
+const HASH_CHUNK_LENGHT: usize = 0x10000;
+
+fn read_range(file: &File, offset: u64, lenght: u64) -> Result<Vec<u8>, String> {
+    if lenght > MAX_READ_LENGHT {
+        return Err(format!(
+            "{:#x} bytes at {:#x} is too big to be read in memory",
+            lenght, offset
+        ));
+    };
+    let mut data = vec![0; lenght as usize];
+    match file.read_exact_at(offset, &mut data) {
+        Ok(()) => Ok(data),
+        Err(err) => Err(format!(
+            "can't read {:#x} bytes at {:#x}: {}",
+            lenght, offset, err
+        )),
+    }
+}
+
+/// Add `count` zeros to the hashed data
+fn hash_zeros(hasher: &mut Sha256, mut count: u64) {
+    let zeros = [0; 0x1000];
+    while count > 0 {
+        let lenght = std::cmp::min(count, zeros.len() as u64) as usize;
+        hasher.update(&zeros[..lenght]);
+        count -= lenght as u64;
+    }
+}
+
+/// Return the SHA-256 of the `lenght` bytes at `offset`, followed by `padding` zeros. The data is read in chunks, so it doesn't need to fit in memory.
+fn hash_range(file: &File, offset: u64, lenght: u64, padding: u64) -> Result<Vec<u8>, String> {
+    let mut hasher = Sha256::new();
+    let mut chunk = vec![0; HASH_CHUNK_LENGHT];
+    let mut hashed = 0;
+    while hashed < lenght {
+        let to_read = std::cmp::min(lenght - hashed, chunk.len() as u64) as usize;
+        if let Err(err) = file.read_exact_at(offset + hashed, &mut chunk[..to_read]) {
+            return Err(format!(
+                "can't read {:#x} bytes at {:#x}: {}",
+                lenght, offset, err
+            ));
+        };
+        hasher.update(&chunk[..to_read]);
+        hashed += to_read as u64;
+    }
+    hash_zeros(&mut hasher, padding);
+    Ok(hasher.finalize().to_vec())
+}
+
+/// The keys used to check the signatures, from the given certificates and the certificates of a CIA
+struct Keys {
+    certificates: Vec<Certificate>,
+    /// The keys of the given certificates, tried for the signatures that don't name their issuer
+    anonymous: Vec<RSAPublicKey>,
+}
+
+impl Keys {
+    fn find_issuer(&self, issuer: &str) -> Option<&Certificate> {
+        self.certificates
+            .iter()
+            .find(|certificate| certificate.full_name() == issuer)
+    }
+
+    /// Check a signature that doesn't name its issuer, like the one of a NCSD header, with every given key
+    fn check_anonymous(&self, report: &mut Report, item: &str, data: &[u8], signature: &[u8]) {
+        if self
+            .anonymous
+            .iter()
+            .any(|key| key.verify_sha256(data, signature))
+        {
+            report.pass(item, String::new());
+        } else {
+            report.fail(item, "not signed by any of the given keys".to_string());
+        };
+    }
+
+    /// Check the signature at the start of `data` (a ticket or a tmd), that sign the `signed_lenght` bytes after the signature block
+    fn check_signed(&self, report: &mut Report, item: &str, data: &[u8], signed_lenght: usize) {
+        if data.len() < 4 {
+            return report.fail(item, "truncated signature".to_string());
+        };
+        let signature_type = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let block_lenght = match (signature_type, signature_block_lenght(signature_type)) {
+            (0x10003 | 0x10004, Some(value)) => value,
+            (_, Some(_)) => {
+                return report.skip(
+                    item,
+                    format!("unsupported signature type {:#x}", signature_type),
+                )
+            }
+            (_, None) => {
+                return report.fail(
+                    item,
+                    format!("unknown signature type {:#x}", signature_type),
+                )
+            }
+        };
+        let signature_lenght = block_lenght - 4 - 0x3C;
+        if data.len() < block_lenght + signed_lenght || signed_lenght < 0x40 {
+            return report.fail(item, "truncated signed data".to_string());
+        };
+        let signed = &data[block_lenght..block_lenght + signed_lenght];
+        let issuer = String::from_utf8_lossy(&signed[..0x40])
+            .trim_end_matches('\0')
+            .to_string();
+        let key = match self
+            .find_issuer(&issuer)
+            .and_then(|certificate| certificate.key.as_ref())
+        {
+            Some(value) => value,
+            None => {
+                return report.skip(
+                    item,
+                    format!("no certificate was given for the issuer {}", issuer),
+                )
+            }
+        };
+        if key.verify_sha256(signed, &data[4..4 + signature_lenght]) {
+            report.pass(item, format!("signed by {}", issuer));
+        } else {
+            report.fail(item, format!("invalid signature from {}", issuer));
+        };
+    }
+}
+
+/// Check the hashes of the levels of an IVFC tree, each level hashing the blocks of the next one
+fn verify_ivfc(report: &mut Report, file: &File, label: &str, (offset, lenght): (u64, u64)) {
+    let item = format!("{} romfs ivfc", label);
+    let header = match read_range(file, offset, 0x60) {
+        Ok(value) => value,
+        Err(err) => return report.fail(&item, err),
+    };
+    if &header[0..4] != b"IVFC" || read_u32_le(&header, 4) != 0x10000 {
+        return report.fail(&item, "invalid ivfc magic".to_string());
+    };
+    let master_hash_lenght = read_u32_le(&header, 8) as u64;
+    let mut levels = Vec::new();
+    for level_nb in 0..3 {
+        let level_lenght = read_u64_le(&header, 0x14 + level_nb * 0x18);
+        let block_size_log2 = read_u32_le(&header, 0x1C + level_nb * 0x18);
+        if !(6..=24).contains(&block_size_log2) {
+            return report.fail(
+                &item,
+                format!("invalid block size for the level {}", level_nb + 1),
+            );
+        };
+        levels.push((level_lenght, 1u64 << block_size_log2));
+    }
+
+    if 0x60 + master_hash_lenght > lenght || master_hash_lenght > MAX_READ_LENGHT {
+        return report.fail(
+            &item,
+            format!("invalid master hash lenght {:#x}", master_hash_lenght),
+        );
+    };
+    for (level_nb, (level_lenght, _)) in levels.iter().enumerate().take(2) {
+        if *level_lenght > MAX_HASH_LEVEL_LENGHT {
+            return report.fail(
+                &item,
+                format!(
+                    "the hash level {} is {:#x} bytes long, more than expected",
+                    level_nb + 1,
+                    level_lenght
+                ),
+            );
+        };
+    }
+
+    // the level 3 is after the master hash, and followed by the levels 1 and 2
+    let level_3_offset = align(0x60 + master_hash_lenght, levels[2].1);
+    let level_1_offset = level_3_offset.and_then(|value| align(value + levels[2].0, levels[0].1));
+    let level_2_offset = level_1_offset.and_then(|value| align(value + levels[0].0, levels[1].1));
+    let physical_offsets = match (level_1_offset, level_2_offset, level_3_offset) {
+        (Some(first), Some(second), Some(third)) => [first, second, third],
+        _ => return report.fail(&item, "the levels overflow".to_string()),
+    };
+    for (level_nb, (physical_offset, (level_lenght, _))) in
+        physical_offsets.iter().zip(levels.iter()).enumerate()
+    {
+        let level_inside = match physical_offset.checked_add(*level_lenght) {
+            Some(end) => end <= lenght,
+            None => false,
+        };
+        if !level_inside {
+            return report.fail(
+                &item,
+                format!("the level {} is outside the romfs", level_nb + 1),
+            );
+        };
+    }
+
+    let mut expected_hashes = match read_range(file, offset + 0x60, master_hash_lenght) {
+        Ok(value) => value,
+        Err(err) => return report.fail(&item, err),
+    };
+    for level_nb in 0..3 {
+        let item = format!("{} romfs ivfc level {}", label, level_nb + 1);
+        let (level_lenght, block_size) = levels[level_nb];
+        let block_count = level_lenght.div_ceil(block_size);
+        if (expected_hashes.len() as u64) < block_count * 0x20 {
+            return report.fail(
+                &item,
+                format!(
+                    "{} blocks, but only {} hashes",
+                    block_count,
+                    expected_hashes.len() / 0x20
+                ),
+            );
+        };
+        // the hash levels are kept, as they are the expected hashes of the next level. The data level is hashed as it is read.
+        let level_offset = offset + physical_offsets[level_nb];
+        let level_data = if level_nb < 2 {
+            match read_range(file, level_offset, level_lenght) {
+                Ok(value) => value,
+                Err(err) => return report.fail(&item, err),
+            }
+        } else {
+            Vec::new()
+        };
+        let mut invalid_blocks = Vec::new();
+        for block_nb in 0..block_count {
+            let block_start = block_nb * block_size;
+            let in_block = std::cmp::min(block_size, level_lenght - block_start);
+            let hash = if level_nb < 2 {
+                let mut hasher = Sha256::new();
+                hasher.update(&level_data[block_start as usize..(block_start + in_block) as usize]);
+                hash_zeros(&mut hasher, block_size - in_block);
+                hasher.finalize().to_vec()
+            } else {
+                match hash_range(
+                    file,
+                    level_offset + block_start,
+                    in_block,
+                    block_size - in_block,
+                ) {
+                    Ok(value) => value,
+                    Err(err) => return report.fail(&item, format!("block {}: {}", block_nb, err)),
+                }
+            };
+            let expected =
+                &expected_hashes[block_nb as usize * 0x20..block_nb as usize * 0x20 + 0x20];
+            if hash != expected {
+                invalid_blocks.push(block_nb);
+            };
+        }
+        match invalid_blocks.first() {
+            None => report.pass(&item, format!("{} blocks", block_count)),
+            Some(first) => report.fail(
+                &item,
+                format!(
+                    "{} of {} blocks don't match their hash, the first one at {:#x}",
+                    invalid_blocks.len(),
+                    block_count,
+                    offset + physical_offsets[level_nb] + first * block_size
+                ),
+            ),
+        };
+        expected_hashes = level_data;
+    }
+}
+
+/// Check the exefs header hash and the hash of every file of the exefs
+fn verify_exefs(
+    report: &mut Report,
+    file: &File,
+    label: &str,
+    ncch_header: &[u8],
+    (offset, lenght): (u64, u64),
+) {
+    let item = format!("{} exefs header hash", label);
+    let hashed_lenght = read_u32_le(ncch_header, 0x1A8) as u64 * 0x200;
+    report.check_hash(
+        &item,
+        file,
+        (offset, std::cmp::min(hashed_lenght, lenght)),
+        &ncch_header[0x1C0..0x1E0],
+    );
+    let header = match read_range(file, offset, 0x200) {
+        Ok(value) => value,
+        Err(err) => return report.fail(&format!("{} exefs", label), err),
+    };
+    for file_nb in 0..10 {
+        let entry = &header[file_nb * 0x10..(file_nb + 1) * 0x10];
+        let name_end = entry[..8].iter().position(|c| *c == 0).unwrap_or(8);
+        if name_end == 0 {
+            continue;
+        };
+        let item = format!(
+            "{} exefs/{} hash",
+            label,
+            String::from_utf8_lossy(&entry[..name_end])
+        );
+        let file_offset = read_u32_le(entry, 8) as u64 + 0x200;
+        let file_lenght = read_u32_le(entry, 12) as u64;
+        if file_offset + file_lenght > lenght {
+            report.fail(&item, "the file is outside the exefs".to_string());
+            continue;
+        };
+        // hashes are stored in the reverse order of the files
+        let hash_offset = 0xC0 + (9 - file_nb) * 0x20;
+        report.check_hash(
+            &item,
+            file,
+            (offset + file_offset, file_lenght),
+            &header[hash_offset..hash_offset + 0x20],
+        );
+    }
+}
+
+fn verify_ncch(
+    report: &mut Report,
+    file: &File,
+    file_lenght: u64,
+    partition_nb: usize,
+    sections: &NCCHSections,
+    keys: Option<&Keys>,
+) {
+    let label = format!("p{}", partition_nb);
+    let (ncch_offset, ncch_lenght) = sections.ncch;
+    let header = match read_range(file, ncch_offset, 0x200) {
+        Ok(value) => value,
+        Err(err) => return report.fail(&format!("{} ncch header", label), err),
+    };
+    if &header[0x100..0x104] == b"NCCH" {
+        report.pass(&format!("{} ncch magic", label), String::new());
+    } else {
+        return report.fail(
+            &format!("{} ncch magic", label),
+            "invalid magic".to_string(),
+        );
+    };
+
+    let content_size = read_u32_le(&header, 0x104) as u64 * 0x200;
+    let item = format!("{} ncch size", label);
+    if content_size != ncch_lenght {
+        report.fail(
+            &item,
+            format!(
+                "the ncch header give {:#x} bytes, but its container {:#x}",
+                content_size, ncch_lenght
+            ),
+        );
+    } else if ncch_offset + content_size > file_lenght {
+        report.fail(&item, "the ncch go past the end of the file".to_string());
+    } else {
+        report.pass(&item, format!("{:#x} bytes", content_size));
+    };
+
+    let mut problems = Vec::new();
+    let mut ranges: Vec<(&str, (u64, u64))> = vec![
+        ("exheader", sections.exheader),
+        ("plain region", sections.plain_region),
+        ("logo region", sections.logo_region),
+        ("exefs", sections.exefs),
+        ("romfs", sections.romfs),
+    ];
+    ranges.retain(|(_, (_, lenght))| *lenght != 0);
+    ranges.sort_by_key(|(_, (offset, _))| *offset);
+    // the hashes of the sections outside the ncch or the file aren't checked
+    let mut outside = Vec::new();
+    let mut previous_end = ncch_offset + 0x200;
+    for (name, (offset, lenght)) in &ranges {
+        if *offset < previous_end {
+            problems.push(format!("the {} overlap the previous section", name));
+        };
+        if offset + lenght > ncch_offset + content_size {
+            problems.push(format!("the {} is outside the ncch", name));
+            outside.push(*name);
+        } else if offset + lenght > file_lenght {
+            problems.push(format!("the {} go past the end of the file", name));
+            outside.push(*name);
+        };
+        previous_end = offset + lenght;
+    }
+    let item = format!("{} sections", label);
+    if problems.is_empty() {
+        report.pass(&item, String::new());
+    } else {
+        report.fail(&item, problems.join(", "));
+    };
+
+    let skip_outside = |report: &mut Report, name: &str, item: &str| {
+        report.skip(
+            item,
+            format!("the {} is outside the ncch or the file", name),
+        )
+    };
+    if sections.exheader.1 != 0 {
+        let item = format!("{} exheader hash", label);
+        if outside.contains(&"exheader") {
+            skip_outside(report, "exheader", &item);
+        } else {
+            report.check_hash(
+                &item,
+                file,
+                (sections.exheader.0, 0x400),
+                &header[0x160..0x180],
+            );
+        };
+    };
+    if sections.logo_region.1 != 0 {
+        let item = format!("{} logo hash", label);
+        if outside.contains(&"logo region") {
+            skip_outside(report, "logo region", &item);
+        } else {
+            report.check_hash(&item, file, sections.logo_region, &header[0x130..0x150]);
+        };
+    };
+    if sections.exefs.1 != 0 {
+        if outside.contains(&"exefs") {
+            skip_outside(report, "exefs", &format!("{} exefs hashes", label));
+        } else {
+            verify_exefs(report, file, &label, &header, sections.exefs);
+        };
+    };
+    if sections.romfs.1 != 0 {
+        if outside.contains(&"romfs") {
+            skip_outside(report, "romfs", &format!("{} romfs hashes", label));
+        } else {
+            let item = format!("{} romfs header hash", label);
+            let hashed_lenght = read_u32_le(&header, 0x1B8) as u64 * 0x200;
+            report.check_hash(
+                &item,
+                file,
+                (
+                    sections.romfs.0,
+                    std::cmp::min(hashed_lenght, sections.romfs.1),
+                ),
+                &header[0x1E0..0x200],
+            );
+            verify_ivfc(report, file, &label, sections.romfs);
+        };
+    };
+
+    let keys = match keys {
+        Some(value) => value,
+        None => return,
+    };
+    // a cxi header is signed by the key in its access descriptor, that is itself signed by Nintendo
+    if sections.exheader.1 != 0 {
+        let item = format!("{} access descriptor signature", label);
+        let access_descriptor = match read_range(file, sections.exheader.0 + 0x400, 0x400) {
+            Ok(value) => value,
+            Err(err) => return report.fail(&item, err),
+        };
+        keys.check_anonymous(
+            report,
+            &item,
+            &access_descriptor[0x100..],
+            &access_descriptor[..0x100],
+        );
+        let ncch_key = RSAPublicKey {
+            modulus: access_descriptor[0x100..0x200].to_vec(),
+            exponent: 0x10001,
+        };
+        let item = format!("{} ncch signature", label);
+        if ncch_key.verify_sha256(&header[0x100..], &header[..0x100]) {
+            report.pass(
+                &item,
+                "signed by the key of the access descriptor".to_string(),
+            );
+        } else {
+            report.fail(
+                &item,
+                "not signed by the key of the access descriptor".to_string(),
+            );
+        };
+    } else {
+        keys.check_anonymous(
+            report,
+            &format!("{} ncch signature", label),
+            &header[0x100..],
+            &header[..0x100],
+        );
+    };
+}
+
+/// Check the partition table and the signature of a raw NCSD header, so a table refused by `TitleLayout` is still reported
+fn verify_ncsd_header(report: &mut Report, header: &[u8], file_lenght: u64, keys: Option<&Keys>) {
+    let mut partitions = Vec::new();
+    for partition_nb in 0..8 {
+        let offset = read_u32_le(header, 0x120 + partition_nb * 8) as u64 * 0x200;
+        let lenght = read_u32_le(header, 0x124 + partition_nb * 8) as u64 * 0x200;
+        if offset != 0 {
+            partitions.push((partition_nb, offset, lenght));
+        };
+    }
+    partitions.sort_by_key(|(_, offset, _)| *offset);
+    let mut previous_end = 0x200;
+    for (partition_nb, offset, lenght) in partitions {
+        let item = format!("ncsd partition {}", partition_nb);
+        if offset < previous_end {
+            report.fail(&item, format!("{:#x} overlap the previous data", offset));
+        } else if offset + lenght > file_lenght {
+            report.fail(
+                &item,
+                format!("{:#x}+{:#x} go past the end of the file", offset, lenght),
+            );
+        } else {
+            report.pass(&item, format!("{:#x}+{:#x}", offset, lenght));
+        };
+        previous_end = std::cmp::max(previous_end, offset + lenght);
+    }
+    let image_size = read_u32_le(header, 0x104) as u64 * 0x200;
+    if image_size < previous_end {
+        report.fail(
+            "ncsd image size",
+            format!("{:#x}, smaller than the partitions", image_size),
+        );
+    } else {
+        report.pass("ncsd image size", format!("{:#x}", image_size));
+    };
+
+    if let Some(keys) = keys {
+        keys.check_anonymous(report, "ncsd signature", &header[0x100..], &header[..0x100]);
+    };
+}
+
+/// Check the hash of the extended header of the first partition, stored in the NCSD header
+fn verify_ncsd_exheader_hash(
+    report: &mut Report,
+    file: &File,
+    header: &[u8],
+    layout: &TitleLayout,
+) {
+    if let Some((0, sections)) = layout.partitions.first() {
+        if sections.exheader.1 != 0 {
+            if header[0x160..0x180].iter().all(|byte| *byte == 0) {
+                report.skip("ncsd exheader hash", "not set".to_string());
+            } else {
+                report.check_hash(
+                    "ncsd exheader hash",
+                    file,
+                    (sections.exheader.0, 0x400),
+                    &header[0x160..0x180],
+                );
+            };
+        };
+    };
+}
+
+/// Check the certificate chain, the ticket and the tmd of a CIA. The certificates of the chain are added to `keys`.
+fn verify_cia_signatures(report: &mut Report, file: &File, keys: &mut Keys) {
+    let header = match read_range(file, 0, 0x20) {
+        Ok(value) => value,
+        Err(err) => return report.fail("cia header", err),
+    };
+    let chain_lenght = read_u32_le(&header, 0x8) as u64;
+    let ticket_lenght = read_u32_le(&header, 0xC) as u64;
+    let tmd_lenght = read_u32_le(&header, 0x10) as u64;
+    let chain_offset = 0x2040;
+    let ticket_offset = chain_offset + align(chain_lenght, 0x40).unwrap_or(u64::MAX / 4);
+    let tmd_offset = ticket_offset + align(ticket_lenght, 0x40).unwrap_or(u64::MAX / 4);
+
+    let chain = match read_range(file, chain_offset, chain_lenght) {
+        Ok(value) => value,
+        Err(err) => return report.fail("cia certificates", err),
+    };
+    let chain = match Certificate::parse_all(&chain) {
+        Ok(value) => value,
+        Err(err) => return report.fail("cia certificates", err.to_string()),
+    };
+    keys.certificates.extend(chain.iter().cloned());
+    for certificate in &chain {
+        let item = format!("certificate {}", certificate.name);
+        let key = match keys
+            .find_issuer(&certificate.issuer)
+            .and_then(|issuer| issuer.key.as_ref())
+        {
+            Some(value) => value,
+            None => {
+                report.skip(
+                    &item,
+                    format!(
+                        "no certificate was given for the issuer {}",
+                        certificate.issuer
+                    ),
+                );
+                continue;
+            }
+        };
+        if !matches!(certificate.signature_type, 0x10003 | 0x10004) {
+            report.skip(
+                &item,
+                format!(
+                    "unsupported signature type {:#x}",
+                    certificate.signature_type
+                ),
+            );
+        } else if key.verify_sha256(&certificate.signed_data, &certificate.signature) {
+            report.pass(&item, format!("signed by {}", certificate.issuer));
+        } else {
+            report.fail(
+                &item,
+                format!("invalid signature from {}", certificate.issuer),
+            );
+        };
+    }
+
+    match read_range(file, ticket_offset, ticket_lenght) {
+        Ok(ticket) => {
+            let signed_lenght = signature_block_lenght_of(&ticket)
+                .map_or(0, |block_lenght| ticket.len().saturating_sub(block_lenght));
+            keys.check_signed(report, "ticket signature", &ticket, signed_lenght)
+        }
+        Err(err) => report.fail("ticket signature", err),
+    };
+    match read_range(file, tmd_offset, tmd_lenght) {
+        // only the tmd header is signed, it contain the hash of the content records
+        Ok(tmd) => keys.check_signed(report, "tmd signature", &tmd, 0xC4),
+        Err(err) => report.fail("tmd signature", err),
+    };
+}
+
+fn signature_block_lenght_of(data: &[u8]) -> Option<usize> {
+    if data.len() < 4 {
+        return None;
+    };
+    signature_block_lenght(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+}
+
+fn read_certificates(paths: Vec<&str>) -> Result<Option<Keys>, String> {
+    if paths.is_empty() {
+        return Ok(None);
+    };
+    let mut certificates = Vec::new();
+    for path in paths {
+        let data = std::fs::read(path).map_err(|err| format!("can't read {}: {}", path, err))?;
+        certificates.extend(
+            Certificate::parse_all(&data)
+                .map_err(|err| format!("can't read the certificates in {}: {}", path, err))?,
+        );
+    }
+    let anonymous = certificates
+        .iter()
+        .filter_map(|certificate| certificate.key.clone())
+        .collect();
+    Ok(Some(Keys {
+        certificates,
+        anonymous,
+    }))
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse_with_values(args, &[], &["--certs"])?;
+    let path = match args.positionals.as_slice() {
+        [path] => path,
+        _ => return Err("usage: fs3ds verify [--certs <file>]... <rom>".to_string()),
+    };
+    let mut keys = read_certificates(args.values_of("--certs").collect())?;
+
+    let mut report = Report::default();
+    let file = File::open(path).map_err(|err| format!("can't open {}: {}", path, err))?;
+    let file_lenght = file
+        .metadata()
+        .map_err(|err| format!("can't read {}: {}", path, err))?
+        .len();
+    // checked before the layout, that can't be read with an invalid partition table
+    let ncsd_header = match read_range(&file, 0, 0x200) {
+        Ok(header) if &header[0x100..0x104] == b"NCSD" => Some(header),
+        _ => None,
+    };
+    if let Some(header) = &ncsd_header {
+        verify_ncsd_header(&mut report, header, file_lenght, keys.as_ref());
+    };
+    match TitleLayout::new(&file) {
+        Ok(layout) => {
+            match (layout.container, &ncsd_header) {
+                (TitleContainer::NCSD, Some(header)) => {
+                    verify_ncsd_exheader_hash(&mut report, &file, header, &layout)
+                }
+                (TitleContainer::CIA, _) => {
+                    if let Some(keys) = &mut keys {
+                        verify_cia_signatures(&mut report, &file, keys);
+                    };
+                }
+                _ => (),
+            };
+            for (partition_nb, sections) in &layout.partitions {
+                verify_ncch(
+                    &mut report,
+                    &file,
+                    file_lenght,
+                    *partition_nb,
+                    sections,
+                    keys.as_ref(),
+                );
+            }
+        }
+        Err(err) => report.fail("format", format!("can't read {}: {}", path, err)),
+    };
+    if keys.is_none() {
+        report.skip(
+            "signatures",
+            "no certificate given with --certs".to_string(),
+        );
+    };
+
+    write_stdout(report.to_string().as_bytes())?;
+    match report.count(Status::Fail) {
+        0 => Ok(()),
+        1 => Err("1 check failed".to_string()),
+        failed => Err(format!("{} checks failed", failed)),
+    }
+}
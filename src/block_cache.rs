@@ -0,0 +1,339 @@
+use crate::{ReadAt, MEDIA_UNIT_SIZE};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::Mutex;
+
+/// The configuration of a `BlockCache`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockCacheConfig {
+    /// The size of a cached block, in bytes. Usually a media unit (0x200) or an IVFC block (0x1000).
+    pub block_size: u64,
+    /// The maximum number of blocks kept in memory
+    pub capacity: usize,
+    /// The number of additional blocks read at once when the reads are sequential
+    pub read_ahead: usize,
+}
+
+impl Default for BlockCacheConfig {
+    /// 1 MiB of media units, with a read-ahead of 8 blocks
+    fn default() -> BlockCacheConfig {
+        BlockCacheConfig {
+            block_size: MEDIA_UNIT_SIZE,
+            capacity: 2048,
+            read_ahead: 8,
+        }
+    }
+}
+
+/// Statistics about the reads of a `BlockCache`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlockCacheStats {
+    /// Number of blocks found in the cache
+    pub hits: u64,
+    /// Number of blocks that needed a read from the source
+    pub misses: u64,
+    /// Number of blocks read from the source in advance
+    pub read_ahead_blocks: u64,
+    /// Number of bytes read from the source
+    pub source_bytes_read: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /// block number -> (data, last use)
+    blocks: HashMap<u64, (Vec<u8>, u64)>,
+    /// last use -> block number, to find the least recently used block
+    usage: BTreeMap<u64, u64>,
+    tick: u64,
+    last_missed_block: Option<u64>,
+    stats: BlockCacheStats,
+}
+
+impl CacheState {
+    fn touch(&mut self, block_nb: u64) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some((_, last_use)) = self.blocks.get_mut(&block_nb) {
+            self.usage.remove(last_use);
+            *last_use = tick;
+            self.usage.insert(tick, block_nb);
+        };
+    }
+
+    fn insert(&mut self, block_nb: u64, data: Vec<u8>, capacity: usize) {
+        if let Some((_, last_use)) = self.blocks.remove(&block_nb) {
+            self.usage.remove(&last_use);
+        };
+        while self.blocks.len() >= capacity {
+            let oldest_block = match self.usage.pop_first() {
+                Some((_, value)) => value,
+                None => break,
+            };
+            self.blocks.remove(&oldest_block);
+        }
+        self.tick += 1;
+        self.blocks.insert(block_nb, (data, self.tick));
+        self.usage.insert(self.tick, block_nb);
+    }
+}
+
+/// A `ReadAt` wrapper that keep the recently read blocks of a slow source in memory, with a least recently used eviction.
+///
+/// It is intended to sit between the raw input and the partitions, like `ReadAtPartition::new(Arc::new(BlockCache::new(file, config)), ..)`.
+/// A `Read + Seek` source can be used by wrapping it in a `Mutex`.
+#[derive(Debug)]
+pub struct BlockCache<T: ReadAt> {
+    source: T,
+    config: BlockCacheConfig,
+    state: Mutex<CacheState>,
+}
+
+/// Read from `source` until `buf` is full or the end of the source is reached
+fn read_up_to_at<T: ReadAt>(source: &T, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+    let mut total_read = 0;
+    while total_read < buf.len() {
+        match source.read_at(offset + total_read as u64, &mut buf[total_read..]) {
+            Ok(0) => break,
+            Ok(read) => total_read += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(total_read)
+}
+
+impl<T: ReadAt> BlockCache<T> {
+    /// Create a new cache. A `block_size` or a `capacity` of 0 is treated as 1.
+    pub fn new(source: T, mut config: BlockCacheConfig) -> BlockCache<T> {
+        config.block_size = config.block_size.max(1);
+        config.capacity = config.capacity.max(1);
+        BlockCache {
+            source,
+            config,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    pub fn config(&self) -> BlockCacheConfig {
+        self.config
+    }
+
+    pub fn stats(&self) -> BlockCacheStats {
+        self.lock_state().stats
+    }
+
+    pub fn reset_stats(&self) {
+        self.lock_state().stats = BlockCacheStats::default();
+    }
+
+    /// Drop all the cached blocks
+    pub fn clear(&self) {
+        let mut state = self.lock_state();
+        state.blocks.clear();
+        state.usage.clear();
+        state.last_missed_block = None;
+    }
+
+    pub fn into_inner(self) -> T {
+        self.source
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        // the state is always coherent between two instructions, so a poisoned lock can still be used
+        match self.state.lock() {
+            Ok(value) => value,
+            Err(err) => err.into_inner(),
+        }
+    }
+
+    /// Copy the cached block `block_nb` from `in_block_offset` to `buf`. Return `None` if it isn't cached.
+    fn read_cached(
+        &self,
+        block_nb: u64,
+        in_block_offset: usize,
+        buf: &mut [u8],
+        count_hit: bool,
+    ) -> Option<usize> {
+        let mut state = self.lock_state();
+        let lenght = match state.blocks.get(&block_nb) {
+            Some((data, _)) => {
+                let available = data.get(in_block_offset..).unwrap_or_default();
+                let lenght = std::cmp::min(available.len(), buf.len());
+                buf[..lenght].copy_from_slice(&available[..lenght]);
+                lenght
+            }
+            None => return None,
+        };
+        state.touch(block_nb);
+        if count_hit {
+            state.stats.hits += 1;
+        };
+        Some(lenght)
+    }
+
+    /// Read the block `block_nb` from the source, with the following blocks if the access is sequential, and cache them
+    fn load_block(&self, block_nb: u64) -> io::Result<()> {
+        let sequential = {
+            let mut state = self.lock_state();
+            let sequential =
+                state.last_missed_block.and_then(|last| last.checked_add(1)) == Some(block_nb);
+            state.last_missed_block = Some(block_nb);
+            sequential
+        };
+        let blocks_to_read = if sequential {
+            1 + self.config.read_ahead.min(self.config.capacity - 1)
+        } else {
+            1
+        };
+        let block_size = self.config.block_size as usize;
+        let offset = match block_nb.checked_mul(self.config.block_size) {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        let mut data = vec![0; block_size * blocks_to_read];
+        let read = read_up_to_at(&self.source, offset, &mut data)?;
+        data.truncate(read);
+
+        let mut state = self.lock_state();
+        state.stats.misses += 1;
+        state.stats.source_bytes_read += read as u64;
+        // always insert the requested block, even if empty, so the end of the source is cached too
+        let mut chunks = data.chunks(block_size);
+        let first_chunk = chunks.next().unwrap_or_default().to_vec();
+        let mut last_block_nb = block_nb;
+        for chunk in chunks {
+            last_block_nb += 1;
+            if !state.blocks.contains_key(&last_block_nb) {
+                state.insert(last_block_nb, chunk.to_vec(), self.config.capacity);
+                state.stats.read_ahead_blocks += 1;
+            };
+        }
+        state.last_missed_block = Some(last_block_nb);
+        // inserted last so it isn't evicted by its own read-ahead
+        state.insert(block_nb, first_chunk, self.config.capacity);
+        Ok(())
+    }
+}
+
+impl<T: ReadAt> ReadAt for BlockCache<T> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let block_size = self.config.block_size;
+        let mut total_read = 0;
+        while total_read < buf.len() {
+            let actual_offset = match offset.checked_add(total_read as u64) {
+                Some(value) => value,
+                None => break,
+            };
+            let block_nb = actual_offset / block_size;
+            let in_block_offset = (actual_offset % block_size) as usize;
+            let read =
+                match self.read_cached(block_nb, in_block_offset, &mut buf[total_read..], true) {
+                    Some(value) => value,
+                    None => {
+                        self.load_block(block_nb)?;
+                        match self.read_cached(
+                            block_nb,
+                            in_block_offset,
+                            &mut buf[total_read..],
+                            false,
+                        ) {
+                            Some(value) => value,
+                            // the block was evicted by another thread: read directly from the source
+                            None => self.source.read_at(actual_offset, &mut buf[total_read..])?,
+                        }
+                    }
+                };
+            total_read += read;
+            // a short block mean the end of the source
+            if (in_block_offset + read) as u64 != block_size {
+                break;
+            };
+        }
+        Ok(total_read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content() -> Vec<u8> {
+        (0..100).collect()
+    }
+
+    #[test]
+    fn test_block_cache_read() {
+        let cache = BlockCache::new(
+            content(),
+            BlockCacheConfig {
+                block_size: 16,
+                capacity: 4,
+                read_ahead: 0,
+            },
+        );
+        let mut buf = [0; 20];
+        assert_eq!(cache.read_at(10, &mut buf).unwrap(), 20);
+        assert_eq!(buf[0], 10);
+        assert_eq!(buf[19], 29);
+        assert_eq!(cache.read_at(12, &mut buf[..4]).unwrap(), 4);
+        assert_eq!(buf[..4], [12, 13, 14, 15]);
+        assert_eq!(cache.read_at(90, &mut buf).unwrap(), 10);
+        assert_eq!(buf[..10], [90, 91, 92, 93, 94, 95, 96, 97, 98, 99]);
+        assert_eq!(cache.read_at(100, &mut buf).unwrap(), 0);
+        assert_eq!(cache.read_at(200, &mut buf).unwrap(), 0);
+        let stats = cache.stats();
+        assert_eq!(stats.misses, 5);
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.read_ahead_blocks, 0);
+    }
+
+    #[test]
+    fn test_block_cache_eviction() {
+        let cache = BlockCache::new(
+            content(),
+            BlockCacheConfig {
+                block_size: 10,
+                capacity: 2,
+                read_ahead: 0,
+            },
+        );
+        let mut buf = [0; 1];
+        cache.read_at(0, &mut buf).unwrap();
+        cache.read_at(50, &mut buf).unwrap();
+        cache.read_at(0, &mut buf).unwrap();
+        cache.read_at(80, &mut buf).unwrap(); // evict the block 5
+        cache.read_at(0, &mut buf).unwrap();
+        assert_eq!(cache.stats().misses, 3);
+        cache.read_at(50, &mut buf).unwrap();
+        assert_eq!(buf[0], 50);
+        assert_eq!(cache.stats().misses, 4);
+    }
+
+    #[test]
+    fn test_block_cache_read_ahead() {
+        let cache = BlockCache::new(
+            content(),
+            BlockCacheConfig {
+                block_size: 10,
+                capacity: 16,
+                read_ahead: 3,
+            },
+        );
+        let mut buf = [0; 10];
+        let mut offset = 0;
+        loop {
+            let read = cache.read_at(offset, &mut buf).unwrap();
+            if read == 0 {
+                break;
+            };
+            assert_eq!(buf[0], offset as u8);
+            offset += read as u64;
+        }
+        assert_eq!(offset, 100);
+        let stats = cache.stats();
+        // block 0, then 1 with 2 to 4, then 5 with 6 to 8, then 9, then the empty block after the end
+        assert_eq!(stats.misses, 5);
+        assert_eq!(stats.read_ahead_blocks, 6);
+        assert_eq!(stats.source_bytes_read, 100);
+    }
+}
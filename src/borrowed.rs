@@ -0,0 +1,205 @@
+use crate::{
+    DirectoryOrFile, FileMetadata, IVFCError, IVFCReader, NCCHError, NCCHReader, NCSDError,
+    NCSDReader,
+};
+use std::io;
+use std::io::Cursor;
+
+/// Return the `lenght` bytes of `data` starting at `offset`, or `None` if they aren't all in `data`
+fn sub_slice(data: &[u8], offset: u64, lenght: u64) -> Option<&[u8]> {
+    let end = offset.checked_add(lenght)?;
+    if end > data.len() as u64 {
+        return None;
+    };
+    Some(&data[offset as usize..end as usize])
+}
+
+/// A NCSD (.3ds) file borrowed from memory. Partitions are sub-slices of the input.
+#[derive(Debug, Clone)]
+pub struct NCSD<'a> {
+    pub size: u64,
+    pub media_id: u64,
+    pub partition_type: u64,
+    pub partitions_id: Vec<[u8; 8]>,
+    pub partition_crypt_type: [u8; 8],
+    partitions: Vec<Option<&'a [u8]>>,
+}
+
+impl<'a> NCSD<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<NCSD<'a>, NCSDError> {
+        let reader = NCSDReader::new(Cursor::new(data))?;
+        let mut partitions = Vec::new();
+        for partition_nb in 0..8 {
+            let (offset, lenght) = match reader.get_partition_bounds(partition_nb) {
+                Ok(value) => value,
+                Err(NCSDError::InexistingPartition(_)) => {
+                    partitions.push(None);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            match sub_slice(data, offset, lenght) {
+                Some(partition) => partitions.push(Some(partition)),
+                None => return Err(NCSDError::TruncatedFile(data.len() as u64)),
+            };
+        }
+        Ok(NCSD {
+            size: reader.size,
+            media_id: reader.media_id,
+            partition_type: reader.partition_type,
+            partitions_id: reader.partitions_id.clone(),
+            partition_crypt_type: reader.partition_crypt_type,
+            partitions,
+        })
+    }
+
+    /// Return the content of a partition
+    pub fn partition(&self, partition_nb: usize) -> Result<&'a [u8], NCSDError> {
+        match self.partitions.get(partition_nb) {
+            Some(Some(partition)) => Ok(partition),
+            _ => Err(NCSDError::InexistingPartition(partition_nb)),
+        }
+    }
+}
+
+/// A NCCH partition borrowed from memory. Regions are sub-slices of the input.
+#[derive(Debug, Clone)]
+pub struct NCCH<'a> {
+    pub content_size: u64,
+    pub version: u16,
+    pub plain_region: &'a [u8],
+    pub logo_region: &'a [u8],
+    pub exefs: &'a [u8],
+    pub romfs: &'a [u8],
+}
+
+impl<'a> NCCH<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<NCCH<'a>, NCCHError> {
+        let reader = NCCHReader::new(Cursor::new(data))?;
+        let region = |(offset, lenght)| match sub_slice(data, offset, lenght) {
+            Some(value) => Ok(value),
+            None => Err(NCCHError::CreatePartitionError(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the region end after the end of the ncch",
+            ))),
+        };
+        Ok(NCCH {
+            content_size: reader.content_size,
+            version: reader.version,
+            plain_region: region(reader.get_plain_region_bounds())?,
+            logo_region: region(reader.get_logo_region_bounds())?,
+            exefs: region(reader.get_exefs_bounds())?,
+            romfs: region(reader.get_romfs_bounds())?,
+        })
+    }
+}
+
+/// A romfs (IVFC) borrowed from memory. File contents are returned as sub-slices of the input.
+#[derive(Debug)]
+pub struct RomFS<'a> {
+    data: &'a [u8],
+    reader: IVFCReader<&'a [u8]>,
+}
+
+impl<'a> RomFS<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<RomFS<'a>, IVFCError> {
+        Ok(RomFS {
+            data,
+            reader: IVFCReader::from_read_at(data)?,
+        })
+    }
+
+    /// The underlying reader, to list directories or walk the files
+    pub fn reader(&self) -> &IVFCReader<&'a [u8]> {
+        &self.reader
+    }
+
+    /// Return the metadata of the file or directory at `path` (like "a/b.bin"). The empty path is the root directory.
+    pub fn get_metadata(&self, path: &str) -> Result<DirectoryOrFile, IVFCError> {
+        self.reader.get_path_metadata(path)
+    }
+
+    /// Return the content of the file at `path`, without copying it
+    pub fn get_file(&self, path: &str) -> Result<&'a [u8], IVFCError> {
+        match self.get_metadata(path)? {
+            DirectoryOrFile::File(file) => self.file_data(&file),
+            DirectoryOrFile::Dir(_) => Err(IVFCError::FileNotFound),
+        }
+    }
+
+    /// Return the content of a file from this romfs, without copying it
+    pub fn file_data(&self, file: &FileMetadata) -> Result<&'a [u8], IVFCError> {
+        match sub_slice(
+            self.data,
+            self.reader.get_file_real_offset(file),
+            file.lenght_file_data,
+        ) {
+            Some(value) => Ok(value),
+            None => Err(IVFCError::ReadError(
+                io::Error::from(io::ErrorKind::UnexpectedEof),
+                "the data of a file",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IVFCWriter, IVFCWriterSource, NCCHWriter, NCSDWriter};
+
+    /// A NCSD with a NCCH in the partition 0, whose romfs contain "a.bin" and "dir/b.bin"
+    fn ncsd_with_romfs() -> Vec<u8> {
+        let mut romfs = IVFCWriter::new();
+        romfs.add_file("a.bin", IVFCWriterSource::Memory(vec![1, 2, 3]));
+        romfs.add_file("dir/b.bin", IVFCWriterSource::Memory(vec![4; 0x300]));
+        let mut ncch = NCCHWriter::new([0; 0x200]);
+        ncch.logo_region = vec![5; 0x10];
+        ncch.romfs = Some(romfs);
+        let mut ncsd = NCSDWriter::new(vec![0; 0x4000]);
+        ncsd.partitions.push((0, ncch));
+        let mut output = Cursor::new(Vec::new());
+        ncsd.write(&mut output).unwrap();
+        output.into_inner()
+    }
+
+    #[test]
+    fn test_borrowed_romfs() {
+        let data = ncsd_with_romfs();
+        let ncsd = NCSD::parse(&data).unwrap();
+        assert!(matches!(
+            ncsd.partition(1),
+            Err(NCSDError::InexistingPartition(1))
+        ));
+        let partition = ncsd.partition(0).unwrap();
+        assert_eq!(partition.as_ptr(), data[0x4000..].as_ptr());
+
+        let ncch = NCCH::parse(partition).unwrap();
+        assert_eq!(&ncch.logo_region[..0x10], &[5; 0x10]);
+        assert!(ncch.exefs.is_empty());
+
+        let romfs = RomFS::parse(ncch.romfs).unwrap();
+        assert_eq!(romfs.get_file("a.bin").unwrap(), &[1, 2, 3]);
+        let b = romfs.get_file("dir/b.bin").unwrap();
+        assert_eq!(b, &[4; 0x300][..]);
+        // borrowed from the input, not copied
+        let data_range = data.as_ptr_range();
+        assert!(data_range.contains(&b.as_ptr()));
+        assert!(matches!(
+            romfs.get_file("dir"),
+            Err(IVFCError::FileNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_borrowed_truncated() {
+        let data = ncsd_with_romfs();
+        assert!(matches!(
+            NCSD::parse(&data[..data.len() - 0x200]),
+            Err(NCSDError::PartitionPastFileEnd(0))
+        ));
+        let ncsd = NCSD::parse(&data).unwrap();
+        let partition = ncsd.partition(0).unwrap();
+        assert!(NCCH::parse(&partition[..0x100]).is_err());
+    }
+}
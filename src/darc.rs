@@ -0,0 +1,500 @@
+use crate::{IVFCMeta, PartitionMutex};
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::SeekFrom;
+use std::io::{Read, Seek};
+use std::path::{Component, PathBuf};
+use std::string::FromUtf16Error;
+use std::sync::{Arc, Mutex};
+
+use vfs::{OpenOptions, VFile, VMetadata, VPath, VFS};
+
+#[derive(Debug)]
+pub enum DARCError {
+    ReadError(io::Error, &'static str),
+    SeekError(io::Error, &'static str),
+    InvalidMagic([u8; 4]), // the invalid magic
+    BigEndian,
+    InvalidEntryCount(u32),
+    ToUTF16Error(FromUtf16Error),
+}
+
+impl Error for DARCError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::ReadError(err, _) => Some(err),
+            Self::SeekError(err, _) => Some(err),
+            Self::ToUTF16Error(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DARCError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadError(_, what) => write!(
+                f,
+                "failed to read the {} due to an error in the source input",
+                what
+            ),
+            Self::SeekError(_, what) => write!(
+                f,
+                "failed to seek to the {} due to an error in the source input",
+                what
+            ),
+            Self::InvalidMagic(magic) => write!(
+                f,
+                "the magic of the darc is invalid. Found {:?}, expected [100, 97, 114, 99].",
+                magic
+            ),
+            Self::BigEndian => write!(f, "big endian darc are not supported"),
+            Self::InvalidEntryCount(count) => write!(
+                f,
+                "the darc file table contain an invalid number of entry ({})",
+                count
+            ),
+            Self::ToUTF16Error(_) => write!(
+                f,
+                "Impossible to convert a darc entry name to an UTF16 String"
+            ),
+        }
+    }
+}
+
+/// An entry of the file table of a DARC
+#[derive(Debug, Clone)]
+pub enum DARCEntry {
+    Dir {
+        name: String,
+        parent: u32,
+        /// The index of the first entry that isn't in this directory
+        next: u32,
+    },
+    File {
+        name: String,
+        /// The offset of the data, relative to the start of the archive
+        offset: u32,
+        lenght: u32,
+    },
+}
+
+impl DARCEntry {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Dir { name, .. } => name,
+            Self::File { name, .. } => name,
+        }
+    }
+}
+
+fn read_u32<T: Read>(file: &mut T, what: &'static str) -> Result<u32, DARCError> {
+    let mut buffer = [0; 4];
+    match file.read_exact(&mut buffer) {
+        Ok(_) => (),
+        Err(err) => return Err(DARCError::ReadError(err, what)),
+    };
+    Ok(u32::from_le_bytes(buffer))
+}
+
+/// Read a DARC archive, as found in the logo region and in the `.arc` files of some romfs.
+#[derive(Debug)]
+pub struct DARCReader<T: Read + Seek> {
+    pub file: Arc<Mutex<T>>,
+    pub entries: Vec<DARCEntry>,
+}
+
+impl<T: Read + Seek> DARCReader<T> {
+    pub fn new(mut file: T) -> Result<DARCReader<T>, DARCError> {
+        match file.seek(SeekFrom::Start(0)) {
+            Ok(_) => (),
+            Err(err) => return Err(DARCError::SeekError(err, "darc header")),
+        };
+
+        let mut magic = [0; 4];
+        match file.read_exact(&mut magic) {
+            Ok(_) => (),
+            Err(err) => return Err(DARCError::ReadError(err, "darc magic")),
+        };
+        if &magic != b"darc" {
+            return Err(DARCError::InvalidMagic(magic));
+        };
+
+        let mut byte_order_mark = [0; 2];
+        match file.read_exact(&mut byte_order_mark) {
+            Ok(_) => (),
+            Err(err) => return Err(DARCError::ReadError(err, "byte order mark")),
+        };
+        if u16::from_le_bytes(byte_order_mark) != 0xFEFF {
+            return Err(DARCError::BigEndian);
+        };
+
+        match file.seek(SeekFrom::Start(0x10)) {
+            Ok(_) => (),
+            Err(err) => return Err(DARCError::SeekError(err, "file table informations")),
+        };
+        let file_table_offset = read_u32(&mut file, "file table offset")?;
+        let file_table_lenght = read_u32(&mut file, "file table lenght")?;
+
+        match file.seek(SeekFrom::Start(file_table_offset as u64)) {
+            Ok(_) => (),
+            Err(err) => return Err(DARCError::SeekError(err, "file table")),
+        };
+        // read through `take`, so a lenght bigger than the input doesn't allocate it all upfront
+        let mut file_table = Vec::new();
+        match file
+            .by_ref()
+            .take(file_table_lenght as u64)
+            .read_to_end(&mut file_table)
+        {
+            Ok(read) if read == file_table_lenght as usize => (),
+            Ok(_) => {
+                return Err(DARCError::ReadError(
+                    io::Error::from(io::ErrorKind::UnexpectedEof),
+                    "file table",
+                ))
+            }
+            Err(err) => return Err(DARCError::ReadError(err, "file table")),
+        };
+
+        let get_u32 = |offset: usize| {
+            u32::from_le_bytes([
+                file_table[offset],
+                file_table[offset + 1],
+                file_table[offset + 2],
+                file_table[offset + 3],
+            ])
+        };
+
+        // the "next" field of the root directory is the number of entries
+        if file_table.len() < 12 {
+            return Err(DARCError::InvalidEntryCount(0));
+        };
+        let entry_count = get_u32(8);
+        if entry_count == 0 || entry_count as usize * 12 > file_table.len() {
+            return Err(DARCError::InvalidEntryCount(entry_count));
+        };
+        let name_table = &file_table[entry_count as usize * 12..];
+
+        let mut entries = Vec::new();
+        for entry_nb in 0..entry_count as usize {
+            let name_field = get_u32(entry_nb * 12);
+            let is_dir = name_field & 0x0100_0000 != 0;
+            let name_offset = (name_field & 0x00FF_FFFF) as usize;
+            let name_numbered: Vec<u16> = name_table
+                .get(name_offset..)
+                .unwrap_or(&[])
+                .chunks_exact(2)
+                .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+                .take_while(|character| *character != 0)
+                .collect();
+            let name = match String::from_utf16(&name_numbered) {
+                Ok(value) => value,
+                Err(err) => return Err(DARCError::ToUTF16Error(err)),
+            };
+            let field_1 = get_u32(entry_nb * 12 + 4);
+            let field_2 = get_u32(entry_nb * 12 + 8);
+            entries.push(if is_dir {
+                DARCEntry::Dir {
+                    name,
+                    parent: field_1,
+                    next: field_2,
+                }
+            } else {
+                DARCEntry::File {
+                    name,
+                    offset: field_1,
+                    lenght: field_2,
+                }
+            });
+        }
+
+        Ok(DARCReader {
+            file: Arc::new(Mutex::new(file)),
+            entries,
+        })
+    }
+
+    /// Return the indexes of the entries directly in the directory at `dir_index`.
+    ///
+    /// The content of "." directories are listed as if they were in their parent.
+    pub fn list_child(&self, dir_index: usize) -> Vec<usize> {
+        let mut result = Vec::new();
+        let end = match self.entries.get(dir_index) {
+            Some(DARCEntry::Dir { next, .. }) => *next as usize,
+            _ => return result,
+        };
+        let mut entry_index = dir_index + 1;
+        while entry_index < end && entry_index < self.entries.len() {
+            match &self.entries[entry_index] {
+                DARCEntry::Dir { name, next, .. } => {
+                    if name == "." {
+                        result.extend(self.list_child(entry_index));
+                    } else {
+                        result.push(entry_index);
+                    };
+                    entry_index = std::cmp::max(*next as usize, entry_index + 1);
+                }
+                DARCEntry::File { .. } => {
+                    result.push(entry_index);
+                    entry_index += 1;
+                }
+            }
+        }
+        result
+    }
+
+    /// Return the index of the entry at `path` (like "blyt/file.bclyt"), or `None` if it doesn't exist
+    pub fn get_entry_index(&self, path: &str) -> Option<usize> {
+        let mut actual_index = 0;
+        for component in PathBuf::from(path).components() {
+            let part = match component {
+                Component::Normal(part) => part.to_string_lossy(),
+                _ => continue,
+            };
+            actual_index = self
+                .list_child(actual_index)
+                .into_iter()
+                .find(|child_index| self.entries[*child_index].name() == part)?;
+        }
+        Some(actual_index)
+    }
+
+    /// Return the offset and the lenght in the archive of the file at `entry_index`
+    pub fn get_file_position(&self, entry_index: usize) -> io::Result<(u64, u64)> {
+        match self.entries.get(entry_index) {
+            Some(DARCEntry::File { offset, lenght, .. }) => Ok((*offset as u64, *lenght as u64)),
+            Some(DARCEntry::Dir { .. }) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "trying to open a directory",
+            )),
+            None => Err(io::Error::from(io::ErrorKind::NotFound)),
+        }
+    }
+
+    /// Open the file at `entry_index`
+    pub fn open_entry(&self, entry_index: usize) -> io::Result<PartitionMutex<T>> {
+        let (offset, lenght) = self.get_file_position(entry_index)?;
+        PartitionMutex::new(self.file.clone(), offset, lenght)
+    }
+}
+
+/// A read only VFS over a DARC archive
+pub struct DARCVFS<T: 'static + Read + Seek + Send + Sync + fmt::Debug> {
+    reader: Arc<DARCReader<T>>,
+}
+
+impl<T: 'static + Read + Seek + Send + Sync + fmt::Debug> DARCVFS<T> {
+    pub fn new(reader: DARCReader<T>) -> DARCVFS<T> {
+        DARCVFS {
+            reader: Arc::new(reader),
+        }
+    }
+
+    pub fn get_reader(&self) -> &DARCReader<T> {
+        &self.reader
+    }
+}
+
+impl<T: 'static + Read + Seek + Send + Sync + fmt::Debug> VFS for DARCVFS<T> {
+    type PATH = DARCVPATH<T>;
+    type METADATA = IVFCMeta;
+    type FILE = PartitionMutex<T>;
+
+    fn path<A: Into<String>>(&self, path: A) -> Self::PATH {
+        DARCVPATH {
+            reader: self.reader.clone(),
+            path: PathBuf::from(path.into()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DARCVPATH<T: Read + Seek + Send + Sync + fmt::Debug> {
+    reader: Arc<DARCReader<T>>,
+    path: PathBuf,
+}
+
+impl<T: Read + Seek + Send + Sync + fmt::Debug> Clone for DARCVPATH<T> {
+    fn clone(&self) -> DARCVPATH<T> {
+        DARCVPATH {
+            reader: self.reader.clone(),
+            path: self.path.clone(),
+        }
+    }
+}
+
+impl<T: 'static + Read + Seek + Send + Sync + fmt::Debug> DARCVPATH<T> {
+    fn get_entry_index(&self) -> io::Result<usize> {
+        match self.reader.get_entry_index(&self.path.to_string_lossy()) {
+            Some(value) => Ok(value),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "the file doesn't exist in the darc",
+            )),
+        }
+    }
+
+    fn with_path(&self, path: PathBuf) -> DARCVPATH<T> {
+        DARCVPATH {
+            reader: self.reader.clone(),
+            path,
+        }
+    }
+}
+
+fn return_ro_error<T>() -> io::Result<T> {
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "read only file system",
+    ))
+}
+
+impl<T: 'static + Read + Seek + Send + Sync + fmt::Debug> VPath for DARCVPATH<T> {
+    fn open_with_options(&self, opt: &OpenOptions) -> io::Result<Box<dyn VFile>> {
+        if opt.write || opt.create || opt.append || opt.truncate {
+            return return_ro_error();
+        };
+        Ok(Box::new(self.reader.open_entry(self.get_entry_index()?)?))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn read_dir(&self) -> io::Result<Box<dyn Iterator<Item = io::Result<Box<dyn VPath>>>>> {
+        let entry_index = self.get_entry_index()?;
+        if let DARCEntry::File { .. } = self.reader.entries[entry_index] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "trying to list content for a file",
+            ));
+        };
+        let childs: Vec<String> = self
+            .reader
+            .list_child(entry_index)
+            .into_iter()
+            .map(|child_index| self.reader.entries[child_index].name().to_string())
+            .collect();
+        let this = self.clone();
+        Ok(Box::new(
+            childs.into_iter().map(move |name| Ok(this.resolve(&name))),
+        ))
+    }
+
+    fn mkdir(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn rm(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn rmrf(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn file_name(&self) -> Option<String> {
+        self.path.file_name()
+    }
+
+    fn extension(&self) -> Option<String> {
+        self.path.extension()
+    }
+
+    fn resolve(&self, path: &String) -> Box<dyn VPath> {
+        let mut new_path = self.path.clone();
+        new_path.push(path);
+        Box::new(self.with_path(new_path))
+    }
+
+    fn parent(&self) -> Option<Box<dyn VPath>> {
+        let mut new_path = self.path.clone();
+        if !new_path.pop() {
+            return None;
+        };
+        Some(Box::new(self.with_path(new_path)))
+    }
+
+    fn to_string(&self) -> Cow<'_, str> {
+        format!("darc://{:?}", self.path).into()
+    }
+
+    fn box_clone(&self) -> Box<dyn VPath> {
+        Box::new(self.clone())
+    }
+
+    fn to_path_buf(&self) -> Option<PathBuf> {
+        Some(self.path.clone())
+    }
+
+    fn exists(&self) -> bool {
+        self.get_entry_index().is_ok()
+    }
+
+    fn metadata(&self) -> io::Result<Box<dyn VMetadata>> {
+        Ok(Box::new(
+            match self.reader.entries[self.get_entry_index()?] {
+                DARCEntry::Dir { .. } => IVFCMeta::Dir,
+                DARCEntry::File { lenght, .. } => IVFCMeta::File(lenght as u64),
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A darc with these files in its root directory
+    pub(crate) fn darc_from_files(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let entry_count = files.len() + 1;
+        let mut entries = Vec::new();
+        let mut names = vec![0, 0];
+        let mut data = Vec::new();
+        let mut file_entries = Vec::new();
+        for (name, content) in files {
+            file_entries.push((names.len() as u32, data.len() as u32, content.len() as u32));
+            for character in name.encode_utf16().chain(std::iter::once(0)) {
+                names.extend_from_slice(&character.to_le_bytes());
+            }
+            data.extend_from_slice(content);
+        }
+        let table_lenght = entry_count * 12 + names.len();
+        let data_offset = (0x1C + table_lenght).div_ceil(4) * 4;
+        for value in &[0x0100_0000u32, 0, entry_count as u32] {
+            entries.extend_from_slice(&value.to_le_bytes());
+        }
+        for (name_offset, offset, lenght) in file_entries {
+            entries.extend_from_slice(&name_offset.to_le_bytes());
+            entries.extend_from_slice(&(data_offset as u32 + offset).to_le_bytes());
+            entries.extend_from_slice(&lenght.to_le_bytes());
+        }
+
+        let mut darc = b"darc".to_vec();
+        darc.extend_from_slice(&0xFEFFu16.to_le_bytes());
+        darc.extend_from_slice(&0x1Cu16.to_le_bytes());
+        darc.extend_from_slice(&0x0100_0000u32.to_le_bytes());
+        darc.extend_from_slice(&((data_offset + data.len()) as u32).to_le_bytes());
+        darc.extend_from_slice(&0x1Cu32.to_le_bytes());
+        darc.extend_from_slice(&(table_lenght as u32).to_le_bytes());
+        darc.extend_from_slice(&(data_offset as u32).to_le_bytes());
+        darc.extend_from_slice(&entries);
+        darc.extend_from_slice(&names);
+        darc.resize(data_offset, 0);
+        darc.extend_from_slice(&data);
+        darc
+    }
+
+    #[test]
+    fn test_file_table_past_the_end() {
+        let mut darc = darc_from_files(&[("a.bin", b"a")]);
+        let reader = DARCReader::new(Cursor::new(darc.clone())).unwrap();
+        assert_eq!(reader.get_entry_index("a.bin"), Some(1));
+        darc[0x14..0x18].copy_from_slice(&0x7FFF_FFFFu32.to_le_bytes());
+        assert!(matches!(
+            DARCReader::new(Cursor::new(darc)),
+            Err(DARCError::ReadError(_, "file table"))
+        ));
+    }
+}
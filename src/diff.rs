@@ -0,0 +1,166 @@
+use crate::ivfc::FileMetadata;
+use crate::{IVFCError, IVFCReader, ReadAt, ReadAtPartition};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+
+#[derive(Debug)]
+pub enum RomFSDiffError {
+    OldRomFSError(IVFCError),
+    NewRomFSError(IVFCError),
+}
+
+impl Error for RomFSDiffError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::OldRomFSError(err) => Some(err),
+            Self::NewRomFSError(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for RomFSDiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OldRomFSError(_) => write!(f, "error while reading the old romfs"),
+            Self::NewRomFSError(_) => write!(f, "error while reading the new romfs"),
+        }
+    }
+}
+
+/// The list of the files that differ between two romfs. Paths are relative to the root, like "a/b.bin", and sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RomFSDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+impl RomFSDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Return the SHA-256 of the content of a file of the romfs.
+pub fn hash_romfs_file<T: ReadAt>(
+    reader: &IVFCReader<T>,
+    file: &FileMetadata,
+) -> Result<[u8; 32], IVFCError> {
+    let mut partition = match ReadAtPartition::new(
+        reader.file.clone(),
+        reader.get_file_real_offset(file),
+        file.lenght_file_data,
+    ) {
+        Ok(value) => value,
+        Err(err) => return Err(IVFCError::SeekError(err, "a file to hash")),
+    };
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 0x10000];
+    let mut remaining = file.lenght_file_data;
+    while remaining > 0 {
+        let to_read = std::cmp::min(buffer.len() as u64, remaining) as usize;
+        match partition.read_exact(&mut buffer[..to_read]) {
+            Ok(_) => (),
+            Err(err) => return Err(IVFCError::ReadError(err, "a file to hash")),
+        };
+        hasher.update(&buffer[..to_read]);
+        remaining -= to_read as u64;
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// Compare two romfs, without extracting them.
+///
+/// Files are first compared by lenght, then by the SHA-256 of their content.
+pub fn diff_romfs<A: ReadAt, B: ReadAt>(
+    old: &IVFCReader<A>,
+    new: &IVFCReader<B>,
+) -> Result<RomFSDiff, RomFSDiffError> {
+    let old_files: BTreeMap<String, FileMetadata> = match old.walk_files() {
+        Ok(value) => value.into_iter().collect(),
+        Err(err) => return Err(RomFSDiffError::OldRomFSError(err)),
+    };
+    let new_files: BTreeMap<String, FileMetadata> = match new.walk_files() {
+        Ok(value) => value.into_iter().collect(),
+        Err(err) => return Err(RomFSDiffError::NewRomFSError(err)),
+    };
+
+    let mut result = RomFSDiff::default();
+
+    for (path, old_file) in &old_files {
+        let new_file = match new_files.get(path) {
+            Some(value) => value,
+            None => {
+                result.removed.push(path.clone());
+                continue;
+            }
+        };
+        if old_file.lenght_file_data != new_file.lenght_file_data {
+            result.modified.push(path.clone());
+            continue;
+        };
+        let old_hash = match hash_romfs_file(old, old_file) {
+            Ok(value) => value,
+            Err(err) => return Err(RomFSDiffError::OldRomFSError(err)),
+        };
+        let new_hash = match hash_romfs_file(new, new_file) {
+            Ok(value) => value,
+            Err(err) => return Err(RomFSDiffError::NewRomFSError(err)),
+        };
+        if old_hash != new_hash {
+            result.modified.push(path.clone());
+        };
+    }
+
+    for path in new_files.keys() {
+        if !old_files.contains_key(path) {
+            result.added.push(path.clone());
+        };
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ivfc::{romfs_from_files, DirectoryOrFile};
+
+    #[test]
+    fn test_diff_romfs() {
+        let old = romfs_from_files(&[
+            ("same.bin", b"same"),
+            ("a/changed.bin", b"old content"),
+            ("a/same_lenght.bin", b"1234"),
+            ("removed.bin", b"removed"),
+        ]);
+        let new = romfs_from_files(&[
+            ("same.bin", b"same"),
+            ("a/changed.bin", b"new"),
+            ("a/same_lenght.bin", b"4321"),
+            ("b/added.bin", b"added"),
+        ]);
+        let old = IVFCReader::from_read_at(old).unwrap();
+        let new = IVFCReader::from_read_at(new).unwrap();
+
+        let diff = diff_romfs(&old, &new).unwrap();
+        assert_eq!(diff.added, ["b/added.bin"]);
+        assert_eq!(diff.removed, ["removed.bin"]);
+        assert_eq!(diff.modified, ["a/changed.bin", "a/same_lenght.bin"]);
+        assert!(diff_romfs(&old, &old).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_hash_romfs_file() {
+        let reader = IVFCReader::from_read_at(romfs_from_files(&[("a.bin", b"abc")])).unwrap();
+        let file = match reader.get_path_metadata("a.bin").unwrap() {
+            DirectoryOrFile::File(file) => file,
+            DirectoryOrFile::Dir(_) => panic!("a.bin is a file"),
+        };
+        let expected: [u8; 32] = Sha256::digest(b"abc").into();
+        assert_eq!(hash_romfs_file(&reader, &file).unwrap(), expected);
+    }
+}
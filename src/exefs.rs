@@ -0,0 +1,231 @@
+use crate::PartitionMutex;
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::SeekFrom;
+use std::io::{Read, Seek};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+pub enum ExeFSError {
+    ReadError(io::Error, &'static str),
+    SeekError(io::Error, &'static str),
+    FileNotFound(String), // the name of the file
+    CreatePartitionError(io::Error),
+    TooManyFiles(usize), // the number of files
+    InvalidName(String), // the invalid name
+    FileTooBig(String),  // the name of the file
+}
+
+impl Error for ExeFSError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::ReadError(err, _) => Some(err),
+            Self::SeekError(err, _) => Some(err),
+            Self::CreatePartitionError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ExeFSError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadError(_, what) => write!(
+                f,
+                "failed to read the {} due to an error in the source input",
+                what
+            ),
+            Self::SeekError(_, what) => write!(
+                f,
+                "failed to seek to the {} due to an error in the source input",
+                what
+            ),
+            Self::FileNotFound(name) => write!(f, "the file {:?} isn't in the exefs", name),
+            Self::CreatePartitionError(_) => write!(f, "failed to create a partition"),
+            Self::TooManyFiles(count) => write!(
+                f,
+                "an exefs can contain at most 10 files, but there are {}",
+                count
+            ),
+            Self::InvalidName(name) => write!(
+                f,
+                "the name {:?} is invalid in an exefs (it should be 1 to 8 ascii characters)",
+                name
+            ),
+            Self::FileTooBig(name) => write!(f, "the file {:?} is too big for an exefs", name),
+        }
+    }
+}
+
+/// The header of a file in an ExeFS
+#[derive(Debug, Clone)]
+pub struct ExeFSFileHeader {
+    pub name: String,
+    /// The offset of the file, relative to the end of the ExeFS header
+    pub offset: u32,
+    pub lenght: u32,
+    /// The SHA-256 of the content of the file
+    pub hash: [u8; 32],
+}
+
+/// Read the ExeFS of a NCCH, that contain the code, the icon, the banner and the logo of a title.
+#[derive(Debug)]
+pub struct ExeFSReader<T: Read + Seek> {
+    pub file: Arc<Mutex<T>>,
+    pub files: Vec<ExeFSFileHeader>,
+}
+
+impl<T: Read + Seek> ExeFSReader<T> {
+    pub fn new(mut file: T) -> Result<ExeFSReader<T>, ExeFSError> {
+        match file.seek(SeekFrom::Start(0)) {
+            Ok(_) => (),
+            Err(err) => return Err(ExeFSError::SeekError(err, "exefs header")),
+        };
+
+        let mut header = [0; 0x200];
+        match file.read_exact(&mut header) {
+            Ok(_) => (),
+            Err(err) => return Err(ExeFSError::ReadError(err, "exefs header")),
+        };
+
+        let mut files = Vec::new();
+        for file_nb in 0..10 {
+            let entry = &header[file_nb * 0x10..(file_nb + 1) * 0x10];
+            let name_end = entry[..8].iter().position(|c| *c == 0).unwrap_or(8);
+            if name_end == 0 {
+                continue;
+            };
+            let name = String::from_utf8_lossy(&entry[..name_end]).into_owned();
+            let offset = u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]);
+            let lenght = u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]);
+            // hashes are stored in the reverse order of the files
+            let hash_offset = 0xC0 + (9 - file_nb) * 0x20;
+            let mut hash = [0; 32];
+            hash.copy_from_slice(&header[hash_offset..hash_offset + 0x20]);
+            files.push(ExeFSFileHeader {
+                name,
+                offset,
+                lenght,
+                hash,
+            });
+        }
+
+        Ok(ExeFSReader {
+            file: Arc::new(Mutex::new(file)),
+            files,
+        })
+    }
+
+    /// Return the names of the files contained in this ExeFS
+    pub fn list_files(&self) -> Vec<String> {
+        self.files.iter().map(|file| file.name.clone()).collect()
+    }
+
+    pub fn get_file_header(&self, name: &str) -> Result<&ExeFSFileHeader, ExeFSError> {
+        match self.files.iter().find(|file| file.name == name) {
+            Some(value) => Ok(value),
+            None => Err(ExeFSError::FileNotFound(name.to_string())),
+        }
+    }
+
+    /// Return the offset of the file relative to the beggining of the ExeFS
+    pub fn get_file_real_offset(&self, file: &ExeFSFileHeader) -> u64 {
+        file.offset as u64 + 0x200
+    }
+
+    pub fn get_file(&self, name: &str) -> Result<PartitionMutex<T>, ExeFSError> {
+        let file_header = self.get_file_header(name)?;
+        match PartitionMutex::new(
+            self.file.clone(),
+            self.get_file_real_offset(file_header),
+            file_header.lenght as u64,
+        ) {
+            Ok(value) => Ok(value),
+            Err(err) => Err(ExeFSError::CreatePartitionError(err)),
+        }
+    }
+}
+
+/// Build an ExeFS
+#[derive(Debug, Clone, Default)]
+pub struct ExeFSWriter {
+    /// The name and the content of the files, in the order they will be stored
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+impl ExeFSWriter {
+    pub fn new() -> ExeFSWriter {
+        ExeFSWriter::default()
+    }
+
+    /// Add a file, replacing the file with the same name if any
+    pub fn add_file(&mut self, name: &str, data: Vec<u8>) {
+        match self.files.iter_mut().find(|file| file.0 == name) {
+            Some(file) => file.1 = data,
+            None => self.files.push((name.to_string(), data)),
+        };
+    }
+
+    /// Return the ExeFS, with every file aligned to a media unit
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ExeFSError> {
+        if self.files.len() > 10 {
+            return Err(ExeFSError::TooManyFiles(self.files.len()));
+        };
+        let mut header = vec![0; 0x200];
+        let mut data = Vec::new();
+        for (file_nb, (name, content)) in self.files.iter().enumerate() {
+            if name.is_empty() || name.len() > 8 || !name.is_ascii() || name.contains('\0') {
+                return Err(ExeFSError::InvalidName(name.clone()));
+            };
+            let (offset, lenght) = match (u32::try_from(data.len()), u32::try_from(content.len())) {
+                (Ok(offset), Ok(lenght)) => (offset, lenght),
+                _ => return Err(ExeFSError::FileTooBig(name.clone())),
+            };
+            let entry = &mut header[file_nb * 0x10..(file_nb + 1) * 0x10];
+            entry[..name.len()].copy_from_slice(name.as_bytes());
+            entry[8..12].copy_from_slice(&offset.to_le_bytes());
+            entry[12..16].copy_from_slice(&lenght.to_le_bytes());
+            // hashes are stored in the reverse order of the files
+            let hash_offset = 0xC0 + (9 - file_nb) * 0x20;
+            header[hash_offset..hash_offset + 0x20].copy_from_slice(&Sha256::digest(content));
+            data.extend_from_slice(content);
+            data.resize(data.len().div_ceil(0x200) * 0x200, 0);
+        }
+        header.extend_from_slice(&data);
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = ExeFSWriter::new();
+        writer.add_file(".code", vec![1; 0x345]);
+        writer.add_file("icon", vec![2; 0x36C0]);
+        writer.add_file("logo", Vec::new());
+        let reader = ExeFSReader::new(Cursor::new(writer.to_bytes().unwrap())).unwrap();
+        assert_eq!(reader.list_files(), [".code", "icon", "logo"]);
+        for (name, content) in &writer.files {
+            let header = reader.get_file_header(name).unwrap();
+            assert_eq!(header.offset % 0x200, 0);
+            assert_eq!(header.hash[..], Sha256::digest(content)[..]);
+            let mut data = Vec::new();
+            reader
+                .get_file(name)
+                .unwrap()
+                .read_to_end(&mut data)
+                .unwrap();
+            assert_eq!(&data, content);
+        }
+
+        writer.add_file("too long name", Vec::new());
+        assert!(matches!(writer.to_bytes(), Err(ExeFSError::InvalidName(_))));
+    }
+}
@@ -0,0 +1,494 @@
+use crate::PartitionMutex;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::SeekFrom;
+use std::io::{Read, Seek, Write};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+pub enum GARCError {
+    ReadError(io::Error, &'static str),
+    SeekError(io::Error, &'static str),
+    WriteError(io::Error),
+    InvalidMagic([u8; 4], &'static str), // the invalid magic, and the section it should be the magic of
+    UnsupportedVersion(u16),
+    TruncatedTable(&'static str),
+    TooManyEntries(usize),
+    ArchiveTooBig,
+    DataOffsetOverflow(usize), // the index of the entry
+}
+
+impl Error for GARCError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::ReadError(err, _) => Some(err),
+            Self::SeekError(err, _) => Some(err),
+            Self::WriteError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for GARCError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadError(_, what) => write!(
+                f,
+                "failed to read the {} due to an error in the source input",
+                what
+            ),
+            Self::SeekError(_, what) => write!(
+                f,
+                "failed to seek to the {} due to an error in the source input",
+                what
+            ),
+            Self::WriteError(_) => write!(f, "failed to write the garc to the output"),
+            Self::InvalidMagic(magic, section) => write!(
+                f,
+                "the magic of the {} section is invalid (found {:?})",
+                section, magic
+            ),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "the garc version {:#x} is not supported", version)
+            }
+            Self::TruncatedTable(what) => write!(f, "the {} of the garc is truncated", what),
+            Self::TooManyEntries(count) => write!(
+                f,
+                "the garc to write have {} entries, but at most 65535 are supported",
+                count
+            ),
+            Self::ArchiveTooBig => write!(f, "the garc to write is bigger than 4GiB"),
+            Self::DataOffsetOverflow(entry_nb) => write!(
+                f,
+                "the data of the entry {} of the garc start after 4GiB",
+                entry_nb
+            ),
+        }
+    }
+}
+
+/// The version of a GARC, that change the size of the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GARCVersion {
+    /// Version 0x0400, used by X/Y
+    V4,
+    /// Version 0x0600, used since Omega Ruby/Alpha Sapphire. It store the alignment of the files.
+    V6,
+}
+
+impl GARCVersion {
+    pub fn from_u16(version: u16) -> Option<GARCVersion> {
+        match version {
+            0x0400 => Some(Self::V4),
+            0x0600 => Some(Self::V6),
+            _ => None,
+        }
+    }
+
+    pub fn to_u16(self) -> u16 {
+        match self {
+            Self::V4 => 0x0400,
+            Self::V6 => 0x0600,
+        }
+    }
+
+    fn header_lenght(self) -> usize {
+        match self {
+            Self::V4 => 0x1C,
+            Self::V6 => 0x24,
+        }
+    }
+}
+
+/// A sub entry of a GARC entry, present if its bit is set in the flags of the entry
+#[derive(Debug, Clone)]
+pub struct GARCSubEntry {
+    /// The index of the bit of this sub entry in the flags of the entry
+    pub bit: u8,
+    /// The offset of the data, relative to the start of the archive
+    pub offset: u32,
+    pub lenght: u32,
+}
+
+/// An entry of the FATB table of a GARC. Most entries have a single sub entry, at bit 0.
+#[derive(Debug, Clone)]
+pub struct GARCEntry {
+    /// Bit `n` is set if there is a sub entry at index `n`
+    pub flags: u32,
+    /// The sub entries, in the order of their bits
+    pub sub_entries: Vec<GARCSubEntry>,
+}
+
+fn get_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes([
+        *data.get(offset)?,
+        *data.get(offset + 1)?,
+    ]))
+}
+
+fn get_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes([
+        *data.get(offset)?,
+        *data.get(offset + 1)?,
+        *data.get(offset + 2)?,
+        *data.get(offset + 3)?,
+    ]))
+}
+
+fn check_magic(
+    data: &[u8],
+    offset: usize,
+    magic: &[u8; 4],
+    section: &'static str,
+) -> Result<(), GARCError> {
+    match data.get(offset..offset + 4) {
+        Some(found) if found == magic => Ok(()),
+        Some(found) => Err(GARCError::InvalidMagic(
+            [found[0], found[1], found[2], found[3]],
+            section,
+        )),
+        None => Err(GARCError::TruncatedTable(section)),
+    }
+}
+
+/// Read a GARC archive, the container used by the Game Freak titles. The magics are stored reversed, like "CRAG".
+#[derive(Debug)]
+pub struct GARCReader<T: Read + Seek> {
+    pub file: Arc<Mutex<T>>,
+    pub version: GARCVersion,
+    /// The alignment of the file data. Always 4 for version 4 archives.
+    pub alignment: u32,
+    pub entries: Vec<GARCEntry>,
+}
+
+impl<T: Read + Seek> GARCReader<T> {
+    pub fn new(mut file: T) -> Result<GARCReader<T>, GARCError> {
+        match file.seek(SeekFrom::Start(0)) {
+            Ok(_) => (),
+            Err(err) => return Err(GARCError::SeekError(err, "garc header")),
+        };
+        let mut header = [0; 0x24];
+        match file.read_exact(&mut header[..0x1C]) {
+            Ok(_) => (),
+            Err(err) => return Err(GARCError::ReadError(err, "garc header")),
+        };
+        check_magic(&header, 0, b"CRAG", "garc header")?;
+        let raw_version = get_u16(&header, 0xA).unwrap_or_default();
+        let version = match GARCVersion::from_u16(raw_version) {
+            Some(value) => value,
+            None => return Err(GARCError::UnsupportedVersion(raw_version)),
+        };
+        let header_lenght = get_u32(&header, 0x4).unwrap_or_default() as usize;
+        let data_offset = get_u32(&header, 0x10).unwrap_or_default();
+        let alignment = match version {
+            GARCVersion::V4 => 4,
+            GARCVersion::V6 => {
+                match file.read_exact(&mut header[0x1C..0x24]) {
+                    Ok(_) => (),
+                    Err(err) => return Err(GARCError::ReadError(err, "garc header")),
+                };
+                get_u32(&header, 0x20).unwrap_or_default()
+            }
+        };
+
+        // the FATO, FATB and FIMB chunks are between the header and the data
+        match file.seek(SeekFrom::Start(header_lenght as u64)) {
+            Ok(_) => (),
+            Err(err) => return Err(GARCError::SeekError(err, "fato chunk")),
+        };
+        // read through `take`, so a data offset bigger than the input doesn't allocate it all upfront
+        let tables_lenght = (data_offset as u64).saturating_sub(header_lenght as u64);
+        let mut tables = Vec::new();
+        match file.by_ref().take(tables_lenght).read_to_end(&mut tables) {
+            Ok(read) if read as u64 == tables_lenght => (),
+            Ok(_) => {
+                return Err(GARCError::ReadError(
+                    io::Error::from(io::ErrorKind::UnexpectedEof),
+                    "fato and fatb chunks",
+                ))
+            }
+            Err(err) => return Err(GARCError::ReadError(err, "fato and fatb chunks")),
+        };
+
+        check_magic(&tables, 0, b"OTAF", "fato chunk")?;
+        let fato_lenght = get_u32(&tables, 0x4).unwrap_or_default() as usize;
+        let entry_count = match get_u16(&tables, 0x8) {
+            Some(value) => value as usize,
+            None => return Err(GARCError::TruncatedTable("fato chunk")),
+        };
+
+        let fatb_offset = fato_lenght;
+        check_magic(&tables, fatb_offset, b"BTAF", "fatb chunk")?;
+        let fatb_entries_offset = fatb_offset + 0xC;
+
+        let mut entries = Vec::new();
+        for entry_nb in 0..entry_count {
+            let entry_offset = match get_u32(&tables, 0xC + entry_nb * 4) {
+                Some(value) => fatb_entries_offset + value as usize,
+                None => return Err(GARCError::TruncatedTable("fato chunk")),
+            };
+            let flags = match get_u32(&tables, entry_offset) {
+                Some(value) => value,
+                None => return Err(GARCError::TruncatedTable("fatb chunk")),
+            };
+            let mut sub_entries = Vec::new();
+            let mut sub_entry_offset = entry_offset + 4;
+            for bit in 0..32 {
+                if flags & (1 << bit) == 0 {
+                    continue;
+                };
+                // each sub entry is start, end (with the padding) and lenght, relative to the data
+                let (start, lenght) = match (
+                    get_u32(&tables, sub_entry_offset),
+                    get_u32(&tables, sub_entry_offset + 8),
+                ) {
+                    (Some(start), Some(lenght)) => (start, lenght),
+                    _ => return Err(GARCError::TruncatedTable("fatb chunk")),
+                };
+                let offset = match data_offset.checked_add(start) {
+                    Some(value) => value,
+                    None => return Err(GARCError::DataOffsetOverflow(entry_nb)),
+                };
+                sub_entries.push(GARCSubEntry {
+                    bit,
+                    offset,
+                    lenght,
+                });
+                sub_entry_offset += 12;
+            }
+            entries.push(GARCEntry { flags, sub_entries });
+        }
+
+        Ok(GARCReader {
+            file: Arc::new(Mutex::new(file)),
+            version,
+            alignment,
+            entries,
+        })
+    }
+
+    /// Open the sub entry at `bit` of the entry at `entry_index`
+    pub fn open_sub_entry(&self, entry_index: usize, bit: u8) -> io::Result<PartitionMutex<T>> {
+        let sub_entry = match self.entries.get(entry_index).and_then(|entry| {
+            entry
+                .sub_entries
+                .iter()
+                .find(|sub_entry| sub_entry.bit == bit)
+        }) {
+            Some(value) => value,
+            None => return Err(io::Error::from(io::ErrorKind::NotFound)),
+        };
+        PartitionMutex::new(
+            self.file.clone(),
+            sub_entry.offset as u64,
+            sub_entry.lenght as u64,
+        )
+    }
+
+    /// Open the first sub entry of the entry at `entry_index`, that is the only one for most entries
+    pub fn open_entry(&self, entry_index: usize) -> io::Result<PartitionMutex<T>> {
+        let bit = match self
+            .entries
+            .get(entry_index)
+            .and_then(|entry| entry.sub_entries.first())
+        {
+            Some(sub_entry) => sub_entry.bit,
+            None => return Err(io::Error::from(io::ErrorKind::NotFound)),
+        };
+        self.open_sub_entry(entry_index, bit)
+    }
+}
+
+/// Build a new GARC, or a repacked version of an existing one.
+#[derive(Debug, Clone)]
+pub struct GARCWriter {
+    pub version: GARCVersion,
+    /// The alignment of the file data. Only stored in version 6 archives, version 4 always use 4.
+    pub alignment: u32,
+    /// The entries, each being the data of its sub entries by bit
+    pub entries: Vec<BTreeMap<u8, Vec<u8>>>,
+}
+
+fn align(value: usize, alignment: usize) -> usize {
+    value.div_ceil(alignment) * alignment
+}
+
+impl GARCWriter {
+    pub fn new(version: GARCVersion) -> GARCWriter {
+        GARCWriter {
+            version,
+            alignment: 4,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Load every entry of `reader`, keeping its version and alignment
+    pub fn from_reader<T: Read + Seek>(reader: &GARCReader<T>) -> io::Result<GARCWriter> {
+        let mut entries = Vec::new();
+        for (entry_index, entry) in reader.entries.iter().enumerate() {
+            let mut sub_entries = BTreeMap::new();
+            for sub_entry in &entry.sub_entries {
+                let mut data = vec![0; sub_entry.lenght as usize];
+                reader
+                    .open_sub_entry(entry_index, sub_entry.bit)?
+                    .read_exact(&mut data)?;
+                sub_entries.insert(sub_entry.bit, data);
+            }
+            entries.push(sub_entries);
+        }
+        Ok(GARCWriter {
+            version: reader.version,
+            alignment: reader.alignment,
+            entries,
+        })
+    }
+
+    /// Add an entry with a single sub entry, returning its index
+    pub fn push_entry(&mut self, data: Vec<u8>) -> usize {
+        let mut sub_entries = BTreeMap::new();
+        sub_entries.insert(0, data);
+        self.entries.push(sub_entries);
+        self.entries.len() - 1
+    }
+
+    /// Build the archive in memory
+    pub fn to_bytes(&self) -> Result<Vec<u8>, GARCError> {
+        if self.entries.len() > u16::MAX as usize {
+            return Err(GARCError::TooManyEntries(self.entries.len()));
+        };
+        let alignment = match self.version {
+            GARCVersion::V4 => 4,
+            GARCVersion::V6 => std::cmp::max(self.alignment, 1) as usize,
+        };
+
+        let mut fato = Vec::new();
+        let mut fatb_entries = Vec::new();
+        let mut data = Vec::new();
+        let mut largest_padded = 0;
+        let mut largest_unpadded = 0;
+        for entry in &self.entries {
+            fato.extend_from_slice(&(fatb_entries.len() as u32).to_le_bytes());
+            let flags = entry
+                .keys()
+                .filter(|bit| **bit < 32)
+                .fold(0u32, |flags, bit| flags | (1 << bit));
+            fatb_entries.extend_from_slice(&flags.to_le_bytes());
+            for (_, sub_entry) in entry.iter().filter(|(bit, _)| **bit < 32) {
+                let start = data.len();
+                data.extend_from_slice(sub_entry);
+                data.resize(align(data.len(), alignment), 0xFF);
+                fatb_entries.extend_from_slice(&(start as u32).to_le_bytes());
+                fatb_entries.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                fatb_entries.extend_from_slice(&(sub_entry.len() as u32).to_le_bytes());
+                largest_padded = std::cmp::max(largest_padded, data.len() - start);
+                largest_unpadded = std::cmp::max(largest_unpadded, sub_entry.len());
+            }
+        }
+
+        let header_lenght = self.version.header_lenght();
+        let fato_lenght = 0xC + fato.len();
+        let fatb_lenght = 0xC + fatb_entries.len();
+        let data_offset = header_lenght + fato_lenght + fatb_lenght + 0xC;
+        let total_lenght = data_offset + data.len();
+        if total_lenght > u32::MAX as usize {
+            return Err(GARCError::ArchiveTooBig);
+        };
+
+        let mut output = Vec::with_capacity(total_lenght);
+        output.extend_from_slice(b"CRAG");
+        output.extend_from_slice(&(header_lenght as u32).to_le_bytes());
+        output.extend_from_slice(&0xFEFFu16.to_le_bytes());
+        output.extend_from_slice(&self.version.to_u16().to_le_bytes());
+        output.extend_from_slice(&4u32.to_le_bytes()); // chunk count
+        output.extend_from_slice(&(data_offset as u32).to_le_bytes());
+        output.extend_from_slice(&(total_lenght as u32).to_le_bytes());
+        match self.version {
+            GARCVersion::V4 => {
+                output.extend_from_slice(&(largest_padded as u32).to_le_bytes());
+            }
+            GARCVersion::V6 => {
+                output.extend_from_slice(&(largest_padded as u32).to_le_bytes());
+                output.extend_from_slice(&(largest_unpadded as u32).to_le_bytes());
+                output.extend_from_slice(&(alignment as u32).to_le_bytes());
+            }
+        };
+
+        output.extend_from_slice(b"OTAF");
+        output.extend_from_slice(&(fato_lenght as u32).to_le_bytes());
+        output.extend_from_slice(&(self.entries.len() as u16).to_le_bytes());
+        output.extend_from_slice(&0xFFFFu16.to_le_bytes());
+        output.extend_from_slice(&fato);
+
+        output.extend_from_slice(b"BTAF");
+        output.extend_from_slice(&(fatb_lenght as u32).to_le_bytes());
+        output.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        output.extend_from_slice(&fatb_entries);
+
+        output.extend_from_slice(b"BMIF");
+        output.extend_from_slice(&0xCu32.to_le_bytes());
+        output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        output.extend_from_slice(&data);
+        Ok(output)
+    }
+
+    pub fn write<W: Write>(&self, output: &mut W) -> Result<(), GARCError> {
+        match output.write_all(&self.to_bytes()?) {
+            Ok(()) => Ok(()),
+            Err(err) => Err(GARCError::WriteError(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_round_trip() {
+        for version in [GARCVersion::V4, GARCVersion::V6].iter() {
+            let mut writer = GARCWriter::new(*version);
+            writer.push_entry(vec![1, 2, 3]);
+            writer.push_entry(Vec::new());
+            writer.push_entry(vec![5; 0x21]);
+            let reader = GARCReader::new(Cursor::new(writer.to_bytes().unwrap())).unwrap();
+            assert_eq!(reader.version, *version);
+            assert_eq!(reader.entries.len(), 3);
+            for (entry_index, data) in [vec![1, 2, 3], Vec::new(), vec![5; 0x21]]
+                .iter()
+                .enumerate()
+            {
+                let mut content = Vec::new();
+                reader
+                    .open_entry(entry_index)
+                    .unwrap()
+                    .read_to_end(&mut content)
+                    .unwrap();
+                assert_eq!(&content, data);
+            }
+            // and back to the same archive
+            assert_eq!(
+                GARCWriter::from_reader(&reader)
+                    .unwrap()
+                    .to_bytes()
+                    .unwrap(),
+                writer.to_bytes().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_huge_data_offset() {
+        let mut writer = GARCWriter::new(GARCVersion::V6);
+        writer.push_entry(vec![1, 2, 3]);
+        let mut garc = writer.to_bytes().unwrap();
+        garc[0x10..0x14].copy_from_slice(&u32::MAX.to_le_bytes());
+        match GARCReader::new(Cursor::new(garc)) {
+            Err(GARCError::ReadError(err, "fato and fatb chunks"))
+                if err.kind() == io::ErrorKind::UnexpectedEof => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        };
+    }
+}
@@ -1,13 +1,18 @@
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
 use std::io;
 
 use std::io::SeekFrom;
-use std::io::{Read, Seek};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
 use std::string::FromUtf16Error;
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use crate::{ReadAt, ReadAtCursor, ReadAtPartition};
+
 #[derive(Debug)]
 pub enum IVFCError {
     ReadError(io::Error, &'static str),
@@ -19,7 +24,11 @@ pub enum IVFCError {
     ToUTF16Error(FromUtf16Error, &'static str),
     DirNotFound,
     FileNotFound,
-    Poisoned,
+    WriteError(io::Error),
+    SourceError(io::Error, String), // String: the path of the file to store in the romfs
+    SourceLenghtChanged(String),    // String: the path of the file to store in the romfs
+    RomFSTooBig,
+    MetadataLoop(&'static str, u32), // what is reached twice, and its offset in its metadata table
 }
 
 impl Error for IVFCError {
@@ -28,6 +37,8 @@ impl Error for IVFCError {
             Self::ReadError(err, _) => Some(err),
             Self::SeekError(err, _) => Some(err),
             Self::ToUTF16Error(err, _) => Some(err),
+            Self::WriteError(err) => Some(err),
+            Self::SourceError(err, _) => Some(err),
             _ => None,
         }
     }
@@ -74,15 +85,28 @@ impl fmt::Display for IVFCError {
                 f,
                 "the file is not found in the hiearchy"
             ),
-            Self::Poisoned => write!(
-                f,
-                "the Mutex is poisoned"
-            ),
             Self::ToUTF16Error(_, what) => write!(
                 f,
                 "Impossible to convert \"{}\" to an UTF16 String",
                 what
-            )
+            ),
+            Self::WriteError(_) => write!(f, "failed to write the romfs to the output"),
+            Self::SourceError(_, path) => write!(
+                f,
+                "failed to read the content of the file {:?} to store in the romfs",
+                path
+            ),
+            Self::SourceLenghtChanged(path) => write!(
+                f,
+                "the lenght of the file {:?} changed while it was stored in the romfs",
+                path
+            ),
+            Self::RomFSTooBig => write!(f, "the romfs to write is too big"),
+            Self::MetadataLoop(what, offset) => write!(
+                f,
+                "the {} metadata at offset {:#x} is reached twice. The romfs is corrupted and contain a loop.",
+                what, offset
+            ),
         }
     }
 }
@@ -180,13 +204,12 @@ impl DirectoryMetadata {
             "offset of the next directory in the same hash table in a directory metadata",
         )?;
 
-        let name;
-        if !is_root {
+        let name = if !is_root {
             let name_lenght = IVFC_read_u32(file, "lenght of the name of a directory")?;
             //let physical_name_lenght = (((name_lenght as f32)/4.0).ceil()*4.0+0.01) as u32;
-            name = Some(IVFC_read_utf_16(file, name_lenght, "directory name")?);
+            Some(IVFC_read_utf_16(file, name_lenght, "directory name")?)
         } else {
-            name = None;
+            None
         };
         Ok(DirectoryMetadata {
             offset_parent,
@@ -233,17 +256,44 @@ impl FileMetadata {
     }
 }
 
+/// Remember an offset of a metadata, to stop on the loops of a corrupted romfs instead of following them forever
+fn mark_visited(
+    visited: &mut HashSet<u32>,
+    offset: u32,
+    what: &'static str,
+) -> Result<(), IVFCError> {
+    if visited.insert(offset) {
+        Ok(())
+    } else {
+        Err(IVFCError::MetadataLoop(what, offset))
+    }
+}
+
 #[derive(Debug)]
-pub struct IVFCReader<T: Read + Seek> {
-    pub file: Arc<Mutex<T>>,
+pub struct IVFCReader<T: ReadAt> {
+    pub file: Arc<T>,
     pub dir_metadata_part_offset: u32,
     pub file_metadata_part_offset: u32,
     pub first_dir_metadata: DirectoryMetadata,
     pub file_data_offset: u32,
 }
 
-impl<T: Read + Seek> IVFCReader<T> {
-    pub fn new(mut file: T) -> Result<IVFCReader<T>, IVFCError> {
+impl<T: Read + Seek> IVFCReader<Mutex<T>> {
+    /// Read a romfs from a `Read + Seek` source. Reads are serialized by a mutex: use `from_read_at` with a positional read source to allow concurrent reads.
+    pub fn new(file: T) -> Result<IVFCReader<Mutex<T>>, IVFCError> {
+        IVFCReader::from_read_at(Mutex::new(file))
+    }
+}
+
+impl<T: ReadAt> IVFCReader<T> {
+    /// Read a romfs from a positional read source, like a `std::fs::File` or a `Vec<u8>`, without any lock.
+    pub fn from_read_at(source: T) -> Result<IVFCReader<T>, IVFCError> {
+        IVFCReader::from_shared(Arc::new(source))
+    }
+
+    /// Read a romfs from a positional read source that is already shared
+    pub fn from_shared(source: Arc<T>) -> Result<IVFCReader<T>, IVFCError> {
+        let mut file = ReadAtCursor::new(&*source);
         // magic "IVFC"
         let mut magic_1 = [0; 4];
         match file.read_exact(&mut magic_1) {
@@ -315,7 +365,7 @@ impl<T: Read + Seek> IVFCReader<T> {
         let first_dir_metadata = DirectoryMetadata::new(&mut file, true)?;
 
         Ok(IVFCReader {
-            file: Arc::new(Mutex::new(file)),
+            file: source,
             dir_metadata_part_offset,
             file_metadata_part_offset,
             first_dir_metadata,
@@ -329,57 +379,67 @@ impl<T: Read + Seek> IVFCReader<T> {
         dir: &DirectoryMetadata,
         path: &str,
     ) -> Result<DirectoryOrFile, IVFCError> {
-        let mut file = match self.file.lock() {
-            Ok(guard) => guard,
-            Err(_err) => return Err(IVFCError::Poisoned),
-        };
+        let mut file = ReadAtCursor::new(&*self.file);
+        let mut visited = HashSet::new();
         // check for folder
-        match file.seek(SeekFrom::Start(match dir.offset_first_subdir {
-            Some(value) => (value + self.dir_metadata_part_offset) as u64,
-            None => return Err(IVFCError::DirNotFound),
-        })) {
-            Ok(_) => (),
-            Err(err) => return Err(IVFCError::SeekError(err, "a directory metadata")),
-        };
-        let mut actual_subdir = DirectoryMetadata::new(&mut *file, false)?;
-        loop {
-            if actual_subdir.name.as_ref().unwrap() == path {
-                return Ok(DirectoryOrFile::Dir(actual_subdir));
-            };
-            //get the next one
-            let offset_to_seek = match actual_subdir.offset_next_sibling {
-                Some(value) => (value + self.dir_metadata_part_offset) as u64,
-                None => break,
-            };
-            match file.seek(SeekFrom::Start(offset_to_seek)) {
+        if let Some(first_subdir_offset) = dir.offset_first_subdir {
+            mark_visited(&mut visited, first_subdir_offset, "directory")?;
+            match file.seek(SeekFrom::Start(
+                first_subdir_offset as u64 + self.dir_metadata_part_offset as u64,
+            )) {
                 Ok(_) => (),
                 Err(err) => return Err(IVFCError::SeekError(err, "a directory metadata")),
             };
-            actual_subdir = DirectoryMetadata::new(&mut *file, false)?;
-        }
+            let mut actual_subdir = DirectoryMetadata::new(&mut file, false)?;
+            loop {
+                if actual_subdir.name.as_ref().unwrap() == path {
+                    return Ok(DirectoryOrFile::Dir(actual_subdir));
+                };
+                //get the next one
+                let offset_to_seek = match actual_subdir.offset_next_sibling {
+                    Some(value) => {
+                        mark_visited(&mut visited, value, "directory")?;
+                        value as u64 + self.dir_metadata_part_offset as u64
+                    }
+                    None => break,
+                };
+                match file.seek(SeekFrom::Start(offset_to_seek)) {
+                    Ok(_) => (),
+                    Err(err) => return Err(IVFCError::SeekError(err, "a directory metadata")),
+                };
+                actual_subdir = DirectoryMetadata::new(&mut file, false)?;
+            }
+        };
         //check for file
         // get the first sub-file
+        visited.clear();
         match file.seek(SeekFrom::Start(match dir.offset_first_file {
-            Some(value) => (value + self.file_metadata_part_offset) as u64,
+            Some(value) => {
+                mark_visited(&mut visited, value, "file")?;
+                value as u64 + self.file_metadata_part_offset as u64
+            }
             None => return Err(IVFCError::FileNotFound),
         })) {
             Ok(_) => (),
             Err(err) => return Err(IVFCError::SeekError(err, "a file metadata")),
         };
-        let mut actual_file = FileMetadata::new(&mut *file)?;
+        let mut actual_file = FileMetadata::new(&mut file)?;
         loop {
             if actual_file.name == path {
                 return Ok(DirectoryOrFile::File(actual_file));
             };
             let offset_to_seek = match actual_file.offset_sibling {
-                Some(value) => (value + self.file_metadata_part_offset) as u64,
+                Some(value) => {
+                    mark_visited(&mut visited, value, "file")?;
+                    value as u64 + self.file_metadata_part_offset as u64
+                }
                 None => break,
             };
             match file.seek(SeekFrom::Start(offset_to_seek)) {
                 Ok(_) => (),
                 Err(err) => return Err(IVFCError::SeekError(err, "a file metadata")),
             };
-            actual_file = FileMetadata::new(&mut *file)?;
+            actual_file = FileMetadata::new(&mut file)?;
         }
         Err(IVFCError::FileNotFound)
     }
@@ -389,13 +449,41 @@ impl<T: Read + Seek> IVFCReader<T> {
         dir: &DirectoryMetadata,
         childs: &mut Vec<String>,
     ) -> Result<(), IVFCError> {
-        let mut file = match self.file.lock() {
-            Ok(file) => file,
-            Err(_) => return Err(IVFCError::Poisoned),
-        };
+        let mut childs_metadata = Vec::new();
+        self.list_file_child_metadata(dir, &mut childs_metadata)?;
+        for child in childs_metadata {
+            childs.push(child.name);
+        }
+        Ok(())
+    }
+
+    pub fn list_dir_child(
+        &self,
+        dir: &DirectoryMetadata,
+        childs: &mut Vec<String>,
+    ) -> Result<(), IVFCError> {
+        let mut childs_metadata = Vec::new();
+        self.list_dir_child_metadata(dir, &mut childs_metadata)?;
+        for child in childs_metadata {
+            childs.push(child.name.unwrap_or_default());
+        }
+        Ok(())
+    }
+
+    /// Add the metadata of all the files directly in `dir` to `childs`
+    pub fn list_file_child_metadata(
+        &self,
+        dir: &DirectoryMetadata,
+        childs: &mut Vec<FileMetadata>,
+    ) -> Result<(), IVFCError> {
+        let mut file = ReadAtCursor::new(&*self.file);
+        let mut visited = HashSet::new();
 
         let first_child_offset = match dir.offset_first_file {
-            Some(value) => value as u64,
+            Some(value) => {
+                mark_visited(&mut visited, value, "file")?;
+                value as u64
+            }
             None => return Ok(()),
         } + self.file_metadata_part_offset as u64;
 
@@ -409,13 +497,16 @@ impl<T: Read + Seek> IVFCReader<T> {
             }
         };
 
-        let mut actual_file_metadata = FileMetadata::new(&mut *file)?;
+        let mut actual_file_metadata = FileMetadata::new(&mut file)?;
 
         loop {
-            childs.push(actual_file_metadata.name.clone());
+            childs.push(actual_file_metadata.clone());
 
             let sibling_file_offset = match actual_file_metadata.offset_sibling {
-                Some(value) => value as u64,
+                Some(value) => {
+                    mark_visited(&mut visited, value, "file")?;
+                    value as u64
+                }
                 None => return Ok(()),
             } + self.file_metadata_part_offset as u64;
 
@@ -429,22 +520,24 @@ impl<T: Read + Seek> IVFCReader<T> {
                 }
             };
 
-            actual_file_metadata = FileMetadata::new(&mut *file)?;
+            actual_file_metadata = FileMetadata::new(&mut file)?;
         }
     }
 
-    pub fn list_dir_child(
+    /// Add the metadata of all the directories directly in `dir` to `childs`
+    pub fn list_dir_child_metadata(
         &self,
         dir: &DirectoryMetadata,
-        childs: &mut Vec<String>,
+        childs: &mut Vec<DirectoryMetadata>,
     ) -> Result<(), IVFCError> {
-        let mut file = match self.file.lock() {
-            Ok(file) => file,
-            Err(_) => return Err(IVFCError::Poisoned),
-        };
+        let mut file = ReadAtCursor::new(&*self.file);
+        let mut visited = HashSet::new();
 
         let first_dir_offset = match dir.offset_first_subdir {
-            Some(value) => value as u64,
+            Some(value) => {
+                mark_visited(&mut visited, value, "directory")?;
+                value as u64
+            }
             None => return Ok(()),
         } + self.dir_metadata_part_offset as u64;
 
@@ -458,13 +551,16 @@ impl<T: Read + Seek> IVFCReader<T> {
             }
         };
 
-        let mut actual_dir_metadata = DirectoryMetadata::new(&mut *file, false)?;
+        let mut actual_dir_metadata = DirectoryMetadata::new(&mut file, false)?;
 
         loop {
-            childs.push(actual_dir_metadata.name.unwrap().clone());
+            childs.push(actual_dir_metadata.clone());
 
             let sibling_dir_offset = match actual_dir_metadata.offset_next_sibling {
-                Some(value) => value as u64,
+                Some(value) => {
+                    mark_visited(&mut visited, value, "directory")?;
+                    value as u64
+                }
                 None => return Ok(()),
             } + self.dir_metadata_part_offset as u64;
 
@@ -478,7 +574,7 @@ impl<T: Read + Seek> IVFCReader<T> {
                 }
             };
 
-            actual_dir_metadata = DirectoryMetadata::new(&mut *file, false)?;
+            actual_dir_metadata = DirectoryMetadata::new(&mut file, false)?;
         }
     }
 
@@ -489,7 +585,835 @@ impl<T: Read + Seek> IVFCReader<T> {
         Ok(childs)
     }
 
+    /// Walk `dir` and all the directories under it, depth first. `visit` is called for each directory with its path relative to `dir` (like "a/b", or "" for `dir` itself), its subdirectories and its files.
+    ///
+    /// Fail with `IVFCError::MetadataLoop` instead of walking forever if a directory is reached twice, like in a corrupted romfs where a directory contain itself.
+    pub fn walk_dir<F: FnMut(&str, &[DirectoryMetadata], &[FileMetadata])>(
+        &self,
+        dir: &DirectoryMetadata,
+        mut visit: F,
+    ) -> Result<(), IVFCError> {
+        let mut visited = HashSet::new();
+        let mut dir_to_walk = vec![(String::new(), dir.clone())];
+        while let Some((dir_path, dir)) = dir_to_walk.pop() {
+            let mut subdirs = Vec::new();
+            let mut files = Vec::new();
+            self.list_dir_child_metadata(&dir, &mut subdirs)?;
+            self.list_file_child_metadata(&dir, &mut files)?;
+            // the offset of a directory is only known by the previous one in the chain
+            let mut subdir_offset = dir.offset_first_subdir;
+            for subdir in &subdirs {
+                if let Some(offset) = subdir_offset {
+                    mark_visited(&mut visited, offset, "directory")?;
+                };
+                subdir_offset = subdir.offset_next_sibling;
+            }
+            visit(&dir_path, &subdirs, &files);
+            for subdir in subdirs.into_iter().rev() {
+                let name = subdir.name.as_deref().unwrap_or_default();
+                let subdir_path = if dir_path.is_empty() {
+                    name.to_string()
+                } else {
+                    format!("{}/{}", dir_path, name)
+                };
+                dir_to_walk.push((subdir_path, subdir));
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the metadata of all the files in the romfs, with their path relative to the root (like "a/b.bin").
+    pub fn walk_files(&self) -> Result<Vec<(String, FileMetadata)>, IVFCError> {
+        let mut result = Vec::new();
+        self.walk_dir(&self.first_dir_metadata, |dir_path, _, files| {
+            for file in files {
+                let file_path = if dir_path.is_empty() {
+                    file.name.clone()
+                } else {
+                    format!("{}/{}", dir_path, file.name)
+                };
+                result.push((file_path, file.clone()));
+            }
+        })?;
+        Ok(result)
+    }
+
+    /// Return the metadata of the file or directory at `path` (like "a/b.bin"). Empty components are ignored, so "" and "/" are the root directory.
+    pub fn get_path_metadata(&self, path: &str) -> Result<DirectoryOrFile, IVFCError> {
+        let mut actual_meta = DirectoryOrFile::Dir(self.first_dir_metadata.clone());
+        for path_part in path.split('/').filter(|part| !part.is_empty()) {
+            actual_meta = match actual_meta {
+                DirectoryOrFile::Dir(actual_dir) => self.get_child(&actual_dir, path_part)?,
+                DirectoryOrFile::File(_) => return Err(IVFCError::DirNotFound),
+            };
+        }
+        Ok(actual_meta)
+    }
+
+    /// Open the file described by `file`, that should come from this romfs
+    pub fn open_file(&self, file: &FileMetadata) -> io::Result<ReadAtPartition<T>> {
+        ReadAtPartition::new(
+            self.file.clone(),
+            self.get_file_real_offset(file),
+            file.lenght_file_data,
+        )
+    }
+
     pub fn get_file_real_offset(&self, file: &FileMetadata) -> u64 {
-        file.offset_file_data + self.file_data_offset as u64
+        // saturating, as a malformed offset should give an unreadable file rather than a panic
+        file.offset_file_data
+            .saturating_add(self.file_data_offset as u64)
+    }
+}
+
+/// Where the content of a file to store in a romfs come from
+#[derive(Debug, Clone)]
+pub enum IVFCWriterSource {
+    Memory(Vec<u8>),
+    /// A file on the disk, only read while the romfs is written
+    Disk(PathBuf),
+}
+
+/// A file to store in a romfs with `IVFCWriter`
+#[derive(Debug, Clone)]
+pub struct IVFCWriterFile {
+    /// The path in the romfs, like "a/b.bin"
+    pub path: String,
+    pub source: IVFCWriterSource,
+}
+
+/// The result of `IVFCWriter::write`
+#[derive(Debug, Clone)]
+pub struct IVFCWriteResult {
+    /// The lenght of the written romfs
+    pub lenght: u64,
+    /// The start of the romfs, with the IVFC header and the master hash, padded to a media unit.
+    ///
+    /// It is the part covered by the romfs superblock hash of a NCCH.
+    pub superblock: Vec<u8>,
+}
+
+/// Build a romfs, with its IVFC hash tree. The files can be read from the disk while writing, so they don't need to fit in memory.
+#[derive(Debug, Clone, Default)]
+pub struct IVFCWriter {
+    files: Vec<IVFCWriterFile>,
+    /// The index in `files` of each path, to replace a file in constant time
+    file_ids: HashMap<String, usize>,
+    /// Directories to create even if they are empty, like "a/b"
+    dirs: Vec<String>,
+    dir_set: HashSet<String>,
+}
+
+const IVFC_BLOCK_SIZE: u64 = 0x1000;
+const IVFC_BLOCK_SIZE_LOG2: u32 = 12;
+const IVFC_HEADER_LENGHT: u64 = 0x60;
+const LEVEL_3_HEADER_LENGHT: u32 = 0x28;
+const NO_ENTRY: u32 = 0xFFFF_FFFF;
+
+fn align_u64(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+/// The hash used to place the directories and the files in the hash tables of a romfs
+fn romfs_name_hash(parent_offset: u32, name: &[u16]) -> u32 {
+    let mut hash = parent_offset ^ 123_456_789;
+    for character in name {
+        hash = hash.rotate_right(5) ^ *character as u32;
+    }
+    hash
+}
+
+/// The number of buckets of a hash table of a romfs with `entry_count` entries
+fn romfs_hash_table_lenght(entry_count: usize) -> usize {
+    if entry_count < 3 {
+        3
+    } else if entry_count < 19 {
+        entry_count | 1
+    } else {
+        let mut lenght = entry_count;
+        while [2, 3, 5, 7, 11, 13, 17]
+            .iter()
+            .any(|divisor| lenght % divisor == 0)
+        {
+            lenght += 1;
+        }
+        lenght
+    }
+}
+
+/// Compute the SHA-256 of every block of the written data, with the last block padded with zeros
+struct BlockHasher {
+    hasher: Sha256,
+    in_block: u64,
+    hashes: Vec<u8>,
+}
+
+impl BlockHasher {
+    fn new() -> BlockHasher {
+        BlockHasher {
+            hasher: Sha256::new(),
+            in_block: 0,
+            hashes: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let lenght = std::cmp::min((IVFC_BLOCK_SIZE - self.in_block) as usize, data.len());
+            self.hasher.update(&data[..lenght]);
+            self.in_block += lenght as u64;
+            data = &data[lenght..];
+            if self.in_block == IVFC_BLOCK_SIZE {
+                self.hashes
+                    .extend_from_slice(&std::mem::take(&mut self.hasher).finalize());
+                self.in_block = 0;
+            };
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.in_block != 0 {
+            let padding = vec![0; (IVFC_BLOCK_SIZE - self.in_block) as usize];
+            self.update(&padding);
+        };
+        self.hashes
+    }
+}
+
+fn hash_blocks(data: &[u8]) -> Vec<u8> {
+    let mut hasher = BlockHasher::new();
+    hasher.update(data);
+    hasher.finish()
+}
+
+fn write_zeros<W: Write>(output: &mut W, mut count: u64) -> Result<(), IVFCError> {
+    let zeros = [0; 0x1000];
+    while count > 0 {
+        let lenght = std::cmp::min(count, zeros.len() as u64) as usize;
+        match output.write_all(&zeros[..lenght]) {
+            Ok(()) => (),
+            Err(err) => return Err(IVFCError::WriteError(err)),
+        };
+        count -= lenght as u64;
+    }
+    Ok(())
+}
+
+struct WriterDir {
+    name: Vec<u16>,
+    parent: usize,
+    subdirs: Vec<usize>,
+    files: Vec<usize>,
+    offset: u32,
+}
+
+struct WriterFile<'a> {
+    name: Vec<u16>,
+    source: &'a IVFCWriterFile,
+    lenght: u64,
+    data_offset: u64,
+    offset: u32,
+}
+
+/// The size of a metadata entry with its name padded to 4 bytes
+fn entry_lenght(base: usize, name: &[u16]) -> usize {
+    base + (name.len() * 2).div_ceil(4) * 4
+}
+
+impl IVFCWriter {
+    pub fn new() -> IVFCWriter {
+        IVFCWriter::default()
+    }
+
+    /// Add all the files and directories in `path` on the disk. The files are only read when the romfs is written.
+    ///
+    /// Symbolic links are added as files and never walked into, so a link to a parent directory can't make it loop forever.
+    pub fn from_dir(path: &Path) -> io::Result<IVFCWriter> {
+        let mut writer = IVFCWriter::new();
+        let mut dir_to_walk = vec![(path.to_path_buf(), String::new())];
+        while let Some((disk_path, romfs_path)) = dir_to_walk.pop() {
+            for entry in std::fs::read_dir(&disk_path)? {
+                let entry = entry?;
+                let name = match entry.file_name().into_string() {
+                    Ok(value) => value,
+                    Err(name) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("the file name {:?} isn't valid unicode", name),
+                        ))
+                    }
+                };
+                let child_romfs_path = if romfs_path.is_empty() {
+                    name
+                } else {
+                    format!("{}/{}", romfs_path, name)
+                };
+                if std::fs::symlink_metadata(entry.path())?.is_dir() {
+                    writer.add_dir(&child_romfs_path);
+                    dir_to_walk.push((entry.path(), child_romfs_path));
+                } else {
+                    writer.add_file(&child_romfs_path, IVFCWriterSource::Disk(entry.path()));
+                };
+            }
+        }
+        Ok(writer)
+    }
+
+    /// Add a file, replacing the file with the same path if any
+    pub fn add_file(&mut self, path: &str, source: IVFCWriterSource) {
+        let new_file = IVFCWriterFile {
+            path: path.trim_matches('/').to_string(),
+            source,
+        };
+        match self.file_ids.get(&new_file.path) {
+            Some(file_id) => self.files[*file_id] = new_file,
+            None => {
+                self.file_ids
+                    .insert(new_file.path.clone(), self.files.len());
+                self.files.push(new_file);
+            }
+        };
+    }
+
+    /// Add a directory, that will be present even if empty
+    pub fn add_dir(&mut self, path: &str) {
+        let path = path.trim_matches('/').to_string();
+        if self.dir_set.insert(path.clone()) {
+            self.dirs.push(path);
+        };
+    }
+
+    /// The files to write, in the order they were first added
+    pub fn files(&self) -> &[IVFCWriterFile] {
+        &self.files
+    }
+
+    /// The directories added with `add_dir`, in the order they were added
+    pub fn dirs(&self) -> &[String] {
+        &self.dirs
+    }
+
+    /// Build the directory tree, sorted by name. Return the directories and the files, the root being the first directory.
+    fn build_tree(&self) -> Result<(Vec<WriterDir>, Vec<WriterFile<'_>>), IVFCError> {
+        let mut dirs = vec![WriterDir {
+            name: Vec::new(),
+            parent: 0,
+            subdirs: Vec::new(),
+            files: Vec::new(),
+            offset: 0,
+        }];
+        let mut dir_ids: BTreeMap<String, usize> = BTreeMap::new();
+        let mut get_dir = |dirs: &mut Vec<WriterDir>, path: &str| -> usize {
+            let mut actual = 0;
+            let mut actual_path = String::new();
+            for part in path.split('/').filter(|part| !part.is_empty()) {
+                if !actual_path.is_empty() {
+                    actual_path.push('/');
+                };
+                actual_path.push_str(part);
+                actual = match dir_ids.get(&actual_path) {
+                    Some(value) => *value,
+                    None => {
+                        let id = dirs.len();
+                        dirs.push(WriterDir {
+                            name: part.encode_utf16().collect(),
+                            parent: actual,
+                            subdirs: Vec::new(),
+                            files: Vec::new(),
+                            offset: 0,
+                        });
+                        dirs[actual].subdirs.push(id);
+                        dir_ids.insert(actual_path.clone(), id);
+                        id
+                    }
+                };
+            }
+            actual
+        };
+        for dir in &self.dirs {
+            get_dir(&mut dirs, dir);
+        }
+        let mut files = Vec::new();
+        for file in &self.files {
+            let (parent_path, name) = match file.path.rsplit_once('/') {
+                Some(value) => value,
+                None => ("", file.path.as_str()),
+            };
+            let parent = get_dir(&mut dirs, parent_path);
+            let lenght = match &file.source {
+                IVFCWriterSource::Memory(data) => data.len() as u64,
+                IVFCWriterSource::Disk(path) => match std::fs::metadata(path) {
+                    Ok(metadata) => metadata.len(),
+                    Err(err) => return Err(IVFCError::SourceError(err, file.path.clone())),
+                },
+            };
+            dirs[parent].files.push(files.len());
+            files.push(WriterFile {
+                name: name.encode_utf16().collect(),
+                source: file,
+                lenght,
+                data_offset: 0,
+                offset: 0,
+            });
+        }
+        for dir_id in 0..dirs.len() {
+            let mut subdirs = std::mem::take(&mut dirs[dir_id].subdirs);
+            subdirs.sort_by(|first, second| dirs[*first].name.cmp(&dirs[*second].name));
+            dirs[dir_id].subdirs = subdirs;
+            let mut dir_files = std::mem::take(&mut dirs[dir_id].files);
+            dir_files.sort_by(|first, second| files[*first].name.cmp(&files[*second].name));
+            dirs[dir_id].files = dir_files;
+        }
+        Ok((dirs, files))
+    }
+
+    /// Build the level 3 metadata (the header, the hash tables and the entries), and set the offset of the files data
+    fn build_metadata(
+        dirs: &mut [WriterDir],
+        files: &mut [WriterFile],
+    ) -> Result<Vec<u8>, IVFCError> {
+        // the entries are stored depth first, so the entries of a directory are close together
+        let mut dir_order = Vec::new();
+        let mut dir_to_visit = vec![0];
+        while let Some(dir_id) = dir_to_visit.pop() {
+            dir_order.push(dir_id);
+            dir_to_visit.extend(dirs[dir_id].subdirs.iter().rev());
+        }
+        let mut dir_metadata_lenght = 0;
+        for dir_id in &dir_order {
+            dirs[*dir_id].offset = dir_metadata_lenght as u32;
+            dir_metadata_lenght += entry_lenght(0x18, &dirs[*dir_id].name);
+        }
+        let mut file_metadata_lenght = 0;
+        let mut data_lenght = 0;
+        for dir_id in &dir_order {
+            for file_id in &dirs[*dir_id].files {
+                let file = &mut files[*file_id];
+                file.offset = file_metadata_lenght as u32;
+                file_metadata_lenght += entry_lenght(0x20, &file.name);
+                file.data_offset = align_u64(data_lenght, 0x10);
+                data_lenght = match file.data_offset.checked_add(file.lenght) {
+                    Some(value) => value,
+                    None => return Err(IVFCError::RomFSTooBig),
+                };
+            }
+        }
+
+        let dir_hash_table_lenght = romfs_hash_table_lenght(dirs.len());
+        let file_hash_table_lenght = romfs_hash_table_lenght(files.len());
+        let mut dir_hash_table = vec![NO_ENTRY; dir_hash_table_lenght];
+        let mut file_hash_table = vec![NO_ENTRY; file_hash_table_lenght];
+
+        let mut dir_metadata = Vec::with_capacity(dir_metadata_lenght);
+        for dir_id in &dir_order {
+            let dir = &dirs[*dir_id];
+            let parent = &dirs[dir.parent];
+            let next_sibling = if *dir_id == 0 {
+                NO_ENTRY
+            } else {
+                let position = parent.subdirs.iter().position(|id| id == dir_id);
+                match position.and_then(|position| parent.subdirs.get(position + 1)) {
+                    Some(sibling) => dirs[*sibling].offset,
+                    None => NO_ENTRY,
+                }
+            };
+            let bucket = romfs_name_hash(parent.offset, &dir.name) as usize % dir_hash_table_lenght;
+            let next_in_bucket = dir_hash_table[bucket];
+            dir_hash_table[bucket] = dir.offset;
+            for value in [
+                parent.offset,
+                next_sibling,
+                dir.subdirs.first().map_or(NO_ENTRY, |id| dirs[*id].offset),
+                dir.files.first().map_or(NO_ENTRY, |id| files[*id].offset),
+                next_in_bucket,
+                (dir.name.len() * 2) as u32,
+            ] {
+                dir_metadata.extend_from_slice(&value.to_le_bytes());
+            }
+            for character in &dir.name {
+                dir_metadata.extend_from_slice(&character.to_le_bytes());
+            }
+            dir_metadata.resize(dir_metadata.len().div_ceil(4) * 4, 0);
+        }
+
+        let mut file_metadata = Vec::with_capacity(file_metadata_lenght);
+        for dir_id in &dir_order {
+            let dir = &dirs[*dir_id];
+            for (position, file_id) in dir.files.iter().enumerate() {
+                let file = &files[*file_id];
+                let next_sibling = match dir.files.get(position + 1) {
+                    Some(sibling) => files[*sibling].offset,
+                    None => NO_ENTRY,
+                };
+                let bucket =
+                    romfs_name_hash(dir.offset, &file.name) as usize % file_hash_table_lenght;
+                let next_in_bucket = file_hash_table[bucket];
+                file_hash_table[bucket] = file.offset;
+                file_metadata.extend_from_slice(&dir.offset.to_le_bytes());
+                file_metadata.extend_from_slice(&next_sibling.to_le_bytes());
+                file_metadata.extend_from_slice(&file.data_offset.to_le_bytes());
+                file_metadata.extend_from_slice(&file.lenght.to_le_bytes());
+                file_metadata.extend_from_slice(&next_in_bucket.to_le_bytes());
+                file_metadata.extend_from_slice(&((file.name.len() * 2) as u32).to_le_bytes());
+                for character in &file.name {
+                    file_metadata.extend_from_slice(&character.to_le_bytes());
+                }
+                file_metadata.resize(file_metadata.len().div_ceil(4) * 4, 0);
+            }
+        }
+
+        let dir_hash_table_offset = LEVEL_3_HEADER_LENGHT as u64;
+        let dir_metadata_offset = dir_hash_table_offset + dir_hash_table_lenght as u64 * 4;
+        let file_hash_table_offset = dir_metadata_offset + dir_metadata.len() as u64;
+        let file_metadata_offset = file_hash_table_offset + file_hash_table_lenght as u64 * 4;
+        let file_data_offset = align_u64(file_metadata_offset + file_metadata.len() as u64, 0x10);
+        if file_data_offset > u32::MAX as u64 {
+            return Err(IVFCError::RomFSTooBig);
+        };
+
+        let mut metadata = Vec::with_capacity(file_data_offset as usize);
+        for value in [
+            LEVEL_3_HEADER_LENGHT as u64,
+            dir_hash_table_offset,
+            dir_hash_table_lenght as u64 * 4,
+            dir_metadata_offset,
+            dir_metadata.len() as u64,
+            file_hash_table_offset,
+            file_hash_table_lenght as u64 * 4,
+            file_metadata_offset,
+            file_metadata.len() as u64,
+            file_data_offset,
+        ] {
+            metadata.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        for value in dir_hash_table {
+            metadata.extend_from_slice(&value.to_le_bytes());
+        }
+        metadata.extend_from_slice(&dir_metadata);
+        for value in file_hash_table {
+            metadata.extend_from_slice(&value.to_le_bytes());
+        }
+        metadata.extend_from_slice(&file_metadata);
+        metadata.resize(file_data_offset as usize, 0);
+        Ok(metadata)
+    }
+
+    /// Write the level 3 (the metadata and the content of the files). Return its lenght and the hashes of its blocks.
+    fn write_level_3<W: Write>(
+        metadata: &[u8],
+        files: &[WriterFile],
+        output: &mut W,
+    ) -> Result<(u64, Vec<u8>), IVFCError> {
+        let mut hasher = BlockHasher::new();
+        let mut lenght = 0;
+        let mut write = |data: &[u8], hasher: &mut BlockHasher| -> Result<(), IVFCError> {
+            hasher.update(data);
+            lenght += data.len() as u64;
+            match output.write_all(data) {
+                Ok(()) => Ok(()),
+                Err(err) => Err(IVFCError::WriteError(err)),
+            }
+        };
+        write(metadata, &mut hasher)?;
+        let mut files_by_offset: Vec<&WriterFile> = files.iter().collect();
+        files_by_offset.sort_by_key(|file| file.data_offset);
+        let mut data_position = 0;
+        let mut buffer = vec![0; 0x10000];
+        for file in files_by_offset {
+            let padding = vec![0; (file.data_offset - data_position) as usize];
+            write(&padding, &mut hasher)?;
+            let source_error = |err| IVFCError::SourceError(err, file.source.path.clone());
+            match &file.source.source {
+                IVFCWriterSource::Memory(data) => write(data, &mut hasher)?,
+                IVFCWriterSource::Disk(path) => {
+                    let mut source = std::fs::File::open(path).map_err(source_error)?;
+                    let mut remaining = file.lenght;
+                    while remaining > 0 {
+                        let to_read = std::cmp::min(remaining, buffer.len() as u64) as usize;
+                        let read = match source.read(&mut buffer[..to_read]) {
+                            Ok(0) => {
+                                return Err(IVFCError::SourceLenghtChanged(
+                                    file.source.path.clone(),
+                                ))
+                            }
+                            Ok(value) => value,
+                            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                            Err(err) => return Err(source_error(err)),
+                        };
+                        write(&buffer[..read], &mut hasher)?;
+                        remaining -= read as u64;
+                    }
+                }
+            };
+            data_position = file.data_offset + file.lenght;
+        }
+        Ok((lenght, hasher.finish()))
+    }
+
+    /// Write the romfs at the current position of `output`
+    pub fn write<W: Write + Seek>(&self, output: &mut W) -> Result<IVFCWriteResult, IVFCError> {
+        let (mut dirs, mut files) = self.build_tree()?;
+        let metadata = IVFCWriter::build_metadata(&mut dirs, &mut files)?;
+
+        let start = match output.stream_position() {
+            Ok(value) => value,
+            Err(err) => return Err(IVFCError::WriteError(err)),
+        };
+        // the level 3 is always just after the header, as the master hash is small
+        let level_3_offset = IVFC_BLOCK_SIZE;
+        write_zeros(output, level_3_offset)?;
+        let (level_3_lenght, level_2) = IVFCWriter::write_level_3(&metadata, &files, output)?;
+        let level_1 = hash_blocks(&level_2);
+        let master_hash = hash_blocks(&level_1);
+        if IVFC_HEADER_LENGHT + master_hash.len() as u64 > level_3_offset {
+            return Err(IVFCError::RomFSTooBig);
+        };
+
+        let level_1_offset = align_u64(level_3_offset + level_3_lenght, IVFC_BLOCK_SIZE);
+        write_zeros(output, level_1_offset - level_3_offset - level_3_lenght)?;
+        let level_2_offset = align_u64(level_1_offset + level_1.len() as u64, IVFC_BLOCK_SIZE);
+        let mut hash_levels = level_1.clone();
+        hash_levels.resize((level_2_offset - level_1_offset) as usize, 0);
+        hash_levels.extend_from_slice(&level_2);
+        match output.write_all(&hash_levels) {
+            Ok(()) => (),
+            Err(err) => return Err(IVFCError::WriteError(err)),
+        };
+        let lenght = level_2_offset + level_2.len() as u64;
+
+        // the logical offsets of the levels, as if they were stored in the order 1, 2, 3
+        let level_1_logical_offset = 0;
+        let level_2_logical_offset = align_u64(level_1.len() as u64, IVFC_BLOCK_SIZE);
+        let level_3_logical_offset = align_u64(
+            level_2_logical_offset + level_2.len() as u64,
+            IVFC_BLOCK_SIZE,
+        );
+        let mut header = Vec::with_capacity(0x200);
+        header.extend_from_slice(b"IVFC");
+        header.extend_from_slice(&0x10000u32.to_le_bytes());
+        header.extend_from_slice(&(master_hash.len() as u32).to_le_bytes());
+        for (logical_offset, level_lenght) in [
+            (level_1_logical_offset, level_1.len() as u64),
+            (level_2_logical_offset, level_2.len() as u64),
+            (level_3_logical_offset, level_3_lenght),
+        ] {
+            header.extend_from_slice(&logical_offset.to_le_bytes());
+            header.extend_from_slice(&level_lenght.to_le_bytes());
+            header.extend_from_slice(&IVFC_BLOCK_SIZE_LOG2.to_le_bytes());
+            header.extend_from_slice(&[0; 4]);
+        }
+        header.extend_from_slice(&[0; 4]);
+        // the size of the optional info
+        header.extend_from_slice(&0x4u32.to_le_bytes());
+        header.resize(IVFC_HEADER_LENGHT as usize, 0);
+        header.extend_from_slice(&master_hash);
+        header.resize(align_u64(header.len() as u64, 0x200) as usize, 0);
+
+        let seek_result = output
+            .seek(SeekFrom::Start(start))
+            .and_then(|_| output.write_all(&header))
+            .and_then(|_| output.seek(SeekFrom::Start(start + lenght)));
+        match seek_result {
+            Ok(_) => (),
+            Err(err) => return Err(IVFCError::WriteError(err)),
+        };
+        Ok(IVFCWriteResult {
+            lenght,
+            superblock: header,
+        })
+    }
+}
+
+/// Build an in-memory romfs containing these files, for the tests
+#[cfg(test)]
+pub(crate) fn romfs_from_files(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = IVFCWriter::new();
+    for (path, content) in files {
+        writer.add_file(path, IVFCWriterSource::Memory(content.to_vec()));
+    }
+    let mut output = std::io::Cursor::new(Vec::new());
+    writer.write(&mut output).unwrap();
+    output.into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A romfs with a single "a.bin" file in the root, and no subdirectory
+    fn romfs_with_one_file() -> Vec<u8> {
+        let mut romfs = b"IVFC\x00\x00\x01\x00".to_vec();
+        romfs.resize(0x1000, 0);
+        for value in &[0x28u32, 0x28, 4, 0x2C, 0x18, 0x44, 4, 0x48, 0x2C, 0x80] {
+            romfs.extend_from_slice(&value.to_le_bytes());
+        }
+        // directory hash table, then root directory metadata
+        for value in &[
+            0xFFFF_FFFFu32,
+            0,
+            0xFFFF_FFFF,
+            0xFFFF_FFFF,
+            0,
+            0xFFFF_FFFF,
+            0,
+        ] {
+            romfs.extend_from_slice(&value.to_le_bytes());
+        }
+        // file hash table, then the file metadata
+        romfs.extend_from_slice(&0u32.to_le_bytes());
+        romfs.extend_from_slice(&0u32.to_le_bytes());
+        romfs.extend_from_slice(&0xFFFF_FFFFu32.to_le_bytes());
+        romfs.extend_from_slice(&0u64.to_le_bytes());
+        romfs.extend_from_slice(&4u64.to_le_bytes());
+        romfs.extend_from_slice(&0xFFFF_FFFFu32.to_le_bytes());
+        romfs.extend_from_slice(&10u32.to_le_bytes());
+        for character in "a.bin".encode_utf16() {
+            romfs.extend_from_slice(&character.to_le_bytes());
+        }
+        romfs.resize(0x1080, 0);
+        romfs.extend_from_slice(b"test");
+        romfs
+    }
+
+    #[test]
+    fn test_get_child_without_subdir() {
+        let reader = IVFCReader::new(Cursor::new(romfs_with_one_file())).unwrap();
+        let root = reader.first_dir_metadata.clone();
+        match reader.get_child(&root, "a.bin").unwrap() {
+            DirectoryOrFile::File(file) => assert_eq!(file.lenght_file_data, 4),
+            DirectoryOrFile::Dir(_) => panic!("a.bin is a file"),
+        };
+        assert!(matches!(
+            reader.get_child(&root, "b.bin"),
+            Err(IVFCError::FileNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_walk_files() {
+        let romfs = romfs_from_files(&[("a/b.bin", b"b"), ("a/c/d.bin", b"d"), ("e.bin", b"e")]);
+        let reader = IVFCReader::from_read_at(romfs).unwrap();
+        let mut paths: Vec<String> = reader
+            .walk_files()
+            .unwrap()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        paths.sort();
+        assert_eq!(paths, ["a/b.bin", "a/c/d.bin", "e.bin"]);
+    }
+
+    #[test]
+    fn test_write_read_round_trip() {
+        let mut writer = IVFCWriter::new();
+        let files: [(&str, Vec<u8>); 4] = [
+            ("a/b.bin", vec![1; 0x1234]),
+            ("a/c/d.bin", Vec::new()),
+            ("é.txt", b"unicode".to_vec()),
+            ("e.bin", (0..=255).collect()),
+        ];
+        for (path, content) in files.iter() {
+            writer.add_file(path, IVFCWriterSource::Memory(content.clone()));
+        }
+        writer.add_dir("empty/dir");
+        let mut output = Cursor::new(Vec::new());
+        let result = writer.write(&mut output).unwrap();
+        let romfs = output.into_inner();
+        assert_eq!(result.lenght, romfs.len() as u64);
+
+        let reader = IVFCReader::from_read_at(romfs.clone()).unwrap();
+        let mut walked = reader.walk_files().unwrap();
+        walked.sort_by(|(path_1, _), (path_2, _)| path_1.cmp(path_2));
+        let mut expected = files.to_vec();
+        expected.sort();
+        assert_eq!(walked.len(), expected.len());
+        for ((path, file), (expected_path, content)) in walked.iter().zip(expected.iter()) {
+            assert_eq!(path, expected_path);
+            let offset = reader.get_file_real_offset(file) as usize;
+            assert_eq!(
+                &romfs[offset..offset + file.lenght_file_data as usize],
+                &content[..]
+            );
+        }
+        assert!(matches!(
+            reader.get_path_metadata("empty/dir"),
+            Ok(DirectoryOrFile::Dir(_))
+        ));
+    }
+
+    #[test]
+    fn test_writer_replace() {
+        let mut writer = IVFCWriter::new();
+        writer.add_file("/a.bin", IVFCWriterSource::Memory(vec![1]));
+        writer.add_file("b.bin", IVFCWriterSource::Memory(vec![2]));
+        writer.add_file("a.bin", IVFCWriterSource::Memory(vec![3]));
+        writer.add_dir("c");
+        writer.add_dir("c/");
+        let files: Vec<(&str, &IVFCWriterSource)> = writer
+            .files()
+            .iter()
+            .map(|file| (file.path.as_str(), &file.source))
+            .collect();
+        assert!(matches!(
+            files[..],
+            [
+                ("a.bin", IVFCWriterSource::Memory(a)),
+                ("b.bin", IVFCWriterSource::Memory(_))
+            ] if a == &[3]
+        ));
+        assert_eq!(writer.dirs(), ["c"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_from_dir_symlink_loop() {
+        let dir = std::env::temp_dir().join(format!("fs3ds-from-dir-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("a")).unwrap();
+        std::fs::write(dir.join("a/b.bin"), b"b").unwrap();
+        std::os::unix::fs::symlink("..", dir.join("a/parent")).unwrap();
+
+        let writer = IVFCWriter::from_dir(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let mut paths: Vec<&str> = writer
+            .files()
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect();
+        paths.sort_unstable();
+        assert_eq!(paths, ["a/b.bin", "a/parent"]);
+        assert_eq!(writer.dirs(), ["a"]);
+    }
+
+    #[test]
+    fn test_walk_loop() {
+        let romfs = romfs_from_files(&[("a/b.bin", b"b")]);
+        let reader = IVFCReader::from_read_at(romfs.clone()).unwrap();
+        let root_offset = reader.dir_metadata_part_offset as usize;
+        let a_offset =
+            root_offset + reader.first_dir_metadata.offset_first_subdir.unwrap() as usize;
+
+        // the root directory contain itself
+        let mut self_parent = romfs.clone();
+        self_parent[root_offset + 8..root_offset + 12].copy_from_slice(&0u32.to_le_bytes());
+        let reader = IVFCReader::from_read_at(self_parent).unwrap();
+        assert!(matches!(
+            reader.walk_files(),
+            Err(IVFCError::MetadataLoop("directory", 0))
+        ));
+
+        // "a" is its own next sibling
+        let mut self_sibling = romfs;
+        let a_relative_offset = (a_offset - root_offset) as u32;
+        self_sibling[a_offset + 4..a_offset + 8].copy_from_slice(&a_relative_offset.to_le_bytes());
+        let reader = IVFCReader::from_read_at(self_sibling).unwrap();
+        assert!(matches!(
+            reader.walk_files(),
+            Err(IVFCError::MetadataLoop("directory", _))
+        ));
+        assert!(matches!(
+            reader.get_path_metadata("c"),
+            Err(IVFCError::MetadataLoop("directory", _))
+        ));
     }
 }
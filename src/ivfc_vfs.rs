@@ -1,34 +1,38 @@
 use crate::ivfc::FileMetadata;
 use crate::ivfc::{DirectoryOrFile, IVFCError};
 use crate::IVFCReader;
-use crate::PartitionMutex;
+use crate::{ReadAt, ReadAtPartition};
 use std::borrow::Cow;
 use std::error::Error;
 use std::fmt;
 use std::io;
-use std::io::{Read, Seek};
 
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use vfs::{OpenOptions, VFile, VMetadata, VPath, VFS};
 
-pub struct IVFCVFS<T: 'static + Read + Seek + Send + Sync + fmt::Debug> {
+pub struct IVFCVFS<T: 'static + ReadAt + Send + Sync + fmt::Debug> {
     reader: Arc<IVFCReader<T>>,
 }
 
-impl<T: 'static + Read + Seek + Send + Sync + fmt::Debug> IVFCVFS<T> {
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> IVFCVFS<T> {
     pub fn new(reader: IVFCReader<T>) -> IVFCVFS<T> {
         IVFCVFS {
             reader: Arc::new(reader),
         }
     }
+
+    /// Return the reader of the romfs, to access the metadata of the files (like their offset) directly
+    pub fn get_reader(&self) -> Arc<IVFCReader<T>> {
+        self.reader.clone()
+    }
 }
 
-impl<T: 'static + Read + Seek + Send + Sync + fmt::Debug> VFS for IVFCVFS<T> {
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> VFS for IVFCVFS<T> {
     type PATH = IVFCVPATH<T>;
     type METADATA = IVFCMeta;
-    type FILE = PartitionMutex<T>;
+    type FILE = ReadAtPartition<T>;
 
     fn path<A: Into<String>>(&self, path: A) -> Self::PATH {
         IVFCVPATH {
@@ -105,12 +109,12 @@ impl Error for GetMetadataError {
     }
 }
 
-pub struct FileNameIterator<T: Read + Seek + Send + Sync + fmt::Debug> {
+pub struct FileNameIterator<T: ReadAt + Send + Sync + fmt::Debug> {
     child_iterator: std::vec::IntoIter<String>,
     child_of: IVFCVPATH<T>,
 }
 
-impl<T: Read + Seek + Send + Sync + std::fmt::Debug> FileNameIterator<T> {
+impl<T: ReadAt + Send + Sync + std::fmt::Debug> FileNameIterator<T> {
     pub fn new(childs: Vec<String>, child_of: IVFCVPATH<T>) -> FileNameIterator<T> {
         FileNameIterator {
             child_iterator: childs.into_iter(),
@@ -119,7 +123,7 @@ impl<T: Read + Seek + Send + Sync + std::fmt::Debug> FileNameIterator<T> {
     }
 }
 
-impl<T: 'static + Read + Seek + Send + Sync + fmt::Debug> Iterator for FileNameIterator<T> {
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> Iterator for FileNameIterator<T> {
     type Item = io::Result<Box<dyn VPath>>;
     fn next(&mut self) -> Option<Self::Item> {
         match self.child_iterator.next() {
@@ -130,12 +134,12 @@ impl<T: 'static + Read + Seek + Send + Sync + fmt::Debug> Iterator for FileNameI
 }
 
 #[derive(Debug)]
-pub struct IVFCVPATH<T: Sync + Send + Read + Seek + fmt::Debug> {
+pub struct IVFCVPATH<T: Sync + Send + ReadAt + fmt::Debug> {
     reader: Arc<IVFCReader<T>>,
     path: PathBuf,
 }
 
-impl<T: Sync + Send + Read + Seek + fmt::Debug> Clone for IVFCVPATH<T> {
+impl<T: Sync + Send + ReadAt + fmt::Debug> Clone for IVFCVPATH<T> {
     fn clone(&self) -> IVFCVPATH<T> {
         let new_path = self.path.clone();
         IVFCVPATH {
@@ -145,7 +149,7 @@ impl<T: Sync + Send + Read + Seek + fmt::Debug> Clone for IVFCVPATH<T> {
     }
 }
 
-impl<T: 'static + Read + Seek + fmt::Debug + Sync + Send> IVFCVPATH<T> {
+impl<T: 'static + ReadAt + fmt::Debug + Sync + Send> IVFCVPATH<T> {
     pub fn new(reader: Arc<IVFCReader<T>>) -> IVFCVPATH<T> {
         IVFCVPATH {
             reader,
@@ -176,6 +180,26 @@ impl<T: 'static + Read + Seek + fmt::Debug + Sync + Send> IVFCVPATH<T> {
         }
         Ok(actual_meta)
     }
+
+    /// Open the file at this path, returning the `ReadAtPartition` directly instead of a boxed `VFile`
+    pub fn open_partition(&self) -> io::Result<ReadAtPartition<T>> {
+        let file_meta = match self.get_internal_meta() {
+            Ok(DirectoryOrFile::File(file_meta)) => file_meta,
+            Ok(DirectoryOrFile::Dir(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "trying to open a directory",
+                ))
+            }
+            Err(err) => return Err(io::Error::other(err)),
+        };
+        self.open_file_metadata(&file_meta)
+    }
+
+    /// Open the file described by `file_meta`, that should come from the same romfs.
+    pub fn open_file_metadata(&self, file_meta: &FileMetadata) -> io::Result<ReadAtPartition<T>> {
+        self.reader.open_file(file_meta)
+    }
 }
 
 fn return_ro_error<T>() -> io::Result<T> {
@@ -185,33 +209,13 @@ fn return_ro_error<T>() -> io::Result<T> {
     ))
 }
 
-impl<T: 'static + Read + Seek + fmt::Debug + Sync + Send> VPath for IVFCVPATH<T> {
+impl<T: 'static + ReadAt + fmt::Debug + Sync + Send> VPath for IVFCVPATH<T> {
     fn open_with_options(&self, opt: &OpenOptions) -> io::Result<Box<dyn VFile>> {
         if opt.write || opt.create || opt.append || opt.truncate {
             return return_ro_error();
         };
 
-        let file_meta = match self.get_internal_meta() {
-            Ok(DirectoryOrFile::File(file_meta)) => file_meta,
-            Ok(DirectoryOrFile::Dir(_)) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "trying to open a directory",
-                ))
-            }
-            Err(err) => return Err(io::Error::new(io::ErrorKind::Other, err)),
-        };
-
-        Ok(Box::new(
-            match PartitionMutex::new(
-                self.reader.file.clone(),
-                self.reader.get_file_real_offset(&file_meta) as usize,
-                file_meta.lenght_file_data as usize,
-            ) {
-                Ok(value) => value,
-                Err(err) => return Err(io::Error::new(io::ErrorKind::Other, err)),
-            },
-        ))
+        Ok(Box::new(self.open_partition()?))
     }
 
     #[allow(clippy::type_complexity)]
@@ -224,12 +228,12 @@ impl<T: 'static + Read + Seek + fmt::Debug + Sync + Send> VPath for IVFCVPATH<T>
                 ))
             }
             Ok(DirectoryOrFile::Dir(dir_meta)) => dir_meta,
-            Err(err) => return Err(io::Error::new(io::ErrorKind::Other, err)),
+            Err(err) => return Err(io::Error::other(err)),
         };
 
         let child_list = match self.reader.list_child(&dir_meta) {
             Ok(value) => value,
-            Err(err) => return Err(io::Error::new(io::ErrorKind::Other, err)),
+            Err(err) => return Err(io::Error::other(err)),
         };
 
         Ok(Box::new(FileNameIterator::new(child_list, self.clone())))
@@ -275,7 +279,7 @@ impl<T: 'static + Read + Seek + fmt::Debug + Sync + Send> VPath for IVFCVPATH<T>
         }))
     }
 
-    fn to_string(&self) -> Cow<str> {
+    fn to_string(&self) -> Cow<'_, str> {
         format!("romfs://{:?}", self.path).into()
     }
 
@@ -307,3 +311,40 @@ impl<T: 'static + Read + Seek + fmt::Debug + Sync + Send> VPath for IVFCVPATH<T>
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ivfc::romfs_from_files;
+    use std::io::Read;
+
+    #[test]
+    fn test_open_file_metadata() {
+        let romfs = romfs_from_files(&[("a/b.bin", b"hello"), ("c.bin", b"world!")]);
+        let vfs = IVFCVFS::new(IVFCReader::from_read_at(romfs).unwrap());
+
+        let path = vfs.path("a/b.bin");
+        let file_meta = match path.get_internal_meta().unwrap() {
+            DirectoryOrFile::File(file_meta) => file_meta,
+            DirectoryOrFile::Dir(_) => panic!("a/b.bin is a file"),
+        };
+        let mut content = String::new();
+        path.open_file_metadata(&file_meta)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "hello");
+        assert_eq!(vfs.path("c.bin").metadata().unwrap().len(), 6);
+        assert!(vfs.path("a").metadata().unwrap().is_dir());
+        assert!(!vfs.path("a/c.bin").exists());
+
+        let mut names: Vec<String> = vfs
+            .path("")
+            .read_dir()
+            .unwrap()
+            .map(|child| child.unwrap().file_name().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["a", "c.bin"]);
+    }
+}
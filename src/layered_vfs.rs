@@ -0,0 +1,346 @@
+use crate::ivfc::DirectoryOrFile;
+use crate::{IVFCMeta, ReadAt, ReadAtPartition, IVFCVFS, IVFCVPATH};
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::SeekFrom;
+use std::io::{Read, Seek, Write};
+use std::path::{Component, PathBuf};
+use std::sync::Arc;
+
+use vfs::{OpenOptions, VFile, VMetadata, VPath, VFS};
+
+/// A read only VFS that put the content of a host directory on top of a romfs, like the LayeredFS of Luma3DS.
+///
+/// A file present in the host directory shadow the file with the same path in the romfs, and file only present in the host directory are added to the listing.
+pub struct LayeredVFS<T: 'static + ReadAt + Send + Sync + fmt::Debug> {
+    romfs: Arc<IVFCVFS<T>>,
+    host: Arc<PathBuf>,
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> LayeredVFS<T> {
+    pub fn new<P: Into<PathBuf>>(romfs: IVFCVFS<T>, host: P) -> LayeredVFS<T> {
+        LayeredVFS {
+            romfs: Arc::new(romfs),
+            host: Arc::new(host.into()),
+        }
+    }
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> VFS for LayeredVFS<T> {
+    type PATH = LayeredVPATH<T>;
+    type METADATA = LayeredMeta;
+    type FILE = LayeredFile<T>;
+
+    fn path<A: Into<String>>(&self, path: A) -> Self::PATH {
+        LayeredVPATH {
+            romfs: self.romfs.clone(),
+            host: self.host.clone(),
+            path: PathBuf::from(path.into()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LayeredFile<T: ReadAt> {
+    Host(fs::File),
+    RomFS(ReadAtPartition<T>),
+}
+
+impl<T: ReadAt> Read for LayeredFile<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Host(file) => file.read(buf),
+            Self::RomFS(file) => file.read(buf),
+        }
+    }
+}
+
+impl<T: ReadAt> Seek for LayeredFile<T> {
+    fn seek(&mut self, target: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::Host(file) => file.seek(target),
+            Self::RomFS(file) => file.seek(target),
+        }
+    }
+}
+
+impl<T: ReadAt> Write for LayeredFile<T> {
+    /// Do not use this write function. It is just here to make ``vfs::VFile`` happy. It will always return an error.
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::from(io::ErrorKind::PermissionDenied))
+    }
+
+    /// Always suceed. It is useless to call it
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum LayeredMeta {
+    Host(fs::Metadata),
+    RomFS(IVFCMeta),
+}
+
+impl VMetadata for LayeredMeta {
+    fn is_dir(&self) -> bool {
+        match self {
+            Self::Host(meta) => meta.is_dir(),
+            Self::RomFS(meta) => meta.is_dir(),
+        }
+    }
+
+    fn is_file(&self) -> bool {
+        !self.is_dir()
+    }
+
+    fn len(&self) -> u64 {
+        match self {
+            Self::Host(meta) => meta.len(),
+            Self::RomFS(meta) => meta.len(),
+        }
+    }
+}
+
+pub struct LayeredVPATH<T: 'static + ReadAt + Send + Sync + fmt::Debug> {
+    romfs: Arc<IVFCVFS<T>>,
+    host: Arc<PathBuf>,
+    path: PathBuf,
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> fmt::Debug for LayeredVPATH<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LayeredVPATH")
+            .field("host", &self.host)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> Clone for LayeredVPATH<T> {
+    fn clone(&self) -> LayeredVPATH<T> {
+        LayeredVPATH {
+            romfs: self.romfs.clone(),
+            host: self.host.clone(),
+            path: self.path.clone(),
+        }
+    }
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> LayeredVPATH<T> {
+    /// Return the path of this file in the host directory.
+    pub fn host_path(&self) -> PathBuf {
+        let mut host_path = (*self.host).clone();
+        for component in self.path.components() {
+            if let Component::Normal(part) = component {
+                host_path.push(part);
+            };
+        }
+        host_path
+    }
+
+    /// Return the path of this file in the romfs.
+    pub fn romfs_path(&self) -> IVFCVPATH<T> {
+        let mut romfs_path = PathBuf::new();
+        for component in self.path.components() {
+            if let Component::Normal(part) = component {
+                romfs_path.push(part);
+            };
+        }
+        self.romfs.path(romfs_path.to_string_lossy())
+    }
+
+    fn with_path(&self, path: PathBuf) -> LayeredVPATH<T> {
+        LayeredVPATH {
+            romfs: self.romfs.clone(),
+            host: self.host.clone(),
+            path,
+        }
+    }
+}
+
+fn return_ro_error<T>() -> io::Result<T> {
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "read only file system",
+    ))
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> VPath for LayeredVPATH<T> {
+    fn open_with_options(&self, opt: &OpenOptions) -> io::Result<Box<dyn VFile>> {
+        if opt.write || opt.create || opt.append || opt.truncate {
+            return return_ro_error();
+        };
+
+        let host_path = self.host_path();
+        if host_path.is_file() {
+            return Ok(Box::new(LayeredFile::<T>::Host(fs::File::open(host_path)?)));
+        };
+
+        let romfs_path = self.romfs_path();
+        let file_meta = match romfs_path.get_internal_meta() {
+            Ok(DirectoryOrFile::File(file_meta)) => file_meta,
+            Ok(DirectoryOrFile::Dir(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "trying to open a directory",
+                ))
+            }
+            Err(err) => return Err(io::Error::other(err)),
+        };
+        Ok(Box::new(LayeredFile::RomFS(
+            romfs_path.open_file_metadata(&file_meta)?,
+        )))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn read_dir(&self) -> io::Result<Box<dyn Iterator<Item = io::Result<Box<dyn VPath>>>>> {
+        let mut childs = Vec::new();
+
+        let romfs_path = self.romfs_path();
+        let romfs_is_dir = match romfs_path.metadata() {
+            Ok(meta) => meta.is_dir(),
+            Err(_) => false,
+        };
+        if romfs_is_dir {
+            for child in romfs_path.read_dir()? {
+                if let Some(name) = child?.file_name() {
+                    childs.push(name);
+                };
+            }
+        };
+
+        let host_path = self.host_path();
+        let host_is_dir = host_path.is_dir();
+        if host_is_dir {
+            for entry in fs::read_dir(host_path)? {
+                let name = entry?.file_name().to_string_lossy().into_owned();
+                if !childs.contains(&name) {
+                    childs.push(name);
+                };
+            }
+        };
+
+        if !romfs_is_dir && !host_is_dir {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "the directory doesn't exist in the romfs nor in the host directory",
+            ));
+        };
+
+        let this = self.clone();
+        Ok(Box::new(
+            childs.into_iter().map(move |name| Ok(this.resolve(&name))),
+        ))
+    }
+
+    fn mkdir(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn rm(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn rmrf(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn file_name(&self) -> Option<String> {
+        self.path.file_name()
+    }
+
+    fn extension(&self) -> Option<String> {
+        self.path.extension()
+    }
+
+    fn resolve(&self, path: &String) -> Box<dyn VPath> {
+        let mut new_path = self.path.clone();
+        new_path.push(path);
+        Box::new(self.with_path(new_path))
+    }
+
+    fn parent(&self) -> Option<Box<dyn VPath>> {
+        let mut new_path = self.path.clone();
+        if !new_path.pop() {
+            return None;
+        };
+        Some(Box::new(self.with_path(new_path)))
+    }
+
+    fn to_string(&self) -> Cow<'_, str> {
+        format!("layeredfs://{:?}", self.path).into()
+    }
+
+    fn box_clone(&self) -> Box<dyn VPath> {
+        Box::new(self.clone())
+    }
+
+    fn to_path_buf(&self) -> Option<PathBuf> {
+        Some(self.path.clone())
+    }
+
+    fn exists(&self) -> bool {
+        self.host_path().exists() || self.romfs_path().exists()
+    }
+
+    fn metadata(&self) -> io::Result<Box<dyn VMetadata>> {
+        let host_path = self.host_path();
+        if host_path.exists() {
+            return Ok(Box::new(LayeredMeta::Host(fs::metadata(host_path)?)));
+        };
+        let romfs_meta = match self.romfs_path().get_internal_meta() {
+            Ok(DirectoryOrFile::Dir(_)) => IVFCMeta::Dir,
+            Ok(DirectoryOrFile::File(meta)) => IVFCMeta::File(meta.lenght_file_data),
+            Err(err) => return Err(err.to_io_error()),
+        };
+        Ok(Box::new(LayeredMeta::RomFS(romfs_meta)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ivfc::romfs_from_files;
+    use crate::IVFCReader;
+
+    fn read_to_string(path: &LayeredVPATH<Vec<u8>>) -> String {
+        let mut content = String::new();
+        path.open().unwrap().read_to_string(&mut content).unwrap();
+        content
+    }
+
+    fn sorted_names(path: &LayeredVPATH<Vec<u8>>) -> Vec<String> {
+        let mut names: Vec<String> = path
+            .read_dir()
+            .unwrap()
+            .map(|child| child.unwrap().file_name().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_host_shadow_romfs() {
+        let host = std::env::temp_dir().join(format!("fs3ds-layered-{}", std::process::id()));
+        fs::create_dir_all(host.join("a")).unwrap();
+        fs::write(host.join("a/b.bin"), b"patched").unwrap();
+        fs::write(host.join("new.bin"), b"new").unwrap();
+
+        let romfs = romfs_from_files(&[("a/b.bin", b"hello"), ("c.bin", b"world!")]);
+        let romfs = IVFCVFS::new(IVFCReader::from_read_at(romfs).unwrap());
+        let vfs = LayeredVFS::new(romfs, &host);
+
+        assert_eq!(read_to_string(&vfs.path("a/b.bin")), "patched");
+        assert_eq!(vfs.path("a/b.bin").metadata().unwrap().len(), 7);
+        assert_eq!(read_to_string(&vfs.path("c.bin")), "world!");
+        assert_eq!(read_to_string(&vfs.path("new.bin")), "new");
+        assert_eq!(sorted_names(&vfs.path("")), ["a", "c.bin", "new.bin"]);
+        assert_eq!(sorted_names(&vfs.path("a")), ["b.bin"]);
+        assert!(!vfs.path("missing.bin").exists());
+
+        fs::remove_dir_all(host).unwrap();
+    }
+}
@@ -8,7 +8,7 @@
 //!
 //! # Examples
 //!
-//! ```rust
+//! ```no_run
 //! use std::fs::File;
 //! use fs3ds::get_romfs_vfs;
 //! let file = File::open("rom.3ds").unwrap(); // get an access to an unencrypted romfs file
@@ -18,27 +18,149 @@
 use std::error::Error;
 use std::fmt;
 use std::io;
+use std::sync::{Arc, Mutex};
 
 mod ncsd;
-pub use ncsd::{NCSDError, NCSDReader};
+pub use ncsd::{NCSDError, NCSDReader, NCSDWriter};
 
 mod ncch;
-pub use ncch::{NCCHError, NCCHReader};
+pub use ncch::{NCCHError, NCCHReader, NCCHWriter, NCCH_EXHEADER_LENGHT, NCCH_EXHEADER_OFFSET};
+
+mod exefs;
+pub use exefs::{ExeFSError, ExeFSFileHeader, ExeFSReader, ExeFSWriter};
+
+mod smdh;
+pub use smdh::{
+    decode_tiled_rgb565, region, SMDHAgeRating, SMDHError, SMDHLanguage, SMDHRatingOrganization,
+    SMDHTitle, LARGE_ICON_SIZE, SMALL_ICON_SIZE, SMDH,
+};
+
+mod lz;
+pub use lz::{
+    compress_lz10, compress_lz11, compress_lz13, decompress_lz, decompress_lz10, decompress_lz11,
+    decompress_lz13, get_lz_decompressed_lenght, LZError,
+};
+
+mod yaz0;
+pub use yaz0::{
+    compress_yaz0, decompress_yaz0, get_yaz0_decompressed_lenght, Yaz0Decoder, Yaz0Error,
+};
+
+mod lz_vfs;
+pub use lz_vfs::{LZMeta, LZVFS, LZVPATH};
+
+mod bcwav;
+pub use bcwav::{BCWAVChannel, BCWAVEncoding, BCWAVError, DSPADPCMInfo, BCWAV};
+
+mod banner;
+pub use banner::{BannerError, BannerReader, BannerRegion};
+
+mod darc;
+pub use darc::{DARCEntry, DARCError, DARCReader, DARCVFS, DARCVPATH};
+
+mod arc_mount_vfs;
+pub use arc_mount_vfs::{ArcMountVFS, ArcMountVPATH};
+
+mod sarc;
+pub use sarc::{
+    sarc_default_alignment, sarc_name_hash, SARCEntry, SARCError, SARCReader, SARCWriter,
+    SARCWriterFile, SARCVFS, SARCVPATH, SARC_DEFAULT_HASH_KEY,
+};
+
+mod garc;
+pub use garc::{GARCEntry, GARCError, GARCReader, GARCSubEntry, GARCVersion, GARCWriter};
+
+mod logo;
+pub use logo::{KnownLogo, LogoError, LogoReader, STANDARD_LOGOS};
+
+mod plain_region;
+pub use plain_region::{PlainRegion, PlainRegionError, SDKLibrary};
+
+mod block_cache;
+pub use block_cache::{BlockCache, BlockCacheConfig, BlockCacheStats};
+
+mod borrowed;
+pub use borrowed::{RomFS, NCCH, NCSD};
+
+mod title_layout;
+pub use title_layout::{
+    NCCHSections, TitleContainer, TitleLayout, TitleLayoutError, TitleNode, TitleNodeKind,
+    TitleTree,
+};
+
+mod read_at;
+pub use read_at::{ReadAt, ReadAtCursor};
+
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "mmap")]
+pub use mmap::{get_romfs_vfs_mmap, open_mmap, Mmap, MmapRomError};
+
+#[cfg(feature = "filesystem")]
+mod romfs_filesystem;
+#[cfg(feature = "filesystem")]
+pub use romfs_filesystem::RomFSFileSystem;
 
 mod partition;
 pub use partition::Partition;
-pub use partition::PartitionMutex;
+pub use partition::{PartitionMutex, ReadAtPartition};
 
 mod ivfc;
-pub use ivfc::{IVFCError, IVFCReader};
+pub use ivfc::{
+    DirectoryMetadata, DirectoryOrFile, FileMetadata, IVFCError, IVFCReader, IVFCWriteResult,
+    IVFCWriter, IVFCWriterFile, IVFCWriterSource,
+};
 
 mod ivfc_vfs;
 pub use ivfc_vfs::{IVFCMeta, IVFCVFS, IVFCVPATH};
 
+mod diff;
+pub use diff::{diff_romfs, hash_romfs_file, RomFSDiff, RomFSDiffError};
+
+mod layered_vfs;
+pub use layered_vfs::{LayeredFile, LayeredMeta, LayeredVFS, LayeredVPATH};
+
+mod title_vfs;
+pub use title_vfs::{TitleVFS, TitleVPATH};
+
+mod signature;
+pub use signature::{signature_block_lenght, Certificate, RSAPublicKey, SignatureError};
+
+/// The size of a media unit, the unit of the offsets and lenghts in the NCSD and NCCH headers
+const MEDIA_UNIT_SIZE: u64 = 0x200;
+
+/// Convert a number of media units to a number of bytes. It can't overflow, as the result is at most 2^41.
+fn media_units_to_bytes(media_units: u32) -> u64 {
+    media_units as u64 * MEDIA_UNIT_SIZE
+}
+
 #[derive(Debug, Clone, Copy)]
 struct PartitionData {
-    offset: u32,
-    lenght: u32,
+    offset: u64,
+    lenght: u64,
+}
+
+impl PartitionData {
+    /// Create a `PartitionData` from an offset and a lenght in media units
+    fn from_media_units(offset: u32, lenght: u32) -> PartitionData {
+        PartitionData {
+            offset: media_units_to_bytes(offset),
+            lenght: media_units_to_bytes(lenght),
+        }
+    }
+
+    /// The offset of the first byte after the partition
+    fn end(&self) -> u64 {
+        self.offset + self.lenght
+    }
+
+    /// Return true if both partitions are non-empty and share at least one byte
+    fn overlap(&self, other: &PartitionData) -> bool {
+        self.lenght != 0
+            && other.lenght != 0
+            && self.offset < other.end()
+            && other.offset < self.end()
+    }
 }
 
 #[derive(Debug)]
@@ -87,9 +209,11 @@ impl From<IVFCError> for GetRomfsError {
 }
 
 /// Read a .3ds file, and return an `IVFCVFS` object if succesfull.
-pub fn get_romfs_vfs<T: io::Read + io::Seek + fmt::Debug + Send + Sync>(
+///
+/// Every read lock the file. Use `get_romfs_vfs_read_at` to read in parallel from a source that implement `ReadAt` (like a `File`).
+pub fn get_romfs_vfs<T: io::Read + io::Seek + fmt::Debug + Send>(
     file: T,
-) -> Result<IVFCVFS<Partition<Partition<T>>>, GetRomfsError> {
+) -> Result<IVFCVFS<Mutex<Partition<Partition<T>>>>, GetRomfsError> {
     let ncsd = NCSDReader::new(file)?;
     let partition = ncsd.load_partition(0)?;
     let ncch = NCCHReader::new(partition)?;
@@ -97,3 +221,27 @@ pub fn get_romfs_vfs<T: io::Read + io::Seek + fmt::Debug + Send + Sync>(
     let ivfc = IVFCReader::new(romfs)?;
     Ok(IVFCVFS::new(ivfc))
 }
+
+/// Read a .3ds file with positional reads, and return an `IVFCVFS` object if succesfull.
+///
+/// Unlike `get_romfs_vfs`, reading files doesn't need to lock the source, so multiple threads can read the romfs at the same time.
+pub fn get_romfs_vfs_read_at<T: ReadAt + fmt::Debug + Send + Sync>(
+    file: T,
+) -> Result<IVFCVFS<ReadAtPartition<T>>, GetRomfsError> {
+    let file = Arc::new(file);
+    let ncsd = NCSDReader::new(ReadAtCursor::new(&*file))?;
+    let (partition_offset, partition_lenght) = ncsd.get_partition_bounds(0)?;
+    let partition = match ReadAtPartition::new(file.clone(), partition_offset, partition_lenght) {
+        Ok(value) => value,
+        Err(err) => return Err(NCSDError::CreatePartitionFail(err).into()),
+    };
+    let ncch = NCCHReader::new(ReadAtCursor::new(&partition))?;
+    let (romfs_offset, romfs_lenght) = ncch.get_romfs_bounds();
+    let romfs =
+        match ReadAtPartition::new(file.clone(), partition_offset + romfs_offset, romfs_lenght) {
+            Ok(value) => value,
+            Err(err) => return Err(NCCHError::CreatePartitionError(err).into()),
+        };
+    let ivfc = IVFCReader::from_read_at(romfs)?;
+    Ok(IVFCVFS::new(ivfc))
+}
@@ -0,0 +1,132 @@
+use crate::lz::{decompress_lz11, LZError};
+use crate::{DARCError, DARCReader, DARCVFS};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::{Cursor, Read};
+
+#[derive(Debug)]
+pub enum LogoError {
+    ReadError(io::Error),
+    DecompressionError(LZError),
+    DARCError(DARCError),
+}
+
+impl Error for LogoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::ReadError(err) => Some(err),
+            Self::DecompressionError(err) => Some(err),
+            Self::DARCError(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for LogoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadError(_) => write!(f, "failed to read the logo region"),
+            Self::DecompressionError(_) => write!(f, "failed to decompress the logo region"),
+            Self::DARCError(_) => write!(f, "failed to read the darc of the logo region"),
+        }
+    }
+}
+
+/// A logo whose SHA-256 is known, used to recognize standard logos
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownLogo {
+    pub name: &'static str,
+    /// The SHA-256 of the whole logo region, as stored in the NCCH
+    pub sha256: [u8; 32],
+}
+
+/// The standard logos of retail titles, recognized by `LogoReader::identify`.
+///
+/// Until it is filled, `identify` consider every logo to be custom. Use `identify_in` to check against other hashes.
+//TODO: the hashes of the "Nintendo", "Licensed by Nintendo", "Distributed by Nintendo", "iQue" and "iQue for Nintendo"
+// logo regions. They must be taken from the logo region of genuine dumps, with `LogoReader::sha256`.
+pub const STANDARD_LOGOS: &[KnownLogo] = &[];
+
+/// Read the logo region of a NCCH, that contain a LZ11 compressed DARC with the layout and textures of the boot splash.
+pub struct LogoReader {
+    /// The SHA-256 of the whole logo region, as stored in the NCCH
+    pub sha256: [u8; 32],
+    darc: DARCReader<Cursor<Vec<u8>>>,
+}
+
+impl LogoReader {
+    pub fn new<T: Read>(mut file: T) -> Result<LogoReader, LogoError> {
+        let mut data = Vec::new();
+        match file.read_to_end(&mut data) {
+            Ok(_) => (),
+            Err(err) => return Err(LogoError::ReadError(err)),
+        };
+        let sha256 = Sha256::digest(&data).into();
+        let decompressed = match decompress_lz11(&data) {
+            Ok(value) => value,
+            Err(err) => return Err(LogoError::DecompressionError(err)),
+        };
+        let darc = match DARCReader::new(Cursor::new(decompressed)) {
+            Ok(value) => value,
+            Err(err) => return Err(LogoError::DARCError(err)),
+        };
+        Ok(LogoReader { sha256, darc })
+    }
+
+    /// Return the standard logo with the same hash as this one, or `None` if this is a custom logo
+    pub fn identify(&self) -> Option<&'static KnownLogo> {
+        self.identify_in(STANDARD_LOGOS)
+    }
+
+    /// Like `identify`, but look for the logo in `known_logos` instead of the standard logos
+    pub fn identify_in<'a>(&self, known_logos: &'a [KnownLogo]) -> Option<&'a KnownLogo> {
+        known_logos.iter().find(|logo| logo.sha256 == self.sha256)
+    }
+
+    pub fn get_darc(&self) -> &DARCReader<Cursor<Vec<u8>>> {
+        &self.darc
+    }
+
+    /// Return a VFS to browse the content of the logo DARC
+    pub fn into_vfs(self) -> DARCVFS<Cursor<Vec<u8>>> {
+        DARCVFS::new(self.darc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::darc::tests::darc_from_files;
+    use crate::lz::compress_lz11;
+
+    #[test]
+    fn test_identify_in() {
+        let logo_region = compress_lz11(&darc_from_files(&[])).unwrap();
+        let logo = LogoReader::new(Cursor::new(&logo_region)).unwrap();
+        let known_logos = [
+            KnownLogo {
+                name: "other",
+                sha256: [0; 32],
+            },
+            KnownLogo {
+                name: "custom",
+                sha256: Sha256::digest(&logo_region).into(),
+            },
+        ];
+        assert_eq!(logo.identify_in(&known_logos).unwrap().name, "custom");
+        assert_eq!(logo.identify_in(&known_logos[..1]), None);
+    }
+
+    #[test]
+    fn test_identify() {
+        let logo_region = compress_lz11(&darc_from_files(&[("custom.bclim", b"custom")])).unwrap();
+        let logo = LogoReader::new(Cursor::new(&logo_region)).unwrap();
+        assert_eq!(logo.identify(), None);
+        for (logo_nb, known_logo) in STANDARD_LOGOS.iter().enumerate() {
+            assert!(STANDARD_LOGOS[..logo_nb]
+                .iter()
+                .all(|other| other.sha256 != known_logo.sha256));
+        }
+    }
+}
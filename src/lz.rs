@@ -0,0 +1,398 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LZError {
+    InvalidMagic(u8), // the invalid first byte
+    TruncatedInput,
+    InvalidDisplacement(usize, usize), // displacement, position in the output
+    InputTooBig(usize),                // the lenght of the data to compress
+}
+
+impl Error for LZError {}
+
+impl fmt::Display for LZError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidMagic(magic) => write!(
+                f,
+                "the first byte of the compressed data is {:#x}, that isn't a supported compression type",
+                magic
+            ),
+            Self::TruncatedInput => write!(f, "the compressed data end before the end of the stream"),
+            Self::InvalidDisplacement(displacement, position) => write!(
+                f,
+                "the displacement {} point before the beggining of the output (at position {})",
+                displacement, position
+            ),
+            Self::InputTooBig(lenght) => write!(
+                f,
+                "the data to compress is {} bytes long, but the header can't store a lenght bigger than 4 GiB",
+                lenght
+            ),
+        }
+    }
+}
+
+fn get_byte(data: &[u8], position: usize) -> Result<u8, LZError> {
+    match data.get(position) {
+        Some(value) => Ok(*value),
+        None => Err(LZError::TruncatedInput),
+    }
+}
+
+/// Read the header of a compressed stream, returning the decompressed lenght and the offset of the data.
+fn read_header(data: &[u8], magic: u8) -> Result<(usize, usize), LZError> {
+    let first_byte = get_byte(data, 0)?;
+    if first_byte != magic {
+        return Err(LZError::InvalidMagic(first_byte));
+    };
+    if data.len() < 4 {
+        return Err(LZError::TruncatedInput);
+    };
+    let lenght = u32::from_le_bytes([data[1], data[2], data[3], 0]) as usize;
+    if lenght == 0 {
+        // a lenght of 0 mean the real lenght is stored in the next 4 bytes
+        if data.len() < 8 {
+            return Err(LZError::TruncatedInput);
+        };
+        Ok((
+            u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize,
+            8,
+        ))
+    } else {
+        Ok((lenght, 4))
+    }
+}
+
+/// The capacity to reserve for the output. The lenght comes from the header and can't be trusted, so it is capped to what the input can reasonably expand to.
+fn output_capacity(data: &[u8], lenght: usize) -> usize {
+    std::cmp::min(lenght, data.len().saturating_mul(8))
+}
+
+fn copy_back_reference(
+    output: &mut Vec<u8>,
+    displacement: usize,
+    lenght: usize,
+) -> Result<(), LZError> {
+    if displacement > output.len() {
+        return Err(LZError::InvalidDisplacement(displacement, output.len()));
+    };
+    let start = output.len() - displacement;
+    for byte_nb in 0..lenght {
+        let byte = output[start + byte_nb];
+        output.push(byte);
+    }
+    Ok(())
+}
+
+/// Decompress a LZ10 (type 0x10) compressed buffer.
+pub fn decompress_lz10(data: &[u8]) -> Result<Vec<u8>, LZError> {
+    let (lenght, mut position) = read_header(data, 0x10)?;
+    let mut output = Vec::with_capacity(output_capacity(data, lenght));
+
+    while output.len() < lenght {
+        let flags = get_byte(data, position)?;
+        position += 1;
+        for bit_nb in (0..8).rev() {
+            if output.len() >= lenght {
+                break;
+            };
+            if flags & (1 << bit_nb) == 0 {
+                output.push(get_byte(data, position)?);
+                position += 1;
+                continue;
+            };
+            let byte_0 = get_byte(data, position)? as usize;
+            let byte_1 = get_byte(data, position + 1)? as usize;
+            position += 2;
+            let copy_lenght = std::cmp::min((byte_0 >> 4) + 3, lenght - output.len());
+            let displacement = (((byte_0 & 0xF) << 8) | byte_1) + 1;
+            copy_back_reference(&mut output, displacement, copy_lenght)?;
+        }
+    }
+
+    Ok(output)
+}
+
+/// Decompress a LZ11 (type 0x11) compressed buffer.
+pub fn decompress_lz11(data: &[u8]) -> Result<Vec<u8>, LZError> {
+    let (lenght, mut position) = read_header(data, 0x11)?;
+    let mut output = Vec::with_capacity(output_capacity(data, lenght));
+
+    while output.len() < lenght {
+        let flags = get_byte(data, position)?;
+        position += 1;
+        for bit_nb in (0..8).rev() {
+            if output.len() >= lenght {
+                break;
+            };
+            if flags & (1 << bit_nb) == 0 {
+                output.push(get_byte(data, position)?);
+                position += 1;
+                continue;
+            };
+            let byte_0 = get_byte(data, position)? as usize;
+            let byte_1 = get_byte(data, position + 1)? as usize;
+            let (copy_lenght, displacement) = match byte_0 >> 4 {
+                0 => {
+                    let byte_2 = get_byte(data, position + 2)? as usize;
+                    position += 3;
+                    (
+                        (((byte_0 & 0xF) << 4) | (byte_1 >> 4)) + 0x11,
+                        (((byte_1 & 0xF) << 8) | byte_2) + 1,
+                    )
+                }
+                1 => {
+                    let byte_2 = get_byte(data, position + 2)? as usize;
+                    let byte_3 = get_byte(data, position + 3)? as usize;
+                    position += 4;
+                    (
+                        (((byte_0 & 0xF) << 12) | (byte_1 << 4) | (byte_2 >> 4)) + 0x111,
+                        (((byte_2 & 0xF) << 8) | byte_3) + 1,
+                    )
+                }
+                indicator => {
+                    position += 2;
+                    (indicator + 1, (((byte_0 & 0xF) << 8) | byte_1) + 1)
+                }
+            };
+            let copy_lenght = std::cmp::min(copy_lenght, lenght - output.len());
+            copy_back_reference(&mut output, displacement, copy_lenght)?;
+        }
+    }
+
+    Ok(output)
+}
+
+/// Decompress a LZ13 (type 0x13) compressed buffer. This is a 4 bytes header followed by a complete LZ11 stream.
+pub fn decompress_lz13(data: &[u8]) -> Result<Vec<u8>, LZError> {
+    let (_, position) = read_header(data, 0x13)?;
+    decompress_lz11(&data[position..])
+}
+
+/// Decompress a buffer compressed with any supported LZ variant, chosen with its first byte.
+pub fn decompress_lz(data: &[u8]) -> Result<Vec<u8>, LZError> {
+    match get_byte(data, 0)? {
+        0x10 => decompress_lz10(data),
+        0x11 => decompress_lz11(data),
+        0x13 => decompress_lz13(data),
+        magic => Err(LZError::InvalidMagic(magic)),
+    }
+}
+
+/// Return the decompressed lenght written in the header of a LZ compressed buffer, or `None` if it isn't a supported LZ header.
+///
+/// `header` should contain at least the first 8 bytes of the file.
+pub fn get_lz_decompressed_lenght(header: &[u8]) -> Option<usize> {
+    match header.first()? {
+        0x10 | 0x11 | 0x13 => match read_header(header, header[0]) {
+            Ok((lenght, _)) => Some(lenght),
+            Err(_) => None,
+        },
+        _ => None,
+    }
+}
+
+fn write_header(output: &mut Vec<u8>, magic: u8, lenght: usize) -> Result<(), LZError> {
+    let lenght_u32 = match u32::try_from(lenght) {
+        Ok(value) => value,
+        Err(_) => return Err(LZError::InputTooBig(lenght)),
+    };
+    // a lenght of 0 in the short header mean the lenght is in the extended header
+    if lenght != 0 && lenght <= 0xFF_FFFF {
+        output.push(magic);
+        output.extend_from_slice(&lenght_u32.to_le_bytes()[..3]);
+    } else {
+        output.extend_from_slice(&[magic, 0, 0, 0]);
+        output.extend_from_slice(&lenght_u32.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// Find the longest match for the data at `position` in the previous 0x1000 bytes.
+///
+/// `heads` contain, for each 3 bytes prefix hash, the last position where it was seen, and `previous` the position before that with the same hash.
+fn find_match(
+    data: &[u8],
+    position: usize,
+    max_lenght: usize,
+    heads: &[usize],
+    previous: &[usize],
+) -> (usize, usize) {
+    let mut best_lenght = 0;
+    let mut best_displacement = 0;
+    if position + 3 > data.len() {
+        return (0, 0);
+    };
+    let max_lenght = std::cmp::min(max_lenght, data.len() - position);
+    let mut candidate = heads[hash_prefix(data, position)];
+    let mut tries = 0;
+    while candidate != usize::MAX && position - candidate <= 0x1000 && tries < 256 {
+        let mut lenght = 0;
+        while lenght < max_lenght && data[candidate + lenght] == data[position + lenght] {
+            lenght += 1;
+        }
+        if lenght > best_lenght {
+            best_lenght = lenght;
+            best_displacement = position - candidate;
+            if lenght == max_lenght {
+                break;
+            };
+        };
+        candidate = previous[candidate];
+        tries += 1;
+    }
+    (best_lenght, best_displacement)
+}
+
+fn hash_prefix(data: &[u8], position: usize) -> usize {
+    ((data[position] as usize) << 8
+        ^ (data[position + 1] as usize) << 4
+        ^ data[position + 2] as usize)
+        & 0xFFFF
+}
+
+fn insert_position(data: &[u8], position: usize, heads: &mut [usize], previous: &mut [usize]) {
+    if position + 3 <= data.len() {
+        let hash = hash_prefix(data, position);
+        previous[position] = heads[hash];
+        heads[hash] = position;
+    };
+}
+
+/// Compress `data` with a greedy LZ77 search, appending the flags, literals and back references to `output`.
+///
+/// A set bit in the flags mark a back reference, or a literal if `flag_literals` is true. `write_reference` encode the back references.
+pub(crate) fn compress_stream(
+    data: &[u8],
+    output: &mut Vec<u8>,
+    max_lenght: usize,
+    flag_literals: bool,
+    write_reference: fn(&mut Vec<u8>, usize, usize),
+) {
+    let mut heads = vec![usize::MAX; 0x10000];
+    let mut previous = vec![usize::MAX; data.len()];
+
+    let mut position = 0;
+    while position < data.len() {
+        let flags_position = output.len();
+        output.push(0);
+        for bit_nb in (0..8).rev() {
+            if position >= data.len() {
+                break;
+            };
+            let (lenght, displacement) = find_match(data, position, max_lenght, &heads, &previous);
+            if lenght >= 3 {
+                if !flag_literals {
+                    output[flags_position] |= 1 << bit_nb;
+                };
+                write_reference(output, lenght, displacement);
+                for inserted_position in position..position + lenght {
+                    insert_position(data, inserted_position, &mut heads, &mut previous);
+                }
+                position += lenght;
+            } else {
+                if flag_literals {
+                    output[flags_position] |= 1 << bit_nb;
+                };
+                output.push(data[position]);
+                insert_position(data, position, &mut heads, &mut previous);
+                position += 1;
+            };
+        }
+    }
+}
+
+fn compress_generic(
+    data: &[u8],
+    magic: u8,
+    max_lenght: usize,
+    write_reference: fn(&mut Vec<u8>, usize, usize),
+) -> Result<Vec<u8>, LZError> {
+    let mut output = Vec::with_capacity(data.len() / 2 + 8);
+    write_header(&mut output, magic, data.len())?;
+    compress_stream(data, &mut output, max_lenght, false, write_reference);
+
+    // pad to a multiple of 4, as expected by some decompressors
+    while output.len() % 4 != 0 {
+        output.push(0);
+    }
+    Ok(output)
+}
+
+/// Compress `data` with LZ10 (type 0x10). Fail if `data` is 4 GiB or more.
+pub fn compress_lz10(data: &[u8]) -> Result<Vec<u8>, LZError> {
+    compress_generic(data, 0x10, 18, |output, lenght, displacement| {
+        let displacement = displacement - 1;
+        output.push((((lenght - 3) << 4) | (displacement >> 8)) as u8);
+        output.push((displacement & 0xFF) as u8);
+    })
+}
+
+/// Compress `data` with LZ11 (type 0x11). Fail if `data` is 4 GiB or more.
+pub fn compress_lz11(data: &[u8]) -> Result<Vec<u8>, LZError> {
+    compress_generic(data, 0x11, 0x10110, |output, lenght, displacement| {
+        let displacement = displacement - 1;
+        if lenght <= 0x10 {
+            output.push((((lenght - 1) << 4) | (displacement >> 8)) as u8);
+            output.push((displacement & 0xFF) as u8);
+        } else if lenght <= 0x110 {
+            let lenght = lenght - 0x11;
+            output.push((lenght >> 4) as u8);
+            output.push((((lenght & 0xF) << 4) | (displacement >> 8)) as u8);
+            output.push((displacement & 0xFF) as u8);
+        } else {
+            let lenght = lenght - 0x111;
+            output.push((0x10 | (lenght >> 12)) as u8);
+            output.push(((lenght >> 4) & 0xFF) as u8);
+            output.push((((lenght & 0xF) << 4) | (displacement >> 8)) as u8);
+            output.push((displacement & 0xFF) as u8);
+        }
+    })
+}
+
+/// Compress `data` with LZ13 (type 0x13), that is a LZ11 stream with an additional header.
+pub fn compress_lz13(data: &[u8]) -> Result<Vec<u8>, LZError> {
+    let mut output = Vec::new();
+    write_header(&mut output, 0x13, data.len())?;
+    output.extend(compress_lz11(data)?);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Data with both repetitions (short and long) and unique bytes
+    fn sample_data() -> Vec<u8> {
+        let mut data = b"abcabcabcabcabcd".to_vec();
+        data.extend((0..0x2000u32).map(|value| (value * 7 % 251) as u8));
+        data.extend(std::iter::repeat(0x42).take(0x12000));
+        data.extend_from_slice(b"abcabcabcabcabcd");
+        data
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = sample_data();
+        for compressed in [
+            compress_lz10(&data).unwrap(),
+            compress_lz11(&data).unwrap(),
+            compress_lz13(&data).unwrap(),
+        ] {
+            assert!(compressed.len() < data.len());
+            assert_eq!(get_lz_decompressed_lenght(&compressed), Some(data.len()));
+            assert_eq!(decompress_lz(&compressed).unwrap(), data);
+        }
+        assert_eq!(decompress_lz(&compress_lz11(&[]).unwrap()).unwrap(), []);
+    }
+
+    #[test]
+    fn test_huge_declared_lenght() {
+        // the header claim 4 GiB, but the stream end after a single literal
+        let compressed = [0x11, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0x42];
+        assert_eq!(decompress_lz11(&compressed), Err(LZError::TruncatedInput));
+    }
+}
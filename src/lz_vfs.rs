@@ -0,0 +1,290 @@
+use crate::lz::{decompress_lz, get_lz_decompressed_lenght};
+use std::borrow::Cow;
+use std::io;
+use std::io::SeekFrom;
+use std::io::{Cursor, Read, Seek, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use vfs::{OpenOptions, VFile, VMetadata, VPath, VFS};
+
+/// A read only VFS that wrap another one, and transparently decompress the LZ10, LZ11 and LZ13 compressed files.
+///
+/// A file is considered compressed if it start with a valid LZ header, so its lenght is known without decompressing it.
+/// As the header is only 4 or 8 bytes long, a raw file may look like a compressed one, and then fail to be opened.
+/// Use `with_extensions` to only decompress the files with some extensions.
+pub struct LZVFS {
+    root: Box<dyn VPath>,
+    extensions: Option<Arc<Vec<String>>>,
+}
+
+impl LZVFS {
+    /// Wrap the VFS whose root is `root`, like `IVFCVFS::path("")`
+    pub fn new<P: VPath + 'static>(root: P) -> LZVFS {
+        LZVFS {
+            root: Box::new(root),
+            extensions: None,
+        }
+    }
+
+    /// Like `new`, but only decompress the files whose extension is one of `extensions` (without the dot). Other files are returned as is.
+    pub fn with_extensions<P: VPath + 'static>(root: P, extensions: &[&str]) -> LZVFS {
+        LZVFS {
+            root: Box::new(root),
+            extensions: Some(Arc::new(
+                extensions
+                    .iter()
+                    .map(|extension| extension.to_string())
+                    .collect(),
+            )),
+        }
+    }
+}
+
+impl VFS for LZVFS {
+    type PATH = LZVPATH;
+    type METADATA = LZMeta;
+    type FILE = Box<dyn VFile>;
+
+    fn path<A: Into<String>>(&self, path: A) -> Self::PATH {
+        LZVPATH {
+            inner: self.root.resolve(&path.into()),
+            extensions: self.extensions.clone(),
+        }
+    }
+}
+
+/// A decompressed file, kept in memory
+#[derive(Debug)]
+struct DecompressedFile(Cursor<Vec<u8>>);
+
+impl Read for DecompressedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Seek for DecompressedFile {
+    fn seek(&mut self, target: SeekFrom) -> io::Result<u64> {
+        self.0.seek(target)
+    }
+}
+
+impl Write for DecompressedFile {
+    /// Do not use this write function. It is just here to make ``vfs::VFile`` happy. It will always return an error.
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::from(io::ErrorKind::PermissionDenied))
+    }
+
+    /// Always suceed. It is useless to call it
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LZMeta {
+    is_dir: bool,
+    lenght: u64,
+    /// true if the file has a LZ header, and `lenght` is the decompressed lenght it contain
+    pub is_compressed: bool,
+}
+
+impl VMetadata for LZMeta {
+    fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    fn is_file(&self) -> bool {
+        !self.is_dir
+    }
+
+    fn len(&self) -> u64 {
+        self.lenght
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LZVPATH {
+    inner: Box<dyn VPath>,
+    extensions: Option<Arc<Vec<String>>>,
+}
+
+fn return_ro_error<T>() -> io::Result<T> {
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "read only file system",
+    ))
+}
+
+impl LZVPATH {
+    fn wrap(&self, inner: Box<dyn VPath>) -> Box<dyn VPath> {
+        Box::new(LZVPATH {
+            inner,
+            extensions: self.extensions.clone(),
+        })
+    }
+
+    /// Return the decompressed lenght written in the header of this file, or `None` if it isn't a LZ compressed file
+    fn get_decompressed_lenght(&self, compressed_lenght: u64) -> io::Result<Option<usize>> {
+        if let Some(extensions) = &self.extensions {
+            match self.inner.extension() {
+                Some(extension) if extensions.contains(&extension) => (),
+                _ => return Ok(None),
+            };
+        };
+        let mut header = vec![0; std::cmp::min(compressed_lenght, 8) as usize];
+        self.inner.open()?.read_exact(&mut header)?;
+        Ok(get_lz_decompressed_lenght(&header))
+    }
+}
+
+impl VPath for LZVPATH {
+    fn open_with_options(&self, opt: &OpenOptions) -> io::Result<Box<dyn VFile>> {
+        if opt.write || opt.create || opt.append || opt.truncate {
+            return return_ro_error();
+        };
+        let compressed_lenght = self.inner.metadata()?.len();
+        if self.get_decompressed_lenght(compressed_lenght)?.is_none() {
+            return self.inner.open_with_options(opt);
+        };
+        let mut compressed = vec![0; compressed_lenght as usize];
+        self.inner.open()?.read_exact(&mut compressed)?;
+        match decompress_lz(&compressed) {
+            Ok(decompressed) => Ok(Box::new(DecompressedFile(Cursor::new(decompressed)))),
+            Err(err) => Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn read_dir(&self) -> io::Result<Box<dyn Iterator<Item = io::Result<Box<dyn VPath>>>>> {
+        let parent = self.clone();
+        Ok(Box::new(
+            self.inner
+                .read_dir()?
+                .map(move |child| child.map(|inner| parent.wrap(inner))),
+        ))
+    }
+
+    fn mkdir(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn rm(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn rmrf(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn file_name(&self) -> Option<String> {
+        self.inner.file_name()
+    }
+
+    fn extension(&self) -> Option<String> {
+        self.inner.extension()
+    }
+
+    fn resolve(&self, path: &String) -> Box<dyn VPath> {
+        self.wrap(self.inner.resolve(path))
+    }
+
+    fn parent(&self) -> Option<Box<dyn VPath>> {
+        self.inner.parent().map(|inner| self.wrap(inner))
+    }
+
+    fn to_string(&self) -> Cow<'_, str> {
+        format!("lz:{}", self.inner.to_string()).into()
+    }
+
+    fn box_clone(&self) -> Box<dyn VPath> {
+        Box::new(self.clone())
+    }
+
+    fn to_path_buf(&self) -> Option<PathBuf> {
+        self.inner.to_path_buf()
+    }
+
+    fn exists(&self) -> bool {
+        self.inner.exists()
+    }
+
+    /// The lenght of a compressed file is read from its header, without decompressing it.
+    fn metadata(&self) -> io::Result<Box<dyn VMetadata>> {
+        let inner_meta = self.inner.metadata()?;
+        if inner_meta.is_dir() {
+            return Ok(Box::new(LZMeta {
+                is_dir: true,
+                lenght: 0,
+                is_compressed: false,
+            }));
+        };
+        Ok(Box::new(
+            match self.get_decompressed_lenght(inner_meta.len())? {
+                Some(decompressed_lenght) => LZMeta {
+                    is_dir: false,
+                    lenght: decompressed_lenght as u64,
+                    is_compressed: true,
+                },
+                None => LZMeta {
+                    is_dir: false,
+                    lenght: inner_meta.len(),
+                    is_compressed: false,
+                },
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ivfc::romfs_from_files;
+    use crate::lz::compress_lz11;
+    use crate::{IVFCReader, IVFCVFS};
+
+    #[test]
+    fn test_decompress_and_fallback() {
+        let compressed = compress_lz11(b"hello hello hello").unwrap();
+        // a first byte that look like a LZ10 header, that can't be decompressed
+        let not_compressed = b"\x10\x20\x00\x00raw";
+        let romfs = romfs_from_files(&[("a.lz", &compressed), ("b.bin", not_compressed)]);
+        let romfs = IVFCVFS::new(IVFCReader::from_read_at(romfs).unwrap());
+
+        let read = |vfs: &LZVFS, path: &str| -> io::Result<Vec<u8>> {
+            let mut content = Vec::new();
+            vfs.path(path).open()?.read_to_end(&mut content)?;
+            Ok(content)
+        };
+
+        // only the files with the given extensions are decompressed
+        let vfs = LZVFS::with_extensions(romfs.path(""), &["lz"]);
+        for (path, expected) in [
+            ("a.lz", &b"hello hello hello"[..]),
+            ("b.bin", &not_compressed[..]),
+        ] {
+            assert_eq!(read(&vfs, path).unwrap(), expected);
+            assert_eq!(
+                vfs.path(path).metadata().unwrap().len(),
+                expected.len() as u64
+            );
+        }
+
+        // otherwise, the header is enough for a file to be considered compressed
+        let vfs = LZVFS::new(romfs.path(""));
+        assert_eq!(read(&vfs, "a.lz").unwrap(), b"hello hello hello");
+        assert_eq!(vfs.path("b.bin").metadata().unwrap().len(), 0x20);
+        assert_eq!(
+            read(&vfs, "b.bin").unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        let listed: Vec<u64> = vfs
+            .path("")
+            .read_dir()
+            .unwrap()
+            .map(|child| child.unwrap().metadata().unwrap().len())
+            .collect();
+        assert_eq!(listed, vec![17, 0x20]);
+    }
+}
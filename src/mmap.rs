@@ -0,0 +1,77 @@
+use crate::{get_romfs_vfs_read_at, GetRomfsError, ReadAt, ReadAtPartition, IVFCVFS};
+pub use memmap2::Mmap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum MmapRomError {
+    OpenError(io::Error),
+    MapError(io::Error),
+    GetRomfsError(GetRomfsError),
+}
+
+impl Error for MmapRomError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::OpenError(err) => Some(err),
+            Self::MapError(err) => Some(err),
+            Self::GetRomfsError(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for MmapRomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OpenError(_) => write!(f, "can't open the rom file"),
+            Self::MapError(_) => write!(f, "can't map the rom file in memory"),
+            Self::GetRomfsError(_) => write!(f, "can't read the romfs of the mapped rom"),
+        }
+    }
+}
+
+impl From<GetRomfsError> for MmapRomError {
+    fn from(e: GetRomfsError) -> MmapRomError {
+        MmapRomError::GetRomfsError(e)
+    }
+}
+
+impl ReadAt for Mmap {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).read_at(offset, buf)
+    }
+}
+
+/// Map the file at `path` in memory, read only.
+///
+/// # Safety
+///
+/// The file must not be modified or truncated, by this process or another one, while the map is alive: the mapped bytes would change under the reader, which is undefined behavior.
+pub unsafe fn open_mmap<P: AsRef<Path>>(path: P) -> Result<Mmap, MmapRomError> {
+    let file = match File::open(path) {
+        Ok(value) => value,
+        Err(err) => return Err(MmapRomError::OpenError(err)),
+    };
+    // safety: the map is read only, and the caller guarantee the file isn't modified while it is mapped
+    match Mmap::map(&file) {
+        Ok(value) => Ok(value),
+        Err(err) => Err(MmapRomError::MapError(err)),
+    }
+}
+
+/// Map the .3ds file at `path` in memory, and return an `IVFCVFS` object over its romfs.
+///
+/// Files opened with `IVFCVPATH::open_partition` are slices of the map, that can be borrowed without copy with `ReadAtPartition::as_slice`.
+///
+/// # Safety
+///
+/// Same as `open_mmap`: the file must not be modified or truncated while the returned `IVFCVFS` (or any file opened from it) is alive.
+pub unsafe fn get_romfs_vfs_mmap<P: AsRef<Path>>(
+    path: P,
+) -> Result<IVFCVFS<ReadAtPartition<Mmap>>, MmapRomError> {
+    let map = open_mmap(path)?;
+    Ok(get_romfs_vfs_read_at(map)?)
+}
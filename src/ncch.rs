@@ -1,10 +1,18 @@
 use crate::Partition;
-use crate::PartitionData;
+use crate::{media_units_to_bytes, PartitionData, MEDIA_UNIT_SIZE};
+use crate::{ExeFSError, ExeFSWriter, IVFCError, IVFCWriter};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt;
 use std::io;
 use std::io::SeekFrom;
-use std::io::{Read, Seek};
+use std::io::{Read, Seek, Write};
+
+/// The offset of the extended header in a NCCH
+pub const NCCH_EXHEADER_OFFSET: u64 = 0x200;
+/// The lenght of the extended header followed by the access descriptor
+pub const NCCH_EXHEADER_LENGHT: u64 = 0x800;
 
 #[derive(Debug)]
 pub enum NCCHError {
@@ -15,12 +23,20 @@ pub enum NCCHError {
     PartitionIdReadError(io::Error),
     MakerCodeReadError(io::Error),
     VersionReadError(io::Error),
-    FlagsSeekError(io::Error),
+    ProgramIdReadError(io::Error),
+    ProductCodeReadError(io::Error),
     FlagsReadError(io::Error),
     OffsetSeekError(io::Error, &'static str),
     OffsetReadError(io::Error, &'static str),
     LenghtReadError(io::Error, &'static str),
     CreatePartitionError(io::Error),
+    RegionPastEnd(&'static str), // the region that end after the content size
+    OverlappingRegions(&'static str, &'static str), // the two regions that overlap
+    WriteError(io::Error),
+    InvalidExheaderLenght(usize), // the lenght of the given extended header
+    ExeFSWriteError(ExeFSError),
+    RomFSWriteError(IVFCError),
+    TooBig(&'static str), // what can't be stored in the header
 }
 
 impl Error for NCCHError {
@@ -32,12 +48,16 @@ impl Error for NCCHError {
             Self::PartitionIdReadError(ioerror) => Some(ioerror),
             Self::MakerCodeReadError(ioerror) => Some(ioerror),
             Self::VersionReadError(ioerror) => Some(ioerror),
-            Self::FlagsSeekError(ioerror) => Some(ioerror),
+            Self::ProgramIdReadError(ioerror) => Some(ioerror),
+            Self::ProductCodeReadError(ioerror) => Some(ioerror),
             Self::FlagsReadError(ioerror) => Some(ioerror),
             Self::OffsetSeekError(ioerror, _) => Some(ioerror),
             Self::OffsetReadError(ioerror, _) => Some(ioerror),
             Self::LenghtReadError(ioerror, _) => Some(ioerror),
             Self::CreatePartitionError(ioerror) => Some(ioerror),
+            Self::WriteError(ioerror) => Some(ioerror),
+            Self::ExeFSWriteError(err) => Some(err),
+            Self::RomFSWriteError(err) => Some(err),
             _ => None,
         }
     }
@@ -45,16 +65,24 @@ impl Error for NCCHError {
 
 impl fmt::Display for NCCHError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            _ => write!(f, "{:?}", self), //TODO: specific error message
-        }
+        write!(f, "{:?}", self) //TODO: specific error message
     }
 }
 
 pub struct NCCHReader<T: Read + Seek> {
     file: T,
-    pub content_size: u32,
+    pub content_size: u64,
+    pub partition_id: u64,
+    /// Two ascii characters, like "01"
+    pub maker_code: String,
     pub version: u16,
+    pub program_id: u64,
+    /// Like "CTR-P-ABCD", without the trailing null bytes
+    pub product_code: String,
+    /// The size of the extended header, without the access descriptor. 0 if there isn't one.
+    pub exheader_size: u32,
+    /// The raw flags. The 6th byte is the content type, and the 8th contain the crypto related flags.
+    pub flags: [u8; 8],
     plain_region: PartitionData,
     logo_region: PartitionData,
     exefs: PartitionData,
@@ -89,7 +117,7 @@ impl<T: Read + Seek> NCCHReader<T> {
             Err(err) => return Err(NCCHError::SizeReadError(err)),
         };
 
-        let content_size = u32::from_le_bytes(content_size) * 0x200;
+        let content_size = media_units_to_bytes(u32::from_le_bytes(content_size));
 
         // partition id
         let mut partition_id = [0; 8];
@@ -114,12 +142,61 @@ impl<T: Read + Seek> NCCHReader<T> {
 
         let version = u16::from_le_bytes(version);
 
-        // flags
-        match file.seek(SeekFrom::Start(0x188)) {
+        let partition_id = u64::from_le_bytes(partition_id);
+        let maker_code = String::from_utf8_lossy(&maker_code)
+            .trim_end_matches('\0')
+            .to_string();
+
+        // program id, after the seed check
+        match file.seek(SeekFrom::Start(0x118)) {
+            Ok(_) => (),
+            Err(err) => return Err(NCCHError::OffsetSeekError(err, "program id")),
+        };
+
+        let mut program_id = [0; 8];
+        match file.read_exact(&mut program_id) {
+            Ok(_) => (),
+            Err(err) => return Err(NCCHError::ProgramIdReadError(err)),
+        };
+
+        let program_id = u64::from_le_bytes(program_id);
+
+        // product code, after the logo hash
+        match file.seek(SeekFrom::Start(0x150)) {
+            Ok(_) => (),
+            Err(err) => return Err(NCCHError::OffsetSeekError(err, "product code")),
+        };
+
+        let mut product_code = [0; 0x10];
+        match file.read_exact(&mut product_code) {
+            Ok(_) => (),
+            Err(err) => return Err(NCCHError::ProductCodeReadError(err)),
+        };
+
+        let product_code = String::from_utf8_lossy(&product_code)
+            .trim_end_matches('\0')
+            .to_string();
+
+        // extended header size
+        match file.seek(SeekFrom::Start(0x180)) {
             Ok(_) => (),
-            Err(err) => return Err(NCCHError::FlagsSeekError(err)),
+            Err(err) => return Err(NCCHError::OffsetSeekError(err, "extended header")),
         };
 
+        let mut exheader_size = [0; 8]; // followed by 4 reserved bytes
+        match file.read_exact(&mut exheader_size) {
+            Ok(_) => (),
+            Err(err) => return Err(NCCHError::LenghtReadError(err, "extended header")),
+        };
+        let exheader_size = u32::from_le_bytes([
+            exheader_size[0],
+            exheader_size[1],
+            exheader_size[2],
+            exheader_size[3],
+        ]);
+
+        // flags
+
         let mut flags = [0; 8];
         match file.read_exact(&mut flags) {
             Ok(_) => (),
@@ -134,57 +211,51 @@ impl<T: Read + Seek> NCCHReader<T> {
             Ok(_) => (),
             Err(err) => return Err(NCCHError::OffsetReadError(err, "plain region")),
         }
-        let plain_region_offset = u32::from_le_bytes(plain_region_offset) * 0x200;
 
         let mut plain_region_lenght = [0; 4];
         match file.read_exact(&mut plain_region_lenght) {
             Ok(_) => (),
             Err(err) => return Err(NCCHError::LenghtReadError(err, "plain region")),
         }
-        let plain_region_lenght = u32::from_le_bytes(plain_region_lenght) * 0x200;
 
-        let plain_region = PartitionData {
-            offset: plain_region_offset,
-            lenght: plain_region_lenght,
-        };
+        let plain_region = PartitionData::from_media_units(
+            u32::from_le_bytes(plain_region_offset),
+            u32::from_le_bytes(plain_region_lenght),
+        );
 
         let mut logo_region_offset = [0; 4];
         match file.read_exact(&mut logo_region_offset) {
             Ok(_) => (),
             Err(err) => return Err(NCCHError::OffsetReadError(err, "logo region")),
         }
-        let logo_region_offset = u32::from_le_bytes(logo_region_offset) * 0x200;
 
         let mut logo_region_lenght = [0; 4];
         match file.read_exact(&mut logo_region_lenght) {
             Ok(_) => (),
             Err(err) => return Err(NCCHError::LenghtReadError(err, "logo region")),
         }
-        let logo_region_lenght = u32::from_le_bytes(logo_region_lenght) * 0x200;
 
-        let logo_region = PartitionData {
-            offset: logo_region_offset,
-            lenght: logo_region_lenght,
-        };
+        let logo_region = PartitionData::from_media_units(
+            u32::from_le_bytes(logo_region_offset),
+            u32::from_le_bytes(logo_region_lenght),
+        );
 
         let mut exefs_offset = [0; 4];
         match file.read_exact(&mut exefs_offset) {
             Ok(_) => (),
             Err(err) => return Err(NCCHError::OffsetReadError(err, "exefs")),
         }
-        let exefs_offset = u32::from_le_bytes(exefs_offset) * 0x200;
 
         let mut exefs_lenght = [0; 4];
         match file.read_exact(&mut exefs_lenght) {
             Ok(_) => (),
             Err(err) => return Err(NCCHError::LenghtReadError(err, "exefs")),
         }
-        let exefs_lenght = u32::from_le_bytes(exefs_lenght) * 0x200;
 
-        let exefs = PartitionData {
-            offset: exefs_offset,
-            lenght: exefs_lenght,
-        };
+        let exefs = PartitionData::from_media_units(
+            u32::from_le_bytes(exefs_offset),
+            u32::from_le_bytes(exefs_lenght),
+        );
 
         match file.seek(SeekFrom::Start(0x1B0)) {
             Ok(_) => (),
@@ -196,24 +267,62 @@ impl<T: Read + Seek> NCCHReader<T> {
             Ok(_) => (),
             Err(err) => return Err(NCCHError::OffsetReadError(err, "romfs")),
         }
-        let romfs_offset = u32::from_le_bytes(romfs_offset) * 0x200;
 
         let mut romfs_lenght = [0; 4];
         match file.read_exact(&mut romfs_lenght) {
             Ok(_) => (),
             Err(err) => return Err(NCCHError::LenghtReadError(err, "exefs")),
         }
-        let romfs_lenght = u32::from_le_bytes(romfs_lenght) * 0x200;
 
-        let romfs = PartitionData {
-            offset: romfs_offset,
-            lenght: romfs_lenght,
+        let romfs = PartitionData::from_media_units(
+            u32::from_le_bytes(romfs_offset),
+            u32::from_le_bytes(romfs_lenght),
+        );
+
+        // the regions should be inside the NCCH, after the header and the extended header
+        let exheader = PartitionData {
+            offset: NCCH_EXHEADER_OFFSET,
+            lenght: if exheader_size == 0 {
+                0
+            } else {
+                NCCH_EXHEADER_LENGHT
+            },
         };
+        let regions = [
+            (
+                "header",
+                PartitionData {
+                    offset: 0,
+                    lenght: 0x200,
+                },
+            ),
+            ("extended header", exheader),
+            ("plain region", plain_region),
+            ("logo region", logo_region),
+            ("exefs", exefs),
+            ("romfs", romfs),
+        ];
+        for (region_nb, (name, region)) in regions.iter().enumerate() {
+            if region.end() > content_size {
+                return Err(NCCHError::RegionPastEnd(name));
+            };
+            for (other_name, other) in regions.iter().skip(region_nb + 1) {
+                if region.overlap(other) {
+                    return Err(NCCHError::OverlappingRegions(name, other_name));
+                };
+            }
+        }
 
         Ok(NCCHReader {
             file,
             content_size,
+            partition_id,
+            maker_code,
             version,
+            program_id,
+            product_code,
+            exheader_size,
+            flags,
             plain_region,
             logo_region,
             exefs,
@@ -221,6 +330,11 @@ impl<T: Read + Seek> NCCHReader<T> {
         })
     }
 
+    /// Return true if the NoCrypto flag is set, meaning the content isn't encrypted
+    pub fn is_no_crypto(&self) -> bool {
+        self.flags[7] & 0x4 != 0
+    }
+
     pub fn get_plain_region(self) -> Result<Partition<T>, NCCHError> {
         let data = self.plain_region;
         self.get_partition(data)
@@ -236,6 +350,37 @@ impl<T: Read + Seek> NCCHReader<T> {
         self.get_partition(data)
     }
 
+    /// Return the offset and the lenght in bytes of the extended header with its access descriptor, relative to the start of the NCCH file.
+    ///
+    /// The lenght is 0 if there isn't an extended header (like in a CFA).
+    pub fn get_exheader_bounds(&self) -> (u64, u64) {
+        if self.exheader_size == 0 {
+            (NCCH_EXHEADER_OFFSET, 0)
+        } else {
+            (NCCH_EXHEADER_OFFSET, NCCH_EXHEADER_LENGHT)
+        }
+    }
+
+    /// Return the offset and the lenght in bytes of the plain region, relative to the start of the NCCH file
+    pub fn get_plain_region_bounds(&self) -> (u64, u64) {
+        (self.plain_region.offset, self.plain_region.lenght)
+    }
+
+    /// Return the offset and the lenght in bytes of the logo region, relative to the start of the NCCH file
+    pub fn get_logo_region_bounds(&self) -> (u64, u64) {
+        (self.logo_region.offset, self.logo_region.lenght)
+    }
+
+    /// Return the offset and the lenght in bytes of the exefs, relative to the start of the NCCH file
+    pub fn get_exefs_bounds(&self) -> (u64, u64) {
+        (self.exefs.offset, self.exefs.lenght)
+    }
+
+    /// Return the offset and the lenght in bytes of the romfs, relative to the start of the NCCH file
+    pub fn get_romfs_bounds(&self) -> (u64, u64) {
+        (self.romfs.offset, self.romfs.lenght)
+    }
+
     pub fn get_romfs(self) -> Result<Partition<T>, NCCHError> {
         let data = self.romfs;
         self.get_partition(data)
@@ -248,3 +393,264 @@ impl<T: Read + Seek> NCCHReader<T> {
         }
     }
 }
+
+/// Build a NCCH from its sections. The romfs is streamed to the output, so its files don't need to fit in memory.
+#[derive(Debug, Clone)]
+pub struct NCCHWriter {
+    /// The header to use. The offsets, the lenghts and the hashes of the sections are updated when writing.
+    pub header: [u8; 0x200],
+    /// The extended header followed by the access descriptor. It should be `NCCH_EXHEADER_LENGHT` bytes long.
+    pub exheader: Option<Vec<u8>>,
+    pub plain_region: Vec<u8>,
+    pub logo_region: Vec<u8>,
+    pub exefs: Option<ExeFSWriter>,
+    pub romfs: Option<IVFCWriter>,
+}
+
+fn write_ncch_data<W: Write>(output: &mut W, data: &[u8]) -> Result<(), NCCHError> {
+    match output.write_all(data) {
+        Ok(()) => Ok(()),
+        Err(err) => Err(NCCHError::WriteError(err)),
+    }
+}
+
+/// Write zeros to `output` until `position` is aligned to `alignment`. Return the new position.
+fn pad_ncch<W: Write>(output: &mut W, position: u64, alignment: u64) -> Result<u64, NCCHError> {
+    let aligned = position.div_ceil(alignment) * alignment;
+    write_ncch_data(output, &vec![0; (aligned - position) as usize])?;
+    Ok(aligned)
+}
+
+/// Convert a number of bytes to a number of media units, rounded up
+fn bytes_to_media_units(bytes: u64, what: &'static str) -> Result<u32, NCCHError> {
+    match u32::try_from(bytes.div_ceil(MEDIA_UNIT_SIZE)) {
+        Ok(value) => Ok(value),
+        Err(_) => Err(NCCHError::TooBig(what)),
+    }
+}
+
+impl NCCHWriter {
+    /// Create a writer with no section, using `header` as the base of the header
+    pub fn new(header: [u8; 0x200]) -> NCCHWriter {
+        NCCHWriter {
+            header,
+            exheader: None,
+            plain_region: Vec::new(),
+            logo_region: Vec::new(),
+            exefs: None,
+            romfs: None,
+        }
+    }
+
+    /// Write the NCCH at the current position of `output`. Return its lenght.
+    pub fn write<W: Write + Seek>(&self, output: &mut W) -> Result<u64, NCCHError> {
+        let start = match output.stream_position() {
+            Ok(value) => value,
+            Err(err) => return Err(NCCHError::WriteError(err)),
+        };
+        let mut header = self.header;
+        let set_u32 = |header: &mut [u8; 0x200], offset: usize, value: u32| {
+            header[offset..offset + 4].copy_from_slice(&value.to_le_bytes())
+        };
+        // the header is written at the end, once all the offsets and hashes are known
+        write_ncch_data(output, &[0; 0x200])?;
+        let mut position = 0x200;
+
+        match &self.exheader {
+            Some(exheader) => {
+                if exheader.len() as u64 != NCCH_EXHEADER_LENGHT {
+                    return Err(NCCHError::InvalidExheaderLenght(exheader.len()));
+                };
+                header[0x160..0x180].copy_from_slice(&Sha256::digest(&exheader[..0x400]));
+                set_u32(&mut header, 0x180, 0x400);
+                write_ncch_data(output, exheader)?;
+                position += NCCH_EXHEADER_LENGHT;
+            }
+            None => {
+                header[0x160..0x180].copy_from_slice(&[0; 0x20]);
+                set_u32(&mut header, 0x180, 0);
+            }
+        };
+
+        // the plain region, the logo and the exefs, with their offset and lenght in the header
+        let exefs = match &self.exefs {
+            Some(exefs) => match exefs.to_bytes() {
+                Ok(value) => value,
+                Err(err) => return Err(NCCHError::ExeFSWriteError(err)),
+            },
+            None => Vec::new(),
+        };
+        for (data, header_offset, what) in [
+            (&self.plain_region, 0x190, "plain region"),
+            (&self.logo_region, 0x198, "logo region"),
+            (&exefs, 0x1A0, "exefs"),
+        ] {
+            if data.is_empty() {
+                set_u32(&mut header, header_offset, 0);
+                set_u32(&mut header, header_offset + 4, 0);
+                continue;
+            };
+            position = pad_ncch(output, position, MEDIA_UNIT_SIZE)?;
+            set_u32(
+                &mut header,
+                header_offset,
+                bytes_to_media_units(position, what)?,
+            );
+            set_u32(
+                &mut header,
+                header_offset + 4,
+                bytes_to_media_units(data.len() as u64, what)?,
+            );
+            write_ncch_data(output, data)?;
+            position += data.len() as u64;
+        }
+        if self.logo_region.is_empty() {
+            header[0x130..0x150].copy_from_slice(&[0; 0x20]);
+        } else {
+            header[0x130..0x150].copy_from_slice(&Sha256::digest(&self.logo_region));
+        };
+        if exefs.is_empty() {
+            set_u32(&mut header, 0x1A8, 0);
+            header[0x1C0..0x1E0].copy_from_slice(&[0; 0x20]);
+        } else {
+            // only the exefs header is hashed, it contain the hashes of the files
+            set_u32(&mut header, 0x1A8, 1);
+            header[0x1C0..0x1E0].copy_from_slice(&Sha256::digest(&exefs[..0x200]));
+        };
+
+        match &self.romfs {
+            Some(romfs) => {
+                position = pad_ncch(output, position, 0x1000)?;
+                set_u32(&mut header, 0x1B0, bytes_to_media_units(position, "romfs")?);
+                let result = match romfs.write(output) {
+                    Ok(value) => value,
+                    Err(err) => return Err(NCCHError::RomFSWriteError(err)),
+                };
+                position += result.lenght;
+                set_u32(
+                    &mut header,
+                    0x1B4,
+                    bytes_to_media_units(result.lenght, "romfs")?,
+                );
+                set_u32(
+                    &mut header,
+                    0x1B8,
+                    bytes_to_media_units(result.superblock.len() as u64, "romfs")?,
+                );
+                header[0x1E0..0x200].copy_from_slice(&Sha256::digest(&result.superblock));
+            }
+            None => {
+                for offset in [0x1B0, 0x1B4, 0x1B8] {
+                    set_u32(&mut header, offset, 0);
+                }
+                header[0x1E0..0x200].copy_from_slice(&[0; 0x20]);
+            }
+        };
+
+        position = pad_ncch(output, position, MEDIA_UNIT_SIZE)?;
+        set_u32(
+            &mut header,
+            0x104,
+            bytes_to_media_units(position, "content size")?,
+        );
+        header[0x100..0x104].copy_from_slice(b"NCCH");
+
+        let result = output
+            .seek(SeekFrom::Start(start))
+            .and_then(|_| output.write_all(&header))
+            .and_then(|_| output.seek(SeekFrom::Start(start + position)));
+        match result {
+            Ok(_) => Ok(position),
+            Err(err) => Err(NCCHError::WriteError(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IVFCReader;
+    use std::io::Cursor;
+
+    /// A NCCH with a plain region at 0x200 and a logo region at 0x400, each one media unit long
+    fn small_ncch() -> Vec<u8> {
+        let mut writer = NCCHWriter::new([0; 0x200]);
+        writer.plain_region = vec![1; 0x10];
+        writer.logo_region = vec![2; 0x10];
+        let mut ncch = Cursor::new(Vec::new());
+        writer.write(&mut ncch).unwrap();
+        ncch.into_inner()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = NCCHWriter::new([0; 0x200]);
+        let mut exheader = vec![3; NCCH_EXHEADER_LENGHT as usize];
+        exheader[0x400] = 4;
+        writer.exheader = Some(exheader.clone());
+        writer.plain_region = b"[SDK+NINTENDO:Firmware-1_0_0]".to_vec();
+        writer.logo_region = vec![5; 0x2000];
+        let mut exefs = ExeFSWriter::new();
+        exefs.add_file(".code", vec![6; 0x300]);
+        writer.exefs = Some(exefs.clone());
+        let mut romfs = IVFCWriter::new();
+        romfs.add_file("a.bin", crate::IVFCWriterSource::Memory(vec![7; 0x10]));
+        writer.romfs = Some(romfs);
+        let mut output = Cursor::new(Vec::new());
+        let lenght = writer.write(&mut output).unwrap();
+        let ncch = output.into_inner();
+        assert_eq!(lenght, ncch.len() as u64);
+
+        let reader = NCCHReader::new(Cursor::new(ncch.clone())).unwrap();
+        assert_eq!(reader.content_size, lenght);
+        assert_eq!(reader.exheader_size, 0x400);
+        let section =
+            |(offset, lenght): (u64, u64)| &ncch[offset as usize..(offset + lenght) as usize];
+        assert_eq!(section(reader.get_exheader_bounds()), &exheader[..]);
+        assert_eq!(&ncch[0x160..0x180], &Sha256::digest(&exheader[..0x400])[..]);
+        assert!(section(reader.get_plain_region_bounds()).starts_with(&writer.plain_region));
+        assert_eq!(
+            section(reader.get_logo_region_bounds()),
+            &writer.logo_region[..]
+        );
+        assert_eq!(
+            &ncch[0x130..0x150],
+            &Sha256::digest(&writer.logo_region)[..]
+        );
+        let exefs_bytes = exefs.to_bytes().unwrap();
+        assert!(section(reader.get_exefs_bounds()).starts_with(&exefs_bytes));
+        let (romfs_offset, _) = reader.get_romfs_bounds();
+        assert_eq!(romfs_offset % 0x1000, 0);
+        let romfs = IVFCReader::from_read_at(section(reader.get_romfs_bounds()).to_vec()).unwrap();
+        assert_eq!(romfs.walk_files().unwrap()[0].0, "a.bin");
+    }
+
+    #[test]
+    fn test_region_bounds() {
+        let reader = NCCHReader::new(Cursor::new(small_ncch())).unwrap();
+        assert_eq!(reader.content_size, 0x600);
+        assert_eq!(reader.get_plain_region_bounds(), (0x200, 0x200));
+        assert_eq!(reader.get_logo_region_bounds(), (0x400, 0x200));
+
+        let mut truncated = small_ncch();
+        truncated[0x104..0x108].copy_from_slice(&2u32.to_le_bytes());
+        assert!(matches!(
+            NCCHReader::new(Cursor::new(truncated)),
+            Err(NCCHError::RegionPastEnd("logo region"))
+        ));
+
+        let mut overlapping = small_ncch();
+        overlapping[0x198..0x19C].copy_from_slice(&1u32.to_le_bytes());
+        assert!(matches!(
+            NCCHReader::new(Cursor::new(overlapping)),
+            Err(NCCHError::OverlappingRegions("plain region", "logo region"))
+        ));
+
+        let mut in_header = small_ncch();
+        in_header[0x190..0x194].copy_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(
+            NCCHReader::new(Cursor::new(in_header)),
+            Err(NCCHError::OverlappingRegions("header", "plain region"))
+        ));
+    }
+}
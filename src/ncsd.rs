@@ -1,9 +1,13 @@
 use crate::Partition;
-use crate::PartitionData;
+use crate::{media_units_to_bytes, PartitionData, MEDIA_UNIT_SIZE};
+use crate::{NCCHError, NCCHWriter};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt;
 use std::io;
-use std::io::{Read, Seek};
+use std::io::SeekFrom;
+use std::io::{Read, Seek, Write};
 
 #[derive(Debug)]
 pub enum NCSDError {
@@ -24,6 +28,19 @@ pub enum NCSDError {
     PartitionIdReadError(io::Error, usize), // usize: partition_nb
     InexistingPartition(usize),             // usize: partition_nb
     CreatePartitionFail(io::Error),
+    TrimSeekError(io::Error),
+    TrimReadError(io::Error),
+    TrimWriteError(io::Error),
+    NonPaddingData(u64), // u64: offset of the first byte that isn't 0xFF
+    TruncatedFile(u64),  // u64: the lenght of the input
+    PartitionEndReadError(io::Error),
+    PartitionPastEnd(usize),             // usize: partition_nb
+    PartitionPastFileEnd(usize),         // usize: partition_nb
+    OverlappingPartitions(usize, usize), // the number of the two partitions
+    WriteError(io::Error),
+    NCCHWriteError(NCCHError, usize), // usize: partition_nb
+    InvalidHeaderLenght(usize),       // usize: the lenght of the given header
+    TooBig,
 }
 
 impl Error for NCSDError {
@@ -43,6 +60,12 @@ impl Error for NCSDError {
             NCSDError::PartitionFlagReadError(ioerror) => Some(ioerror),
             NCSDError::PartitionIdReadError(ioerror, _) => Some(ioerror),
             NCSDError::CreatePartitionFail(err) => Some(err),
+            NCSDError::TrimSeekError(err) => Some(err),
+            NCSDError::TrimReadError(err) => Some(err),
+            NCSDError::TrimWriteError(err) => Some(err),
+            NCSDError::PartitionEndReadError(err) => Some(err),
+            NCSDError::WriteError(err) => Some(err),
+            NCSDError::NCCHWriteError(err, _) => Some(err),
             _ => None,
         }
     }
@@ -63,6 +86,35 @@ impl fmt::Display for NCSDError {
             NCSDError::ReadSizeError(_) => {
                 write!(f, "Unable to read the size of the file in the CCI file")
             }
+            NCSDError::NonPaddingData(offset) => write!(
+                f,
+                "The CCI file contain non-padding data at offset {:#x}, after the last partition",
+                offset
+            ),
+            NCSDError::TruncatedFile(lenght) => write!(
+                f,
+                "The CCI file is truncated before the end of the last partition (it's {:#x} bytes long)",
+                lenght
+            ),
+            NCSDError::PartitionPastEnd(partition_nb) => write!(
+                f,
+                "The partition {} end after the end of the CCI image",
+                partition_nb
+            ),
+            NCSDError::PartitionPastFileEnd(partition_nb) => write!(
+                f,
+                "The CCI file is truncated before the end of the partition {}",
+                partition_nb
+            ),
+            NCSDError::OverlappingPartitions(first, second) => write!(
+                f,
+                "The partitions {} and {} of the CCI file overlap",
+                first, second
+            ),
+            NCSDError::NCCHWriteError(_, partition_nb) => {
+                write!(f, "Unable to write the partition {}", partition_nb)
+            }
+            NCSDError::TooBig => write!(f, "The CCI file is too big to be described by its header"),
             _ => write!(f, "{:?}", self), //TODO: specific error message
         }
     }
@@ -70,7 +122,7 @@ impl fmt::Display for NCSDError {
 
 pub struct NCSDReader<T: Read + Seek> {
     file: T,
-    pub size: u32,
+    pub size: u64,
     pub media_id: u64,
     pub partition_type: u64,
     pub partitions_id: Vec<[u8; 8]>,
@@ -106,7 +158,7 @@ impl<T: Read + Seek> NCSDReader<T> {
             Err(err) => return Err(NCSDError::ReadSizeError(err)),
         };
 
-        let size = u32::from_le_bytes(size_media_image) * 0x200;
+        let size = media_units_to_bytes(u32::from_le_bytes(size_media_image));
 
         // media id
         let mut media_id = [0; 0x8];
@@ -146,16 +198,16 @@ impl<T: Read + Seek> NCSDReader<T> {
                 Ok(_) => (),
                 Err(err) => return Err(NCSDError::ReadPartitionOffsetError(err, partition_nb)),
             };
-            let offset = u32::from_le_bytes(offset) * 0x200;
 
             let mut lenght = [0; 0x4];
             match file.read_exact(&mut lenght) {
                 Ok(_) => (),
                 Err(err) => return Err(NCSDError::ReadPartitionLenghtError(err, partition_nb)),
             };
-            let lenght = u32::from_le_bytes(lenght) * 0x200;
-
-            partitions.push(PartitionData { offset, lenght });
+            partitions.push(PartitionData::from_media_units(
+                u32::from_le_bytes(offset),
+                u32::from_le_bytes(lenght),
+            ));
         }
 
         // ex header
@@ -196,6 +248,38 @@ impl<T: Read + Seek> NCSDReader<T> {
             partitions_id.push(partition_id);
         }
 
+        // the partitions should be inside the image, and inside the file (that may be trimmed)
+        let mut last_partition: Option<(usize, u64)> = None;
+        for (partition_nb, partition) in partitions.iter().enumerate() {
+            if partition.end() > size {
+                return Err(NCSDError::PartitionPastEnd(partition_nb));
+            };
+            for (other_nb, other) in partitions.iter().enumerate().skip(partition_nb + 1) {
+                if partition.overlap(other) {
+                    return Err(NCSDError::OverlappingPartitions(partition_nb, other_nb));
+                };
+            }
+            let is_last = match last_partition {
+                Some((_, end)) => partition.end() > end,
+                None => partition.lenght != 0,
+            };
+            if is_last {
+                last_partition = Some((partition_nb, partition.end()));
+            };
+        }
+        // the lenght of the input may be unknown (like with a `ReadAtCursor`), so the last byte is read instead
+        if let Some((partition_nb, end)) = last_partition {
+            let mut last_byte = [0; 1];
+            let result = file
+                .seek(SeekFrom::Start(end - 1))
+                .and_then(|_| file.read(&mut last_byte));
+            match result {
+                Ok(1) => (),
+                Ok(_) => return Err(NCSDError::PartitionPastFileEnd(partition_nb)),
+                Err(err) => return Err(NCSDError::PartitionEndReadError(err)),
+            };
+        };
+
         Ok(NCSDReader {
             file,
             size,
@@ -207,7 +291,8 @@ impl<T: Read + Seek> NCSDReader<T> {
         })
     }
 
-    pub fn load_partition(self, partition_nb: usize) -> Result<Partition<T>, NCSDError> {
+    /// Return the offset and the lenght in bytes of a partition, relative to the start of the NCSD file
+    pub fn get_partition_bounds(&self, partition_nb: usize) -> Result<(u64, u64), NCSDError> {
         if partition_nb >= 8 {
             return Err(NCSDError::InexistingPartition(partition_nb));
         };
@@ -215,9 +300,314 @@ impl<T: Read + Seek> NCSDReader<T> {
         if partition.offset == 0 {
             return Err(NCSDError::InexistingPartition(partition_nb));
         };
-        match Partition::new(self.file, partition.offset, partition.lenght) {
+        Ok((partition.offset, partition.lenght))
+    }
+
+    pub fn load_partition(self, partition_nb: usize) -> Result<Partition<T>, NCSDError> {
+        let (offset, lenght) = self.get_partition_bounds(partition_nb)?;
+        match Partition::new(self.file, offset, lenght) {
             Ok(value) => Ok(value),
             Err(err) => Err(NCSDError::CreatePartitionFail(err)),
         }
     }
+
+    /// Return the offset of the first byte after the last partition. Every byte after it should be padding.
+    pub fn used_size(&self) -> u64 {
+        self.partitions
+            .iter()
+            .filter(|partition| partition.offset != 0)
+            .map(|partition| partition.offset + partition.lenght)
+            .max()
+            .unwrap_or(0x4000)
+    }
+
+    /// Check that every byte from `start` to the end of the file is 0xFF padding. Return the lenght of the file.
+    fn check_padding(&mut self, start: u64) -> Result<u64, NCSDError> {
+        let file_lenght = match self.file.seek(SeekFrom::End(0)) {
+            Ok(value) => value,
+            Err(err) => return Err(NCSDError::TrimSeekError(err)),
+        };
+        if file_lenght < start {
+            return Ok(file_lenght);
+        };
+        match self.file.seek(SeekFrom::Start(start)) {
+            Ok(_) => (),
+            Err(err) => return Err(NCSDError::TrimSeekError(err)),
+        };
+        let mut buffer = vec![0; 0x10000];
+        let mut offset = start;
+        while offset < file_lenght {
+            let to_read = std::cmp::min(buffer.len() as u64, file_lenght - offset) as usize;
+            match self.file.read_exact(&mut buffer[..to_read]) {
+                Ok(_) => (),
+                Err(err) => return Err(NCSDError::TrimReadError(err)),
+            };
+            if let Some(position) = buffer[..to_read].iter().position(|byte| *byte != 0xFF) {
+                return Err(NCSDError::NonPaddingData(offset + position as u64));
+            };
+            offset += to_read as u64;
+        }
+        Ok(file_lenght)
+    }
+
+    /// Copy the first `lenght` bytes of the file to `output`.
+    fn copy_start<W: Write>(&mut self, output: &mut W, lenght: u64) -> Result<(), NCSDError> {
+        match self.file.seek(SeekFrom::Start(0)) {
+            Ok(_) => (),
+            Err(err) => return Err(NCSDError::TrimSeekError(err)),
+        };
+        let mut buffer = vec![0; 0x10000];
+        let mut remaining = lenght;
+        while remaining > 0 {
+            let to_read = std::cmp::min(buffer.len() as u64, remaining) as usize;
+            match self.file.read_exact(&mut buffer[..to_read]) {
+                Ok(_) => (),
+                Err(err) => return Err(NCSDError::TrimReadError(err)),
+            };
+            match output.write_all(&buffer[..to_read]) {
+                Ok(_) => (),
+                Err(err) => return Err(NCSDError::TrimWriteError(err)),
+            };
+            remaining -= to_read as u64;
+        }
+        Ok(())
+    }
+
+    /// Write a trimmed copy of the CCI file to `output`, removing the padding after the last partition.
+    ///
+    /// Fail with `NCSDError::NonPaddingData` if the removed part contain anything else than 0xFF. Return the lenght of the trimmed file.
+    pub fn trim<W: Write>(&mut self, output: &mut W) -> Result<u64, NCSDError> {
+        let used_size = self.used_size();
+        let file_lenght = self.check_padding(used_size)?;
+        if file_lenght < used_size {
+            return Err(NCSDError::TruncatedFile(file_lenght));
+        };
+        self.copy_start(output, used_size)?;
+        Ok(used_size)
+    }
+
+    /// Write an untrimmed copy of the CCI file to `output`, padding it with 0xFF up to `self.size`.
+    ///
+    /// The already present data after the last partition should be padding, like with `trim`. Return the lenght of the untrimmed file.
+    pub fn untrim<W: Write>(&mut self, output: &mut W) -> Result<u64, NCSDError> {
+        let used_size = self.used_size();
+        let file_lenght = self.check_padding(used_size)?;
+        if file_lenght < used_size {
+            return Err(NCSDError::TruncatedFile(file_lenght));
+        };
+        let final_lenght = std::cmp::max(self.size, file_lenght);
+        self.copy_start(output, file_lenght)?;
+        let padding = vec![0xFF; 0x10000];
+        let mut remaining = final_lenght - file_lenght;
+        while remaining > 0 {
+            let to_write = std::cmp::min(padding.len() as u64, remaining) as usize;
+            match output.write_all(&padding[..to_write]) {
+                Ok(_) => (),
+                Err(err) => return Err(NCSDError::TrimWriteError(err)),
+            };
+            remaining -= to_write as u64;
+        }
+        Ok(final_lenght)
+    }
+}
+
+/// Build a NCSD from its partitions
+#[derive(Debug, Clone)]
+pub struct NCSDWriter {
+    /// Everything before the first partition (the NCSD header, followed by the card info). It should be at least 0x200 bytes long.
+    ///
+    /// The partition table and the size of the image are updated when writing.
+    pub header: Vec<u8>,
+    /// The number of each partition (0 to 7) and its content
+    pub partitions: Vec<(usize, NCCHWriter)>,
+}
+
+impl NCSDWriter {
+    pub fn new(header: Vec<u8>) -> NCSDWriter {
+        NCSDWriter {
+            header,
+            partitions: Vec::new(),
+        }
+    }
+
+    /// Write the NCSD at the current position of `output`. Return its lenght.
+    pub fn write<W: Write + Seek>(&self, output: &mut W) -> Result<u64, NCSDError> {
+        if self.header.len() < 0x200 {
+            return Err(NCSDError::InvalidHeaderLenght(self.header.len()));
+        };
+        if let Some((partition_nb, _)) = self.partitions.iter().find(|(nb, _)| *nb >= 8) {
+            return Err(NCSDError::InexistingPartition(*partition_nb));
+        };
+        let start = match output.stream_position() {
+            Ok(value) => value,
+            Err(err) => return Err(NCSDError::WriteError(err)),
+        };
+        let to_media_units = |bytes: u64| match u32::try_from(bytes / MEDIA_UNIT_SIZE) {
+            Ok(value) => Ok(value),
+            Err(_) => Err(NCSDError::TooBig),
+        };
+
+        let mut header = self.header.clone();
+        header.resize(
+            (header.len() as u64).div_ceil(MEDIA_UNIT_SIZE) as usize * MEDIA_UNIT_SIZE as usize,
+            0,
+        );
+        // the header is rewritten at the end, once the partition table is known
+        match output.write_all(&header) {
+            Ok(()) => (),
+            Err(err) => return Err(NCSDError::WriteError(err)),
+        };
+        let mut position = header.len() as u64;
+        header[0x120..0x160].copy_from_slice(&[0; 0x40]);
+
+        let mut partitions: Vec<&(usize, NCCHWriter)> = self.partitions.iter().collect();
+        partitions.sort_by_key(|(partition_nb, _)| *partition_nb);
+        for (partition_nb, ncch) in partitions {
+            let lenght = match ncch.write(output) {
+                Ok(value) => value,
+                Err(err) => return Err(NCSDError::NCCHWriteError(err, *partition_nb)),
+            };
+            let entry_offset = 0x120 + partition_nb * 8;
+            header[entry_offset..entry_offset + 4]
+                .copy_from_slice(&to_media_units(position)?.to_le_bytes());
+            header[entry_offset + 4..entry_offset + 8]
+                .copy_from_slice(&to_media_units(lenght)?.to_le_bytes());
+            if *partition_nb == 0 {
+                // the hash of the extended header of the first partition is copied in the NCSD header
+                match &ncch.exheader {
+                    Some(exheader) => {
+                        header[0x160..0x180].copy_from_slice(&Sha256::digest(&exheader[..0x400]))
+                    }
+                    None => header[0x160..0x180].copy_from_slice(&[0; 0x20]),
+                };
+            };
+            position += lenght;
+        }
+
+        let old_size =
+            u32::from_le_bytes([header[0x104], header[0x105], header[0x106], header[0x107]]);
+        let size = std::cmp::max(old_size, to_media_units(position)?);
+        header[0x104..0x108].copy_from_slice(&size.to_le_bytes());
+        header[0x100..0x104].copy_from_slice(b"NCSD");
+
+        let result = output
+            .seek(SeekFrom::Start(start))
+            .and_then(|_| output.write_all(&header))
+            .and_then(|_| output.seek(SeekFrom::Start(start + position)));
+        match result {
+            Ok(_) => Ok(position),
+            Err(err) => Err(NCSDError::WriteError(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A NCSD of 0x40 media units with a single partition from 0x4000 to 0x6000, followed by 0x1000 bytes of padding
+    fn trimmable_ncsd() -> Vec<u8> {
+        let mut ncsd = vec![0; 0x4000];
+        ncsd[0x100..0x104].copy_from_slice(b"NCSD");
+        ncsd[0x104..0x108].copy_from_slice(&0x40u32.to_le_bytes());
+        ncsd[0x120..0x124].copy_from_slice(&0x20u32.to_le_bytes());
+        ncsd[0x124..0x128].copy_from_slice(&0x10u32.to_le_bytes());
+        ncsd.resize(0x6000, 0xAB);
+        ncsd.resize(0x7000, 0xFF);
+        ncsd
+    }
+
+    #[test]
+    fn test_trim_untrim() {
+        let ncsd = trimmable_ncsd();
+        let mut reader = NCSDReader::new(Cursor::new(ncsd.clone())).unwrap();
+        assert_eq!(reader.used_size(), 0x6000);
+
+        let mut trimmed = Vec::new();
+        assert_eq!(reader.trim(&mut trimmed).unwrap(), 0x6000);
+        assert_eq!(trimmed, &ncsd[..0x6000]);
+
+        let mut untrimmed = Vec::new();
+        let mut trimmed_reader = NCSDReader::new(Cursor::new(trimmed)).unwrap();
+        assert_eq!(trimmed_reader.untrim(&mut untrimmed).unwrap(), 0x8000);
+        assert_eq!(untrimmed.len(), 0x8000);
+        assert_eq!(&untrimmed[..0x6000], &ncsd[..0x6000]);
+        assert!(untrimmed[0x6000..].iter().all(|byte| *byte == 0xFF));
+    }
+
+    #[test]
+    fn test_trim_non_padding() {
+        let mut ncsd = trimmable_ncsd();
+        ncsd[0x6800] = 0;
+        let mut reader = NCSDReader::new(Cursor::new(ncsd)).unwrap();
+        assert!(matches!(
+            reader.trim(&mut Vec::new()),
+            Err(NCSDError::NonPaddingData(0x6800))
+        ));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = NCSDWriter::new(vec![0; 0x4000]);
+        for (partition_nb, logo) in [(0, 1), (1, 2), (7, 3)] {
+            let mut ncch = NCCHWriter::new([0; 0x200]);
+            ncch.logo_region = vec![logo; 0x200];
+            writer.partitions.push((partition_nb, ncch));
+        }
+        let mut output = Cursor::new(Vec::new());
+        let lenght = writer.write(&mut output).unwrap();
+        let ncsd = output.into_inner();
+        assert_eq!(lenght, ncsd.len() as u64);
+
+        let reader = NCSDReader::new(Cursor::new(ncsd.clone())).unwrap();
+        assert_eq!(reader.size, lenght);
+        let mut end = 0x4000;
+        for (partition_nb, logo) in [(0, 1), (1, 2), (7, 3)] {
+            let (offset, lenght) = reader.get_partition_bounds(partition_nb).unwrap();
+            assert_eq!(offset, end);
+            end += lenght;
+            let partition = &ncsd[offset as usize..end as usize];
+            let ncch = crate::NCCHReader::new(Cursor::new(partition)).unwrap();
+            let (logo_offset, _) = ncch.get_logo_region_bounds();
+            assert_eq!(partition[logo_offset as usize], logo);
+        }
+        assert!(matches!(
+            reader.get_partition_bounds(2),
+            Err(NCSDError::InexistingPartition(2))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_partition_table() {
+        // the lenght of a `ReadAtCursor` is unknown, but the partitions are still checked
+        let ncsd = trimmable_ncsd();
+        assert!(NCSDReader::new(crate::ReadAtCursor::new(&ncsd)).is_ok());
+        assert!(matches!(
+            NCSDReader::new(crate::ReadAtCursor::new(&ncsd[..0x5000])),
+            Err(NCSDError::PartitionPastFileEnd(0))
+        ));
+
+        let mut past_image = trimmable_ncsd();
+        past_image[0x104..0x108].copy_from_slice(&0x28u32.to_le_bytes());
+        assert!(matches!(
+            NCSDReader::new(Cursor::new(past_image)),
+            Err(NCSDError::PartitionPastEnd(0))
+        ));
+
+        let mut truncated = trimmable_ncsd();
+        truncated.truncate(0x5000);
+        assert!(matches!(
+            NCSDReader::new(Cursor::new(truncated)),
+            Err(NCSDError::PartitionPastFileEnd(0))
+        ));
+
+        let mut overlapping = trimmable_ncsd();
+        overlapping[0x130..0x134].copy_from_slice(&0x28u32.to_le_bytes());
+        overlapping[0x134..0x138].copy_from_slice(&0x8u32.to_le_bytes());
+        assert!(matches!(
+            NCSDReader::new(Cursor::new(overlapping)),
+            Err(NCSDError::OverlappingPartitions(0, 2))
+        ));
+    }
 }
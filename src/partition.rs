@@ -1,50 +1,38 @@
+use crate::ReadAt;
 use std::io;
 use std::io::SeekFrom;
 use std::io::{Read, Seek, Write};
 use std::sync::{Arc, Mutex};
 
+/// Read at most `buf.len()` bytes, without going past `end`. The file should already be at `pointer`.
+///
+/// Return the new pointer, and the result of the read.
 fn partition_read<T: Read + Seek>(
     buf: &mut [u8],
     file: &mut T,
-    start: usize,
-    end: usize,
-    mut pointer: usize,
-) -> (usize, io::Result<usize>) {
-    let end_byte_absolute = buf.len() + pointer as usize;
-    if end_byte_absolute >= end {
-        let loop_total_nb = end - pointer;
-        let mut single_value = [0];
-
-        for loop_nb in 0..loop_total_nb {
-            match file.read_exact(&mut single_value) {
-                Ok(_) => (),
-                Err(err) => {
-                    let _ = file.seek(SeekFrom::Start(start as u64 + pointer as u64));
-                    return (pointer, Err(err));
-                }
-            }
-            pointer += 1;
-            buf[loop_nb] = single_value[0];
-        }
-        (pointer, Ok(loop_total_nb))
-    } else {
-        (pointer, file.read(buf))
+    end: u64,
+    pointer: u64,
+) -> (u64, io::Result<usize>) {
+    if pointer >= end {
+        return (pointer, Ok(0));
+    };
+    let lenght = std::cmp::min(buf.len() as u64, end - pointer) as usize;
+    match file.read(&mut buf[..lenght]) {
+        Ok(read) => (pointer + read as u64, Ok(read)),
+        Err(err) => (pointer, Err(err)),
     }
 }
 
-fn partition_seek<T: Read + Seek>(
-    file: &mut T,
-    start: usize,
-    end: usize,
-    pointer: usize,
-    target: SeekFrom,
-) -> (usize, io::Result<u64>) {
-    let new_real_pos = match target {
-        SeekFrom::Start(nb) => start + nb as usize,
-        SeekFrom::End(nb) => (end as i64 + nb) as usize,
-        SeekFrom::Current(nb) => ((start + pointer) as i64 + nb) as usize,
+/// Compute the new pointer after seeking to `target`. The pointer can go past `end`, as it will be caught by read.
+///
+/// Return the new pointer, and the result of the seek (the position relative to `start`).
+fn partition_seek(start: u64, end: u64, pointer: u64, target: SeekFrom) -> (u64, io::Result<u64>) {
+    let new_pointer = match target {
+        SeekFrom::Start(nb) => start as i128 + nb as i128,
+        SeekFrom::End(nb) => end as i128 + nb as i128,
+        SeekFrom::Current(nb) => pointer as i128 + nb as i128,
     };
-    if new_real_pos < start {
+    if new_pointer < start as i128 {
         return (
             pointer,
             Err(io::Error::new(
@@ -53,142 +41,177 @@ fn partition_seek<T: Read + Seek>(
             )),
         );
     };
-    // do not block seeking post-partition, as it will be caught by read
-    match file.seek(SeekFrom::Start(new_real_pos as u64)) {
-        Ok(_) => (),
-        Err(err) => return (pointer, Err(err)),
+    if new_pointer > u64::MAX as i128 {
+        return (
+            pointer,
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't seek that far after the end of the partition",
+            )),
+        );
     };
-    (pointer, Ok(new_real_pos as u64 - start as u64))
+    let new_pointer = new_pointer as u64;
+    (new_pointer, Ok(new_pointer - start))
+}
+
+fn partition_end(start: u64, lenght: u64) -> io::Result<u64> {
+    match start.checked_add(lenght) {
+        Some(value) => Ok(value),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the end of the partition is after the maximum offset",
+        )),
+    }
 }
 
 #[derive(Debug)]
 pub struct Partition<T: Read + Seek> {
     file: T,
     /// The offset of the first byte that should be included
-    start: usize,
-    pointer: usize,
+    start: u64,
+    pointer: u64,
     /// The offset of the first byte that should be NOT included
-    end: usize,
+    end: u64,
 }
 
 impl<T: Read + Seek> Partition<T> {
-    pub fn new(file: T, start: u32, lenght: u32) -> io::Result<Partition<T>> {
+    pub fn new(file: T, start: u64, lenght: u64) -> io::Result<Partition<T>> {
         let mut result = Partition {
             file,
-            start: start as usize,
-            pointer: start as usize,
-            end: start as usize + lenght as usize,
+            start,
+            pointer: start,
+            end: partition_end(start, lenght)?,
         };
         result.seek(SeekFrom::Start(0))?;
         Ok(result)
     }
 }
 
-impl<T: Read + Seek + std::fmt::Debug> Read for Partition<T> {
+impl<T: Read + Seek> Read for Partition<T> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let end_byte_absolute = self.pointer as usize + buf.len();
-        if end_byte_absolute >= self.end {
-            let loop_total_nb = self.end - self.pointer;
-            let mut single_value = [0];
-
-            for loop_nb in 0..loop_total_nb {
-                match self.file.read_exact(&mut single_value) {
-                    Ok(_) => (),
-                    Err(err) => {
-                        let _ = self
-                            .file
-                            .seek(SeekFrom::Start(self.start as u64 + self.pointer as u64));
-                        return Err(err);
-                    }
-                }
-                self.pointer += 1;
-                buf[loop_nb] = single_value[0];
-            }
-
-            Ok(loop_total_nb)
-        } else {
-            self.pointer = end_byte_absolute;
-            self.file.read(buf)
-        }
+        let (pointer, result) = partition_read(buf, &mut self.file, self.end, self.pointer);
+        self.pointer = pointer;
+        if result.is_err() {
+            // the position of the file is unknown after a failed read
+            let _ = self.file.seek(SeekFrom::Start(self.pointer));
+        };
+        result
     }
 }
 
 impl<T: Seek + Read> Seek for Partition<T> {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        let new_real_pos = match pos {
-            SeekFrom::Start(nb) => self.start + nb as usize,
-            SeekFrom::End(nb) => (self.end as i64 + nb) as usize,
-            SeekFrom::Current(nb) => ((self.start + self.pointer) as i64 + nb) as usize,
-        };
-        if new_real_pos < self.start {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "can't seek before the beggining of the partition",
-            ));
-        };
-        // do not block seeking post-partition, as it will be caught by read
-        self.file.seek(SeekFrom::Start(new_real_pos as u64))?;
-        self.pointer = new_real_pos;
-        Ok(self.pointer as u64 - self.start as u64)
+        let (pointer, result) = partition_seek(self.start, self.end, self.pointer, pos);
+        let position = result?;
+        self.file.seek(SeekFrom::Start(pointer))?;
+        self.pointer = pointer;
+        Ok(position)
     }
 }
 
+/// A partition of a `ReadAt` source, shared with an `Arc`. Reads doesn't need any lock, unless the source is a `Mutex`.
 #[derive(Debug)]
-pub struct PartitionMutex<T: Read + Seek> {
-    file: Arc<Mutex<T>>,
-    start: usize,
-    pointer: usize,
-    end: usize,
+pub struct ReadAtPartition<T: ReadAt> {
+    file: Arc<T>,
+    start: u64,
+    pointer: u64,
+    end: u64,
 }
 
-impl<T: Read + Seek> PartitionMutex<T> {
-    pub fn new(file: Arc<Mutex<T>>, start: usize, lenght: usize) -> io::Result<PartitionMutex<T>> {
-        let mut result = PartitionMutex {
+/// A partition of a `Read + Seek` source shared behind a mutex, that is locked for every read.
+pub type PartitionMutex<T> = ReadAtPartition<Mutex<T>>;
+
+impl<T: ReadAt> ReadAtPartition<T> {
+    pub fn new(file: Arc<T>, start: u64, lenght: u64) -> io::Result<ReadAtPartition<T>> {
+        Ok(ReadAtPartition {
             file,
             start,
             pointer: start,
-            end: start + lenght,
+            end: partition_end(start, lenght)?,
+        })
+    }
+
+    /// Return the partition of `lenght` bytes at `offset` in this one, reading from the same source.
+    ///
+    /// It is truncated at the end of this partition.
+    pub fn sub_partition(&self, offset: u64, lenght: u64) -> ReadAtPartition<T> {
+        let start = std::cmp::min(self.start.saturating_add(offset), self.end);
+        ReadAtPartition {
+            file: self.file.clone(),
+            start,
+            pointer: start,
+            end: std::cmp::min(start.saturating_add(lenght), self.end),
+        }
+    }
+
+    /// The lenght of the partition
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end == self.start
+    }
+}
+
+impl<T: ReadAt + AsRef<[u8]>> ReadAtPartition<T> {
+    /// Borrow the bytes of the partition from an in-memory source, without copying them.
+    ///
+    /// The slice is truncated if the partition end after the source.
+    pub fn as_slice(&self) -> &[u8] {
+        let data = (*self.file).as_ref();
+        let start = std::cmp::min(self.start, data.len() as u64) as usize;
+        let end = std::cmp::min(self.end, data.len() as u64) as usize;
+        &data[start..end]
+    }
+}
+
+impl<T: ReadAt + AsRef<[u8]>> AsRef<[u8]> for ReadAtPartition<T> {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl<T: ReadAt> Clone for ReadAtPartition<T> {
+    fn clone(&self) -> ReadAtPartition<T> {
+        ReadAtPartition {
+            file: self.file.clone(),
+            start: self.start,
+            pointer: self.pointer,
+            end: self.end,
+        }
+    }
+}
+
+impl<T: ReadAt> ReadAt for ReadAtPartition<T> {
+    /// Read at `offset` relative to the start of the partition, without moving the position of this partition
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let absolute_offset = match self.start.checked_add(offset) {
+            Some(value) if value < self.end => value,
+            _ => return Ok(0),
         };
-        result.seek(SeekFrom::Start(0))?;
-        Ok(result)
+        let lenght = std::cmp::min(buf.len() as u64, self.end - absolute_offset) as usize;
+        self.file.read_at(absolute_offset, &mut buf[..lenght])
     }
 }
 
-impl<T: Read + Seek> Read for PartitionMutex<T> {
+impl<T: ReadAt> Read for ReadAtPartition<T> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let mut file = match self.file.lock() {
-            Ok(value) => value,
-            Err(_) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    "the fie mutex is poisoned",
-                ))
-            }
-        };
-        let result = partition_read(buf, &mut *file, self.start, self.end, self.pointer);
-        self.pointer = result.0;
-        result.1
+        let read = self.read_at(self.pointer - self.start, buf)?;
+        self.pointer += read as u64;
+        Ok(read)
     }
 }
 
-impl<T: Read + Seek> Seek for PartitionMutex<T> {
+impl<T: ReadAt> Seek for ReadAtPartition<T> {
     fn seek(&mut self, target: SeekFrom) -> io::Result<u64> {
-        let mut file = match self.file.lock() {
-            Ok(value) => value,
-            Err(_) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    "the fie mutex is poisoned",
-                ))
-            }
-        };
-        let result = partition_seek(&mut *file, self.start, self.end, self.pointer, target);
+        let result = partition_seek(self.start, self.end, self.pointer, target);
         self.pointer = result.0;
         result.1
     }
 }
 
-impl<T: Read + Seek> Write for PartitionMutex<T> {
+impl<T: ReadAt> Write for ReadAtPartition<T> {
     /// Do not use this write function. It is just here to make ``vfs::VFile`` happy. It will always return an error.
     fn write(&mut self, _: &[u8]) -> io::Result<usize> {
         Err(io::Error::from(io::ErrorKind::PermissionDenied))
@@ -200,21 +223,178 @@ impl<T: Read + Seek> Write for PartitionMutex<T> {
     }
 }
 
-//TODO: make a test, and find a type that implement Read + Seek for in memory data
-/*
-#[test]
-fn test_partition_read() {
-    let mut partition = Partition::new(content, 4, 6);
-    let mut buf = [0; 4];
-    partition.read(&mut buf);
-    assert_eq!(buf, [4,5,6,7]);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
 
-    buf = [0; 4];
-    partition.read(&mut buf);
-    assert_eq!(buf, [8,9,0,0]);
+    fn content() -> Cursor<Vec<u8>> {
+        Cursor::new((0..16).collect())
+    }
+
+    /// A reader that return at most one byte per read, like a slow pipe
+    #[derive(Debug)]
+    struct OneByteReader(Cursor<Vec<u8>>);
 
-    buf = [0; 4];
-    partition.read(&mut buf);
-    assert_eq!(buf, [0,0,0,0]);
+    impl Read for OneByteReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let lenght = std::cmp::min(buf.len(), 1);
+            self.0.read(&mut buf[..lenght])
+        }
+    }
+
+    impl Seek for OneByteReader {
+        fn seek(&mut self, target: SeekFrom) -> io::Result<u64> {
+            self.0.seek(target)
+        }
+    }
+
+    /// A 8GiB virtual file, where each byte is the lowest byte of its offset
+    #[derive(Debug)]
+    struct HugeFile(u64);
+
+    impl Read for HugeFile {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            for byte in buf.iter_mut() {
+                *byte = self.0 as u8;
+                self.0 += 1;
+            }
+            Ok(buf.len())
+        }
+    }
+
+    impl Seek for HugeFile {
+        fn seek(&mut self, target: SeekFrom) -> io::Result<u64> {
+            match target {
+                SeekFrom::Start(offset) => self.0 = offset,
+                SeekFrom::End(offset) => self.0 = (0x2_0000_0000i64 + offset) as u64,
+                SeekFrom::Current(offset) => self.0 = (self.0 as i64 + offset) as u64,
+            };
+            Ok(self.0)
+        }
+    }
+
+    #[test]
+    fn test_partition_read() {
+        let mut partition = Partition::new(content(), 4, 6).unwrap();
+        let mut buf = [0; 4];
+        assert_eq!(partition.read(&mut buf).unwrap(), 4);
+        assert_eq!(buf, [4, 5, 6, 7]);
+
+        buf = [0; 4];
+        assert_eq!(partition.read(&mut buf).unwrap(), 2);
+        assert_eq!(buf, [8, 9, 0, 0]);
+
+        buf = [0; 4];
+        assert_eq!(partition.read(&mut buf).unwrap(), 0);
+        assert_eq!(buf, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_partition_exact_fit_read() {
+        let mut partition = Partition::new(content(), 4, 6).unwrap();
+        let mut buf = [0; 6];
+        partition.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [4, 5, 6, 7, 8, 9]);
+        assert_eq!(partition.read(&mut buf).unwrap(), 0);
+
+        partition.seek(SeekFrom::Start(0)).unwrap();
+        let mut all = Vec::new();
+        partition.read_to_end(&mut all).unwrap();
+        assert_eq!(all, [4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn test_partition_short_read() {
+        let mut partition = Partition::new(OneByteReader(content()), 4, 6).unwrap();
+        let mut buf = [0; 4];
+        assert_eq!(partition.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 4);
+        assert_eq!(partition.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 5);
+        assert_eq!(partition.stream_position().unwrap(), 2);
+
+        let mut rest = Vec::new();
+        partition.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, [6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn test_partition_seek() {
+        let mut partition = Partition::new(content(), 4, 6).unwrap();
+        assert_eq!(partition.seek(SeekFrom::Start(2)).unwrap(), 2);
+        assert_eq!(partition.seek(SeekFrom::Current(1)).unwrap(), 3);
+        let mut buf = [0; 1];
+        partition.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [7]);
+        assert_eq!(partition.seek(SeekFrom::End(-1)).unwrap(), 5);
+        partition.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [9]);
+        assert!(partition.seek(SeekFrom::Current(-10)).is_err());
+        assert_eq!(partition.seek(SeekFrom::End(3)).unwrap(), 9);
+        assert_eq!(partition.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_partition_after_4gib() {
+        let mut partition = Partition::new(HugeFile(0), 0x1_0000_0010, 0x1_0000_0000).unwrap();
+        let mut buf = [0; 2];
+        partition.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0x10, 0x11]);
+        assert_eq!(partition.seek(SeekFrom::End(-1)).unwrap(), 0xFFFF_FFFF);
+        assert_eq!(partition.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 0x0F);
+    }
+
+    #[test]
+    fn test_read_at_partition() {
+        let data: Arc<Vec<u8>> = Arc::new((0..16).collect());
+        let mut partition = ReadAtPartition::new(data, 4, 6).unwrap();
+        let mut other = partition.clone();
+        let mut buf = [0; 4];
+        assert_eq!(partition.read(&mut buf).unwrap(), 4);
+        assert_eq!(buf, [4, 5, 6, 7]);
+        assert_eq!(other.read(&mut buf[..1]).unwrap(), 1);
+        assert_eq!(buf[0], 4);
+        assert_eq!(partition.read(&mut buf).unwrap(), 2);
+        assert_eq!(buf[..2], [8, 9]);
+        assert_eq!(partition.read(&mut buf).unwrap(), 0);
+        assert_eq!(partition.read_at(5, &mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 9);
+        assert_eq!(partition.read_at(6, &mut buf).unwrap(), 0);
+        assert_eq!(partition.as_slice(), &[4, 5, 6, 7, 8, 9]);
+        assert_eq!(partition.sub_partition(2, 8).as_slice(), &[6, 7, 8, 9]);
+        assert_eq!(partition.sub_partition(8, u64::MAX).as_slice(), &[]);
+        let nested = ReadAtPartition::new(Arc::new(partition), 2, 8).unwrap();
+        assert_eq!(nested.as_slice(), &[6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn test_partition_overflow() {
+        assert!(Partition::new(content(), u64::MAX, 1).is_err());
+        let file = Arc::new(Mutex::new(content()));
+        assert!(PartitionMutex::new(file, 1, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_partition_mutex_shared_file() {
+        let file = Arc::new(Mutex::new(content()));
+        let mut first = PartitionMutex::new(file.clone(), 2, 4).unwrap();
+        let mut second = PartitionMutex::new(file, 10, 6).unwrap();
+        let mut buf = [0; 2];
+        first.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [2, 3]);
+        second.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [10, 11]);
+        first.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [4, 5]);
+        assert_eq!(first.read(&mut buf).unwrap(), 0);
+
+        let mut rest = Vec::new();
+        second.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, [12, 13, 14, 15]);
+        assert_eq!(second.seek(SeekFrom::Start(1)).unwrap(), 1);
+        second.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [11, 12]);
+    }
 }
-*/
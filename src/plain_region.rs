@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::Read;
+
+#[derive(Debug)]
+pub enum PlainRegionError {
+    ReadError(io::Error),
+}
+
+impl Error for PlainRegionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::ReadError(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for PlainRegionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadError(_) => write!(f, "failed to read the plain region"),
+        }
+    }
+}
+
+/// A SDK library linked into a title, parsed from a string like `[SDK+NINTENDO:CTR_SDK-2_2_0]`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SDKLibrary {
+    /// The vendor, like "SDK+NINTENDO"
+    pub vendor: String,
+    pub name: String,
+    pub version: String,
+}
+
+impl SDKLibrary {
+    /// Parse a string of the plain region. Return `None` if it doesn't have the expected format.
+    pub fn parse(string: &str) -> Option<SDKLibrary> {
+        let content = string.strip_prefix('[')?.strip_suffix(']')?;
+        let (vendor, library) = content.split_once(':')?;
+        // the name may contain '-', but not the version
+        let (name, version) = library.rsplit_once('-').unwrap_or((library, ""));
+        Some(SDKLibrary {
+            vendor: vendor.to_string(),
+            name: name.to_string(),
+            version: version.to_string(),
+        })
+    }
+}
+
+/// The plain region of a NCCH, that list the SDK libraries (and their versions) linked into the title.
+#[derive(Debug, Clone)]
+pub struct PlainRegion {
+    /// The raw strings, in the order they are stored
+    pub strings: Vec<String>,
+}
+
+impl PlainRegion {
+    pub fn new<T: Read>(mut file: T) -> Result<PlainRegion, PlainRegionError> {
+        let mut data = Vec::new();
+        match file.read_to_end(&mut data) {
+            Ok(_) => (),
+            Err(err) => return Err(PlainRegionError::ReadError(err)),
+        };
+        let strings = data
+            .split(|byte| *byte == 0)
+            .filter(|string| !string.is_empty())
+            .map(|string| String::from_utf8_lossy(string).into_owned())
+            .collect();
+        Ok(PlainRegion { strings })
+    }
+
+    /// Return the parsed libraries. Strings that doesn't look like a library are ignored.
+    pub fn libraries(&self) -> Vec<SDKLibrary> {
+        self.strings
+            .iter()
+            .filter_map(|string| SDKLibrary::parse(string))
+            .collect()
+    }
+
+    /// Return the versions of each library, grouped by the name of the library
+    pub fn versions_by_library(&self) -> BTreeMap<String, Vec<String>> {
+        let mut result: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for library in self.libraries() {
+            let versions = result.entry(library.name).or_default();
+            if !versions.contains(&library.version) {
+                versions.push(library.version);
+            };
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sdk_library_parse() {
+        assert_eq!(
+            SDKLibrary::parse("[SDK+NINTENDO:CTR-EXT_SDK-2_2_0]"),
+            Some(SDKLibrary {
+                vendor: "SDK+NINTENDO".to_string(),
+                name: "CTR-EXT_SDK".to_string(),
+                version: "2_2_0".to_string(),
+            })
+        );
+        assert_eq!(
+            SDKLibrary::parse("[SDK+NINTENDO:Backup]").unwrap().version,
+            ""
+        );
+        assert_eq!(SDKLibrary::parse("SDK+NINTENDO:Backup-1_0"), None);
+    }
+}
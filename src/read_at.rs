@@ -0,0 +1,141 @@
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+/// A source that can be read at any offset through a shared reference, so concurrent readers doesn't need a global lock.
+pub trait ReadAt {
+    /// Read bytes starting at `offset` into `buf`, returning the number of bytes read.
+    ///
+    /// Like `Read::read`, it may read less bytes than asked, and return 0 at the end of the source.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Read exactly `buf.len()` bytes starting at `offset`
+    fn read_exact_at(&self, mut offset: u64, mut buf: &mut [u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(offset, buf) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                Ok(read) => {
+                    buf = &mut buf[read..];
+                    offset += read as u64;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+impl ReadAt for std::fs::File {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(self, buf, offset)
+    }
+}
+
+#[cfg(windows)]
+impl ReadAt for std::fs::File {
+    /// On Windows, this also move the position of the file
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+    }
+}
+
+impl ReadAt for [u8] {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if offset >= self.len() as u64 {
+            return Ok(0);
+        };
+        let available = &self[offset as usize..];
+        let lenght = std::cmp::min(available.len(), buf.len());
+        buf[..lenght].copy_from_slice(&available[..lenght]);
+        Ok(lenght)
+    }
+}
+
+impl ReadAt for Vec<u8> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.as_slice().read_at(offset, buf)
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).read_at(offset, buf)
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Box<T> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).read_at(offset, buf)
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Arc<T> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).read_at(offset, buf)
+    }
+}
+
+/// The fallback for sources that only implement `Read + Seek`: every read lock the mutex, and seek before reading.
+impl<T: Read + Seek> ReadAt for Mutex<T> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let mut file = match self.lock() {
+            Ok(value) => value,
+            Err(_) => return Err(io::Error::other("the file mutex is poisoned")),
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        file.read(buf)
+    }
+}
+
+/// A `Read + Seek` view of a `ReadAt` source, that keep its own position.
+///
+/// The lenght of the source is unknown, so seeking relative to the end isn't supported.
+#[derive(Debug)]
+pub struct ReadAtCursor<'a, T: ReadAt + ?Sized> {
+    source: &'a T,
+    position: u64,
+}
+
+impl<'a, T: ReadAt + ?Sized> ReadAtCursor<'a, T> {
+    pub fn new(source: &'a T) -> ReadAtCursor<'a, T> {
+        ReadAtCursor {
+            source,
+            position: 0,
+        }
+    }
+}
+
+impl<T: ReadAt + ?Sized> Read for ReadAtCursor<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.source.read_at(self.position, buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<T: ReadAt + ?Sized> Seek for ReadAtCursor<'_, T> {
+    fn seek(&mut self, target: SeekFrom) -> io::Result<u64> {
+        let new_position = match target {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "can't seek relative to the end of a ReadAt source",
+                ))
+            }
+        };
+        match new_position {
+            Some(value) => {
+                self.position = value;
+                Ok(value)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't seek before the beggining of the source",
+            )),
+        }
+    }
+}
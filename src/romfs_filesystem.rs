@@ -0,0 +1,211 @@
+use crate::{DirectoryOrFile, IVFCError, IVFCReader, ReadAt};
+use std::fmt;
+use std::io::Write;
+use std::sync::Arc;
+use vfs_filesystem::error::VfsErrorKind;
+use vfs_filesystem::{FileSystem, SeekAndRead, VfsFileType, VfsMetadata, VfsResult};
+
+/// A read only romfs, implementing the `FileSystem` trait of recent versions of the `vfs` crate.
+///
+/// It can be used with `vfs::VfsPath::new`, and mounted in an `OverlayFS` or an `AltrootFS`.
+pub struct RomFSFileSystem<T: 'static + ReadAt + Send + Sync + fmt::Debug> {
+    reader: Arc<IVFCReader<T>>,
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> RomFSFileSystem<T> {
+    pub fn new(reader: IVFCReader<T>) -> RomFSFileSystem<T> {
+        RomFSFileSystem::from_shared(Arc::new(reader))
+    }
+
+    pub fn from_shared(reader: Arc<IVFCReader<T>>) -> RomFSFileSystem<T> {
+        RomFSFileSystem { reader }
+    }
+
+    fn get_path_metadata(&self, path: &str) -> VfsResult<DirectoryOrFile> {
+        match self.reader.get_path_metadata(path) {
+            Ok(value) => Ok(value),
+            Err(IVFCError::FileNotFound) | Err(IVFCError::DirNotFound) => {
+                Err(VfsErrorKind::FileNotFound.into())
+            }
+            Err(err) => Err(VfsErrorKind::Other(err.to_string()).into()),
+        }
+    }
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> fmt::Debug for RomFSFileSystem<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RomFSFileSystem").finish()
+    }
+}
+
+fn return_ro_error<T>() -> VfsResult<T> {
+    Err(VfsErrorKind::NotSupported.into())
+}
+
+impl<T: 'static + ReadAt + Send + Sync + fmt::Debug> FileSystem for RomFSFileSystem<T> {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        let dir = match self.get_path_metadata(path)? {
+            DirectoryOrFile::Dir(value) => value,
+            DirectoryOrFile::File(_) => {
+                return Err(VfsErrorKind::Other("not a directory".to_string()).into())
+            }
+        };
+        match self.reader.list_child(&dir) {
+            Ok(childs) => Ok(Box::new(childs.into_iter())),
+            Err(err) => Err(VfsErrorKind::Other(err.to_string()).into()),
+        }
+    }
+
+    fn create_dir(&self, _path: &str) -> VfsResult<()> {
+        return_ro_error()
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        let file = match self.get_path_metadata(path)? {
+            DirectoryOrFile::File(value) => value,
+            DirectoryOrFile::Dir(_) => {
+                return Err(VfsErrorKind::Other("not a file".to_string()).into())
+            }
+        };
+        Ok(Box::new(self.reader.open_file(&file)?))
+    }
+
+    fn create_file(&self, _path: &str) -> VfsResult<Box<dyn Write + Send>> {
+        return_ro_error()
+    }
+
+    fn append_file(&self, _path: &str) -> VfsResult<Box<dyn Write + Send>> {
+        return_ro_error()
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        Ok(match self.get_path_metadata(path)? {
+            DirectoryOrFile::Dir(_) => VfsMetadata {
+                file_type: VfsFileType::Directory,
+                len: 0,
+            },
+            DirectoryOrFile::File(file) => VfsMetadata {
+                file_type: VfsFileType::File,
+                len: file.lenght_file_data,
+            },
+        })
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        match self.get_path_metadata(path) {
+            Ok(_) => Ok(true),
+            Err(err) => match err.kind() {
+                VfsErrorKind::FileNotFound => Ok(false),
+                _ => Err(err),
+            },
+        }
+    }
+
+    fn remove_file(&self, _path: &str) -> VfsResult<()> {
+        return_ro_error()
+    }
+
+    fn remove_dir(&self, _path: &str) -> VfsResult<()> {
+        return_ro_error()
+    }
+}
+
+#[cfg(all(test, feature = "filesystem"))]
+mod tests {
+    use super::*;
+    use crate::ivfc::romfs_from_files;
+    use std::io::Read;
+    use vfs_filesystem::{MemoryFS, OverlayFS, VfsPath};
+
+    fn romfs() -> RomFSFileSystem<Vec<u8>> {
+        RomFSFileSystem::new(
+            IVFCReader::from_read_at(romfs_from_files(&[
+                ("a.bin", b"romfs a"),
+                ("dir/b.bin", b"romfs b"),
+            ]))
+            .unwrap(),
+        )
+    }
+
+    fn read(path: &VfsPath) -> String {
+        let mut content = String::new();
+        path.open_file()
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn test_romfs_filesystem() {
+        let romfs = romfs();
+        let mut childs: Vec<String> = romfs.read_dir("").unwrap().collect();
+        childs.sort();
+        assert_eq!(childs, ["a.bin", "dir"]);
+        assert_eq!(
+            romfs.read_dir("/dir").unwrap().collect::<Vec<_>>(),
+            ["b.bin"]
+        );
+        assert!(romfs.read_dir("/a.bin").is_err());
+
+        let mut content = String::new();
+        romfs
+            .open_file("/dir/b.bin")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "romfs b");
+        assert!(romfs.open_file("/dir").is_err());
+        assert!(matches!(
+            romfs
+                .open_file("/missing.bin")
+                .map(|_| ())
+                .unwrap_err()
+                .kind(),
+            VfsErrorKind::FileNotFound
+        ));
+
+        let metadata = romfs.metadata("/a.bin").unwrap();
+        assert_eq!(metadata.file_type, VfsFileType::File);
+        assert_eq!(metadata.len, 7);
+        assert_eq!(
+            romfs.metadata("/dir").unwrap().file_type,
+            VfsFileType::Directory
+        );
+
+        assert!(romfs.exists("/dir/b.bin").unwrap());
+        assert!(!romfs.exists("/missing.bin").unwrap());
+        assert!(!romfs.exists("/dir/missing/c.bin").unwrap());
+        assert!(romfs.create_file("/c.bin").is_err());
+    }
+
+    #[test]
+    fn test_overlay() {
+        let host = VfsPath::new(MemoryFS::new());
+        host.join("a.bin")
+            .unwrap()
+            .create_file()
+            .unwrap()
+            .write_all(b"host a")
+            .unwrap();
+        host.join("c.bin")
+            .unwrap()
+            .create_file()
+            .unwrap()
+            .write_all(b"host c")
+            .unwrap();
+        let overlay = VfsPath::new(OverlayFS::new(&[host, VfsPath::new(romfs())]));
+
+        assert_eq!(read(&overlay.join("a.bin").unwrap()), "host a");
+        assert_eq!(read(&overlay.join("dir/b.bin").unwrap()), "romfs b");
+        assert_eq!(read(&overlay.join("c.bin").unwrap()), "host c");
+        let mut childs: Vec<String> = overlay
+            .read_dir()
+            .unwrap()
+            .map(|child| child.filename())
+            .collect();
+        childs.sort();
+        assert_eq!(childs, ["a.bin", "c.bin", "dir"]);
+        assert!(!overlay.join("missing.bin").unwrap().exists().unwrap());
+    }
+}
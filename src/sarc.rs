@@ -0,0 +1,806 @@
+use crate::{IVFCMeta, PartitionMutex};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::SeekFrom;
+use std::io::{Read, Seek, Write};
+use std::path::PathBuf;
+use std::string::FromUtf8Error;
+use std::sync::{Arc, Mutex};
+
+use vfs::{OpenOptions, VFile, VMetadata, VPath, VFS};
+
+/// The hash key used by nearly every SARC
+pub const SARC_DEFAULT_HASH_KEY: u32 = 0x65;
+
+#[derive(Debug)]
+pub enum SARCError {
+    ReadError(io::Error, &'static str),
+    SeekError(io::Error, &'static str),
+    WriteError(io::Error),
+    InvalidMagic([u8; 4], &'static str), // the invalid magic, and the section it should be the magic of
+    InvalidByteOrderMark([u8; 2]),
+    TruncatedTable(&'static str),
+    InvalidNameOffset(u32),
+    NameToUTF8Error(FromUtf8Error),
+    ArchiveTooBig,
+    TooManyEntries(usize),
+    NameTableTooBig,
+    DataOffsetOverflow(usize), // the index of the entry
+}
+
+impl Error for SARCError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::ReadError(err, _) => Some(err),
+            Self::SeekError(err, _) => Some(err),
+            Self::WriteError(err) => Some(err),
+            Self::NameToUTF8Error(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SARCError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadError(_, what) => write!(
+                f,
+                "failed to read the {} due to an error in the source input",
+                what
+            ),
+            Self::SeekError(_, what) => write!(
+                f,
+                "failed to seek to the {} due to an error in the source input",
+                what
+            ),
+            Self::WriteError(_) => write!(f, "failed to write the sarc to the output"),
+            Self::InvalidMagic(magic, section) => write!(
+                f,
+                "the magic of the {} section is invalid (found {:?})",
+                section, magic
+            ),
+            Self::InvalidByteOrderMark(mark) => {
+                write!(f, "the byte order mark of the sarc is invalid ({:?})", mark)
+            }
+            Self::TruncatedTable(what) => write!(f, "the {} of the sarc is truncated", what),
+            Self::InvalidNameOffset(offset) => write!(
+                f,
+                "a sarc entry have a name at {:#x}, outside of the name table",
+                offset
+            ),
+            Self::NameToUTF8Error(_) => {
+                write!(
+                    f,
+                    "Impossible to convert a sarc entry name to an UTF8 String"
+                )
+            }
+            Self::ArchiveTooBig => write!(f, "the sarc to write is bigger than 4GiB"),
+            Self::TooManyEntries(count) => write!(
+                f,
+                "the sarc to write have {} entries, but at most 65535 are supported",
+                count
+            ),
+            Self::NameTableTooBig => write!(
+                f,
+                "the name table of the sarc to write is too big for the name offsets"
+            ),
+            Self::DataOffsetOverflow(entry_nb) => write!(
+                f,
+                "the data of the entry {} of the sarc start after 4GiB",
+                entry_nb
+            ),
+        }
+    }
+}
+
+/// Compute the hash of a file name, as stored in the SFAT table
+pub fn sarc_name_hash(name: &str, key: u32) -> u32 {
+    name.bytes().fold(0u32, |hash, byte| {
+        hash.wrapping_mul(key).wrapping_add(byte as u32)
+    })
+}
+
+/// An entry of the SFAT table of a SARC
+#[derive(Debug, Clone)]
+pub struct SARCEntry {
+    pub hash: u32,
+    /// `None` if the entry doesn't have a name in the SFNT table
+    pub name: Option<String>,
+    /// The offset of the data, relative to the start of the archive
+    pub offset: u32,
+    pub lenght: u32,
+}
+
+impl SARCEntry {
+    /// Return the name of this entry, or the hash formatted as `0x0123abcd` if it doesn't have one
+    pub fn display_name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("{:#010x}", self.hash),
+        }
+    }
+}
+
+fn get_u16(data: &[u8], offset: usize, big_endian: bool) -> u16 {
+    let bytes = [data[offset], data[offset + 1]];
+    if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    }
+}
+
+fn get_u32(data: &[u8], offset: usize, big_endian: bool) -> u32 {
+    let bytes = [
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ];
+    if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    }
+}
+
+/// Read a SARC archive, in either byte order. The entries are sorted by hash, as in the SFAT table.
+#[derive(Debug)]
+pub struct SARCReader<T: Read + Seek> {
+    pub file: Arc<Mutex<T>>,
+    pub big_endian: bool,
+    pub hash_key: u32,
+    pub entries: Vec<SARCEntry>,
+}
+
+impl<T: Read + Seek> SARCReader<T> {
+    pub fn new(mut file: T) -> Result<SARCReader<T>, SARCError> {
+        match file.seek(SeekFrom::Start(0)) {
+            Ok(_) => (),
+            Err(err) => return Err(SARCError::SeekError(err, "sarc header")),
+        };
+        let mut header = [0; 0x14];
+        match file.read_exact(&mut header) {
+            Ok(_) => (),
+            Err(err) => return Err(SARCError::ReadError(err, "sarc header")),
+        };
+        if &header[0..4] != b"SARC" {
+            return Err(SARCError::InvalidMagic(
+                [header[0], header[1], header[2], header[3]],
+                "sarc",
+            ));
+        };
+        let big_endian = match [header[6], header[7]] {
+            [0xFE, 0xFF] => true,
+            [0xFF, 0xFE] => false,
+            mark => return Err(SARCError::InvalidByteOrderMark(mark)),
+        };
+        let header_lenght = get_u16(&header, 4, big_endian) as u64;
+        let data_offset = get_u32(&header, 0xC, big_endian);
+
+        // the SFAT and SFNT tables are between the header and the data
+        match file.seek(SeekFrom::Start(header_lenght)) {
+            Ok(_) => (),
+            Err(err) => return Err(SARCError::SeekError(err, "sfat table")),
+        };
+        // read through `take`, so a data offset bigger than the input doesn't allocate it all upfront
+        let tables_lenght = (data_offset as u64).saturating_sub(header_lenght);
+        let mut tables = Vec::new();
+        match file.by_ref().take(tables_lenght).read_to_end(&mut tables) {
+            Ok(read) if read as u64 == tables_lenght => (),
+            Ok(_) => {
+                return Err(SARCError::ReadError(
+                    io::Error::from(io::ErrorKind::UnexpectedEof),
+                    "sfat and sfnt tables",
+                ))
+            }
+            Err(err) => return Err(SARCError::ReadError(err, "sfat and sfnt tables")),
+        };
+
+        if tables.len() < 0xC {
+            return Err(SARCError::TruncatedTable("sfat header"));
+        };
+        if &tables[0..4] != b"SFAT" {
+            return Err(SARCError::InvalidMagic(
+                [tables[0], tables[1], tables[2], tables[3]],
+                "sfat",
+            ));
+        };
+        let sfat_header_lenght = get_u16(&tables, 4, big_endian) as usize;
+        let node_count = get_u16(&tables, 6, big_endian) as usize;
+        let hash_key = get_u32(&tables, 8, big_endian);
+
+        let sfnt_offset = sfat_header_lenght + node_count * 0x10;
+        if tables.len() < sfnt_offset + 8 {
+            return Err(SARCError::TruncatedTable("sfat table"));
+        };
+        if &tables[sfnt_offset..sfnt_offset + 4] != b"SFNT" {
+            let magic = &tables[sfnt_offset..sfnt_offset + 4];
+            return Err(SARCError::InvalidMagic(
+                [magic[0], magic[1], magic[2], magic[3]],
+                "sfnt",
+            ));
+        };
+        let name_table = match tables
+            .get(sfnt_offset + get_u16(&tables, sfnt_offset + 4, big_endian) as usize..)
+        {
+            Some(value) => value,
+            None => return Err(SARCError::TruncatedTable("sfnt header")),
+        };
+
+        let mut entries = Vec::new();
+        for node_nb in 0..node_count {
+            let node_offset = sfat_header_lenght + node_nb * 0x10;
+            let hash = get_u32(&tables, node_offset, big_endian);
+            let attributes = get_u32(&tables, node_offset + 4, big_endian);
+            let data_start = get_u32(&tables, node_offset + 8, big_endian);
+            let data_end = get_u32(&tables, node_offset + 12, big_endian);
+
+            let name = if attributes & 0xFF00_0000 != 0 {
+                let name_offset = (attributes & 0x00FF_FFFF) * 4;
+                let name_bytes: Vec<u8> = match name_table.get(name_offset as usize..) {
+                    Some(value) => value
+                        .iter()
+                        .take_while(|byte| **byte != 0)
+                        .copied()
+                        .collect(),
+                    None => return Err(SARCError::InvalidNameOffset(name_offset)),
+                };
+                match String::from_utf8(name_bytes) {
+                    Ok(value) => Some(value),
+                    Err(err) => return Err(SARCError::NameToUTF8Error(err)),
+                }
+            } else {
+                None
+            };
+
+            let offset = match data_offset.checked_add(data_start) {
+                Some(value) => value,
+                None => return Err(SARCError::DataOffsetOverflow(node_nb)),
+            };
+            entries.push(SARCEntry {
+                hash,
+                name,
+                offset,
+                lenght: data_end.saturating_sub(data_start),
+            });
+        }
+
+        Ok(SARCReader {
+            file: Arc::new(Mutex::new(file)),
+            big_endian,
+            hash_key,
+            entries,
+        })
+    }
+
+    /// Return the index of the entry named `name`, found with a binary search on its hash.
+    ///
+    /// Entries without a name can be found with their hash formatted as in `SARCEntry::display_name`.
+    pub fn get_entry_index(&self, name: &str) -> Option<usize> {
+        let name = name.trim_start_matches('/');
+        let hash = sarc_name_hash(name, self.hash_key);
+        let first = self.entries.partition_point(|entry| entry.hash < hash);
+        let found = self.entries[first..]
+            .iter()
+            .take_while(|entry| entry.hash == hash)
+            .position(|entry| entry.name.as_deref() == Some(name));
+        if let Some(position) = found {
+            return Some(first + position);
+        };
+        let unnamed_hash = u32::from_str_radix(name.strip_prefix("0x")?, 16).ok()?;
+        self.entries
+            .iter()
+            .position(|entry| entry.name.is_none() && entry.hash == unnamed_hash)
+    }
+
+    /// Open the file at `entry_index`
+    pub fn open_entry(&self, entry_index: usize) -> io::Result<PartitionMutex<T>> {
+        match self.entries.get(entry_index) {
+            Some(entry) => {
+                PartitionMutex::new(self.file.clone(), entry.offset as u64, entry.lenght as u64)
+            }
+            None => Err(io::Error::from(io::ErrorKind::NotFound)),
+        }
+    }
+
+    /// Open the file named `name`
+    pub fn get_file(&self, name: &str) -> io::Result<PartitionMutex<T>> {
+        match self.get_entry_index(name) {
+            Some(entry_index) => self.open_entry(entry_index),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "the file doesn't exist in the sarc",
+            )),
+        }
+    }
+
+    /// Return the names of the direct childs of the directory at `dir`. SARC doesn't store directories, so they are deduced from the '/' in the names.
+    ///
+    /// The boolean is true if the child is a directory.
+    pub fn list_child(&self, dir: &str) -> Vec<(String, bool)> {
+        let dir = dir.trim_matches('/');
+        let mut result = BTreeSet::new();
+        for entry in &self.entries {
+            let name = entry.display_name();
+            let rest = if dir.is_empty() {
+                name.as_str()
+            } else {
+                match name
+                    .strip_prefix(dir)
+                    .and_then(|rest| rest.strip_prefix('/'))
+                {
+                    Some(rest) => rest,
+                    None => continue,
+                }
+            };
+            match rest.split_once('/') {
+                Some((child_dir, _)) => result.insert((child_dir.to_string(), true)),
+                None => result.insert((rest.to_string(), false)),
+            };
+        }
+        result.into_iter().collect()
+    }
+
+    /// Return true if at least one file is in the directory at `dir`
+    pub fn is_dir(&self, dir: &str) -> bool {
+        let dir = dir.trim_matches('/');
+        dir.is_empty()
+            || self.entries.iter().any(|entry| {
+                entry
+                    .display_name()
+                    .strip_prefix(dir)
+                    .is_some_and(|rest| rest.starts_with('/'))
+            })
+    }
+}
+
+/// A file to store in a SARC with `SARCWriter`
+#[derive(Debug, Clone)]
+pub struct SARCWriterFile {
+    pub name: String,
+    pub data: Vec<u8>,
+    /// The alignment of the data in the archive, as a power of two
+    pub alignment: u32,
+}
+
+/// Build a new SARC, or a patched version of an existing one.
+#[derive(Debug, Clone)]
+pub struct SARCWriter {
+    pub big_endian: bool,
+    pub hash_key: u32,
+    pub files: Vec<SARCWriterFile>,
+}
+
+/// Return the alignment the official tools use for the data of the file named `name`
+pub fn sarc_default_alignment(name: &str) -> u32 {
+    let extension = match name.rsplit_once('.') {
+        Some((_, extension)) => extension.to_ascii_lowercase(),
+        None => return 4,
+    };
+    match extension.as_str() {
+        "bclim" | "bflim" | "bcfnt" | "bffnt" => 0x80,
+        "bcwav" | "bfwav" | "bcstm" | "bfstm" | "bcsar" | "bfsar" => 0x20,
+        _ => 4,
+    }
+}
+
+fn align(value: usize, alignment: usize) -> usize {
+    value.div_ceil(alignment) * alignment
+}
+
+impl SARCWriter {
+    pub fn new(big_endian: bool) -> SARCWriter {
+        SARCWriter {
+            big_endian,
+            hash_key: SARC_DEFAULT_HASH_KEY,
+            files: Vec::new(),
+        }
+    }
+
+    /// Load every file of `reader`, keeping the byte order, the hash key and the alignment of each file.
+    ///
+    /// Entries without a name are stored with the name from `SARCEntry::display_name`.
+    pub fn from_reader<T: Read + Seek>(reader: &SARCReader<T>) -> io::Result<SARCWriter> {
+        let mut files = Vec::new();
+        for (entry_index, entry) in reader.entries.iter().enumerate() {
+            let mut data = vec![0; entry.lenght as usize];
+            reader.open_entry(entry_index)?.read_exact(&mut data)?;
+            // the biggest power of two that divide the offset, capped to the biggest alignment used by the official tools
+            let alignment = if entry.offset == 0 {
+                0x80
+            } else {
+                std::cmp::min(1 << entry.offset.trailing_zeros(), 0x80)
+            };
+            files.push(SARCWriterFile {
+                name: entry.display_name(),
+                data,
+                alignment: std::cmp::max(alignment, 4),
+            });
+        }
+        Ok(SARCWriter {
+            big_endian: reader.big_endian,
+            hash_key: reader.hash_key,
+            files,
+        })
+    }
+
+    /// Add a file with the alignment from `sarc_default_alignment`, replacing the file with the same name if any
+    pub fn add_file(&mut self, name: &str, data: Vec<u8>) {
+        self.add_file_aligned(name, data, sarc_default_alignment(name));
+    }
+
+    /// Add a file with a custom alignment (that should be a power of two), replacing the file with the same name if any
+    pub fn add_file_aligned(&mut self, name: &str, data: Vec<u8>, alignment: u32) {
+        let new_file = SARCWriterFile {
+            name: name.trim_start_matches('/').to_string(),
+            data,
+            alignment: std::cmp::max(alignment, 1),
+        };
+        match self
+            .files
+            .iter_mut()
+            .find(|file| file.name == new_file.name)
+        {
+            Some(file) => *file = new_file,
+            None => self.files.push(new_file),
+        };
+    }
+
+    /// Remove the file named `name`. Return false if there were no such file.
+    pub fn remove_file(&mut self, name: &str) -> bool {
+        let name = name.trim_start_matches('/');
+        let lenght_before = self.files.len();
+        self.files.retain(|file| file.name != name);
+        self.files.len() != lenght_before
+    }
+
+    fn push_u16(&self, output: &mut Vec<u8>, value: u16) {
+        output.extend_from_slice(&if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        });
+    }
+
+    fn push_u32(&self, output: &mut Vec<u8>, value: u32) {
+        output.extend_from_slice(&if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        });
+    }
+
+    /// Build the archive in memory
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SARCError> {
+        if self.files.len() > u16::MAX as usize {
+            return Err(SARCError::TooManyEntries(self.files.len()));
+        };
+        // the SFAT table is sorted by hash, and by name for files with the same hash
+        let mut files: Vec<(u32, &SARCWriterFile)> = self
+            .files
+            .iter()
+            .map(|file| (sarc_name_hash(&file.name, self.hash_key), file))
+            .collect();
+        files.sort_by(|(hash_1, file_1), (hash_2, file_2)| {
+            hash_1.cmp(hash_2).then(file_1.name.cmp(&file_2.name))
+        });
+
+        let mut name_table = Vec::new();
+        let mut name_offsets = Vec::new();
+        for (_, file) in &files {
+            name_offsets.push(name_table.len());
+            name_table.extend_from_slice(file.name.as_bytes());
+            name_table.push(0);
+            name_table.resize(align(name_table.len(), 4), 0);
+        }
+        // the name offsets are stored divided by 4, on 24 bits
+        if name_offsets
+            .last()
+            .is_some_and(|offset| offset / 4 > 0xFF_FFFF)
+        {
+            return Err(SARCError::NameTableTooBig);
+        };
+
+        let data_alignment = files
+            .iter()
+            .map(|(_, file)| file.alignment as usize)
+            .max()
+            .unwrap_or(4);
+        let tables_end = 0x14 + 0xC + files.len() * 0x10 + 0x8 + name_table.len();
+        let data_offset = align(tables_end, data_alignment);
+
+        let mut data_positions = Vec::new();
+        let mut data_lenght = 0;
+        for (_, file) in &files {
+            data_lenght = align(data_lenght, file.alignment as usize);
+            data_positions.push(data_lenght);
+            data_lenght += file.data.len();
+        }
+        let total_lenght = data_offset + data_lenght;
+        if total_lenght > u32::MAX as usize {
+            return Err(SARCError::ArchiveTooBig);
+        };
+
+        let mut output = Vec::with_capacity(total_lenght);
+        output.extend_from_slice(b"SARC");
+        self.push_u16(&mut output, 0x14);
+        self.push_u16(&mut output, 0xFEFF);
+        self.push_u32(&mut output, total_lenght as u32);
+        self.push_u32(&mut output, data_offset as u32);
+        self.push_u16(&mut output, 0x0100);
+        self.push_u16(&mut output, 0);
+
+        output.extend_from_slice(b"SFAT");
+        self.push_u16(&mut output, 0xC);
+        self.push_u16(&mut output, files.len() as u16);
+        self.push_u32(&mut output, self.hash_key);
+        let mut previous_hash = None;
+        let mut collision_count = 0;
+        for (file_nb, (hash, file)) in files.iter().enumerate() {
+            // the high byte of the attributes count the files with the same hash, starting from 1
+            if previous_hash == Some(*hash) {
+                collision_count += 1;
+            } else {
+                collision_count = 1;
+            };
+            previous_hash = Some(*hash);
+            self.push_u32(&mut output, *hash);
+            self.push_u32(
+                &mut output,
+                (collision_count << 24) | (name_offsets[file_nb] / 4) as u32,
+            );
+            self.push_u32(&mut output, data_positions[file_nb] as u32);
+            self.push_u32(
+                &mut output,
+                (data_positions[file_nb] + file.data.len()) as u32,
+            );
+        }
+
+        output.extend_from_slice(b"SFNT");
+        self.push_u16(&mut output, 0x8);
+        self.push_u16(&mut output, 0);
+        output.extend_from_slice(&name_table);
+
+        for (file_nb, (_, file)) in files.iter().enumerate() {
+            output.resize(data_offset + data_positions[file_nb], 0);
+            output.extend_from_slice(&file.data);
+        }
+        Ok(output)
+    }
+
+    pub fn write<W: Write>(&self, output: &mut W) -> Result<(), SARCError> {
+        match output.write_all(&self.to_bytes()?) {
+            Ok(()) => Ok(()),
+            Err(err) => Err(SARCError::WriteError(err)),
+        }
+    }
+}
+
+/// A read only VFS over a SARC archive. Directories are deduced from the '/' in the file names.
+pub struct SARCVFS<T: 'static + Read + Seek + Send + Sync + fmt::Debug> {
+    reader: Arc<SARCReader<T>>,
+}
+
+impl<T: 'static + Read + Seek + Send + Sync + fmt::Debug> SARCVFS<T> {
+    pub fn new(reader: SARCReader<T>) -> SARCVFS<T> {
+        SARCVFS {
+            reader: Arc::new(reader),
+        }
+    }
+}
+
+impl<T: 'static + Read + Seek + Send + Sync + fmt::Debug> VFS for SARCVFS<T> {
+    type PATH = SARCVPATH<T>;
+    type METADATA = IVFCMeta;
+    type FILE = PartitionMutex<T>;
+
+    fn path<A: Into<String>>(&self, path: A) -> Self::PATH {
+        SARCVPATH {
+            reader: self.reader.clone(),
+            path: PathBuf::from(path.into()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SARCVPATH<T: Read + Seek + Send + Sync + fmt::Debug> {
+    reader: Arc<SARCReader<T>>,
+    path: PathBuf,
+}
+
+impl<T: Read + Seek + Send + Sync + fmt::Debug> Clone for SARCVPATH<T> {
+    fn clone(&self) -> SARCVPATH<T> {
+        SARCVPATH {
+            reader: self.reader.clone(),
+            path: self.path.clone(),
+        }
+    }
+}
+
+impl<T: 'static + Read + Seek + Send + Sync + fmt::Debug> SARCVPATH<T> {
+    /// The path as used in the SARC, with '/' as separator
+    fn sarc_name(&self) -> String {
+        self.path
+            .iter()
+            .map(|part| part.to_string_lossy())
+            .filter(|part| part != "/")
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn with_path(&self, path: PathBuf) -> SARCVPATH<T> {
+        SARCVPATH {
+            reader: self.reader.clone(),
+            path,
+        }
+    }
+}
+
+fn return_ro_error<T>() -> io::Result<T> {
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "read only file system",
+    ))
+}
+
+impl<T: 'static + Read + Seek + Send + Sync + fmt::Debug> VPath for SARCVPATH<T> {
+    fn open_with_options(&self, opt: &OpenOptions) -> io::Result<Box<dyn VFile>> {
+        if opt.write || opt.create || opt.append || opt.truncate {
+            return return_ro_error();
+        };
+        Ok(Box::new(self.reader.get_file(&self.sarc_name())?))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn read_dir(&self) -> io::Result<Box<dyn Iterator<Item = io::Result<Box<dyn VPath>>>>> {
+        let name = self.sarc_name();
+        if !self.reader.is_dir(&name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "trying to list content for a file",
+            ));
+        };
+        let this = self.clone();
+        Ok(Box::new(
+            self.reader
+                .list_child(&name)
+                .into_iter()
+                .map(move |(child, _)| Ok(this.resolve(&child))),
+        ))
+    }
+
+    fn mkdir(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn rm(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn rmrf(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn file_name(&self) -> Option<String> {
+        self.path.file_name()
+    }
+
+    fn extension(&self) -> Option<String> {
+        self.path.extension()
+    }
+
+    fn resolve(&self, path: &String) -> Box<dyn VPath> {
+        let mut new_path = self.path.clone();
+        new_path.push(path);
+        Box::new(self.with_path(new_path))
+    }
+
+    fn parent(&self) -> Option<Box<dyn VPath>> {
+        let mut new_path = self.path.clone();
+        if !new_path.pop() {
+            return None;
+        };
+        Some(Box::new(self.with_path(new_path)))
+    }
+
+    fn to_string(&self) -> Cow<'_, str> {
+        format!("sarc://{:?}", self.path).into()
+    }
+
+    fn box_clone(&self) -> Box<dyn VPath> {
+        Box::new(self.clone())
+    }
+
+    fn to_path_buf(&self) -> Option<PathBuf> {
+        Some(self.path.clone())
+    }
+
+    fn exists(&self) -> bool {
+        let name = self.sarc_name();
+        self.reader.get_entry_index(&name).is_some() || self.reader.is_dir(&name)
+    }
+
+    fn metadata(&self) -> io::Result<Box<dyn VMetadata>> {
+        let name = self.sarc_name();
+        if let Some(entry_index) = self.reader.get_entry_index(&name) {
+            return Ok(Box::new(IVFCMeta::File(
+                self.reader.entries[entry_index].lenght as u64,
+            )));
+        };
+        if self.reader.is_dir(&name) {
+            return Ok(Box::new(IVFCMeta::Dir));
+        };
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "the file doesn't exist in the sarc",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_round_trip() {
+        for big_endian in [false, true].iter() {
+            let mut writer = SARCWriter::new(*big_endian);
+            writer.add_file("a.bin", vec![1, 2, 3]);
+            writer.add_file("dir/b.bclim", vec![4; 0x90]);
+            writer.add_file("dir/c", Vec::new());
+            let reader = SARCReader::new(Cursor::new(writer.to_bytes().unwrap())).unwrap();
+            assert_eq!(reader.big_endian, *big_endian);
+            assert_eq!(reader.entries.len(), 3);
+            for (name, data) in [
+                ("a.bin", vec![1, 2, 3]),
+                ("dir/b.bclim", vec![4; 0x90]),
+                ("dir/c", Vec::new()),
+            ]
+            .iter()
+            {
+                let mut content = Vec::new();
+                reader
+                    .get_file(name)
+                    .unwrap()
+                    .read_to_end(&mut content)
+                    .unwrap();
+                assert_eq!(&content, data);
+            }
+            assert_eq!(
+                reader.list_child("dir"),
+                vec![("b.bclim".to_string(), false), ("c".to_string(), false)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_data_offset_overflow() {
+        let mut writer = SARCWriter::new(false);
+        writer.add_file("a.bin", vec![1, 2, 3]);
+        let mut sarc = writer.to_bytes().unwrap();
+        // the data start of the only node of the SFAT
+        sarc[0x14 + 0xC + 8..0x14 + 0xC + 12].copy_from_slice(&u32::MAX.to_le_bytes());
+        match SARCReader::new(Cursor::new(sarc)) {
+            Err(SARCError::DataOffsetOverflow(0)) => (),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        };
+    }
+
+    #[test]
+    fn test_huge_data_offset() {
+        let mut writer = SARCWriter::new(false);
+        writer.add_file("a.bin", vec![1, 2, 3]);
+        let mut sarc = writer.to_bytes().unwrap();
+        sarc[0xC..0x10].copy_from_slice(&u32::MAX.to_le_bytes());
+        match SARCReader::new(Cursor::new(sarc)) {
+            Err(SARCError::ReadError(err, "sfat and sfnt tables"))
+                if err.kind() == io::ErrorKind::UnexpectedEof => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        };
+    }
+}
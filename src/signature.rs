@@ -0,0 +1,289 @@
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum SignatureError {
+    TooShort(&'static str),      // what is truncated
+    UnknownSignatureType(u32),   // the unknown signature type
+    UnknownKeyType(u32, String), // the unknown key type, and the name of the certificate
+}
+
+impl Error for SignatureError {}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooShort(what) => write!(f, "the {} is truncated", what),
+            Self::UnknownSignatureType(signature_type) => {
+                write!(f, "unknown signature type {:#x}", signature_type)
+            }
+            Self::UnknownKeyType(key_type, name) => write!(
+                f,
+                "unknown key type {:#x} in the certificate {:?}",
+                key_type, name
+            ),
+        }
+    }
+}
+
+/// Return the lenght of the signature block that start with a signature of this type (the type, the signature and the padding), or `None` if the type is unknown
+pub fn signature_block_lenght(signature_type: u32) -> Option<usize> {
+    match signature_type {
+        0x10000 | 0x10003 => Some(0x240),
+        0x10001 | 0x10004 => Some(0x140),
+        0x10002 | 0x10005 => Some(0x80),
+        _ => None,
+    }
+}
+
+/// The DER encoded prefix of a SHA-256 hash in a PKCS#1 v1.5 signature
+const SHA256_DIGEST_INFO: [u8; 19] = [
+    0x30, 0x31, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05,
+    0x00, 0x04, 0x20,
+];
+
+/// A big unsigned number, stored as little endian 32 bits limbs
+fn big_from_be_bytes(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .rchunks(4)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .fold(0, |value, byte| (value << 8) | *byte as u32)
+        })
+        .collect()
+}
+
+fn big_to_be_bytes(limbs: &[u32], lenght: usize) -> Vec<u8> {
+    let mut bytes: Vec<u8> = limbs.iter().flat_map(|limb| limb.to_le_bytes()).collect();
+    bytes.resize(std::cmp::max(bytes.len(), lenght), 0);
+    bytes.truncate(lenght);
+    bytes.reverse();
+    bytes
+}
+
+fn big_mul(first: &[u32], second: &[u32]) -> Vec<u32> {
+    let mut result = vec![0; first.len() + second.len()];
+    for (first_position, first_limb) in first.iter().enumerate() {
+        let mut carry = 0;
+        for (second_position, second_limb) in second.iter().enumerate() {
+            let actual = result[first_position + second_position] as u64
+                + *first_limb as u64 * *second_limb as u64
+                + carry;
+            result[first_position + second_position] = actual as u32;
+            carry = actual >> 32;
+        }
+        result[first_position + second.len()] = carry as u32;
+    }
+    result
+}
+
+/// Compare two numbers with the same number of limbs
+fn big_greater_or_equal(first: &[u32], second: &[u32]) -> bool {
+    for (first_limb, second_limb) in first.iter().rev().zip(second.iter().rev()) {
+        if first_limb != second_limb {
+            return first_limb > second_limb;
+        };
+    }
+    true
+}
+
+/// Subtract `second` from `first`, that have the same number of limbs and is greater or equal
+fn big_sub_assign(first: &mut [u32], second: &[u32]) {
+    let mut borrow = 0;
+    for (first_limb, second_limb) in first.iter_mut().zip(second.iter()) {
+        let actual = *first_limb as i64 - *second_limb as i64 - borrow;
+        *first_limb = actual as u32;
+        borrow = if actual < 0 { 1 } else { 0 };
+    }
+}
+
+/// Compute `value % modulus`, one bit at a time. Slow, but only a few reductions are needed per signature.
+fn big_rem(value: &[u32], modulus: &[u32]) -> Vec<u32> {
+    // one more limb, as the remainder is shifted before being reduced
+    let mut modulus_extended = modulus.to_vec();
+    modulus_extended.push(0);
+    let mut remainder = vec![0; modulus_extended.len()];
+    for bit in (0..value.len() * 32).rev() {
+        let mut carry = (value[bit / 32] >> (bit % 32)) & 1;
+        for limb in remainder.iter_mut() {
+            let next_carry = *limb >> 31;
+            *limb = (*limb << 1) | carry;
+            carry = next_carry;
+        }
+        if big_greater_or_equal(&remainder, &modulus_extended) {
+            big_sub_assign(&mut remainder, &modulus_extended);
+        };
+    }
+    remainder.truncate(modulus.len());
+    remainder
+}
+
+fn big_mod_pow(base: &[u32], exponent: u32, modulus: &[u32]) -> Vec<u32> {
+    let mut result = vec![0; modulus.len()];
+    result[0] = 1;
+    let base = big_rem(base, modulus);
+    for bit in (0..32).rev() {
+        result = big_rem(&big_mul(&result, &result), modulus);
+        if (exponent >> bit) & 1 == 1 {
+            result = big_rem(&big_mul(&result, &base), modulus);
+        };
+    }
+    result
+}
+
+/// A RSA public key, like the ones in the certificates or in the access descriptor of a NCCH
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RSAPublicKey {
+    /// The big endian modulus
+    pub modulus: Vec<u8>,
+    pub exponent: u32,
+}
+
+impl RSAPublicKey {
+    /// Check a PKCS#1 v1.5 signature of the SHA-256 of `data`
+    pub fn verify_sha256(&self, data: &[u8], signature: &[u8]) -> bool {
+        let lenght = self.modulus.len();
+        if signature.len() != lenght || lenght < SHA256_DIGEST_INFO.len() + 32 + 11 {
+            return false;
+        };
+        let modulus = big_from_be_bytes(&self.modulus);
+        let signature = big_from_be_bytes(signature);
+        if big_greater_or_equal(&signature, &modulus) {
+            return false;
+        };
+        let decrypted = big_to_be_bytes(&big_mod_pow(&signature, self.exponent, &modulus), lenght);
+
+        let mut expected = vec![0x00, 0x01];
+        expected.resize(lenght - SHA256_DIGEST_INFO.len() - 32 - 1, 0xFF);
+        expected.push(0x00);
+        expected.extend_from_slice(&SHA256_DIGEST_INFO);
+        expected.extend_from_slice(&Sha256::digest(data));
+        decrypted == expected
+    }
+}
+
+/// A certificate, that sign a public key with the key of its issuer
+#[derive(Debug, Clone)]
+pub struct Certificate {
+    pub signature_type: u32,
+    pub signature: Vec<u8>,
+    /// The full name of the certificate that signed this one, like "Root-CA00000003"
+    pub issuer: String,
+    pub name: String,
+    /// `None` for an ECC key
+    pub key: Option<RSAPublicKey>,
+    /// The signed part of the certificate, from the issuer to the end of the key
+    pub signed_data: Vec<u8>,
+}
+
+/// Read a nul padded string
+fn read_name(data: &[u8]) -> String {
+    String::from_utf8_lossy(data)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+impl Certificate {
+    /// Read the certificate at the start of `data`. Return it with its lenght.
+    pub fn parse(data: &[u8]) -> Result<(Certificate, usize), SignatureError> {
+        if data.len() < 4 {
+            return Err(SignatureError::TooShort("certificate signature type"));
+        };
+        let signature_type = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let signature_block = match signature_block_lenght(signature_type) {
+            Some(value) => value,
+            None => return Err(SignatureError::UnknownSignatureType(signature_type)),
+        };
+        // the signature is followed by padding, up to a multiple of 0x40
+        let signature_lenght = match signature_block {
+            0x240 => 0x200,
+            0x140 => 0x100,
+            _ => 0x3C,
+        };
+        let header_end = signature_block + 0x88;
+        if data.len() < header_end {
+            return Err(SignatureError::TooShort("certificate header"));
+        };
+        let issuer = read_name(&data[signature_block..signature_block + 0x40]);
+        let key_type = u32::from_be_bytes([
+            data[signature_block + 0x40],
+            data[signature_block + 0x41],
+            data[signature_block + 0x42],
+            data[signature_block + 0x43],
+        ]);
+        let name = read_name(&data[signature_block + 0x44..signature_block + 0x84]);
+        let (modulus_lenght, key_lenght) = match key_type {
+            0 => (Some(0x200), 0x238),
+            1 => (Some(0x100), 0x138),
+            2 => (None, 0x78),
+            _ => return Err(SignatureError::UnknownKeyType(key_type, name)),
+        };
+        let lenght = header_end + key_lenght;
+        if data.len() < lenght {
+            return Err(SignatureError::TooShort("certificate public key"));
+        };
+        let key = modulus_lenght.map(|modulus_lenght| {
+            let exponent = &data[header_end + modulus_lenght..header_end + modulus_lenght + 4];
+            RSAPublicKey {
+                modulus: data[header_end..header_end + modulus_lenght].to_vec(),
+                exponent: u32::from_be_bytes([exponent[0], exponent[1], exponent[2], exponent[3]]),
+            }
+        });
+        Ok((
+            Certificate {
+                signature_type,
+                signature: data[4..4 + signature_lenght].to_vec(),
+                issuer,
+                name,
+                key,
+                signed_data: data[signature_block..lenght].to_vec(),
+            },
+            lenght,
+        ))
+    }
+
+    /// Read a chain of certificates stored one after the other, like in a CIA
+    pub fn parse_all(mut data: &[u8]) -> Result<Vec<Certificate>, SignatureError> {
+        let mut certificates = Vec::new();
+        // the chain may be padded with zeros
+        while data.iter().any(|byte| *byte != 0) {
+            let (certificate, lenght) = Certificate::parse(data)?;
+            certificates.push(certificate);
+            data = &data[lenght..];
+        }
+        Ok(certificates)
+    }
+
+    /// The name used by what this certificate sign as its issuer, like "Root-CA00000003-CP0000000b"
+    pub fn full_name(&self) -> String {
+        format!("{}-{}", self.issuer, self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mod_pow() {
+        // the textbook RSA example, with p = 61 and q = 53
+        let modulus = big_from_be_bytes(&3233u32.to_be_bytes());
+        let encrypted = big_mod_pow(&big_from_be_bytes(&[65]), 17, &modulus);
+        assert_eq!(big_to_be_bytes(&encrypted, 2), 2790u16.to_be_bytes());
+        let decrypted = big_mod_pow(&encrypted, 2753, &modulus);
+        assert_eq!(big_to_be_bytes(&decrypted, 2), 65u16.to_be_bytes());
+    }
+
+    #[test]
+    fn test_big_rem_multiple_limbs() {
+        let value = big_from_be_bytes(&[0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0xDE, 0xF0, 0x11]);
+        let modulus = big_from_be_bytes(&[0x01, 0x00, 0x00, 0x00, 0x07]);
+        let expected = 0x123456789ABCDEF011u128 % 0x0100000007u128;
+        assert_eq!(
+            big_to_be_bytes(&big_rem(&value, &modulus), 16),
+            expected.to_be_bytes()
+        );
+    }
+}
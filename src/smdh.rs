@@ -0,0 +1,377 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::Read;
+use std::string::FromUtf16Error;
+
+/// The width and height of the small icon, in pixel
+pub const SMALL_ICON_SIZE: usize = 24;
+/// The width and height of the large icon, in pixel
+pub const LARGE_ICON_SIZE: usize = 48;
+
+#[derive(Debug)]
+pub enum SMDHError {
+    ReadError(io::Error),
+    InvalidMagic([u8; 4]), // the invalid magic
+    ToUTF16Error(FromUtf16Error, &'static str),
+    InvalidIcon(&'static str), // which icon
+}
+
+impl Error for SMDHError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::ReadError(err) => Some(err),
+            Self::ToUTF16Error(err, _) => Some(err),
+            Self::InvalidMagic(_) | Self::InvalidIcon(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for SMDHError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadError(_) => write!(f, "failed to read the smdh"),
+            Self::InvalidMagic(magic) => write!(
+                f,
+                "the magic of the smdh is invalid. Found {:?}, expected [83, 77, 68, 72].",
+                magic
+            ),
+            Self::ToUTF16Error(_, what) => {
+                write!(f, "Impossible to convert \"{}\" to an UTF16 String", what)
+            }
+            Self::InvalidIcon(what) => write!(f, "the {} of the smdh can't be decoded", what),
+        }
+    }
+}
+
+/// The languages of the titles of a SMDH, in the order they are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SMDHLanguage {
+    Japanese = 0,
+    English = 1,
+    French = 2,
+    German = 3,
+    Italian = 4,
+    Spanish = 5,
+    SimplifiedChinese = 6,
+    Korean = 7,
+    Dutch = 8,
+    Portuguese = 9,
+    Russian = 10,
+    TraditionalChinese = 11,
+}
+
+/// The rating organizations of a SMDH, with the index of their rating
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SMDHRatingOrganization {
+    CERO = 0,
+    ESRB = 1,
+    USK = 3,
+    PEGIGEN = 4,
+    PEGIPRT = 6,
+    PEGIBBFC = 7,
+    COB = 8,
+    GRB = 9,
+    CGSRR = 10,
+}
+
+/// The bits of the region lockout of a SMDH
+pub mod region {
+    pub const JAPAN: u32 = 0x01;
+    pub const NORTH_AMERICA: u32 = 0x02;
+    pub const EUROPE: u32 = 0x04;
+    pub const AUSTRALIA: u32 = 0x08;
+    pub const CHINA: u32 = 0x10;
+    pub const KOREA: u32 = 0x20;
+    pub const TAIWAN: u32 = 0x40;
+    pub const REGION_FREE: u32 = 0x7FFF_FFFF;
+}
+
+/// The titles of an application in one language
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SMDHTitle {
+    pub short_description: String,
+    pub long_description: String,
+    pub publisher: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SMDHAgeRating {
+    pub enabled: bool,
+    pub pending: bool,
+    pub no_age_restriction: bool,
+    pub age: u8,
+}
+
+impl From<u8> for SMDHAgeRating {
+    fn from(value: u8) -> SMDHAgeRating {
+        SMDHAgeRating {
+            enabled: value & 0x80 != 0,
+            pending: value & 0x40 != 0,
+            no_age_restriction: value & 0x20 != 0,
+            age: value & 0x1F,
+        }
+    }
+}
+
+/// The icon and metadata of a title, stored in the `icon` file of the ExeFS.
+#[derive(Debug, Clone)]
+pub struct SMDH {
+    pub version: u16,
+    /// The titles, indexed by `SMDHLanguage`. The last 4 are unused.
+    pub titles: Vec<SMDHTitle>,
+    /// The age ratings, indexed by `SMDHRatingOrganization`
+    pub ratings: [SMDHAgeRating; 16],
+    /// A combination of the bits in `region`
+    pub region_lockout: u32,
+    pub match_maker_id: u32,
+    pub match_maker_bit_id: u64,
+    pub flags: u32,
+    pub eula_version: u16,
+    pub optimal_animation_default_frame: f32,
+    pub cec_id: u32,
+    /// The 24x24 icon, in linear RGBA
+    pub small_icon: Vec<u8>,
+    /// The 48x48 icon, in linear RGBA
+    pub large_icon: Vec<u8>,
+}
+
+fn read_utf16_nul_terminated(data: &[u8], what: &'static str) -> Result<String, SMDHError> {
+    let string_numbered: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+        .take_while(|character| *character != 0)
+        .collect();
+    match String::from_utf16(&string_numbered) {
+        Ok(value) => Ok(value),
+        Err(err) => Err(SMDHError::ToUTF16Error(err, what)),
+    }
+}
+
+/// Decode a tiled RGB565 icon, as found in a SMDH, to a linear RGBA buffer.
+///
+/// Return `None` if `size` isn't a multiple of the 8 pixels of a tile, or if `data` is shorter than `size * size` pixels.
+pub fn decode_tiled_rgb565(data: &[u8], size: usize) -> Option<Vec<u8>> {
+    if size % 8 != 0 || data.len() / 2 < size.checked_mul(size)? {
+        return None;
+    };
+    let mut result = vec![0; size * size * 4];
+    let mut pixel_nb = 0;
+    for tile_y in (0..size).step_by(8) {
+        for tile_x in (0..size).step_by(8) {
+            for pixel_in_tile in 0..64 {
+                // pixels in a tile are in the Z-order
+                let x =
+                    (pixel_in_tile & 1) | ((pixel_in_tile >> 1) & 2) | ((pixel_in_tile >> 2) & 4);
+                let y = ((pixel_in_tile >> 1) & 1)
+                    | ((pixel_in_tile >> 2) & 2)
+                    | ((pixel_in_tile >> 3) & 4);
+                let value = u16::from_le_bytes([data[pixel_nb * 2], data[pixel_nb * 2 + 1]]);
+                pixel_nb += 1;
+                let red = ((value >> 11) & 0x1F) as u8;
+                let green = ((value >> 5) & 0x3F) as u8;
+                let blue = (value & 0x1F) as u8;
+                let output_offset = ((tile_y + y) * size + tile_x + x) * 4;
+                result[output_offset] = (red << 3) | (red >> 2);
+                result[output_offset + 1] = (green << 2) | (green >> 4);
+                result[output_offset + 2] = (blue << 3) | (blue >> 2);
+                result[output_offset + 3] = 0xFF;
+            }
+        }
+    }
+    Some(result)
+}
+
+impl SMDH {
+    pub fn new<T: Read>(mut file: T) -> Result<SMDH, SMDHError> {
+        let mut data = vec![0; 0x36C0];
+        match file.read_exact(&mut data) {
+            Ok(_) => (),
+            Err(err) => return Err(SMDHError::ReadError(err)),
+        };
+
+        let mut magic = [0; 4];
+        magic.copy_from_slice(&data[0..4]);
+        if &magic != b"SMDH" {
+            return Err(SMDHError::InvalidMagic(magic));
+        };
+
+        let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ])
+        };
+
+        let version = read_u16(4);
+
+        let mut titles = Vec::new();
+        for title_nb in 0..16 {
+            let title_data = &data[0x8 + title_nb * 0x200..0x8 + (title_nb + 1) * 0x200];
+            titles.push(SMDHTitle {
+                short_description: read_utf16_nul_terminated(
+                    &title_data[0..0x80],
+                    "short description",
+                )?,
+                long_description: read_utf16_nul_terminated(
+                    &title_data[0x80..0x180],
+                    "long description",
+                )?,
+                publisher: read_utf16_nul_terminated(&title_data[0x180..0x200], "publisher")?,
+            });
+        }
+
+        let mut ratings = [SMDHAgeRating::from(0); 16];
+        for (rating_nb, rating) in ratings.iter_mut().enumerate() {
+            *rating = SMDHAgeRating::from(data[0x2008 + rating_nb]);
+        }
+
+        let small_icon = match decode_tiled_rgb565(&data[0x2040..0x24C0], SMALL_ICON_SIZE) {
+            Some(value) => value,
+            None => return Err(SMDHError::InvalidIcon("small icon")),
+        };
+        let large_icon = match decode_tiled_rgb565(&data[0x24C0..0x36C0], LARGE_ICON_SIZE) {
+            Some(value) => value,
+            None => return Err(SMDHError::InvalidIcon("large icon")),
+        };
+
+        Ok(SMDH {
+            version,
+            titles,
+            ratings,
+            region_lockout: read_u32(0x2018),
+            match_maker_id: read_u32(0x201C),
+            match_maker_bit_id: read_u32(0x2020) as u64 | ((read_u32(0x2024) as u64) << 32),
+            flags: read_u32(0x2028),
+            eula_version: read_u16(0x202C),
+            optimal_animation_default_frame: f32::from_bits(read_u32(0x2030)),
+            cec_id: read_u32(0x2034),
+            small_icon,
+            large_icon,
+        })
+    }
+
+    pub fn get_title(&self, language: SMDHLanguage) -> &SMDHTitle {
+        &self.titles[language as usize]
+    }
+
+    pub fn get_rating(&self, organization: SMDHRatingOrganization) -> SMDHAgeRating {
+        self.ratings[organization as usize]
+    }
+
+    pub fn is_region_free(&self) -> bool {
+        self.region_lockout == region::REGION_FREE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_tiled_rgb565_morton_order() {
+        // the index of a pixel in the tile, and its position in the decoded image
+        for (pixel_in_tile, x, y) in &[
+            (0, 0, 0),
+            (1, 1, 0),
+            (2, 0, 1),
+            (3, 1, 1),
+            (4, 2, 0),
+            (8, 0, 2),
+            (16, 4, 0),
+            (32, 0, 4),
+            (63, 7, 7),
+        ] {
+            let mut data = vec![0; 8 * 8 * 2];
+            data[pixel_in_tile * 2..pixel_in_tile * 2 + 2]
+                .copy_from_slice(&0xF81Fu16.to_le_bytes());
+            let decoded = decode_tiled_rgb565(&data, 8).unwrap();
+            for (pixel_nb, pixel) in decoded.chunks(4).enumerate() {
+                if pixel_nb == y * 8 + x {
+                    assert_eq!(pixel, [0xFF, 0, 0xFF, 0xFF]);
+                } else {
+                    assert_eq!(pixel, [0, 0, 0, 0xFF]);
+                };
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_tiled_rgb565_invalid() {
+        assert_eq!(decode_tiled_rgb565(&[0; 8 * 8 * 2 - 1], 8), None);
+        assert_eq!(decode_tiled_rgb565(&[], 8), None);
+        assert_eq!(decode_tiled_rgb565(&[0; 12 * 12 * 2], 12), None);
+        assert_eq!(decode_tiled_rgb565(&[], 0), Some(Vec::new()));
+    }
+
+    fn write_utf16(data: &mut [u8], text: &str) {
+        for (character_nb, character) in text.encode_utf16().enumerate() {
+            data[character_nb * 2..character_nb * 2 + 2].copy_from_slice(&character.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_smdh() {
+        let mut data = vec![0; 0x36C0];
+        data[0..4].copy_from_slice(b"SMDH");
+        data[4..6].copy_from_slice(&3u16.to_le_bytes());
+        for (language, short_description, publisher) in &[
+            (SMDHLanguage::Japanese, "テスト", "任天堂"),
+            (SMDHLanguage::English, "Test", "Nintendo"),
+        ] {
+            let offset = 0x8 + *language as usize * 0x200;
+            write_utf16(&mut data[offset..], short_description);
+            write_utf16(&mut data[offset + 0x80..], "A long description");
+            write_utf16(&mut data[offset + 0x180..], publisher);
+        }
+        data[0x2008 + SMDHRatingOrganization::CERO as usize] = 0x80 | 12;
+        data[0x2008 + SMDHRatingOrganization::ESRB as usize] = 0x80 | 0x40;
+        data[0x2018..0x201C].copy_from_slice(&(region::JAPAN | region::EUROPE).to_le_bytes());
+        // the first pixel of the small icon is red, the last pixel of the large icon is blue
+        data[0x2040..0x2042].copy_from_slice(&0xF800u16.to_le_bytes());
+        data[0x36BE..0x36C0].copy_from_slice(&0x001Fu16.to_le_bytes());
+
+        let smdh = SMDH::new(&data[..]).unwrap();
+        assert_eq!(smdh.version, 3);
+        assert_eq!(smdh.titles.len(), 16);
+        let japanese = smdh.get_title(SMDHLanguage::Japanese);
+        assert_eq!(japanese.short_description, "テスト");
+        assert_eq!(japanese.publisher, "任天堂");
+        let english = smdh.get_title(SMDHLanguage::English);
+        assert_eq!(english.short_description, "Test");
+        assert_eq!(english.long_description, "A long description");
+        assert_eq!(english.publisher, "Nintendo");
+        assert_eq!(smdh.get_title(SMDHLanguage::French), &SMDHTitle::default());
+
+        let cero = smdh.get_rating(SMDHRatingOrganization::CERO);
+        assert!(cero.enabled && !cero.pending);
+        assert_eq!(cero.age, 12);
+        assert!(smdh.get_rating(SMDHRatingOrganization::ESRB).pending);
+        assert!(!smdh.get_rating(SMDHRatingOrganization::USK).enabled);
+
+        assert_eq!(smdh.region_lockout, region::JAPAN | region::EUROPE);
+        assert!(!smdh.is_region_free());
+
+        assert_eq!(smdh.small_icon.len(), SMALL_ICON_SIZE * SMALL_ICON_SIZE * 4);
+        assert_eq!(smdh.small_icon[0..4], [0xFF, 0, 0, 0xFF]);
+        assert_eq!(smdh.small_icon[4..8], [0, 0, 0, 0xFF]);
+        assert_eq!(smdh.large_icon.len(), LARGE_ICON_SIZE * LARGE_ICON_SIZE * 4);
+        assert_eq!(
+            smdh.large_icon[smdh.large_icon.len() - 4..],
+            [0, 0, 0xFF, 0xFF]
+        );
+
+        data[0] = b'X';
+        assert!(matches!(
+            SMDH::new(&data[..]),
+            Err(SMDHError::InvalidMagic(magic)) if &magic == b"XMDH"
+        ));
+        assert!(matches!(
+            SMDH::new(&data[..0x100]),
+            Err(SMDHError::ReadError(_))
+        ));
+    }
+}
@@ -0,0 +1,532 @@
+use crate::{
+    ExeFSError, ExeFSReader, IVFCError, IVFCReader, NCCHError, NCCHReader, NCSDError, NCSDReader,
+    ReadAt, ReadAtCursor, ReadAtPartition,
+};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum TitleLayoutError {
+    ReadError(io::Error, &'static str),
+    UnknownFormat,
+    NCSDError(NCSDError),
+    NCCHError(NCCHError, usize), // usize: partition or content index
+    ExeFSError(ExeFSError, usize),
+    IVFCError(IVFCError, usize),
+    InvalidCIA(&'static str),
+    EncryptedContent(usize), // usize: content index
+    OffsetOverflow(&'static str),
+}
+
+impl Error for TitleLayoutError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::ReadError(err, _) => Some(err),
+            Self::NCSDError(err) => Some(err),
+            Self::NCCHError(err, _) => Some(err),
+            Self::ExeFSError(err, _) => Some(err),
+            Self::IVFCError(err, _) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TitleLayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadError(_, what) => write!(f, "failed to read the {}", what),
+            Self::UnknownFormat => write!(f, "the file isn't a NCSD, a NCCH or a CIA"),
+            Self::NCSDError(_) => write!(f, "error with the ncsd header"),
+            Self::NCCHError(_, nb) => write!(f, "error with the ncch of the partition {}", nb),
+            Self::ExeFSError(_, nb) => write!(f, "error with the exefs of the partition {}", nb),
+            Self::IVFCError(_, nb) => write!(f, "error with the romfs of the partition {}", nb),
+            Self::InvalidCIA(what) => write!(f, "the cia is invalid: {}", what),
+            Self::EncryptedContent(nb) => write!(
+                f,
+                "the content {} of the cia is encrypted, and only decrypted cia are supported",
+                nb
+            ),
+            Self::OffsetOverflow(what) => write!(f, "the offset of the {} overflow", what),
+        }
+    }
+}
+
+/// The kind of file a title is stored in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TitleContainer {
+    /// A .3ds/.cci file, with up to 8 partitions
+    NCSD,
+    /// A lone .cxi/.cfa
+    NCCH,
+    /// A decrypted .cia, with one NCCH per content
+    CIA,
+}
+
+/// The offset and the lenght of the sections of a NCCH, relative to the start of the input file.
+///
+/// A section with a lenght of 0 doesn't exist.
+#[derive(Debug, Clone)]
+pub struct NCCHSections {
+    /// offset and lenght of the whole NCCH
+    pub ncch: (u64, u64),
+    pub exheader: (u64, u64),
+    pub plain_region: (u64, u64),
+    pub logo_region: (u64, u64),
+    pub exefs: (u64, u64),
+    pub romfs: (u64, u64),
+}
+
+impl NCCHSections {
+    /// Read the NCCH at `offset`. Its lenght is read from its header if `lenght` is `None`.
+    fn new<T: ReadAt + ?Sized>(
+        file: &T,
+        offset: u64,
+        lenght: Option<u64>,
+        nb: usize,
+    ) -> Result<NCCHSections, TitleLayoutError> {
+        let max_lenght = lenght.unwrap_or(u64::MAX - offset);
+        let partition = match ReadAtPartition::new(Arc::new(file), offset, max_lenght) {
+            Ok(value) => value,
+            Err(err) => {
+                return Err(TitleLayoutError::NCCHError(
+                    NCCHError::CreatePartitionError(err),
+                    nb,
+                ))
+            }
+        };
+        let ncch = match NCCHReader::new(ReadAtCursor::new(&partition)) {
+            Ok(value) => value,
+            Err(err) => return Err(TitleLayoutError::NCCHError(err, nb)),
+        };
+        let absolute = |(section_offset, section_lenght): (u64, u64)| match offset
+            .checked_add(section_offset)
+        {
+            Some(value) => Ok((value, section_lenght)),
+            None => Err(TitleLayoutError::OffsetOverflow("section of a ncch")),
+        };
+        Ok(NCCHSections {
+            ncch: (offset, lenght.unwrap_or(ncch.content_size)),
+            exheader: absolute(ncch.get_exheader_bounds())?,
+            plain_region: absolute(ncch.get_plain_region_bounds())?,
+            logo_region: absolute(ncch.get_logo_region_bounds())?,
+            exefs: absolute(ncch.get_exefs_bounds())?,
+            romfs: absolute(ncch.get_romfs_bounds())?,
+        })
+    }
+}
+
+/// Where the NCCH partitions of a title are in its file
+#[derive(Debug, Clone)]
+pub struct TitleLayout {
+    pub container: TitleContainer,
+    /// The partitions (or contents for a CIA) with their index, sorted by index
+    pub partitions: Vec<(usize, NCCHSections)>,
+}
+
+fn read_at_exact<T: ReadAt + ?Sized>(
+    file: &T,
+    offset: u64,
+    buf: &mut [u8],
+    what: &'static str,
+) -> Result<(), TitleLayoutError> {
+    match file.read_exact_at(offset, buf) {
+        Ok(()) => Ok(()),
+        Err(err) => Err(TitleLayoutError::ReadError(err, what)),
+    }
+}
+
+fn align_0x40(value: u64) -> Option<u64> {
+    Some(value.checked_add(0x3F)? & !0x3F)
+}
+
+const CIA_HEADER_SIZE: u32 = 0x2020;
+
+impl TitleLayout {
+    /// Find the partitions of a .3ds, .cxi, .cfa or decrypted .cia file
+    pub fn new<T: ReadAt + ?Sized>(file: &T) -> Result<TitleLayout, TitleLayoutError> {
+        let mut header = [0; 0x104];
+        read_at_exact(file, 0, &mut header, "header")?;
+        if &header[0x100..0x104] == b"NCSD" {
+            TitleLayout::new_ncsd(file)
+        } else if &header[0x100..0x104] == b"NCCH" {
+            Ok(TitleLayout {
+                container: TitleContainer::NCCH,
+                partitions: vec![(0, NCCHSections::new(file, 0, None, 0)?)],
+            })
+        } else if header[0..4] == CIA_HEADER_SIZE.to_le_bytes() {
+            TitleLayout::new_cia(file)
+        } else {
+            Err(TitleLayoutError::UnknownFormat)
+        }
+    }
+
+    fn new_ncsd<T: ReadAt + ?Sized>(file: &T) -> Result<TitleLayout, TitleLayoutError> {
+        let ncsd = match NCSDReader::new(ReadAtCursor::new(file)) {
+            Ok(value) => value,
+            Err(err) => return Err(TitleLayoutError::NCSDError(err)),
+        };
+        let mut partitions = Vec::new();
+        for partition_nb in 0..8 {
+            let (offset, lenght) = match ncsd.get_partition_bounds(partition_nb) {
+                Ok(value) => value,
+                Err(NCSDError::InexistingPartition(_)) => continue,
+                Err(err) => return Err(TitleLayoutError::NCSDError(err)),
+            };
+            partitions.push((
+                partition_nb,
+                NCCHSections::new(file, offset, Some(lenght), partition_nb)?,
+            ));
+        }
+        Ok(TitleLayout {
+            container: TitleContainer::NCSD,
+            partitions,
+        })
+    }
+
+    fn new_cia<T: ReadAt + ?Sized>(file: &T) -> Result<TitleLayout, TitleLayoutError> {
+        let mut header = [0; 0x20];
+        read_at_exact(file, 0, &mut header, "cia header")?;
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes([
+                header[offset],
+                header[offset + 1],
+                header[offset + 2],
+                header[offset + 3],
+            ]) as u64
+        };
+        let cert_offset = align_0x40(CIA_HEADER_SIZE as u64);
+        let ticket_offset = cert_offset.and_then(|offset| align_0x40(offset + read_u32(0x8)));
+        let tmd_offset = ticket_offset.and_then(|offset| align_0x40(offset + read_u32(0xC)));
+        let content_offset = tmd_offset.and_then(|offset| align_0x40(offset + read_u32(0x10)));
+        let (tmd_offset, mut content_offset) = match (tmd_offset, content_offset) {
+            (Some(tmd), Some(content)) => (tmd, content),
+            _ => return Err(TitleLayoutError::OffsetOverflow("cia sections")),
+        };
+
+        let mut signature_type = [0; 4];
+        read_at_exact(file, tmd_offset, &mut signature_type, "tmd signature type")?;
+        let signature_lenght = match u32::from_be_bytes(signature_type) {
+            0x10000 | 0x10003 => 0x240,
+            0x10001 | 0x10004 => 0x140,
+            0x10002 | 0x10005 => 0x80,
+            _ => return Err(TitleLayoutError::InvalidCIA("unknown tmd signature type")),
+        };
+        let tmd_header_offset = tmd_offset + signature_lenght;
+        let mut content_count = [0; 2];
+        read_at_exact(
+            file,
+            tmd_header_offset + 0x9E,
+            &mut content_count,
+            "tmd content count",
+        )?;
+        let content_count = u16::from_be_bytes(content_count) as u64;
+
+        let mut partitions = Vec::new();
+        for content_nb in 0..content_count {
+            let mut chunk = [0; 0x30];
+            read_at_exact(
+                file,
+                tmd_header_offset + 0x9C4 + content_nb * 0x30,
+                &mut chunk,
+                "tmd content chunk record",
+            )?;
+            let index = u16::from_be_bytes([chunk[4], chunk[5]]) as usize;
+            let content_type = u16::from_be_bytes([chunk[6], chunk[7]]);
+            let mut size = [0; 8];
+            size.copy_from_slice(&chunk[8..16]);
+            let size = u64::from_be_bytes(size);
+            if content_type & 1 != 0 {
+                return Err(TitleLayoutError::EncryptedContent(index));
+            };
+            partitions.push((
+                index,
+                NCCHSections::new(file, content_offset, Some(size), index)?,
+            ));
+            content_offset = match content_offset.checked_add(size).and_then(align_0x40) {
+                Some(value) => value,
+                None => return Err(TitleLayoutError::OffsetOverflow("cia content")),
+            };
+        }
+        partitions.sort_by_key(|(index, _)| *index);
+        Ok(TitleLayout {
+            container: TitleContainer::CIA,
+            partitions,
+        })
+    }
+
+    pub fn get_partition(&self, partition_nb: usize) -> Option<&NCCHSections> {
+        self.partitions
+            .iter()
+            .find(|(nb, _)| *nb == partition_nb)
+            .map(|(_, sections)| sections)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TitleNodeKind {
+    /// The index of the childs in the `TitleTree`
+    Directory(Vec<usize>),
+    /// A file, stored at `offset` in the input file
+    File { offset: u64, lenght: u64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct TitleNode {
+    pub name: String,
+    /// The index of the parent. The root is its own parent.
+    pub parent: usize,
+    pub kind: TitleNodeKind,
+}
+
+/// Every section of a title, as a tree of directories and files that are plain ranges of the input file.
+///
+/// The first partition is at the root, with `exheader.bin`, `plain.bin`, `logo.bin`, `exefs/` and `romfs/`. The other partitions are in `p1/`, `p2/`...
+#[derive(Debug, Clone)]
+pub struct TitleTree {
+    nodes: Vec<TitleNode>,
+}
+
+impl TitleTree {
+    pub const ROOT: usize = 0;
+
+    pub fn new<T: ReadAt + ?Sized>(
+        file: &T,
+        layout: &TitleLayout,
+    ) -> Result<TitleTree, TitleLayoutError> {
+        let mut tree = TitleTree {
+            nodes: vec![TitleNode {
+                name: String::new(),
+                parent: TitleTree::ROOT,
+                kind: TitleNodeKind::Directory(Vec::new()),
+            }],
+        };
+        for (position, (partition_nb, sections)) in layout.partitions.iter().enumerate() {
+            let dir = if position == 0 {
+                TitleTree::ROOT
+            } else {
+                tree.add_dir(TitleTree::ROOT, format!("p{}", partition_nb))
+            };
+            tree.add_ncch(file, dir, *partition_nb, sections)?;
+        }
+        Ok(tree)
+    }
+
+    fn add_node(&mut self, parent: usize, name: String, kind: TitleNodeKind) -> usize {
+        let id = self.nodes.len();
+        self.nodes.push(TitleNode { name, parent, kind });
+        if let TitleNodeKind::Directory(childs) = &mut self.nodes[parent].kind {
+            childs.push(id);
+        };
+        id
+    }
+
+    fn add_dir(&mut self, parent: usize, name: String) -> usize {
+        self.add_node(parent, name, TitleNodeKind::Directory(Vec::new()))
+    }
+
+    fn add_file(&mut self, parent: usize, name: &str, (offset, lenght): (u64, u64)) {
+        if lenght != 0 {
+            self.add_node(
+                parent,
+                name.to_string(),
+                TitleNodeKind::File { offset, lenght },
+            );
+        };
+    }
+
+    fn add_ncch<T: ReadAt + ?Sized>(
+        &mut self,
+        file: &T,
+        dir: usize,
+        partition_nb: usize,
+        sections: &NCCHSections,
+    ) -> Result<(), TitleLayoutError> {
+        self.add_file(dir, "exheader.bin", sections.exheader);
+        self.add_file(dir, "plain.bin", sections.plain_region);
+        self.add_file(dir, "logo.bin", sections.logo_region);
+
+        let (exefs_offset, exefs_lenght) = sections.exefs;
+        if exefs_lenght != 0 {
+            let exefs_dir = self.add_dir(dir, "exefs".to_string());
+            let partition = match ReadAtPartition::new(Arc::new(file), exefs_offset, exefs_lenght) {
+                Ok(value) => value,
+                Err(err) => {
+                    return Err(TitleLayoutError::ExeFSError(
+                        ExeFSError::CreatePartitionError(err),
+                        partition_nb,
+                    ))
+                }
+            };
+            let exefs = match ExeFSReader::new(ReadAtCursor::new(&partition)) {
+                Ok(value) => value,
+                Err(err) => return Err(TitleLayoutError::ExeFSError(err, partition_nb)),
+            };
+            for exefs_file in &exefs.files {
+                let offset = exefs_offset.saturating_add(exefs.get_file_real_offset(exefs_file));
+                self.add_node(
+                    exefs_dir,
+                    exefs_file.name.clone(),
+                    TitleNodeKind::File {
+                        offset,
+                        lenght: exefs_file.lenght as u64,
+                    },
+                );
+            }
+        };
+
+        let (romfs_offset, romfs_lenght) = sections.romfs;
+        if romfs_lenght != 0 {
+            let romfs_dir = self.add_dir(dir, "romfs".to_string());
+            let partition = match ReadAtPartition::new(Arc::new(file), romfs_offset, romfs_lenght) {
+                Ok(value) => value,
+                Err(err) => {
+                    return Err(TitleLayoutError::IVFCError(
+                        IVFCError::ReadError(err, "romfs partition"),
+                        partition_nb,
+                    ))
+                }
+            };
+            let reader = match IVFCReader::from_read_at(partition) {
+                Ok(value) => value,
+                Err(err) => return Err(TitleLayoutError::IVFCError(err, partition_nb)),
+            };
+            // the nodes of the romfs directories, by path. A directory is always visited before its subdirectories.
+            let mut dir_nodes = HashMap::new();
+            dir_nodes.insert(String::new(), romfs_dir);
+            let result = reader.walk_dir(&reader.first_dir_metadata, |dir_path, subdirs, files| {
+                let node = dir_nodes[dir_path];
+                for romfs_file in files {
+                    let offset =
+                        romfs_offset.saturating_add(reader.get_file_real_offset(romfs_file));
+                    self.add_node(
+                        node,
+                        romfs_file.name.clone(),
+                        TitleNodeKind::File {
+                            offset,
+                            lenght: romfs_file.lenght_file_data,
+                        },
+                    );
+                }
+                for subdir in subdirs {
+                    let name = subdir.name.clone().unwrap_or_default();
+                    let subdir_path = if dir_path.is_empty() {
+                        name.clone()
+                    } else {
+                        format!("{}/{}", dir_path, name)
+                    };
+                    let subdir_node = self.add_dir(node, name);
+                    dir_nodes.insert(subdir_path, subdir_node);
+                }
+            });
+            if let Err(err) = result {
+                return Err(TitleLayoutError::IVFCError(err, partition_nb));
+            };
+        };
+        Ok(())
+    }
+
+    pub fn get(&self, id: usize) -> Option<&TitleNode> {
+        self.nodes.get(id)
+    }
+
+    /// The number of nodes, including the root
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn get_child(&self, dir: usize, name: &str) -> Option<usize> {
+        match &self.get(dir)?.kind {
+            TitleNodeKind::Directory(childs) => childs
+                .iter()
+                .copied()
+                .find(|child| self.nodes[*child].name == name),
+            TitleNodeKind::File { .. } => None,
+        }
+    }
+
+    /// Find the node at `path` (like "romfs/a/b.bin"). Empty components are ignored, so "" and "/" are the root.
+    pub fn lookup(&self, path: &str) -> Option<usize> {
+        let mut node = TitleTree::ROOT;
+        for part in path.split('/').filter(|part| !part.is_empty()) {
+            node = self.get_child(node, part)?;
+        }
+        Some(node)
+    }
+
+    /// Return the path of a node relative to the root, like "romfs/a/b.bin"
+    pub fn path_of(&self, mut id: usize) -> String {
+        let mut parts = Vec::new();
+        while id != TitleTree::ROOT {
+            let node = match self.get(id) {
+                Some(value) => value,
+                None => break,
+            };
+            parts.push(node.name.as_str());
+            id = node.parent;
+        }
+        parts.reverse();
+        parts.join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IVFCWriter, IVFCWriterSource, NCCHWriter};
+    use std::io::Cursor;
+
+    /// A lone NCCH, whose romfs contain "a/b.bin"
+    fn ncch_with_romfs() -> Vec<u8> {
+        let mut romfs = IVFCWriter::new();
+        romfs.add_file("a/b.bin", IVFCWriterSource::Memory(b"content".to_vec()));
+        let mut ncch = NCCHWriter::new([0; 0x200]);
+        ncch.romfs = Some(romfs);
+        let mut output = Cursor::new(Vec::new());
+        ncch.write(&mut output).unwrap();
+        output.into_inner()
+    }
+
+    #[test]
+    fn test_title_tree() {
+        let ncch = ncch_with_romfs();
+        let layout = TitleLayout::new(&ncch).unwrap();
+        let tree = TitleTree::new(&ncch, &layout).unwrap();
+        let b = tree.lookup("romfs/a/b.bin").unwrap();
+        assert_eq!(tree.path_of(b), "romfs/a/b.bin");
+        match tree.get(b).unwrap().kind {
+            TitleNodeKind::File { offset, lenght } => {
+                assert_eq!(
+                    &ncch[offset as usize..(offset + lenght) as usize],
+                    b"content"
+                )
+            }
+            TitleNodeKind::Directory(_) => panic!("romfs/a/b.bin is a directory"),
+        };
+        assert!(tree.lookup("exefs").is_none());
+    }
+
+    #[test]
+    fn test_title_tree_romfs_loop() {
+        let mut ncch = ncch_with_romfs();
+        let (romfs_offset, _) = TitleLayout::new(&ncch).unwrap().partitions[0].1.romfs;
+        let reader = IVFCReader::from_read_at(ncch[romfs_offset as usize..].to_vec()).unwrap();
+        // the root directory contain itself
+        let root_offset = romfs_offset as usize + reader.dir_metadata_part_offset as usize;
+        ncch[root_offset + 8..root_offset + 12].copy_from_slice(&0u32.to_le_bytes());
+        let layout = TitleLayout::new(&ncch).unwrap();
+        assert!(matches!(
+            TitleTree::new(&ncch, &layout),
+            Err(TitleLayoutError::IVFCError(
+                IVFCError::MetadataLoop("directory", 0),
+                0
+            ))
+        ));
+    }
+}
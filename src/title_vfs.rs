@@ -0,0 +1,244 @@
+use crate::{IVFCMeta, ReadAt, IVFCVFS};
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use std::path::{Component, PathBuf};
+use std::sync::Arc;
+
+use vfs::{OpenOptions, VFile, VMetadata, VPath, VFS};
+
+/// A read only VFS that merge the romfs of a base title with the romfs of its update and DLCs, like the console see it.
+///
+/// Layers are looked up from the last added to the base one. The first layer that contain a file is the one that is used, and directory listings are merged.
+pub struct TitleVFS {
+    layers: Arc<Vec<Box<dyn VPath>>>,
+}
+
+impl TitleVFS {
+    pub fn new<T: 'static + ReadAt + Send + Sync + fmt::Debug>(base: IVFCVFS<T>) -> TitleVFS {
+        TitleVFS {
+            layers: Arc::new(vec![Box::new(base.path(""))]),
+        }
+    }
+
+    /// Add a patch romfs on top of all the existing layers
+    pub fn add_layer<T: 'static + ReadAt + Send + Sync + fmt::Debug>(&mut self, layer: IVFCVFS<T>) {
+        Arc::make_mut(&mut self.layers).push(Box::new(layer.path("")));
+    }
+
+    /// Return the number of layers, including the base one
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+}
+
+impl VFS for TitleVFS {
+    type PATH = TitleVPATH;
+    type METADATA = IVFCMeta;
+    type FILE = Box<dyn VFile>;
+
+    fn path<A: Into<String>>(&self, path: A) -> Self::PATH {
+        TitleVPATH {
+            layers: self.layers.clone(),
+            path: PathBuf::from(path.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TitleVPATH {
+    layers: Arc<Vec<Box<dyn VPath>>>,
+    path: PathBuf,
+}
+
+impl TitleVPATH {
+    /// Return the paths in each layers, from the one with the highest priority to the base one
+    fn layer_paths(&self) -> impl Iterator<Item = Box<dyn VPath>> + '_ {
+        self.layers.iter().rev().map(move |layer| {
+            let mut result = layer.clone();
+            for component in self.path.components() {
+                if let Component::Normal(part) = component {
+                    result = result.resolve(&part.to_string_lossy().into_owned());
+                };
+            }
+            result
+        })
+    }
+
+    /// Return the index of the layer this path is taken from (0 being the base romfs), or `None` if it doesn't exist
+    pub fn layer_index(&self) -> Option<usize> {
+        self.layer_paths()
+            .position(|path| path.exists())
+            .map(|position| self.layers.len() - 1 - position)
+    }
+
+    fn with_path(&self, path: PathBuf) -> TitleVPATH {
+        TitleVPATH {
+            layers: self.layers.clone(),
+            path,
+        }
+    }
+}
+
+fn return_ro_error<T>() -> io::Result<T> {
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "read only file system",
+    ))
+}
+
+impl VPath for TitleVPATH {
+    fn open_with_options(&self, opt: &OpenOptions) -> io::Result<Box<dyn VFile>> {
+        if opt.write || opt.create || opt.append || opt.truncate {
+            return return_ro_error();
+        };
+
+        for layer_path in self.layer_paths() {
+            if layer_path.exists() {
+                return layer_path.open_with_options(opt);
+            };
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "the file doesn't exist in any layer",
+        ))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn read_dir(&self) -> io::Result<Box<dyn Iterator<Item = io::Result<Box<dyn VPath>>>>> {
+        let mut childs = Vec::new();
+        let mut found_dir = false;
+
+        // start from the base, so the order of the listing stay stable when adding layers
+        let layer_paths: Vec<Box<dyn VPath>> = self.layer_paths().collect();
+        for layer_path in layer_paths.iter().rev() {
+            let is_dir = match layer_path.metadata() {
+                Ok(meta) => meta.is_dir(),
+                Err(_) => false,
+            };
+            if !is_dir {
+                continue;
+            };
+            found_dir = true;
+            for child in layer_path.read_dir()? {
+                if let Some(name) = child?.file_name() {
+                    if !childs.contains(&name) {
+                        childs.push(name);
+                    };
+                };
+            }
+        }
+
+        if !found_dir {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "the directory doesn't exist in any layer",
+            ));
+        };
+
+        let this = self.clone();
+        Ok(Box::new(
+            childs.into_iter().map(move |name| Ok(this.resolve(&name))),
+        ))
+    }
+
+    fn mkdir(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn rm(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn rmrf(&self) -> io::Result<()> {
+        return_ro_error()
+    }
+
+    fn file_name(&self) -> Option<String> {
+        self.path.file_name()
+    }
+
+    fn extension(&self) -> Option<String> {
+        self.path.extension()
+    }
+
+    fn resolve(&self, path: &String) -> Box<dyn VPath> {
+        let mut new_path = self.path.clone();
+        new_path.push(path);
+        Box::new(self.with_path(new_path))
+    }
+
+    fn parent(&self) -> Option<Box<dyn VPath>> {
+        let mut new_path = self.path.clone();
+        if !new_path.pop() {
+            return None;
+        };
+        Some(Box::new(self.with_path(new_path)))
+    }
+
+    fn to_string(&self) -> Cow<'_, str> {
+        format!("title://{:?}", self.path).into()
+    }
+
+    fn box_clone(&self) -> Box<dyn VPath> {
+        Box::new(self.clone())
+    }
+
+    fn to_path_buf(&self) -> Option<PathBuf> {
+        Some(self.path.clone())
+    }
+
+    fn exists(&self) -> bool {
+        self.layer_paths().any(|path| path.exists())
+    }
+
+    fn metadata(&self) -> io::Result<Box<dyn VMetadata>> {
+        for layer_path in self.layer_paths() {
+            if layer_path.exists() {
+                return layer_path.metadata();
+            };
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "the file doesn't exist in any layer",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ivfc::romfs_from_files;
+    use crate::IVFCReader;
+
+    fn romfs_vfs(files: &[(&str, &[u8])]) -> IVFCVFS<Vec<u8>> {
+        IVFCVFS::new(IVFCReader::from_read_at(romfs_from_files(files)).unwrap())
+    }
+
+    #[test]
+    fn test_layers() {
+        let mut vfs = TitleVFS::new(romfs_vfs(&[("a.bin", b"base"), ("b/c.bin", b"c")]));
+        vfs.add_layer(romfs_vfs(&[("a.bin", b"patched"), ("b/d.bin", b"d")]));
+        assert_eq!(vfs.layer_count(), 2);
+
+        let mut content = String::new();
+        vfs.path("a.bin")
+            .open()
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "patched");
+        assert_eq!(vfs.path("a.bin").layer_index(), Some(1));
+        assert_eq!(vfs.path("b/c.bin").layer_index(), Some(0));
+        assert_eq!(vfs.path("b/c.bin").metadata().unwrap().len(), 1);
+        assert_eq!(vfs.path("missing.bin").layer_index(), None);
+
+        let names: Vec<String> = vfs
+            .path("b")
+            .read_dir()
+            .unwrap()
+            .map(|child| child.unwrap().file_name().unwrap())
+            .collect();
+        assert_eq!(names, ["c.bin", "d.bin"]);
+    }
+}
@@ -0,0 +1,348 @@
+use crate::lz::compress_stream;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+#[derive(Debug)]
+pub enum Yaz0Error {
+    ReadError(io::Error),
+    InvalidMagic([u8; 4]), // the invalid magic
+    TruncatedInput,
+    InvalidDisplacement(usize, usize), // displacement, position in the output
+    InputTooBig(usize),                // the lenght of the data to compress
+}
+
+impl Error for Yaz0Error {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::ReadError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Yaz0Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadError(_) => write!(f, "failed to read the compressed data"),
+            Self::InvalidMagic(magic) => write!(
+                f,
+                "the magic of the yaz0 file is invalid. Found {:?}, expected [89, 97, 122, 48].",
+                magic
+            ),
+            Self::TruncatedInput => {
+                write!(f, "the compressed data end before the end of the stream")
+            }
+            Self::InvalidDisplacement(displacement, position) => write!(
+                f,
+                "the displacement {} point before the beggining of the output (at position {})",
+                displacement, position
+            ),
+            Self::InputTooBig(lenght) => write!(
+                f,
+                "the data to compress is {} bytes long, but the header can't store a lenght bigger than 4 GiB",
+                lenght
+            ),
+        }
+    }
+}
+
+impl From<Yaz0Error> for io::Error {
+    fn from(err: Yaz0Error) -> io::Error {
+        match err {
+            Yaz0Error::ReadError(err) => err,
+            Yaz0Error::TruncatedInput => io::Error::new(io::ErrorKind::UnexpectedEof, err),
+            _ => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+const YAZ0_HEADER_LENGHT: usize = 0x10;
+const YAZ0_WINDOW_LENGHT: usize = 0x1000;
+
+/// Return the decompressed lenght written in a Yaz0 header, or `None` if `header` doesn't start with a Yaz0 header.
+pub fn get_yaz0_decompressed_lenght(header: &[u8]) -> Option<usize> {
+    if header.len() < 8 || &header[0..4] != b"Yaz0" {
+        return None;
+    };
+    Some(u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize)
+}
+
+/// Decompress a Yaz0 buffer.
+pub fn decompress_yaz0(data: &[u8]) -> Result<Vec<u8>, Yaz0Error> {
+    let mut decoder = Yaz0Decoder::new(data)?;
+    // the header may claim up to 4 GiB, so grow the output as the data is decompressed
+    let mut output = Vec::with_capacity(std::cmp::min(
+        decoder.decompressed_lenght() as usize,
+        data.len() * 8,
+    ));
+    let mut chunk = [0; YAZ0_WINDOW_LENGHT];
+    loop {
+        let read = decoder.read_all(&mut chunk)?;
+        if read == 0 {
+            break;
+        };
+        output.extend_from_slice(&chunk[..read]);
+    }
+    Ok(output)
+}
+
+/// Compress `data` with Yaz0. Fail if `data` is 4 GiB or more.
+pub fn compress_yaz0(data: &[u8]) -> Result<Vec<u8>, Yaz0Error> {
+    let lenght = match u32::try_from(data.len()) {
+        Ok(value) => value,
+        Err(_) => return Err(Yaz0Error::InputTooBig(data.len())),
+    };
+    let mut output = Vec::with_capacity(data.len() / 2 + YAZ0_HEADER_LENGHT);
+    output.extend_from_slice(b"Yaz0");
+    output.extend_from_slice(&lenght.to_be_bytes());
+    output.extend_from_slice(&[0; 8]);
+    compress_stream(
+        data,
+        &mut output,
+        0x111,
+        true,
+        |output, lenght, displacement| {
+            let displacement = displacement - 1;
+            if lenght < 0x12 {
+                output.push((((lenght - 2) << 4) | (displacement >> 8)) as u8);
+                output.push((displacement & 0xFF) as u8);
+            } else {
+                output.push((displacement >> 8) as u8);
+                output.push((displacement & 0xFF) as u8);
+                output.push((lenght - 0x12) as u8);
+            }
+        },
+    );
+    Ok(output)
+}
+
+/// A streaming Yaz0 decoder, that only keep the last 0x1000 decompressed bytes in memory.
+///
+/// It can seek if the input can seek: seeking backward restart the decompression from the beggining.
+#[derive(Debug)]
+pub struct Yaz0Decoder<R: Read> {
+    input: R,
+    /// bytes read from the input but not yet decoded
+    input_buffer: Vec<u8>,
+    input_buffer_position: usize,
+    /// the number of bytes read from the input since the end of the header
+    input_read: u64,
+    decompressed_lenght: u64,
+    position: u64,
+    window: Vec<u8>,
+    flags: u8,
+    flags_left: u8,
+    copy_displacement: usize,
+    copy_left: usize,
+}
+
+impl<R: Read> Yaz0Decoder<R> {
+    /// Read the header of the Yaz0 stream at the current position of `input`
+    pub fn new(mut input: R) -> Result<Yaz0Decoder<R>, Yaz0Error> {
+        let mut header = [0; YAZ0_HEADER_LENGHT];
+        match input.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(Yaz0Error::TruncatedInput)
+            }
+            Err(err) => return Err(Yaz0Error::ReadError(err)),
+        };
+        let decompressed_lenght = match get_yaz0_decompressed_lenght(&header) {
+            Some(value) => value as u64,
+            None => {
+                return Err(Yaz0Error::InvalidMagic([
+                    header[0], header[1], header[2], header[3],
+                ]))
+            }
+        };
+        Ok(Yaz0Decoder {
+            input,
+            input_buffer: Vec::new(),
+            input_buffer_position: 0,
+            input_read: 0,
+            decompressed_lenght,
+            position: 0,
+            window: vec![0; YAZ0_WINDOW_LENGHT],
+            flags: 0,
+            flags_left: 0,
+            copy_displacement: 0,
+            copy_left: 0,
+        })
+    }
+
+    /// The lenght of the data once decompressed, as written in the header
+    pub fn decompressed_lenght(&self) -> u64 {
+        self.decompressed_lenght
+    }
+
+    fn next_byte(&mut self) -> Result<u8, Yaz0Error> {
+        if self.input_buffer_position >= self.input_buffer.len() {
+            self.input_buffer.resize(0x1000, 0);
+            let read = match self.input.read(&mut self.input_buffer) {
+                Ok(value) => value,
+                Err(err) => return Err(Yaz0Error::ReadError(err)),
+            };
+            if read == 0 {
+                return Err(Yaz0Error::TruncatedInput);
+            };
+            self.input_buffer.truncate(read);
+            self.input_buffer_position = 0;
+            self.input_read += read as u64;
+        };
+        let byte = self.input_buffer[self.input_buffer_position];
+        self.input_buffer_position += 1;
+        Ok(byte)
+    }
+
+    /// Decompress the next byte. Should only be called before the end of the output.
+    fn decode_byte(&mut self) -> Result<u8, Yaz0Error> {
+        if self.copy_left == 0 {
+            if self.flags_left == 0 {
+                self.flags = self.next_byte()?;
+                self.flags_left = 8;
+            };
+            let is_literal = self.flags & 0x80 != 0;
+            self.flags <<= 1;
+            self.flags_left -= 1;
+            if is_literal {
+                let byte = self.next_byte()?;
+                self.push_output(byte);
+                return Ok(byte);
+            };
+            let byte_0 = self.next_byte()? as usize;
+            let byte_1 = self.next_byte()? as usize;
+            self.copy_displacement = (((byte_0 & 0xF) << 8) | byte_1) + 1;
+            self.copy_left = match byte_0 >> 4 {
+                0 => self.next_byte()? as usize + 0x12,
+                lenght => lenght + 2,
+            };
+            if self.copy_displacement as u64 > self.position {
+                self.copy_left = 0;
+                return Err(Yaz0Error::InvalidDisplacement(
+                    self.copy_displacement,
+                    self.position as usize,
+                ));
+            };
+        };
+        let source = (self.position as usize + YAZ0_WINDOW_LENGHT - self.copy_displacement)
+            % YAZ0_WINDOW_LENGHT;
+        let byte = self.window[source];
+        self.copy_left -= 1;
+        self.push_output(byte);
+        Ok(byte)
+    }
+
+    fn push_output(&mut self, byte: u8) {
+        self.window[self.position as usize % YAZ0_WINDOW_LENGHT] = byte;
+        self.position += 1;
+    }
+
+    /// Fill `buf` with decompressed data, returning the number of bytes written (smaller than `buf` only at the end of the output)
+    fn read_all(&mut self, buf: &mut [u8]) -> Result<usize, Yaz0Error> {
+        let mut written = 0;
+        while written < buf.len() && self.position < self.decompressed_lenght {
+            buf[written] = self.decode_byte()?;
+            written += 1;
+        }
+        Ok(written)
+    }
+}
+
+impl<R: Read> Read for Yaz0Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.read_all(buf)?)
+    }
+}
+
+impl<R: Read + Seek> Seek for Yaz0Decoder<R> {
+    fn seek(&mut self, target: SeekFrom) -> io::Result<u64> {
+        let target = match target {
+            SeekFrom::Start(offset) => offset as i128,
+            SeekFrom::Current(offset) => self.position as i128 + offset as i128,
+            SeekFrom::End(offset) => self.decompressed_lenght as i128 + offset as i128,
+        };
+        if target < 0 || target > self.decompressed_lenght as i128 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "trying to seek outside of the decompressed data",
+            ));
+        };
+        let target = target as u64;
+        if target < self.position {
+            self.input
+                .seek(SeekFrom::Current(-(self.input_read as i64)))?;
+            self.input_buffer.clear();
+            self.input_buffer_position = 0;
+            self.input_read = 0;
+            self.position = 0;
+            self.flags_left = 0;
+            self.copy_left = 0;
+        };
+        while self.position < target {
+            self.decode_byte()?;
+        }
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn sample() -> Vec<u8> {
+        let mut data = b"yaz0 yaz0 yaz0 ".to_vec();
+        data.extend((0..0x3000).map(|value: u32| (value % 0x97) as u8));
+        data.extend(vec![0x42; 0x300]);
+        data
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = sample();
+        let compressed = compress_yaz0(&data).unwrap();
+        assert!(compressed.len() < data.len());
+        assert_eq!(get_yaz0_decompressed_lenght(&compressed), Some(data.len()));
+        assert_eq!(decompress_yaz0(&compressed).unwrap(), data);
+        assert_eq!(
+            decompress_yaz0(&compress_yaz0(&[]).unwrap()).unwrap(),
+            Vec::<u8>::new()
+        );
+    }
+
+    #[test]
+    fn test_decoder_seek() {
+        let data = sample();
+        let mut decoder = Yaz0Decoder::new(Cursor::new(compress_yaz0(&data).unwrap())).unwrap();
+        let mut buffer = [0; 0x20];
+        for offset in [0x2000, 0x10, 0x3100, 0x2FF0].iter() {
+            assert_eq!(decoder.seek(SeekFrom::Start(*offset)).unwrap(), *offset);
+            decoder.read_exact(&mut buffer).unwrap();
+            assert_eq!(
+                &buffer[..],
+                &data[*offset as usize..*offset as usize + 0x20]
+            );
+        }
+        assert_eq!(
+            decoder.seek(SeekFrom::End(-4)).unwrap(),
+            data.len() as u64 - 4
+        );
+        assert!(decoder.seek(SeekFrom::End(1)).is_err());
+    }
+
+    #[test]
+    fn test_huge_declared_lenght() {
+        // a header claiming 4 GiB, with a single literal byte
+        let mut compressed = b"Yaz0".to_vec();
+        compressed.extend_from_slice(&u32::MAX.to_be_bytes());
+        compressed.extend_from_slice(&[0; 8]);
+        compressed.extend_from_slice(&[0x80, 0x12]);
+        match decompress_yaz0(&compressed) {
+            Err(Yaz0Error::TruncatedInput) => (),
+            other => panic!("unexpected result: {:?}", other.map(|data| data.len())),
+        };
+    }
+}
@@ -0,0 +1,91 @@
+//! Run `fs3ds extract` and `fs3ds build` on a generated rom
+
+use fs3ds::{ExeFSWriter, IVFCWriter, IVFCWriterSource, NCCHWriter, NCSDWriter};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn ncch(logo: u8, with_exefs: bool) -> NCCHWriter {
+    let mut ncch = NCCHWriter::new([0; 0x200]);
+    ncch.logo_region = vec![logo; 0x2000];
+    if with_exefs {
+        ncch.exheader = Some(vec![1; 0x800]);
+        ncch.plain_region = b"[SDK+NINTENDO:Firmware-1_0_0]".to_vec();
+        let mut exefs = ExeFSWriter::new();
+        exefs.add_file(".code", vec![2; 0x345]);
+        exefs.add_file("icon", vec![3; 0x36C0]);
+        ncch.exefs = Some(exefs);
+    };
+    let mut romfs = IVFCWriter::new();
+    romfs.add_file("a/b.bin", IVFCWriterSource::Memory(vec![logo; 0x1234]));
+    romfs.add_file("c.bin", IVFCWriterSource::Memory(b"c".to_vec()));
+    ncch.romfs = Some(romfs);
+    ncch
+}
+
+/// The content of every file under `dir`, by path relative to `dir`
+fn read_tree(dir: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+    let mut result = BTreeMap::new();
+    let mut dir_to_walk = vec![dir.to_path_buf()];
+    while let Some(actual_dir) = dir_to_walk.pop() {
+        for entry in fs::read_dir(&actual_dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dir_to_walk.push(path);
+            } else {
+                let content = fs::read(&path).unwrap();
+                result.insert(path.strip_prefix(dir).unwrap().to_path_buf(), content);
+            };
+        }
+    }
+    result
+}
+
+fn fs3ds(args: &[&Path]) -> Result<(), String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_fs3ds"))
+        .args(args)
+        .output()
+        .unwrap();
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+}
+
+#[test]
+fn test_extract_build_extract() {
+    let temp = std::env::temp_dir().join(format!("fs3ds-extract-build-{}", std::process::id()));
+    fs::create_dir_all(&temp).unwrap();
+    let rom = temp.join("rom.3ds");
+    let mut ncsd = NCSDWriter::new(vec![0; 0x4000]);
+    ncsd.partitions.push((0, ncch(4, true)));
+    ncsd.partitions.push((1, ncch(5, false)));
+    let mut output = Cursor::new(Vec::new());
+    ncsd.write(&mut output).unwrap();
+    fs::write(&rom, output.into_inner()).unwrap();
+
+    let (first_dir, rebuilt, second_dir) =
+        (temp.join("1"), temp.join("rebuilt.3ds"), temp.join("2"));
+    fs3ds(&[Path::new("extract"), &rom, &first_dir]).unwrap();
+    fs3ds(&[Path::new("build"), &first_dir, &rebuilt]).unwrap();
+    fs3ds(&[Path::new("extract"), &rebuilt, &second_dir]).unwrap();
+
+    let first_tree = read_tree(&first_dir);
+    assert!(first_tree.contains_key(Path::new("romfs/a/b.bin")));
+    assert!(first_tree.contains_key(Path::new("p1/romfs/c.bin")));
+    assert!(first_tree.contains_key(Path::new("exefs/icon")));
+    assert_eq!(first_tree, read_tree(&second_dir));
+    assert_eq!(fs::read(&rom).unwrap(), fs::read(&rebuilt).unwrap());
+
+    // without the NCSD header, the other partitions come from a cia, that can't be rebuilt
+    fs::remove_file(first_dir.join("ncsd_header.bin")).unwrap();
+    let cia_rebuilt = temp.join("cia.cxi");
+    let err = fs3ds(&[Path::new("build"), &first_dir, &cia_rebuilt]).unwrap_err();
+    assert!(err.contains("cia"), "unexpected error: {}", err);
+    assert!(!cia_rebuilt.exists());
+
+    fs::remove_dir_all(temp).unwrap();
+}
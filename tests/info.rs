@@ -0,0 +1,158 @@
+//! Run `fs3ds info --json` on a generated rom, and check it against the layout read by the library
+
+use fs3ds::{
+    ExeFSWriter, IVFCWriter, IVFCWriterSource, NCCHWriter, NCSDReader, NCSDWriter, ReadAtCursor,
+    TitleLayout,
+};
+use std::fs;
+use std::fs::File;
+use std::io::Cursor;
+use std::path::Path;
+use std::process::Command;
+
+fn ncch(content_type: u8, romfs_files: &[&str], with_exefs: bool) -> NCCHWriter {
+    let mut header = [0; 0x200];
+    header[0x188 + 5] = content_type;
+    let mut ncch = NCCHWriter::new(header);
+    ncch.logo_region = vec![1; 0x2000];
+    if with_exefs {
+        ncch.exheader = Some(vec![2; 0x800]);
+        ncch.plain_region = b"[SDK+NINTENDO:Firmware-1_0_0]".to_vec();
+        let mut exefs = ExeFSWriter::new();
+        exefs.add_file(".code", vec![3; 0x345]);
+        ncch.exefs = Some(exefs);
+    };
+    let mut romfs = IVFCWriter::new();
+    for path in romfs_files {
+        romfs.add_file(path, IVFCWriterSource::Memory(path.as_bytes().to_vec()));
+    }
+    ncch.romfs = Some(romfs);
+    ncch
+}
+
+/// Remove the whitespaces outside of the strings, so the output can be compared without depending on the indentation
+fn compact(json: &str) -> String {
+    let mut result = String::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for chara in json.chars() {
+        if in_string {
+            result.push(chara);
+            match chara {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            };
+        } else if !chara.is_whitespace() {
+            result.push(chara);
+            in_string = chara == '"';
+        };
+    }
+    result
+}
+
+fn assert_contains(output: &str, expected: &str) {
+    assert!(
+        output.contains(expected),
+        "{:?} not found in:\n{}",
+        expected,
+        output
+    );
+}
+
+#[test]
+fn test_info_json() {
+    let path = std::env::temp_dir().join(format!("fs3ds-info-{}.3ds", std::process::id()));
+    let mut ncsd = NCSDWriter::new(vec![0; 0x4000]);
+    ncsd.partitions.push((
+        0,
+        ncch(0x03, &["a/b.bin", "a/c/d.bin", "e.bin", "f/g.bin"], true),
+    ));
+    ncsd.partitions
+        .push((1, ncch(0x09, &["manual.bcma"], false)));
+    let mut output = Cursor::new(Vec::new());
+    ncsd.write(&mut output).unwrap();
+    fs::write(&path, output.into_inner()).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_fs3ds"))
+        .args([Path::new("info"), Path::new("--json"), &path])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let json = compact(&String::from_utf8(output.stdout).unwrap());
+    assert!(json.starts_with('{') && json.ends_with('}'), "{}", json);
+    assert_contains(&json, r#""format":"NCSD""#);
+    assert_contains(
+        &json,
+        &format!(r#""file_size":{}"#, fs::metadata(&path).unwrap().len()),
+    );
+
+    // the partition table of the NCSD header
+    let file = File::open(&path).unwrap();
+    let ncsd = NCSDReader::new(ReadAtCursor::new(&file)).unwrap();
+    for (index, role) in [(0, "main"), (1, "manual")].iter() {
+        let (offset, lenght) = ncsd.get_partition_bounds(*index).unwrap();
+        assert_contains(
+            &json,
+            &format!(
+                r#"{{"index":{},"offset":{},"size":{},"id":"0x0000000000000000","role":"{}"}}"#,
+                index, offset, lenght, role
+            ),
+        );
+    }
+
+    // the sections of each NCCH, and the content of their romfs
+    let layout = TitleLayout::new(&file).unwrap();
+    assert_eq!(layout.partitions.len(), 2);
+    for (index, sections) in &layout.partitions {
+        let (kind, content_type, files, directories) = match index {
+            0 => ("CXI", "application", 4, 3),
+            _ => ("CFA", "manual", 1, 0),
+        };
+        assert_contains(
+            &json,
+            &format!(
+                r#"{{"index":{},"kind":"{}","role":"{}","offset":{},"size":{},"#,
+                index,
+                kind,
+                if *index == 0 { "main" } else { "manual" },
+                sections.ncch.0,
+                sections.ncch.1
+            ),
+        );
+        assert_contains(&json, &format!(r#""content_type":"{}""#, content_type));
+        for (name, (offset, lenght)) in [
+            ("exheader", sections.exheader),
+            ("plain region", sections.plain_region),
+            ("logo region", sections.logo_region),
+            ("exefs", sections.exefs),
+            ("romfs", sections.romfs),
+        ]
+        .iter()
+        {
+            let section = format!(
+                r#"{{"name":"{}","offset":{},"size":{}}}"#,
+                name, offset, lenght
+            );
+            if *lenght == 0 {
+                assert!(!json.contains(&section));
+            } else {
+                assert_contains(&json, &section);
+            };
+        }
+        assert_contains(
+            &json,
+            &format!(
+                r#""romfs":{{"files":{},"directories":{}}}"#,
+                files, directories
+            ),
+        );
+    }
+
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,145 @@
+//! Run `fs3ds ls` and `fs3ds cat` on a generated rom
+
+use fs3ds::{ExeFSWriter, IVFCWriter, IVFCWriterSource, NCCHWriter, NCSDWriter};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const ICON: &[u8] = b"the icon of the first partition";
+
+fn ncch(romfs_files: &[(&str, &[u8])], with_exefs: bool) -> NCCHWriter {
+    let mut ncch = NCCHWriter::new([0; 0x200]);
+    ncch.logo_region = vec![1; 0x2000];
+    if with_exefs {
+        ncch.exheader = Some(vec![2; 0x800]);
+        let mut exefs = ExeFSWriter::new();
+        exefs.add_file(".code", vec![3; 0x345]);
+        exefs.add_file("icon", ICON.to_vec());
+        exefs.add_file("banner", vec![4; 0x10]);
+        ncch.exefs = Some(exefs);
+    };
+    let mut romfs = IVFCWriter::new();
+    for (path, content) in romfs_files {
+        romfs.add_file(path, IVFCWriterSource::Memory(content.to_vec()));
+    }
+    ncch.romfs = Some(romfs);
+    ncch
+}
+
+fn write_rom(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("fs3ds-{}-{}.3ds", name, std::process::id()));
+    let mut ncsd = NCSDWriter::new(vec![0; 0x4000]);
+    ncsd.partitions.push((
+        0,
+        ncch(
+            &[
+                ("a/b.bin", b"b"),
+                ("a/c/d.bin", &[5; 0x1234]),
+                ("e.bin", b"the content of e"),
+            ],
+            true,
+        ),
+    ));
+    ncsd.partitions
+        .push((1, ncch(&[("manual.bcma", b"manual")], false)));
+    let mut output = Cursor::new(Vec::new());
+    ncsd.write(&mut output).unwrap();
+    fs::write(&path, output.into_inner()).unwrap();
+    path
+}
+
+/// Run `fs3ds <command> <options> <rom> <path>`, returning its standard output, or its error output if it failed
+fn fs3ds(
+    command: &str,
+    options: &[&str],
+    rom: &Path,
+    path: Option<&str>,
+) -> Result<Vec<u8>, String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_fs3ds"))
+        .arg(command)
+        .args(options)
+        .arg(rom)
+        .args(path)
+        .output()
+        .unwrap();
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+}
+
+fn ls(options: &[&str], rom: &Path, path: Option<&str>) -> String {
+    String::from_utf8(fs3ds("ls", options, rom, path).unwrap()).unwrap()
+}
+
+fn cat(rom: &Path, path: &str) -> Result<Vec<u8>, String> {
+    fs3ds("cat", &[], rom, Some(path))
+}
+
+/// The lenght and the offset of a line of `ls -l`
+fn parse_long_line(line: &str) -> (usize, usize) {
+    let columns: Vec<&str> = line.split_whitespace().collect();
+    (
+        columns[0].parse().unwrap(),
+        usize::from_str_radix(columns[1].trim_start_matches("0x"), 16).unwrap(),
+    )
+}
+
+#[test]
+fn test_ls() {
+    let rom = write_rom("ls");
+    assert_eq!(ls(&[], &rom, None), "a/\ne.bin\n");
+    assert_eq!(ls(&[], &rom, Some("/a")), "b.bin\nc/\n");
+    assert_eq!(
+        ls(&["-R"], &rom, None),
+        "a/\na/b.bin\na/c/\na/c/d.bin\ne.bin\n"
+    );
+    assert_eq!(ls(&["-R"], &rom, Some("romfs:/a/c")), "d.bin\n");
+    assert_eq!(ls(&[], &rom, Some("exefs:/")), ".code\nbanner\nicon\n");
+    assert_eq!(ls(&[], &rom, Some("p1:romfs:/")), "manual.bcma\n");
+    assert_eq!(ls(&[], &rom, Some("p1:/manual.bcma")), "manual.bcma\n");
+    assert!(fs3ds("ls", &[], &rom, Some("/missing")).is_err());
+    assert!(fs3ds("ls", &[], &rom, Some("p1:exefs:/")).is_err());
+    assert!(fs3ds("ls", &[], &rom, Some("p3:/")).is_err());
+
+    // the long listing give the lenght of the files, and their offset in the rom
+    let content = fs::read(&rom).unwrap();
+    let long = ls(&["-l", "-R"], &rom, None);
+    let mut file_count = 0;
+    for line in long.lines() {
+        let expected: &[u8] = match line.split_whitespace().last().unwrap() {
+            "a/b.bin" => b"b",
+            "a/c/d.bin" => &[5; 0x1234],
+            "e.bin" => b"the content of e",
+            dir => {
+                assert!(["a/", "a/c/"].contains(&dir), "{}", line);
+                assert!(line.trim_start().starts_with("- "), "{}", line);
+                continue;
+            }
+        };
+        let (lenght, offset) = parse_long_line(line);
+        assert_eq!(lenght, expected.len());
+        assert_eq!(&content[offset..offset + lenght], expected);
+        file_count += 1;
+    }
+    assert_eq!(file_count, 3);
+    let (lenght, offset) = parse_long_line(&ls(&["-l"], &rom, Some("exefs:/icon")));
+    assert_eq!(&content[offset..offset + lenght], ICON);
+
+    fs::remove_file(rom).unwrap();
+}
+
+#[test]
+fn test_cat() {
+    let rom = write_rom("cat");
+    assert_eq!(cat(&rom, "/e.bin").unwrap(), b"the content of e");
+    assert_eq!(cat(&rom, "romfs:a/c/d.bin").unwrap(), vec![5; 0x1234]);
+    assert_eq!(cat(&rom, "exefs:/icon").unwrap(), ICON);
+    assert_eq!(cat(&rom, "p1:romfs:/manual.bcma").unwrap(), b"manual");
+    let err = cat(&rom, "/a").unwrap_err();
+    assert!(err.contains("is a directory"), "{}", err);
+    assert!(cat(&rom, "exefs:/missing").is_err());
+    fs::remove_file(rom).unwrap();
+}
@@ -0,0 +1,135 @@
+//! Mount a generated rom with `fs3ds-mount` and read it through the kernel.
+//!
+//! Skipped when `/dev/fuse` is missing, or when the file system can't be mounted (not root, and no `fusermount3` helper usable).
+
+use fs3ds::{ExeFSWriter, IVFCWriter, IVFCWriterSource, NCCHWriter, NCSDWriter};
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+fn ncch(romfs_file: &str, content: &[u8], with_exefs: bool) -> NCCHWriter {
+    let mut ncch = NCCHWriter::new([0; 0x200]);
+    ncch.logo_region = vec![1; 0x2000];
+    if with_exefs {
+        ncch.exheader = Some(vec![2; 0x800]);
+        let mut exefs = ExeFSWriter::new();
+        exefs.add_file(".code", vec![3; 0x345]);
+        exefs.add_file("icon", vec![4; 0x36C0]);
+        ncch.exefs = Some(exefs);
+    };
+    let mut romfs = IVFCWriter::new();
+    romfs.add_file(romfs_file, IVFCWriterSource::Memory(content.to_vec()));
+    ncch.romfs = Some(romfs);
+    ncch
+}
+
+fn names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Stop `fs3ds-mount` with SIGTERM, that make it unmount the file system
+fn stop(mut child: Child) {
+    // safety: kill has no memory safety requirement
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+    let start = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if start.elapsed() > Duration::from_secs(10) {
+            child.kill().unwrap();
+            panic!("fs3ds-mount didn't stop on SIGTERM");
+        };
+        sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn test_mount() {
+    if !Path::new("/dev/fuse").exists() {
+        eprintln!("skipped: /dev/fuse is missing");
+        return;
+    };
+    let temp = std::env::temp_dir().join(format!("fs3ds-mount-{}", std::process::id()));
+    let mountpoint = temp.join("mountpoint");
+    fs::create_dir_all(&mountpoint).unwrap();
+    let rom = temp.join("rom.3ds");
+    let mut ncsd = NCSDWriter::new(vec![0; 0x4000]);
+    ncsd.partitions
+        .push((0, ncch("a/b.bin", &[5; 0x1234], true)));
+    ncsd.partitions
+        .push((1, ncch("c.bin", b"second partition", false)));
+    let mut output = Cursor::new(Vec::new());
+    ncsd.write(&mut output).unwrap();
+    fs::write(&rom, output.into_inner()).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fs3ds-mount"))
+        .args([&rom, &mountpoint])
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let start = Instant::now();
+    while !mountpoint.join("romfs").exists() {
+        if let Some(status) = child.try_wait().unwrap() {
+            let mut err = String::new();
+            child
+                .stderr
+                .take()
+                .unwrap()
+                .read_to_string(&mut err)
+                .unwrap();
+            fs::remove_dir_all(&temp).unwrap();
+            if err.starts_with("can't mount") {
+                eprintln!("skipped: {}", err.trim());
+                return;
+            };
+            panic!("fs3ds-mount exited with {}: {}", status, err);
+        };
+        if start.elapsed() > Duration::from_secs(10) {
+            stop(child);
+            panic!("the rom wasn't mounted after 10 seconds");
+        };
+        sleep(Duration::from_millis(20));
+    }
+
+    let result = std::panic::catch_unwind(|| {
+        let root_names = names(&mountpoint);
+        for expected in ["romfs", "exefs", "exheader.bin", "logo.bin", "p1"].iter() {
+            assert!(
+                root_names.contains(&expected.to_string()),
+                "{:?}",
+                root_names
+            );
+        }
+        assert_eq!(names(&mountpoint.join("romfs")), ["a"]);
+        assert_eq!(
+            fs::read(mountpoint.join("romfs/a/b.bin")).unwrap(),
+            vec![5; 0x1234]
+        );
+        assert_eq!(names(&mountpoint.join("exefs")), [".code", "icon"]);
+        assert_eq!(
+            fs::read(mountpoint.join("exefs/icon")).unwrap(),
+            vec![4; 0x36C0]
+        );
+        assert_eq!(
+            fs::metadata(mountpoint.join("exheader.bin")).unwrap().len(),
+            0x800
+        );
+        assert_eq!(
+            fs::read(mountpoint.join("p1/romfs/c.bin")).unwrap(),
+            b"second partition"
+        );
+        assert!(!mountpoint.join("p1/exefs").exists());
+        assert!(fs::write(mountpoint.join("romfs/new.bin"), b"new").is_err());
+    });
+    stop(child);
+    fs::remove_dir_all(&temp).unwrap();
+    if let Err(err) = result {
+        std::panic::resume_unwind(err);
+    };
+}
@@ -0,0 +1,130 @@
+//! Run `fs3ds verify` on generated roms, valid and corrupted
+
+use fs3ds::{ExeFSWriter, IVFCWriter, IVFCWriterSource, NCCHWriter, NCSDWriter};
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::process::Command;
+
+const ROMFS_CONTENT: &[u8] = b"the content of the only file of the romfs";
+
+fn write_ncch(ncch: &NCCHWriter) -> Vec<u8> {
+    let mut output = Cursor::new(Vec::new());
+    ncch.write(&mut output).unwrap();
+    output.into_inner()
+}
+
+fn valid_ncch() -> Vec<u8> {
+    let mut ncch = NCCHWriter::new([0; 0x200]);
+    ncch.exheader = Some(vec![1; 0x800]);
+    ncch.logo_region = vec![2; 0x2000];
+    let mut exefs = ExeFSWriter::new();
+    exefs.add_file(".code", vec![3; 0x345]);
+    ncch.exefs = Some(exefs);
+    let mut romfs = IVFCWriter::new();
+    romfs.add_file("a.bin", IVFCWriterSource::Memory(ROMFS_CONTENT.to_vec()));
+    ncch.romfs = Some(romfs);
+    write_ncch(&ncch)
+}
+
+/// Run `fs3ds verify` on `rom`, returning its exit code and its output
+fn verify(name: &str, rom: &[u8]) -> (Option<i32>, String) {
+    let path = std::env::temp_dir().join(format!("fs3ds-verify-{}-{}", std::process::id(), name));
+    fs::write(&path, rom).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_fs3ds"))
+        .args([Path::new("verify"), &path])
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+    )
+}
+
+fn find_line<'a>(output: &'a str, check: &str) -> &'a str {
+    match output.lines().find(|line| {
+        line.split_whitespace()
+            .skip(1)
+            .collect::<Vec<_>>()
+            .join(" ")
+            .starts_with(check)
+    }) {
+        Some(line) => line,
+        None => panic!("no {:?} check in:\n{}", check, output),
+    }
+}
+
+#[test]
+fn test_verify_valid() {
+    let (code, output) = verify("valid", &valid_ncch());
+    assert_eq!(code, Some(0), "{}", output);
+    assert!(find_line(&output, "p0 romfs ivfc level 3").starts_with("PASS"));
+    assert!(find_line(&output, "p0 exefs/.code hash").starts_with("PASS"));
+}
+
+#[test]
+fn test_verify_hash_mismatch() {
+    let mut ncch = valid_ncch();
+    let content_offset = ncch
+        .windows(ROMFS_CONTENT.len())
+        .position(|window| window == ROMFS_CONTENT)
+        .unwrap();
+    ncch[content_offset] ^= 0xFF;
+    let (code, output) = verify("mismatch", &ncch);
+    assert_eq!(code, Some(1), "{}", output);
+    assert!(find_line(&output, "p0 romfs ivfc level 3").starts_with("FAIL"));
+    assert!(find_line(&output, "p0 romfs ivfc level 2").starts_with("PASS"));
+    assert!(find_line(&output, "p0 logo hash").starts_with("PASS"));
+}
+
+#[test]
+fn test_verify_section_past_the_end() {
+    // a logo region of almost 1 TiB, that must not be read in memory
+    let mut ncch = NCCHWriter::new([0; 0x200]);
+    ncch.logo_region = vec![2; 0x200];
+    let mut ncch = write_ncch(&ncch);
+    ncch[0x104..0x108].copy_from_slice(&u32::MAX.to_le_bytes());
+    ncch[0x19C..0x1A0].copy_from_slice(&0x7FFF_FFFFu32.to_le_bytes());
+    let (code, output) = verify("past-the-end", &ncch);
+    assert_eq!(code, Some(1), "{}", output);
+    assert!(find_line(&output, "p0 sections").starts_with("FAIL"));
+    assert!(find_line(&output, "p0 logo hash").starts_with("SKIP"));
+}
+
+#[test]
+fn test_verify_invalid_ncsd_partitions() {
+    let mut ncsd = NCSDWriter::new(vec![0; 0x4000]);
+    let mut ncch = NCCHWriter::new([0; 0x200]);
+    ncch.logo_region = vec![2; 0x200];
+    ncsd.partitions.push((0, ncch));
+    let mut ncch = NCCHWriter::new([0; 0x200]);
+    ncch.logo_region = vec![3; 0x200];
+    ncsd.partitions.push((1, ncch));
+    let mut output = Cursor::new(Vec::new());
+    ncsd.write(&mut output).unwrap();
+    let mut rom = output.into_inner();
+    let read_u32 = |rom: &[u8], offset: usize| {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&rom[offset..offset + 4]);
+        u32::from_le_bytes(bytes)
+    };
+
+    // the partition 1 start inside the partition 0
+    let partition_0_offset = read_u32(&rom, 0x120);
+    rom[0x128..0x12C].copy_from_slice(&(partition_0_offset + 1).to_le_bytes());
+    let (code, output) = verify("ncsd-overlap", &rom);
+    assert_eq!(code, Some(1), "{}", output);
+    assert!(find_line(&output, "ncsd partition 0").starts_with("PASS"));
+    assert!(find_line(&output, "ncsd partition 1").starts_with("FAIL"));
+
+    // the partition 1 go past the end of the file
+    let partition_1_offset = read_u32(&rom, 0x120) + read_u32(&rom, 0x124);
+    rom[0x128..0x12C].copy_from_slice(&partition_1_offset.to_le_bytes());
+    rom[0x12C..0x130].copy_from_slice(&0x10_0000u32.to_le_bytes());
+    let (code, output) = verify("ncsd-past-the-end", &rom);
+    assert_eq!(code, Some(1), "{}", output);
+    assert!(find_line(&output, "ncsd partition 0").starts_with("PASS"));
+    assert!(find_line(&output, "ncsd partition 1").starts_with("FAIL"));
+    assert!(find_line(&output, "ncsd image size").starts_with("FAIL"));
+}